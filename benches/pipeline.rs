@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Benchmarks of the import pipeline.
+//!
+//! Run with `cargo bench --features bench`.
+
+use {
+    apple_opensource_downloader::{
+        crawler::{ReleasesPage, VersionsPage},
+        testing::tarball,
+        tree::{tar_data_to_tree, TreeOptions},
+    },
+    criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput},
+    git2::{Odb, Repository},
+};
+
+const RELEASES_HTML: &str = include_str!("fixtures/releases.html");
+const VERSIONS_HTML: &str = include_str!("fixtures/xnu.html");
+
+fn memory_repo() -> Repository {
+    let odb = Odb::new().unwrap();
+    odb.add_new_mempack_backend(1).unwrap();
+    Repository::from_odb(odb).unwrap()
+}
+
+/// A tarball resembling a component with many small source files.
+fn component_tarball() -> Vec<u8> {
+    let content = (0..200)
+        .map(|i| format!("int function_{}(void) {{ return {}; }}\n", i, i))
+        .collect::<String>();
+
+    let paths = (0..1000)
+        .map(|i| format!("dir{}/sub{}/file{}.c", i % 20, i % 7, i))
+        .collect::<Vec<_>>();
+
+    let files = paths
+        .iter()
+        .map(|path| (path.as_str(), content.as_bytes()))
+        .collect::<Vec<_>>();
+
+    tarball("component-1.0", &files)
+}
+
+fn tar_to_tree(c: &mut Criterion) {
+    let data = component_tarball();
+    let options = TreeOptions::default();
+
+    let mut group = c.benchmark_group("tree");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("tar_data_to_tree", |b| {
+        b.iter_batched(
+            memory_repo,
+            |repo| tar_data_to_tree(&data, &repo, &options).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn version_sorting(c: &mut Criterion) {
+    let versions = VersionsPage::parse_html("xnu", VERSIONS_HTML)
+        .unwrap()
+        .versions;
+
+    c.bench_function("sort_versions", |b| {
+        b.iter_batched(
+            || versions.iter().rev().cloned().collect::<Vec<_>>(),
+            |mut versions| versions.sort(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn html_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("html");
+    group.bench_function("releases_page", |b| {
+        b.iter(|| ReleasesPage::parse_html(RELEASES_HTML).unwrap())
+    });
+    group.bench_function("versions_page", |b| {
+        b.iter(|| VersionsPage::parse_html("xnu", VERSIONS_HTML).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tar_to_tree, version_sorting, html_parsing);
+criterion_main!(benches);
@@ -0,0 +1,185 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A blocking facade over the async API.
+//!
+//! [Downloader] owns a Tokio runtime and drives the async
+//! [crate::download::Downloader] to completion on each call, so consumers
+//! that aren't async don't need to set one up. Calls must not be made from
+//! within an async runtime.
+
+use {
+    crate::{
+        crawler::{ComponentManifest, ReleasePage},
+        download::{self, ComponentRecord, ReleaseComponentRecord, ReleaseRecord},
+        git::{self, ImportOptions},
+        status::{self, RepoStatus},
+    },
+    anyhow::{Context, Result},
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        future::Future,
+        path::Path,
+        sync::Arc,
+    },
+    tokio::runtime::Runtime,
+};
+
+/// A blocking client for Apple's open source software.
+///
+/// Clones share the same runtime.
+#[derive(Clone)]
+pub struct Downloader {
+    inner: download::Downloader,
+    runtime: Arc<Runtime>,
+}
+
+impl Downloader {
+    /// Construct an instance with the default configuration.
+    pub fn new() -> Result<Self> {
+        Self::from_async(download::Downloader::new()?)
+    }
+
+    /// Construct an instance wrapping a configured async downloader.
+    ///
+    /// Use [download::Downloader::builder] to customize the source and
+    /// layers.
+    pub fn from_async(inner: download::Downloader) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("building Tokio runtime")?;
+
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The wrapped async downloader.
+    pub fn as_async(&self) -> &download::Downloader {
+        &self.inner
+    }
+
+    /// Run a future on this instance's runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Obtain records describing Apple software releases.
+    pub fn get_releases(&self) -> Result<Vec<ReleaseRecord>> {
+        self.block_on(self.inner.get_releases())
+    }
+
+    /// Obtain releases, including updates only linked from release pages.
+    pub fn get_releases_with_updates(&self) -> Result<Vec<ReleaseRecord>> {
+        self.block_on(self.inner.get_releases_with_updates())
+    }
+
+    /// Find a release by entity and version.
+    pub fn find_release(&self, entity: &str, version: &str) -> Result<ReleaseRecord> {
+        self.block_on(self.inner.find_release(entity, version))
+    }
+
+    /// Obtain the page describing a given Apple software release.
+    pub fn get_release_page(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        self.block_on(self.inner.get_release_page(record))
+    }
+
+    /// Obtain the software components in a given Apple software release.
+    pub fn get_release_components(
+        &self,
+        record: &ReleaseRecord,
+        all_artifacts: bool,
+    ) -> Result<Vec<ReleaseComponentRecord>> {
+        self.block_on(self.inner.get_release_components(record, all_artifacts))
+    }
+
+    /// Obtain the set of named components.
+    pub fn get_components(&self) -> Result<BTreeSet<String>> {
+        self.block_on(self.inner.get_components())
+    }
+
+    /// Obtain the available versions of a component.
+    pub fn get_component_versions(&self, component: &str) -> Result<Vec<ComponentRecord>> {
+        self.block_on(self.inner.get_component_versions(component))
+    }
+
+    /// Obtain the `.plist` manifests published alongside a component's tarballs.
+    pub fn get_component_manifests(&self, component: &str) -> Result<Vec<ComponentManifest>> {
+        self.block_on(self.inner.get_component_manifests(component))
+    }
+
+    /// Obtain metadata about all versions of all components.
+    pub fn get_components_versions(&self) -> Result<BTreeMap<String, Vec<ComponentRecord>>> {
+        self.block_on(self.inner.get_components_versions())
+    }
+
+    /// Obtain the content of an arbitrary artifact URL.
+    pub fn get_artifact(&self, url: &str) -> Result<Vec<u8>> {
+        self.block_on(self.inner.get_artifact(url))
+    }
+
+    /// Get data for a given [ComponentRecord].
+    pub fn get_component_record(&self, record: &ComponentRecord) -> Result<Vec<u8>> {
+        self.block_on(self.inner.get_component_record(record))
+    }
+
+    /// Obtain payload for a release component from its record.
+    pub fn get_release_component_record(&self, record: &ReleaseComponentRecord) -> Result<Vec<u8>> {
+        self.block_on(self.inner.get_release_component_record(record))
+    }
+
+    /// Create a Git repository for a component. See
+    /// [git::create_component_repository].
+    pub fn create_component_repository(
+        &self,
+        path: impl AsRef<Path>,
+        component: &str,
+        options: &ImportOptions,
+    ) -> Result<()> {
+        self.block_on(git::create_component_repository(
+            &self.inner,
+            path,
+            component,
+            options,
+        ))
+    }
+
+    /// Create Git repositories for all components. See
+    /// [git::create_components_repositories].
+    pub fn create_components_repositories(
+        &self,
+        path: &Path,
+        options: &ImportOptions,
+    ) -> Result<()> {
+        self.block_on(git::create_components_repositories(
+            &self.inner,
+            path,
+            options,
+        ))
+    }
+
+    /// Create a Git repository for a software release. See
+    /// [git::create_release_repository].
+    pub fn create_release_repository(
+        &self,
+        path: &Path,
+        release: &str,
+        options: &ImportOptions,
+    ) -> Result<()> {
+        self.block_on(git::create_release_repository(
+            &self.inner,
+            path,
+            release,
+            options,
+        ))
+    }
+
+    /// Compare a component repository with the available versions. See
+    /// [status::repo_status].
+    pub fn repo_status(&self, path: &Path, component: &str) -> Result<RepoStatus> {
+        self.block_on(status::repo_status(&self.inner, path, component))
+    }
+}
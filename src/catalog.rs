@@ -0,0 +1,588 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Shareable snapshots of crawled metadata.
+//!
+//! Crawling every index page of a source takes thousands of requests. A
+//! catalog records everything a crawl discovers — components, their
+//! versions, releases and the artifacts they map to — along with the SHA-256
+//! digests of artifacts known to the artifact cache, as a JSON file. One
+//! person crawls and exports a catalog and others serve index pages from it
+//! with [CatalogSource], verifying downloaded artifacts against its digests.
+
+use {
+    crate::{
+        crawler::{ComponentsPage, ReleasePage, ReleasesPage, Source, VersionsPage},
+        download::{ArtifactKind, ComponentRecord, ReleaseComponentRecord, ReleaseRecord},
+        middleware::CacheLayer,
+        tasks::TaskSet,
+    },
+    anyhow::{anyhow, Context, Result},
+    async_trait::async_trait,
+    chrono::{NaiveDate, NaiveDateTime},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        path::Path,
+        sync::Arc,
+    },
+};
+
+/// Version of the catalog format written by this crate.
+pub const CATALOG_FORMAT: u32 = 1;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A software release and the artifacts it links to.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CatalogRelease {
+    pub entity: String,
+    pub version: String,
+    pub url: String,
+    pub date: Option<String>,
+    pub original_entity: Option<String>,
+    /// Artifacts of the release, if its page was crawled.
+    #[serde(default)]
+    pub components: Vec<CatalogArtifact>,
+    #[serde(default)]
+    pub other_artifacts: Vec<CatalogArtifact>,
+    /// URLs of releases linked from the page of the release.
+    #[serde(default)]
+    pub updates: Vec<String>,
+    /// Whether the index of releases lists the release, rather than only the
+    /// pages of other releases.
+    pub listed: bool,
+    /// Whether the page of the release was crawled.
+    pub crawled: bool,
+}
+
+impl CatalogRelease {
+    fn from_record(record: &ReleaseRecord) -> Self {
+        Self {
+            entity: record.entity.clone(),
+            version: record.version.clone(),
+            url: record.url.clone(),
+            date: record.date.map(|d| d.format(DATE_FORMAT).to_string()),
+            original_entity: record.original_entity.clone(),
+            components: vec![],
+            other_artifacts: vec![],
+            updates: vec![],
+            listed: false,
+            crawled: false,
+        }
+    }
+
+    fn to_record(&self) -> Result<ReleaseRecord> {
+        Ok(ReleaseRecord {
+            entity: self.entity.clone(),
+            version: self.version.clone(),
+            url: self.url.clone(),
+            date: self
+                .date
+                .as_deref()
+                .map(|d| NaiveDate::parse_from_str(d, DATE_FORMAT))
+                .transpose()
+                .with_context(|| format!("parsing date of release {}", self.url))?,
+            original_entity: self.original_entity.clone(),
+        })
+    }
+}
+
+/// An artifact linked from a release page.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CatalogArtifact {
+    pub component: String,
+    pub url: String,
+    pub kind: String,
+}
+
+impl CatalogArtifact {
+    fn from_record(record: &ReleaseComponentRecord) -> Self {
+        Self {
+            component: record.component.clone(),
+            url: record.url.clone(),
+            kind: record.kind.as_str().to_string(),
+        }
+    }
+
+    fn to_record(&self, entity: &str) -> Result<ReleaseComponentRecord> {
+        let kind = match self.kind.as_str() {
+            "tarball" => ArtifactKind::Tarball,
+            "pkg" => ArtifactKind::Package,
+            "dmg" => ArtifactKind::DiskImage,
+            "zip" => ArtifactKind::Zip,
+            kind => return Err(anyhow!("unknown kind {} of artifact {}", kind, self.url)),
+        };
+
+        Ok(ReleaseComponentRecord {
+            entity: entity.to_string(),
+            component: self.component.clone(),
+            url: self.url.clone(),
+            kind,
+        })
+    }
+}
+
+/// A version of a component.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CatalogVersion {
+    pub version: String,
+    pub filename: String,
+    pub url: String,
+    pub size: Option<u64>,
+    pub last_modified: Option<String>,
+}
+
+impl CatalogVersion {
+    fn from_record(record: &ComponentRecord) -> Self {
+        Self {
+            version: record.version.clone(),
+            filename: record.filename.clone(),
+            url: record.url.clone(),
+            size: record.size,
+            last_modified: record
+                .last_modified
+                .map(|t| t.format(DATE_TIME_FORMAT).to_string()),
+        }
+    }
+
+    fn to_record(&self, component: &str) -> Result<ComponentRecord> {
+        Ok(ComponentRecord {
+            component: component.to_string(),
+            filename: self.filename.clone(),
+            url: self.url.clone(),
+            version: self.version.clone(),
+            size: self.size,
+            last_modified: self
+                .last_modified
+                .as_deref()
+                .map(|t| NaiveDateTime::parse_from_str(t, DATE_TIME_FORMAT))
+                .transpose()
+                .with_context(|| format!("parsing modification time of {}", self.url))?,
+        })
+    }
+}
+
+/// The versions of a component.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CatalogComponent {
+    pub versions: Vec<CatalogVersion>,
+    /// URLs of `.plist` manifests of the component.
+    #[serde(default)]
+    pub manifests: Vec<String>,
+    /// Versions published as browsable source trees, if they were crawled.
+    #[serde(default)]
+    pub source_trees: Vec<CatalogVersion>,
+}
+
+/// Crawled metadata of a [Source].
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Catalog {
+    pub format: u32,
+    /// The [name](Source::name) of the crawled source.
+    pub source: String,
+    /// Releases, in the order the source listed them, followed by updates
+    /// only linked from release pages.
+    pub releases: Vec<CatalogRelease>,
+    pub components: BTreeMap<String, CatalogComponent>,
+    /// SHA-256 digests of artifacts, keyed by URL.
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+}
+
+impl Catalog {
+    /// Crawl every index page of a source.
+    ///
+    /// Release pages that can't be fetched are reported and recorded as not
+    /// crawled. Browsable source trees are only crawled if `source_trees` is
+    /// true.
+    pub async fn crawl(source: Arc<dyn Source>, source_trees: bool) -> Result<Self> {
+        let mut catalog = Self {
+            format: CATALOG_FORMAT,
+            source: source.name(),
+            ..Self::default()
+        };
+
+        let mut pending = source
+            .releases()
+            .await
+            .context("fetching releases")?
+            .releases;
+        let listed = pending
+            .iter()
+            .map(|record| record.url.clone())
+            .collect::<BTreeSet<_>>();
+        let mut seen = BTreeSet::new();
+
+        while !pending.is_empty() {
+            let mut tasks = TaskSet::with_limit(8);
+
+            for record in pending.drain(..) {
+                if !seen.insert(record.url.clone()) {
+                    continue;
+                }
+
+                let source = source.clone();
+                catalog.releases.push(CatalogRelease {
+                    listed: listed.contains(&record.url),
+                    ..CatalogRelease::from_record(&record)
+                });
+
+                tasks.spawn(
+                    record.url.clone(),
+                    async move { source.release(&record).await },
+                );
+            }
+
+            for outcome in tasks.join().await {
+                let release = catalog
+                    .releases
+                    .iter_mut()
+                    .find(|release| release.url == outcome.label)
+                    .expect("crawled release is recorded");
+
+                match outcome.result {
+                    Ok(page) => {
+                        release.components = page
+                            .components
+                            .iter()
+                            .map(CatalogArtifact::from_record)
+                            .collect();
+                        release.other_artifacts = page
+                            .other_artifacts
+                            .iter()
+                            .map(CatalogArtifact::from_record)
+                            .collect();
+                        release.updates = page.updates.iter().map(|r| r.url.clone()).collect();
+                        release.crawled = true;
+
+                        pending.extend(page.updates);
+                    }
+                    Err(e) => println!("warning: unable to crawl {}: {:?}", outcome.label, e),
+                }
+            }
+        }
+
+        let components = source
+            .components()
+            .await
+            .context("fetching components")?
+            .components;
+
+        let mut tasks = TaskSet::new();
+        for component in components {
+            let source = source.clone();
+
+            tasks.spawn(component.clone(), async move {
+                let page = source.versions(&component).await?;
+
+                let trees = if source_trees {
+                    // Most components don't have source trees.
+                    source
+                        .source_trees(&component)
+                        .await
+                        .map(|page| page.versions)
+                        .unwrap_or_default()
+                } else {
+                    vec![]
+                };
+
+                Ok((
+                    component,
+                    CatalogComponent {
+                        versions: page
+                            .versions
+                            .iter()
+                            .map(CatalogVersion::from_record)
+                            .collect(),
+                        manifests: page.manifests,
+                        source_trees: trees.iter().map(CatalogVersion::from_record).collect(),
+                    },
+                ))
+            });
+        }
+
+        catalog.components = tasks.try_join().await?.into_iter().collect();
+
+        Ok(catalog)
+    }
+
+    /// Record the digests of artifacts of the catalog downloaded through an
+    /// artifact cache.
+    ///
+    /// Returns the number of recorded digests.
+    pub fn add_cached_checksums(&mut self, cache: &CacheLayer) -> Result<usize> {
+        let urls = self.artifact_urls();
+        let mut count = 0;
+
+        for (url, digest) in cache.digests()? {
+            if urls.contains(&url) {
+                self.checksums.insert(url, digest);
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// URLs of all artifacts in the catalog.
+    pub fn artifact_urls(&self) -> BTreeSet<String> {
+        let releases = self.releases.iter().flat_map(|release| {
+            release
+                .components
+                .iter()
+                .chain(&release.other_artifacts)
+                .map(|artifact| artifact.url.clone())
+        });
+        let components = self.components.values().flat_map(|component| {
+            component
+                .versions
+                .iter()
+                .map(|version| version.url.clone())
+                .chain(component.manifests.iter().cloned())
+        });
+
+        releases.chain(components).collect()
+    }
+
+    /// Serialize to JSON.
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let mut data = serde_json::to_vec_pretty(self)?;
+        data.push(b'\n');
+
+        Ok(data)
+    }
+
+    /// Parse and validate a catalog.
+    pub fn from_json(data: &[u8]) -> Result<Self> {
+        let catalog: Self = serde_json::from_slice(data)?;
+        catalog.validate()?;
+
+        Ok(catalog)
+    }
+
+    /// Read a catalog from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("reading catalog {}", path.display()))?;
+
+        Self::from_json(&data).with_context(|| format!("parsing catalog {}", path.display()))
+    }
+
+    /// Write a catalog to a file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_json()?)
+            .with_context(|| format!("writing catalog {}", path.display()))
+    }
+
+    /// Verify the catalog is well-formed.
+    ///
+    /// Every record must be parseable, every update must refer to a release
+    /// of the catalog and every digest must be a SHA-256 digest.
+    pub fn validate(&self) -> Result<()> {
+        if self.format != CATALOG_FORMAT {
+            return Err(anyhow!(
+                "unsupported catalog format {}; expected {}",
+                self.format,
+                CATALOG_FORMAT
+            ));
+        }
+
+        let urls = self
+            .releases
+            .iter()
+            .map(|release| release.url.as_str())
+            .collect::<BTreeSet<_>>();
+
+        for release in &self.releases {
+            release.to_record()?;
+
+            for artifact in release.components.iter().chain(&release.other_artifacts) {
+                artifact.to_record(&release.entity)?;
+            }
+
+            if let Some(update) = release.updates.iter().find(|u| !urls.contains(u.as_str())) {
+                return Err(anyhow!(
+                    "release {} links to unknown release {}",
+                    release.url,
+                    update
+                ));
+            }
+        }
+
+        for (name, component) in &self.components {
+            for version in component.versions.iter().chain(&component.source_trees) {
+                version.to_record(name)?;
+            }
+        }
+
+        for (url, digest) in &self.checksums {
+            if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(anyhow!("{} has malformed SHA-256 digest {}", url, digest));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compare the digests of the catalog with the digests of artifacts
+    /// downloaded through an artifact cache.
+    pub fn verify_cache(&self, cache: &CacheLayer) -> Result<CacheVerification> {
+        let cached = cache.digests()?;
+        let mut verification = CacheVerification::default();
+
+        for (url, expected) in &self.checksums {
+            match cached.get(url) {
+                Some(actual) if actual == expected => verification.verified += 1,
+                Some(actual) => verification.mismatched.push(DigestMismatch {
+                    url: url.clone(),
+                    expected: expected.clone(),
+                    actual: actual.clone(),
+                }),
+                None => verification.missing += 1,
+            }
+        }
+
+        Ok(verification)
+    }
+}
+
+/// An artifact whose digest differs from the digest in a catalog.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DigestMismatch {
+    pub url: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// The result of [Catalog::verify_cache].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheVerification {
+    /// Number of cached artifacts having the digest of the catalog.
+    pub verified: usize,
+    /// Number of artifacts with a digest in the catalog that aren't cached.
+    pub missing: usize,
+    pub mismatched: Vec<DigestMismatch>,
+}
+
+/// A [Source] serving index pages from a [Catalog].
+///
+/// Artifacts are fetched with another source.
+pub struct CatalogSource {
+    catalog: Catalog,
+    inner: Arc<dyn Source>,
+}
+
+impl CatalogSource {
+    pub fn new(catalog: Catalog, inner: Arc<dyn Source>) -> Self {
+        Self { catalog, inner }
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    fn find_release(&self, url: &str) -> Option<&CatalogRelease> {
+        self.catalog
+            .releases
+            .iter()
+            .find(|release| release.url == url)
+    }
+
+    fn component(&self, component: &str) -> Result<&CatalogComponent> {
+        self.catalog
+            .components
+            .get(component)
+            .ok_or_else(|| anyhow!("component {} isn't in the catalog", component))
+    }
+}
+
+#[async_trait]
+impl Source for CatalogSource {
+    /// The name of the source the catalog was crawled from, so provenance
+    /// doesn't depend on how metadata was obtained.
+    fn name(&self) -> String {
+        self.catalog.source.clone()
+    }
+
+    async fn get_from(&self, url: &str, offset: u64) -> Result<reqwest::Response> {
+        self.inner.get_from(url, offset).await
+    }
+
+    async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        self.inner.head(url).await
+    }
+
+    async fn releases(&self) -> Result<ReleasesPage> {
+        Ok(ReleasesPage {
+            releases: self
+                .catalog
+                .releases
+                .iter()
+                .filter(|release| release.listed)
+                .map(|release| release.to_record())
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    async fn release(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        let release = self
+            .find_release(&record.url)
+            .filter(|release| release.crawled)
+            .ok_or_else(|| anyhow!("release {} isn't in the catalog", record.url))?;
+
+        Ok(ReleasePage {
+            components: release
+                .components
+                .iter()
+                .map(|artifact| artifact.to_record(&record.entity))
+                .collect::<Result<Vec<_>>>()?,
+            other_artifacts: release
+                .other_artifacts
+                .iter()
+                .map(|artifact| artifact.to_record(&record.entity))
+                .collect::<Result<Vec<_>>>()?,
+            html: None,
+            updates: release
+                .updates
+                .iter()
+                .filter_map(|url| self.find_release(url))
+                .map(|update| update.to_record())
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    async fn components(&self) -> Result<ComponentsPage> {
+        Ok(ComponentsPage {
+            components: self.catalog.components.keys().cloned().collect(),
+        })
+    }
+
+    async fn versions(&self, component: &str) -> Result<VersionsPage> {
+        let entry = self.component(component)?;
+
+        Ok(VersionsPage {
+            versions: entry
+                .versions
+                .iter()
+                .map(|version| version.to_record(component))
+                .collect::<Result<Vec<_>>>()?,
+            manifests: entry.manifests.clone(),
+        })
+    }
+
+    async fn source_trees(&self, component: &str) -> Result<VersionsPage> {
+        let entry = self.component(component)?;
+
+        Ok(VersionsPage {
+            versions: entry
+                .source_trees
+                .iter()
+                .map(|version| version.to_record(component))
+                .collect::<Result<Vec<_>>>()?,
+            manifests: vec![],
+        })
+    }
+}
@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Chronological ordering of software releases.
+//!
+//! Release repositories commit releases oldest to newest. Release dates are
+//! the primary order. Releases without a known date are placed by version
+//! among dated releases.
+//!
+//! The resulting order is verified: versions should increase monotonically.
+//! When they don't, e.g. because an update of an older version was published
+//! after a newer version, the history is ambiguous.
+
+use {
+    crate::download::{compare_version_string, ReleaseRecord},
+    anyhow::{anyhow, Result},
+    chrono::NaiveDate,
+    std::{
+        cmp::Ordering,
+        collections::BTreeMap,
+        fmt::{Display, Formatter},
+    },
+};
+
+/// How to handle releases whose chronological order is ambiguous.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OrderVerification {
+    /// Print a warning and import in chronological order.
+    #[default]
+    Warn,
+    /// Fail before importing anything.
+    Abort,
+}
+
+/// Why the position of a release in history is ambiguous.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OrderingIssueKind {
+    /// The release has a lower version than the release before it.
+    VersionRegression,
+    /// The release has the same version as the release before it.
+    DuplicateVersion,
+}
+
+/// A release whose position in history is ambiguous.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OrderingIssue {
+    pub kind: OrderingIssueKind,
+    /// The release preceding [Self::release].
+    pub previous: ReleaseRecord,
+    pub release: ReleaseRecord,
+}
+
+impl Display for OrderingIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let describe = |record: &ReleaseRecord| match record.date {
+            Some(date) => format!("{} {} ({})", record.entity, record.version, date),
+            None => format!("{} {} (undated)", record.entity, record.version),
+        };
+
+        match self.kind {
+            OrderingIssueKind::VersionRegression => write!(
+                f,
+                "{} follows newer version {}",
+                describe(&self.release),
+                describe(&self.previous)
+            ),
+            OrderingIssueKind::DuplicateVersion => write!(
+                f,
+                "{} has the same version as {}",
+                describe(&self.release),
+                describe(&self.previous)
+            ),
+        }
+    }
+}
+
+/// Order releases oldest to newest.
+///
+/// Dated releases are ordered by date, then version. Each undated release is
+/// placed before the first dated release with a greater version.
+pub fn order_releases(releases: impl IntoIterator<Item = ReleaseRecord>) -> Vec<ReleaseRecord> {
+    let by_version = |a: &ReleaseRecord, b: &ReleaseRecord| {
+        compare_version_string(&a.version, &b.version)
+            .then_with(|| a.entity.cmp(&b.entity))
+            .then_with(|| a.url.cmp(&b.url))
+    };
+
+    let (mut dated, mut undated): (Vec<_>, Vec<_>) =
+        releases.into_iter().partition(|r| r.date.is_some());
+    dated.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| by_version(a, b)));
+    undated.sort_by(by_version);
+
+    let mut ordered = Vec::with_capacity(dated.len() + undated.len());
+    let mut undated = undated.into_iter().peekable();
+
+    for record in dated {
+        while let Some(next) = undated
+            .next_if(|u| compare_version_string(&u.version, &record.version) != Ordering::Greater)
+        {
+            ordered.push(next);
+        }
+
+        ordered.push(record);
+    }
+    ordered.extend(undated);
+
+    ordered
+}
+
+/// Select the releases of a history published on or after `since`, oldest to
+/// newest.
+///
+/// Undated releases are placed by [order_releases] and kept unless the dated
+/// release following them is older than `since`.
+pub fn releases_since(
+    releases: impl IntoIterator<Item = ReleaseRecord>,
+    since: NaiveDate,
+) -> Vec<ReleaseRecord> {
+    let mut next_date = None;
+    let mut selected = vec![];
+
+    for record in order_releases(releases).into_iter().rev() {
+        if record.date.is_some() {
+            next_date = record.date;
+        }
+
+        if next_date.is_none_or(|date| date >= since) {
+            selected.push(record);
+        }
+    }
+
+    selected.reverse();
+
+    selected
+}
+
+/// Order releases by platform and each platform's releases oldest to newest,
+/// optionally only selecting those published on or after `since`.
+pub fn order_platform_releases(
+    releases: impl IntoIterator<Item = ReleaseRecord>,
+    since: Option<NaiveDate>,
+) -> Vec<ReleaseRecord> {
+    let mut platforms = BTreeMap::<String, Vec<ReleaseRecord>>::new();
+
+    for record in releases {
+        platforms
+            .entry(record.platform().as_str().to_string())
+            .or_default()
+            .push(record);
+    }
+
+    platforms
+        .into_values()
+        .flat_map(|history| match since {
+            Some(since) => releases_since(history, since),
+            None => order_releases(history),
+        })
+        .collect()
+}
+
+/// Find releases whose versions don't increase monotonically.
+pub fn verify_order(releases: &[ReleaseRecord]) -> Vec<OrderingIssue> {
+    releases
+        .windows(2)
+        .filter_map(|pair| {
+            let kind = match compare_version_string(&pair[0].version, &pair[1].version) {
+                Ordering::Less => return None,
+                Ordering::Equal => OrderingIssueKind::DuplicateVersion,
+                Ordering::Greater => OrderingIssueKind::VersionRegression,
+            };
+
+            Some(OrderingIssue {
+                kind,
+                previous: pair[0].clone(),
+                release: pair[1].clone(),
+            })
+        })
+        .collect()
+}
+
+/// Order releases chronologically and verify the order.
+///
+/// Ambiguities are printed as warnings or returned as an error, depending
+/// on `verification`.
+pub fn chronological_releases(
+    releases: impl IntoIterator<Item = ReleaseRecord>,
+    verification: OrderVerification,
+) -> Result<Vec<ReleaseRecord>> {
+    let releases = order_releases(releases);
+    let issues = verify_order(&releases);
+
+    match verification {
+        OrderVerification::Warn => {
+            for issue in &issues {
+                println!("warning: ambiguous release order: {}", issue);
+            }
+        }
+        OrderVerification::Abort if !issues.is_empty() => {
+            return Err(anyhow!(
+                "ambiguous release order:\n{}",
+                issues
+                    .iter()
+                    .map(|issue| format!("  {}", issue))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ));
+        }
+        OrderVerification::Abort => {}
+    }
+
+    Ok(releases)
+}
+
+/// Interleave the chronological release histories of several entities.
+///
+/// The earliest next release of any history is taken first. Undated releases
+/// can't be placed, so they are taken as soon as they are next in their
+/// history. Returns the index of each release's history with the release.
+pub fn interleave_releases(histories: Vec<Vec<ReleaseRecord>>) -> Vec<(usize, ReleaseRecord)> {
+    let mut histories = histories
+        .into_iter()
+        .map(|history| history.into_iter().peekable())
+        .collect::<Vec<_>>();
+
+    let mut releases = vec![];
+
+    while let Some(index) = histories
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(index, history)| history.peek().map(|record| (index, record.date)))
+        .min_by_key(|(index, date)| (date.is_some(), *date, *index))
+        .map(|(index, _)| index)
+    {
+        let record = histories[index].next().expect("peeked release exists");
+        releases.push((index, record));
+    }
+
+    releases
+}
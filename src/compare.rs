@@ -0,0 +1,119 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Cross-verification of independently imported repositories.
+//!
+//! Imports are deterministic, so mirrors produced by different people from
+//! the same archives should have identical trees for every tag. Comparing
+//! tree IDs rather than commit IDs tolerates differences in commit metadata,
+//! such as `--commit-date` or importer trailers, while still detecting any
+//! difference in content.
+
+use {
+    crate::{download::compare_version_string, export::Table},
+    anyhow::{Context, Result},
+    git2::{Oid, Repository},
+    std::{collections::BTreeMap, path::Path},
+};
+
+/// How a tag compares between two repositories.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TagStatus {
+    /// Both repositories have the same tree for the tag.
+    Match,
+    /// The repositories have different trees for the tag.
+    Differ,
+    /// Only the first repository has the tag.
+    OnlyA,
+    /// Only the second repository has the tag.
+    OnlyB,
+}
+
+impl TagStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Match => "match",
+            Self::Differ => "differ",
+            Self::OnlyA => "only-a",
+            Self::OnlyB => "only-b",
+        }
+    }
+}
+
+/// The trees of a tag in two repositories.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TagComparison {
+    pub tag: String,
+    /// The tree of the tag in the first repository.
+    pub a: Option<Oid>,
+    /// The tree of the tag in the second repository.
+    pub b: Option<Oid>,
+}
+
+impl TagComparison {
+    pub fn status(&self) -> TagStatus {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) if a == b => TagStatus::Match,
+            (Some(_), Some(_)) => TagStatus::Differ,
+            (Some(_), None) => TagStatus::OnlyA,
+            (None, _) => TagStatus::OnlyB,
+        }
+    }
+}
+
+/// Resolve the tree of every tag in a repository.
+pub fn tag_trees(repo_path: &Path) -> Result<BTreeMap<String, Oid>> {
+    let repo = Repository::open(repo_path)
+        .with_context(|| format!("opening repository {}", repo_path.display()))?;
+
+    let mut trees = BTreeMap::new();
+
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let tree = repo
+            .revparse_single(&format!("refs/tags/{}", name))?
+            .peel_to_tree()
+            .with_context(|| format!("resolving tree of tag {}", name))?;
+
+        trees.insert(name.to_string(), tree.id());
+    }
+
+    Ok(trees)
+}
+
+/// Compare the trees of the tags of two repositories.
+///
+/// Every tag of either repository is compared, in version order.
+pub fn compare_repositories(a: &Path, b: &Path) -> Result<Vec<TagComparison>> {
+    let a = tag_trees(a)?;
+    let b = tag_trees(b)?;
+
+    let mut comparisons = a
+        .keys()
+        .chain(b.keys().filter(|tag| !a.contains_key(*tag)))
+        .map(|tag| TagComparison {
+            tag: tag.clone(),
+            a: a.get(tag).copied(),
+            b: b.get(tag).copied(),
+        })
+        .collect::<Vec<_>>();
+    comparisons.sort_by(|x, y| compare_version_string(&x.tag, &y.tag));
+
+    Ok(comparisons)
+}
+
+/// Tabulate comparisons for export.
+pub fn comparisons_table(comparisons: &[TagComparison]) -> Table {
+    let mut table = Table::new(&["tag", "status", "tree_a", "tree_b"]);
+
+    for comparison in comparisons {
+        table.push(vec![
+            Some(comparison.tag.clone()),
+            Some(comparison.status().as_str().to_string()),
+            comparison.a.map(|oid| oid.to_string()),
+            comparison.b.map(|oid| oid.to_string()),
+        ]);
+    }
+
+    table
+}
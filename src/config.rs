@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Configuration files.
+//!
+//! Configuration is read from a JSON file passed with `--config`.
+
+use {
+    crate::{
+        crawler::{UrlSchemes, VersionParser},
+        download::VersionOrders,
+        platform::EntityAliases,
+        sync::SyncConfig,
+        tree::ModeOverride,
+    },
+    anyhow::{Context, Result},
+    serde::Deserialize,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Settings read from a configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Regular expressions parsing versions from tarball filenames, keyed by
+    /// component.
+    ///
+    /// Expressions must have a `version` named group. Filenames not matching
+    /// are ignored.
+    pub filename_parsers: BTreeMap<String, String>,
+
+    /// Canonical entity names of release entities, keyed by alias.
+    ///
+    /// These take precedence over the built-in aliases of known platforms.
+    pub entity_aliases: BTreeMap<String, String>,
+
+    /// Leave release entity names as published. `--raw-entities` also does
+    /// this.
+    pub raw_entities: bool,
+
+    /// Directory caching downloaded artifacts. `--cache-dir` takes
+    /// precedence.
+    pub cache_dir: Option<PathBuf>,
+
+    /// File holding a hexadecimal AES-256 key encrypting cached artifacts.
+    /// `--cache-key-file` takes precedence.
+    pub cache_key_file: Option<PathBuf>,
+
+    /// Directory caching index pages. `--index-cache-dir` takes precedence.
+    pub index_cache_dir: Option<PathBuf>,
+
+    /// How long cached index pages are used, e.g. `12h`. Defaults to an hour.
+    /// `--index-cache-ttl` takes precedence.
+    pub index_cache_ttl: Option<String>,
+
+    /// What the `sync` command mirrors.
+    pub sync: SyncConfig,
+
+    /// Limit of the total size of archives buffered at once by imports, e.g.
+    /// `4GB`. `--memory-budget` takes precedence.
+    pub memory_budget: Option<String>,
+
+    /// Number of async runtime worker threads. `--threads` takes precedence.
+    pub threads: Option<usize>,
+
+    /// Maximum number of threads for blocking work. `--blocking-threads`
+    /// takes precedence.
+    pub blocking_threads: Option<usize>,
+
+    /// Templates of URLs artifacts are looked up under when their published
+    /// URL isn't found, in order of preference. An empty list disables the
+    /// fallback. Defaults to the schemes opensource.apple.com has used.
+    pub url_schemes: Option<Vec<String>>,
+
+    /// Orders of versions of components whose version numbers don't sort
+    /// chronologically, keyed by component.
+    pub version_orders: BTreeMap<String, VersionOrderConfig>,
+
+    /// Git file modes forced on files at paths matching patterns, in order of
+    /// precedence.
+    pub mode_overrides: Vec<ModeOverrideConfig>,
+
+    /// File runs append timing and download statistics to.
+    /// `--telemetry-log` takes precedence.
+    pub telemetry_log: Option<PathBuf>,
+}
+
+/// A Git file mode forced on files at paths matching a pattern.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModeOverrideConfig {
+    /// Pattern matched against paths in trees, like `--exclude-path`.
+    pub pattern: String,
+    /// `100644` or `100755`.
+    pub mode: String,
+}
+
+/// How the versions of a component are ordered.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum VersionOrderConfig {
+    /// Versions in order, oldest first. Versions not listed follow.
+    Versions(Vec<String>),
+    /// Regular expressions matching versioning schemes, oldest first.
+    /// Versions matching no scheme follow.
+    Schemes { schemes: Vec<String> },
+}
+
+impl Config {
+    /// Read configuration from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("reading config {}", path.display()))?;
+
+        serde_json::from_slice(&data).with_context(|| format!("parsing config {}", path.display()))
+    }
+
+    /// Obtain the parser of component versions as configured.
+    pub fn version_parser(&self) -> Result<VersionParser> {
+        self.filename_parsers
+            .iter()
+            .try_fold(VersionParser::default(), |parser, (component, pattern)| {
+                parser.with_override(component, pattern)
+            })
+    }
+
+    /// Obtain the orders of component versions as configured.
+    pub fn version_orders(&self) -> Result<VersionOrders> {
+        self.version_orders.iter().try_fold(
+            VersionOrders::default(),
+            |orders, (component, order)| match order {
+                VersionOrderConfig::Versions(versions) => {
+                    Ok(orders.with_versions(component, versions))
+                }
+                VersionOrderConfig::Schemes { schemes } => orders.with_schemes(component, schemes),
+            },
+        )
+    }
+
+    /// Obtain the file mode overrides as configured.
+    pub fn mode_overrides(&self) -> Result<Vec<ModeOverride>> {
+        self.mode_overrides
+            .iter()
+            .map(|o| ModeOverride::new(&o.pattern, &o.mode))
+            .collect()
+    }
+
+    /// Obtain the URL schemes of artifacts as configured.
+    pub fn url_schemes(&self) -> Result<Option<UrlSchemes>> {
+        match &self.url_schemes {
+            None => Ok(Some(UrlSchemes::default())),
+            Some(templates) if templates.is_empty() => Ok(None),
+            Some(templates) => Ok(Some(UrlSchemes::new(templates)?)),
+        }
+    }
+
+    /// Obtain the canonicalization of release entities as configured.
+    pub fn entity_aliases(&self) -> EntityAliases {
+        if self.raw_entities {
+            return EntityAliases::disabled();
+        }
+
+        self.entity_aliases
+            .iter()
+            .fold(EntityAliases::default(), |aliases, (alias, canonical)| {
+                aliases.with_alias(alias, canonical)
+            })
+    }
+}
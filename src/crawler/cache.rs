@@ -0,0 +1,264 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! On-disk caching of index pages.
+//!
+//! Listings of releases, components, and versions change rarely, so sources
+//! can reuse a previously fetched page until it is older than a time to live.
+//! This is separate from the artifact cache, whose entries never expire.
+//!
+//! The `ETag` and SHA-256 digest of each page are stored alongside it. When a
+//! page expires, it is requested conditionally with its `ETag` and reused if
+//! the server reports it unchanged. Fetched pages are compared with the
+//! previous content by digest, so callers can tell which pages changed.
+
+use {
+    crate::{
+        crypto::sha256_hex,
+        error::HttpStatusError,
+        middleware::{cache_filename, partial_path},
+    },
+    anyhow::{Context, Result},
+    reqwest::{header, Client, StatusCode},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        future::Future,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    },
+};
+
+/// The default time to live of cached index pages.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How a fetched page compares to the page previously cached for its URL.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PageChange {
+    /// No page was cached.
+    New,
+    /// The content of the page differs from the cached page.
+    Changed,
+    /// The page has the content of the cached page.
+    Unchanged,
+}
+
+impl PageChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Changed => "changed",
+            Self::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Identifies the version of a cached page.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PageValidators {
+    /// The `ETag` the server sent with the page.
+    pub etag: Option<String>,
+    /// The SHA-256 digest of the page.
+    ///
+    /// Pages cached before digests were stored don't have one.
+    pub sha256: Option<String>,
+}
+
+/// A directory of index pages, keyed by URL.
+#[derive(Clone, Debug)]
+pub struct PageCache {
+    dir: PathBuf,
+    ttl: Duration,
+    /// How pages fetched by this instance compare to the cached pages.
+    changes: Arc<Mutex<BTreeMap<String, PageChange>>>,
+}
+
+impl PageCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+            changes: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(cache_filename(url))
+    }
+
+    fn validators_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.meta", cache_filename(url)))
+    }
+
+    /// How pages fetched by this instance compared to the pages previously
+    /// cached for their URLs, keyed by URL.
+    ///
+    /// Pages served from the cache without being fetched aren't listed.
+    pub fn changes(&self) -> BTreeMap<String, PageChange> {
+        self.changes.lock().unwrap().clone()
+    }
+
+    /// Obtain the cached content of a URL and its validators, even if it has
+    /// expired.
+    pub async fn get_stale(&self, url: &str) -> Option<(String, PageValidators)> {
+        let text = tokio::fs::read_to_string(self.path(url)).await.ok()?;
+
+        let validators = tokio::fs::read(self.validators_path(url))
+            .await
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        Some((text, validators))
+    }
+
+    /// Obtain the cached content of a URL, if it hasn't expired.
+    pub async fn get(&self, url: &str) -> Option<String> {
+        let path = self.path(url);
+
+        let modified = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+
+        if age >= self.ttl {
+            return None;
+        }
+
+        tokio::fs::read_to_string(&path).await.ok()
+    }
+
+    /// Store the content of a URL.
+    pub async fn put(&self, url: &str, text: &str) -> Result<()> {
+        self.store(url, text, None).await?;
+
+        Ok(())
+    }
+
+    /// Store the content of a URL with the `ETag` it was served with.
+    ///
+    /// Returns how the content compares to the previously cached content.
+    pub async fn store(&self, url: &str, text: &str, etag: Option<&str>) -> Result<PageChange> {
+        let sha256 = sha256_hex(text.as_bytes());
+
+        let change = match self.get_stale(url).await {
+            None => PageChange::New,
+            Some((_, validators)) if validators.sha256.as_deref() == Some(sha256.as_str()) => {
+                PageChange::Unchanged
+            }
+            Some((previous, _)) if previous == text => PageChange::Unchanged,
+            Some(_) => PageChange::Changed,
+        };
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("creating index cache directory {}", self.dir.display()))?;
+
+        let path = self.path(url);
+        let temp_path = partial_path(&path);
+        tokio::fs::write(&temp_path, text)
+            .await
+            .with_context(|| format!("writing {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, &path)
+            .await
+            .with_context(|| format!("renaming {}", temp_path.display()))?;
+
+        let validators = PageValidators {
+            etag: etag.map(|etag| etag.to_string()),
+            sha256: Some(sha256),
+        };
+        let validators_path = self.validators_path(url);
+        tokio::fs::write(&validators_path, serde_json::to_vec(&validators)?)
+            .await
+            .with_context(|| format!("writing {}", validators_path.display()))?;
+
+        self.changes.lock().unwrap().insert(url.to_string(), change);
+
+        Ok(change)
+    }
+
+    /// Obtain the cached content of a URL or fetch and store it.
+    pub async fn get_or_fetch(
+        &self,
+        url: &str,
+        fetch: impl Future<Output = Result<String>>,
+    ) -> Result<String> {
+        if let Some(text) = self.get(url).await {
+            return Ok(text);
+        }
+
+        let text = fetch.await?;
+        self.put(url, &text).await?;
+
+        Ok(text)
+    }
+}
+
+/// Fetch the content of a URL with a GET request through an optional cache.
+///
+/// Expired pages having an `ETag` are requested conditionally and reused if
+/// the server responds with `304 Not Modified`.
+pub(crate) async fn revalidated(
+    cache: Option<&PageCache>,
+    client: &Client,
+    url: &str,
+) -> Result<String> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return Ok(super::get_request(client, url, 0).await?.text().await?),
+    };
+
+    if let Some(text) = cache.get(url).await {
+        return Ok(text);
+    }
+
+    let stale = cache.get_stale(url).await;
+
+    let mut req = client.get(url);
+    if let Some(etag) = stale.as_ref().and_then(|(_, v)| v.etag.as_deref()) {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+
+    let res = req.send().await?;
+
+    if res.status() == StatusCode::NOT_MODIFIED {
+        if let Some((text, validators)) = stale {
+            cache.store(url, &text, validators.etag.as_deref()).await?;
+
+            return Ok(text);
+        }
+    }
+
+    if !res.status().is_success() {
+        return Err(HttpStatusError {
+            status: res.status().as_u16(),
+            url: url.to_string(),
+        }
+        .into());
+    }
+
+    let etag = res
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let text = res.text().await?;
+
+    cache.store(url, &text, etag.as_deref()).await?;
+
+    Ok(text)
+}
+
+/// Fetch the content of a URL through an optional cache.
+pub(crate) async fn cached(
+    cache: Option<&PageCache>,
+    url: &str,
+    fetch: impl Future<Output = Result<String>>,
+) -> Result<String> {
+    match cache {
+        Some(cache) => cache.get_or_fetch(url, fetch).await,
+        None => fetch.await,
+    }
+}
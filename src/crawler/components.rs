@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Component index page.
+
+use {anyhow::Result, regex::Regex, std::collections::BTreeSet};
+
+/// The index of components.
+#[derive(Clone, Debug, Default)]
+pub struct ComponentsPage {
+    /// Names of components. e.g. `hfs` and `AppleFileSystemDriver`.
+    pub components: BTreeSet<String>,
+}
+
+impl ComponentsPage {
+    /// Parse the HTML of the tarballs directory listing.
+    pub fn parse_html(text: &str) -> Result<Self> {
+        // One does not use regular expressions to parse HTML. Meh.
+        let re = Regex::new(
+            r#"<tr><td valign="top"><a href="(?P<component>[^/]+)/"><img src="/static/images/icons/folder.png""#,
+        )?;
+
+        Ok(Self::from_links(text, &re))
+    }
+
+    /// Parse the HTML of the tarballs directory listing on the 2011-era site,
+    /// which used Apache's default directory index.
+    pub fn parse_legacy_html(text: &str) -> Result<Self> {
+        // Parent directory and sorting links have a / or ? before the end.
+        let re = Regex::new(r#"<a href="(?P<component>[^"/?]+)/">"#)?;
+
+        Ok(Self::from_links(text, &re))
+    }
+
+    fn from_links(text: &str, re: &Regex) -> Self {
+        Self {
+            components: BTreeSet::from_iter(
+                re.captures_iter(text)
+                    .map(|caps| caps["component"].to_string()),
+            ),
+        }
+    }
+}
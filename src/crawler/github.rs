@@ -0,0 +1,319 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Source backed by the apple-oss-distributions GitHub organization.
+//!
+//! Each component is a repository whose tags are named `<component>-<version>`.
+//! Software releases are tags of `distribution-<entity>` repositories, whose
+//! trees contain a submodule for each component in the release.
+
+use {
+    super::{cache, ComponentsPage, PageCache, ReleasePage, ReleasesPage, Source, VersionsPage},
+    crate::{
+        download::{ArtifactKind, ComponentRecord, ReleaseComponentRecord, ReleaseRecord},
+        error::HttpStatusError,
+    },
+    anyhow::{Context, Result},
+    async_trait::async_trait,
+    reqwest::{header::HeaderMap, Client, StatusCode},
+    serde::{de::DeserializeOwned, Deserialize},
+    std::time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The GitHub organization Apple publishes open source code to.
+pub const GITHUB_ORG: &str = "apple-oss-distributions";
+
+pub const API_URL: &str = "https://api.github.com";
+
+/// Number of items requested per page of paginated API results.
+const PER_PAGE: usize = 100;
+
+/// Prefix of repositories describing software releases.
+const DISTRIBUTION_PREFIX: &str = "distribution-";
+
+#[derive(Deserialize)]
+struct Repo {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+    tarball_url: String,
+}
+
+#[derive(Deserialize)]
+struct Tree {
+    tree: Vec<TreeEntry>,
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    sha: String,
+}
+
+/// Resolve how long to wait before retrying a rate limited request.
+///
+/// Returns `None` if the response doesn't indicate rate limiting.
+fn rate_limit_wait(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    // Secondary rate limits advertise how long to wait.
+    if let Some(seconds) = header("retry-after").and_then(|v| v.parse::<u64>().ok()) {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    if header("x-ratelimit-remaining") == Some("0") {
+        let reset = header("x-ratelimit-reset")?.parse::<u64>().ok()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // Allow for clock skew.
+        return Some(Duration::from_secs(reset.saturating_sub(now) + 1));
+    }
+
+    None
+}
+
+/// A [Source] using the GitHub API.
+pub struct GitHubSource {
+    client: Client,
+    token: Option<String>,
+    page_cache: Option<PageCache>,
+}
+
+impl GitHubSource {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            token: None,
+            page_cache: None,
+        }
+    }
+
+    /// Authenticate API requests with a token.
+    ///
+    /// Unauthenticated requests are limited to 60 per hour.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Reuse API responses fetched recently.
+    pub fn with_page_cache(mut self, cache: Option<PageCache>) -> Self {
+        self.page_cache = cache;
+        self
+    }
+
+    /// Issue a request, waiting out rate limits.
+    async fn request(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        offset: u64,
+    ) -> Result<reqwest::Response> {
+        loop {
+            let mut req = self
+                .client
+                .request(method.clone(), url)
+                .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+
+            if let Some(token) = &self.token {
+                req = req.bearer_auth(token);
+            }
+
+            if offset > 0 {
+                req = req.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+            }
+
+            let res = req.send().await?;
+
+            if res.status().is_success() {
+                return Ok(res);
+            }
+
+            if let Some(wait) = rate_limit_wait(res.status(), res.headers()) {
+                println!(
+                    "GitHub API rate limit exceeded; waiting {}s before retrying {}",
+                    wait.as_secs(),
+                    url
+                );
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+
+            return Err(HttpStatusError {
+                status: res.status().as_u16(),
+                url: url.to_string(),
+            }
+            .into());
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let data = cache::cached(self.page_cache.as_ref(), url, async {
+            Ok(self
+                .request(reqwest::Method::GET, url, 0)
+                .await?
+                .text()
+                .await?)
+        })
+        .await?;
+
+        serde_json::from_str(&data).with_context(|| format!("parsing JSON from {}", url))
+    }
+
+    /// Fetch all pages of a paginated API endpoint.
+    async fn get_all_pages<T: DeserializeOwned>(&self, url: &str) -> Result<Vec<T>> {
+        let mut items = vec![];
+
+        for page in 1.. {
+            let page_items: Vec<T> = self
+                .get_json(&format!("{}?per_page={}&page={}", url, PER_PAGE, page))
+                .await?;
+            let count = page_items.len();
+            items.extend(page_items);
+
+            if count < PER_PAGE {
+                break;
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn repos(&self) -> Result<Vec<Repo>> {
+        self.get_all_pages(&format!("{}/orgs/{}/repos", API_URL, GITHUB_ORG))
+            .await
+            .context("fetching GitHub repositories")
+    }
+
+    async fn tags(&self, repo: &str) -> Result<Vec<Tag>> {
+        self.get_all_pages(&format!("{}/repos/{}/{}/tags", API_URL, GITHUB_ORG, repo))
+            .await
+            .with_context(|| format!("fetching tags of GitHub repository {}", repo))
+    }
+}
+
+#[async_trait]
+impl Source for GitHubSource {
+    fn name(&self) -> String {
+        "github".to_string()
+    }
+
+    async fn get_from(&self, url: &str, offset: u64) -> Result<reqwest::Response> {
+        self.request(reqwest::Method::GET, url, offset).await
+    }
+
+    async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        self.request(reqwest::Method::HEAD, url, 0).await
+    }
+
+    async fn releases(&self) -> Result<ReleasesPage> {
+        let mut releases = vec![];
+
+        for repo in self.repos().await? {
+            if !repo.name.starts_with(DISTRIBUTION_PREFIX) {
+                continue;
+            }
+
+            for tag in self.tags(&repo.name).await? {
+                // Tags are named like release pages, e.g. `macos-1151`.
+                if let Some((entity, version)) = tag.name.rsplit_once('-') {
+                    releases.push(ReleaseRecord {
+                        entity: entity.to_string(),
+                        version: version.to_string(),
+                        url: format!(
+                            "{}/repos/{}/{}/git/trees/{}",
+                            API_URL, GITHUB_ORG, repo.name, tag.name
+                        ),
+                        date: None,
+                        original_entity: None,
+                    });
+                }
+            }
+        }
+
+        releases.sort();
+
+        Ok(ReleasesPage { releases })
+    }
+
+    async fn release(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        let tree: Tree = self
+            .get_json(&record.url)
+            .await
+            .context("fetching release tree")?;
+
+        Ok(ReleasePage {
+            components: tree
+                .tree
+                .into_iter()
+                .filter(|entry| entry.kind == "commit")
+                .map(|entry| ReleaseComponentRecord {
+                    entity: record.entity.clone(),
+                    url: format!(
+                        "{}/repos/{}/{}/tarball/{}",
+                        API_URL, GITHUB_ORG, entry.path, entry.sha
+                    ),
+                    component: entry.path,
+                    kind: ArtifactKind::Tarball,
+                })
+                .collect(),
+            other_artifacts: vec![],
+            html: None,
+            updates: vec![],
+        })
+    }
+
+    async fn components(&self) -> Result<ComponentsPage> {
+        Ok(ComponentsPage {
+            components: self
+                .repos()
+                .await?
+                .into_iter()
+                .map(|repo| repo.name)
+                .filter(|name| !name.starts_with(DISTRIBUTION_PREFIX))
+                .collect(),
+        })
+    }
+
+    async fn versions(&self, component: &str) -> Result<VersionsPage> {
+        let prefix = format!("{}-", component);
+
+        let mut versions = self
+            .tags(component)
+            .await?
+            .into_iter()
+            .filter_map(|tag| {
+                let version = tag.name.strip_prefix(&prefix)?.to_string();
+
+                Some(ComponentRecord {
+                    component: component.to_string(),
+                    filename: format!("{}.tar.gz", tag.name),
+                    url: tag.tarball_url,
+                    version,
+                    size: None,
+                    last_modified: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        versions.sort();
+
+        Ok(VersionsPage {
+            versions,
+            manifests: vec![],
+        })
+    }
+}
@@ -0,0 +1,103 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Detection of the layout of historical versions of opensource.apple.com.
+//!
+//! Snapshots of the site, such as those archived by the Wayback Machine, come
+//! in a few layouts:
+//!
+//! * The 2011-era site, whose release pages were directories (links end in
+//!   `/`) and whose tarball listings were Apache's default directory index.
+//! * The 2017-era site, structured like the current site but linking pages
+//!   with absolute `http://` URLs.
+//! * The current site.
+
+use {
+    super::{ComponentsPage, ReleasePage, ReleasesPage, VersionParser, VersionsPage},
+    crate::download::ReleaseRecord,
+    anyhow::Result,
+    regex::Regex,
+    std::borrow::Cow,
+};
+
+/// A layout of opensource.apple.com pages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SiteLayout {
+    /// The 2011-era site.
+    Legacy,
+    /// The 2017-era site.
+    Classic,
+    /// The current site.
+    Current,
+}
+
+impl SiteLayout {
+    /// Detect the layout of a page.
+    pub fn detect(text: &str) -> Self {
+        let legacy_release =
+            Regex::new(r#"href="(?:https?://opensource\.apple\.com)?/release/[^"]+/""#)
+                .expect("regex is valid");
+
+        if text.contains("/icons/folder.gif")
+            || text.contains("/icons/compressed.gif")
+            || legacy_release.is_match(text)
+        {
+            Self::Legacy
+        } else if absolute_links().is_match(text) {
+            Self::Classic
+        } else {
+            Self::Current
+        }
+    }
+
+    pub fn parse_releases(self, text: &str) -> Result<ReleasesPage> {
+        let text = relative_links(text);
+
+        match self {
+            Self::Legacy => ReleasesPage::parse_legacy_html(&text),
+            Self::Classic | Self::Current => ReleasesPage::parse_html(&text),
+        }
+    }
+
+    /// Release pages link tarballs the same way in every layout.
+    pub fn parse_release(self, record: &ReleaseRecord, text: &str) -> Result<ReleasePage> {
+        let mut page = ReleasePage::parse_html(record, &relative_links(text))?;
+
+        // Keep the page as it was published.
+        page.html = Some(text.to_string());
+
+        Ok(page)
+    }
+
+    pub fn parse_components(self, text: &str) -> Result<ComponentsPage> {
+        match self {
+            Self::Legacy => ComponentsPage::parse_legacy_html(text),
+            Self::Classic | Self::Current => ComponentsPage::parse_html(&relative_links(text)),
+        }
+    }
+
+    pub fn parse_versions(
+        self,
+        component: &str,
+        text: &str,
+        parser: &VersionParser,
+    ) -> Result<VersionsPage> {
+        match self {
+            Self::Legacy => VersionsPage::parse_legacy_html_with(component, text, parser),
+            Self::Classic | Self::Current => {
+                VersionsPage::parse_html_with(component, &relative_links(text), parser)
+            }
+        }
+    }
+}
+
+fn absolute_links() -> Regex {
+    Regex::new(r#"(?P<attr>href|src)="https?://(?:www\.)?opensource\.apple\.com/"#)
+        .expect("regex is valid")
+}
+
+/// Rewrite absolute links to the site into paths, like the current site uses.
+fn relative_links(text: &str) -> Cow<'_, str> {
+    absolute_links().replace_all(text, r#"$attr="/"#)
+}
@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Property list manifests published alongside component tarballs.
+
+use {
+    anyhow::{Context, Result},
+    plist::Value,
+    std::collections::BTreeMap,
+};
+
+/// Keys holding the original name of the project, in order of preference.
+const PROJECT_NAME_KEYS: &[&str] = &["OriginalProjectName", "ProjectName", "Name"];
+
+/// Keys holding the version of the project, in order of preference.
+const VERSION_KEYS: &[&str] = &["ProjectVersion", "SourceVersion", "Version"];
+
+/// Metadata from a `.plist` file in a component's directory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ComponentManifest {
+    /// URL the manifest was obtained from.
+    pub url: String,
+    /// Original name of the project.
+    pub project_name: Option<String>,
+    /// Version of the project.
+    pub version: Option<String>,
+    /// All top-level scalar properties, rendered as strings.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl ComponentManifest {
+    /// Parse a binary or XML property list.
+    pub fn parse(url: &str, data: &[u8]) -> Result<Self> {
+        let value = Value::from_reader(std::io::Cursor::new(data))
+            .with_context(|| format!("parsing property list {}", url))?;
+
+        let mut properties = BTreeMap::new();
+
+        if let Some(dict) = value.as_dictionary() {
+            for (key, value) in dict {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Integer(i) => i.to_string(),
+                    Value::Real(r) => r.to_string(),
+                    Value::Boolean(b) => b.to_string(),
+                    _ => continue,
+                };
+
+                properties.insert(key.clone(), value);
+            }
+        }
+
+        let find = |keys: &[&str]| {
+            keys.iter().find_map(|key| {
+                properties
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v.clone())
+            })
+        };
+
+        Ok(Self {
+            url: url.to_string(),
+            project_name: find(PROJECT_NAME_KEYS),
+            version: find(VERSION_KEYS),
+            properties,
+        })
+    }
+
+    /// Lines describing the manifest, for inclusion in commit messages.
+    pub fn provenance_notes(&self) -> String {
+        let mut notes = String::new();
+
+        if let Some(name) = &self.project_name {
+            notes.push_str(&format!("Project: {}\n", name));
+        }
+        if let Some(version) = &self.version {
+            notes.push_str(&format!("Project version: {}\n", version));
+        }
+        notes.push_str(&format!("Manifest: {}\n", self.url));
+
+        notes
+    }
+}
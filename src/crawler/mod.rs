@@ -0,0 +1,218 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Discovery of available Apple open source software.
+//!
+//! Each kind of index page has a typed model in its own module which can be
+//! parsed from the page content. A [Source] fetches and parses these pages,
+//! allowing different page formats to coexist.
+
+pub mod cache;
+pub mod components;
+pub mod github;
+pub mod layout;
+pub mod manifest;
+pub mod releases;
+pub mod schemes;
+pub mod source_tree;
+pub mod versions;
+pub mod wayback;
+
+use {
+    crate::{download::ReleaseRecord, error::HttpStatusError},
+    anyhow::{Context, Result},
+    async_trait::async_trait,
+    reqwest::Client,
+};
+
+pub use {
+    cache::{PageCache, PageChange},
+    components::ComponentsPage,
+    github::GitHubSource,
+    layout::SiteLayout,
+    manifest::ComponentManifest,
+    releases::{ReleasePage, ReleasesPage},
+    schemes::UrlSchemes,
+    versions::{VersionParser, VersionsPage},
+    wayback::WaybackSource,
+};
+
+pub const URL_MAIN: &str = "https://opensource.apple.com/";
+pub const URL_TARBALLS: &str = "https://opensource.apple.com/tarballs";
+pub const URL_SOURCE: &str = "https://opensource.apple.com/source";
+
+/// Issue a GET request, failing on non-success HTTP statuses.
+///
+/// If `offset` is non-zero, only content starting at that byte offset is
+/// requested.
+pub(crate) async fn get_request(
+    client: &Client,
+    url: &str,
+    offset: u64,
+) -> Result<reqwest::Response> {
+    let mut req = client.get(url);
+
+    if offset > 0 {
+        req = req.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+
+    check_status(req.send().await?, url)
+}
+
+/// Issue a HEAD request for a URL.
+pub(crate) async fn head_request(client: &Client, url: &str) -> Result<reqwest::Response> {
+    check_status(client.head(url).send().await?, url)
+}
+
+/// Fail with an [HttpStatusError] unless a response is successful.
+fn check_status(res: reqwest::Response, url: &str) -> Result<reqwest::Response> {
+    if res.status().is_success() {
+        Ok(res)
+    } else {
+        Err(HttpStatusError {
+            status: res.status().as_u16(),
+            url: url.to_string(),
+        }
+        .into())
+    }
+}
+
+/// A provider of index pages describing available software.
+#[async_trait]
+pub trait Source: Send + Sync {
+    /// Identifies the source in the provenance of imported repositories,
+    /// like the `--source` argument selecting it.
+    fn name(&self) -> String;
+
+    /// Issue a GET request for content starting at a byte offset of a URL
+    /// advertised by this source.
+    ///
+    /// Servers not supporting range requests may respond with the full content.
+    async fn get_from(&self, url: &str, offset: u64) -> Result<reqwest::Response>;
+
+    /// Issue a GET request for a URL advertised by this source.
+    async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        self.get_from(url, 0).await
+    }
+
+    /// Issue a HEAD request for a URL advertised by this source.
+    async fn head(&self, url: &str) -> Result<reqwest::Response>;
+
+    /// Obtain the index of software releases.
+    async fn releases(&self) -> Result<ReleasesPage>;
+
+    /// Obtain the page listing the artifacts in a software release.
+    async fn release(&self, record: &ReleaseRecord) -> Result<ReleasePage>;
+
+    /// Obtain the index of components.
+    async fn components(&self) -> Result<ComponentsPage>;
+
+    /// Obtain the index of versions of a component.
+    async fn versions(&self, component: &str) -> Result<VersionsPage>;
+
+    /// Obtain the versions of a component published as browsable source
+    /// trees. See [source_tree].
+    async fn source_trees(&self, component: &str) -> Result<VersionsPage> {
+        let text = self
+            .get(&source_tree::component_url(component))
+            .await?
+            .text()
+            .await?;
+
+        Ok(source_tree::parse_versions(component, &text))
+    }
+}
+
+/// A [Source] scraping the HTML pages of opensource.apple.com.
+pub struct HtmlSource {
+    client: Client,
+    version_parser: VersionParser,
+    page_cache: Option<PageCache>,
+}
+
+impl HtmlSource {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            version_parser: VersionParser::default(),
+            page_cache: None,
+        }
+    }
+
+    /// Resolve versions from tarball filenames with a custom parser.
+    pub fn with_version_parser(mut self, parser: VersionParser) -> Self {
+        self.version_parser = parser;
+        self
+    }
+
+    /// Reuse index pages fetched recently.
+    pub fn with_page_cache(mut self, cache: Option<PageCache>) -> Self {
+        self.page_cache = cache;
+        self
+    }
+
+    async fn get_text(&self, url: &str) -> Result<String> {
+        cache::revalidated(self.page_cache.as_ref(), &self.client, url).await
+    }
+}
+
+#[async_trait]
+impl Source for HtmlSource {
+    fn name(&self) -> String {
+        "apple".to_string()
+    }
+
+    async fn get_from(&self, url: &str, offset: u64) -> Result<reqwest::Response> {
+        get_request(&self.client, url, offset).await
+    }
+
+    async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        head_request(&self.client, url).await
+    }
+
+    async fn releases(&self) -> Result<ReleasesPage> {
+        let text = self
+            .get_text(URL_MAIN)
+            .await
+            .context("fetching main releases URL")?;
+
+        ReleasesPage::parse_html(&text)
+    }
+
+    async fn release(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        let text = self
+            .get_text(&record.url)
+            .await
+            .context("fetching release components")?;
+
+        ReleasePage::parse_html(record, &text)
+    }
+
+    async fn components(&self) -> Result<ComponentsPage> {
+        let text = self
+            .get_text(URL_TARBALLS)
+            .await
+            .context("fetching component tarballs URL")?;
+
+        ComponentsPage::parse_html(&text)
+    }
+
+    async fn versions(&self, component: &str) -> Result<VersionsPage> {
+        let text = self
+            .get_text(&format!("{}/{}/", URL_TARBALLS, component))
+            .await
+            .context("fetching versions of component")?;
+
+        VersionsPage::parse_html_with(component, &text, &self.version_parser)
+    }
+
+    async fn source_trees(&self, component: &str) -> Result<VersionsPage> {
+        let text = self
+            .get_text(&source_tree::component_url(component))
+            .await
+            .context("fetching source trees of component")?;
+
+        Ok(source_tree::parse_versions(component, &text))
+    }
+}
@@ -0,0 +1,335 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Software release index and release pages.
+
+use {
+    super::URL_MAIN,
+    crate::download::{ArtifactKind, ReleaseComponentRecord, ReleaseRecord},
+    anyhow::{anyhow, Context, Result},
+    chrono::NaiveDate,
+    regex::Regex,
+};
+
+/// The index of software releases.
+#[derive(Clone, Debug, Default)]
+pub struct ReleasesPage {
+    /// Software releases, sorted.
+    pub releases: Vec<ReleaseRecord>,
+}
+
+impl ReleasesPage {
+    /// Parse the HTML of the main page listing releases.
+    ///
+    /// The date of a release is taken from the text following its link.
+    pub fn parse_html(text: &str) -> Result<Self> {
+        let re = Regex::new(r#"<a href="/release/(?P<entity>[^"]+)">(?P<version>[^<]+)</a>"#)?;
+
+        Self::parse_links(text, &re, ".html")
+    }
+
+    /// Parse the HTML of the main page of the 2011-era site.
+    ///
+    /// Release pages were directories, so links end in `/` instead of `.html`.
+    pub fn parse_legacy_html(text: &str) -> Result<Self> {
+        let re = Regex::new(r#"<a href="/release/(?P<entity>[^"]+/)">(?P<version>[^<]+)</a>"#)?;
+
+        Self::parse_links(text, &re, "/")
+    }
+
+    /// Resolve releases from links whose `entity` group is the path of a
+    /// release page relative to `/release/`, ending in `suffix`.
+    fn parse_links(text: &str, re: &Regex, suffix: &str) -> Result<Self> {
+        let matches = re.captures_iter(text).collect::<Vec<_>>();
+
+        let mut releases = vec![];
+
+        for (i, caps) in matches.iter().enumerate() {
+            let version = &caps["version"];
+
+            let url = format!("{}release/{}", URL_MAIN, &caps["entity"]);
+
+            let s = caps["entity"]
+                .strip_suffix(suffix)
+                .ok_or_else(|| anyhow!("{} does not end in {}", &caps["entity"], suffix))?;
+
+            // The version component is the part after the final hyphen. e.g.
+            // `iphone-sdkb8` or `developer-tools-91`.
+            let name = s
+                .rsplit_once('-')
+                .ok_or_else(|| anyhow!("{} does not contain a -", s))?
+                .0;
+
+            let start = caps.get(0).expect("match always present").end();
+            let end = matches
+                .get(i + 1)
+                .and_then(|next| next.get(0))
+                .map(|m| m.start())
+                .unwrap_or(text.len());
+
+            releases.push(ReleaseRecord {
+                entity: name.to_string(),
+                version: version.to_string(),
+                url,
+                date: find_date(&text[start..end])?,
+                original_entity: None,
+            });
+        }
+
+        releases.sort();
+
+        Ok(Self { releases })
+    }
+}
+
+/// Find the first date in text.
+///
+/// Dates may be formatted like `2021-07-26` or `July 26, 2021`.
+fn find_date(text: &str) -> Result<Option<NaiveDate>> {
+    let re = Regex::new(
+        r"(?P<iso>\d{4}-\d{2}-\d{2})|(?P<long>(?:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)[a-z]*\.? \d{1,2}, \d{4})",
+    )?;
+
+    for caps in re.captures_iter(text) {
+        let date = if let Some(iso) = caps.name("iso") {
+            NaiveDate::parse_from_str(iso.as_str(), "%Y-%m-%d").ok()
+        } else {
+            let long = caps["long"].replace('.', "");
+
+            NaiveDate::parse_from_str(&long, "%B %d, %Y")
+                .or_else(|_| NaiveDate::parse_from_str(&long, "%b %d, %Y"))
+                .ok()
+        };
+
+        if date.is_some() {
+            return Ok(date);
+        }
+    }
+
+    Ok(None)
+}
+
+/// The page listing the artifacts of a software release.
+#[derive(Clone, Debug, Default)]
+pub struct ReleasePage {
+    /// Source tarballs of components in the release.
+    pub components: Vec<ReleaseComponentRecord>,
+    /// Other downloadable artifacts linked from the page, such as installer
+    /// packages and disk images.
+    pub other_artifacts: Vec<ReleaseComponentRecord>,
+    /// HTML of the page, if the release came from a web page.
+    pub html: Option<String>,
+    /// Releases linked from the page, such as point and security updates,
+    /// which may not be listed in the index of releases.
+    pub updates: Vec<ReleaseRecord>,
+}
+
+/// Parse links to other release pages from a release page.
+///
+/// Links whose text is a version are attributed to the entity in their page
+/// name, like links in the index. Other links, like `Security Update
+/// 2022-007`, are attributed to the linking release's entity and their text
+/// is turned into a version.
+fn parse_update_links(record: &ReleaseRecord, text: &str) -> Result<Vec<ReleaseRecord>> {
+    let re = Regex::new(
+        r#"<a href="(?P<href>[^"]*/release/(?P<slug>[^"/]+)\.html)">(?P<text>[^<]+)</a>"#,
+    )?;
+    let version_re = Regex::new(r"^[0-9][0-9A-Za-z.]*$")?;
+
+    let base_url = reqwest::Url::parse(&record.url)?;
+    let matches = re.captures_iter(text).collect::<Vec<_>>();
+
+    let mut updates = vec![];
+
+    for (i, caps) in matches.iter().enumerate() {
+        let url = base_url
+            .join(&caps["href"])
+            .with_context(|| format!("resolving release URL {}", &caps["href"]))?
+            .to_string();
+
+        if url == record.url || updates.iter().any(|r: &ReleaseRecord| r.url == url) {
+            continue;
+        }
+
+        let link_text = caps["text"].trim();
+
+        let (entity, version) = if version_re.is_match(link_text) {
+            let entity = caps["slug"]
+                .rsplit_once('-')
+                .map(|(name, _)| name)
+                .unwrap_or(&record.entity);
+
+            (entity.to_string(), link_text.to_string())
+        } else {
+            (record.entity.clone(), slugify(link_text))
+        };
+
+        if version.is_empty() {
+            continue;
+        }
+
+        let start = caps.get(0).expect("match always present").end();
+        let end = matches
+            .get(i + 1)
+            .and_then(|next| next.get(0))
+            .map(|m| m.start())
+            .unwrap_or(text.len());
+
+        updates.push(ReleaseRecord {
+            entity,
+            version,
+            url,
+            date: find_date(&text[start..end])?,
+            original_entity: None,
+        });
+    }
+
+    updates.sort();
+
+    Ok(updates)
+}
+
+/// Turn text into a lowercase, hyphenated name.
+fn slugify(text: &str) -> String {
+    text.to_ascii_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl ReleasePage {
+    /// Parse the HTML of a release page.
+    pub fn parse_html(record: &ReleaseRecord, text: &str) -> Result<Self> {
+        let re = Regex::new(r#"<a href="(?P<href>[^"]+)">"#)?;
+
+        let base_url = reqwest::Url::parse(&record.url)?;
+
+        let mut page = Self {
+            html: Some(text.to_string()),
+            ..Self::default()
+        };
+
+        for caps in re.captures_iter(text) {
+            let href = &caps["href"];
+
+            if let Some(path) = href.strip_prefix("/tarballs/") {
+                if let Some(s) = path.strip_suffix(".tar.gz") {
+                    let component = s
+                        .split_once('/')
+                        .ok_or_else(|| anyhow!("{} does not have a /", s))?
+                        .0;
+
+                    page.components.push(ReleaseComponentRecord {
+                        entity: record.entity.clone(),
+                        component: component.to_string(),
+                        url: format!("{}tarballs/{}", URL_MAIN, path),
+                        kind: ArtifactKind::Tarball,
+                    });
+
+                    continue;
+                }
+            }
+
+            if let Some(kind) = ArtifactKind::from_path(href) {
+                let url = base_url
+                    .join(href)
+                    .with_context(|| format!("resolving artifact URL {}", href))?;
+
+                // Non-tarball artifacts aren't grouped by component, so use the
+                // filename without its extension.
+                let filename = url
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .unwrap_or_default();
+                let component = filename
+                    .split_once('.')
+                    .map(|(stem, _)| stem)
+                    .unwrap_or(filename);
+
+                page.other_artifacts.push(ReleaseComponentRecord {
+                    entity: record.entity.clone(),
+                    component: component.to_string(),
+                    url: url.to_string(),
+                    kind,
+                });
+            }
+        }
+
+        page.updates = parse_update_links(record, text)?;
+
+        Ok(page)
+    }
+
+    /// Render the text of the page as Markdown.
+    ///
+    /// Links are preserved with absolute URLs so license and readme
+    /// references keep working outside the web site. Returns `None` if the
+    /// release did not come from a web page.
+    pub fn notes(&self, record: &ReleaseRecord) -> Result<Option<String>> {
+        let html = if let Some(html) = &self.html {
+            html
+        } else {
+            return Ok(None);
+        };
+
+        let base_url = reqwest::Url::parse(&record.url)?;
+
+        let hidden_re =
+            Regex::new(r"(?is)<(script|style|head)\b.*?</(script|style|head)>|<!--.*?-->")?;
+        let link_re = Regex::new(r#"(?is)<a\s[^>]*href="(?P<href>[^"]+)"[^>]*>(?P<text>.*?)</a>"#)?;
+        let block_re = Regex::new(r"(?i)</?(br|p|div|tr|li|ul|ol|table|h[1-6])\b[^>]*>")?;
+        let tag_re = Regex::new(r"<[^>]*>")?;
+
+        let text = hidden_re.replace_all(html, "");
+        let text = link_re.replace_all(&text, |caps: &regex::Captures| {
+            let text = tag_re.replace_all(&caps["text"], "");
+            let text = text.trim();
+
+            match base_url.join(&caps["href"]) {
+                Ok(url) if !text.is_empty() => format!("[{}]({})", text, url),
+                Ok(url) => format!("<{}>", url),
+                Err(_) => text.to_string(),
+            }
+        });
+        let text = block_re.replace_all(&text, "\n");
+        let text = tag_re.replace_all(&text, "");
+        let text = decode_entities(&text);
+
+        let mut notes = format!(
+            "# {} {}\n\nCaptured from <{}>.\n",
+            record.entity, record.version, record.url
+        );
+
+        let mut blank = true;
+        for line in text.lines() {
+            let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+
+            if line.is_empty() {
+                blank = true;
+                continue;
+            }
+
+            if blank {
+                notes.push('\n');
+                blank = false;
+            }
+
+            notes.push_str(&line);
+            notes.push('\n');
+        }
+
+        Ok(Some(notes))
+    }
+}
+
+/// Decode the HTML character references commonly found on release pages.
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
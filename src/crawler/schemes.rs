@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Path schemes under which artifacts are published.
+//!
+//! opensource.apple.com is periodically reorganized, moving tarballs from
+//! `/tarballs/<component>/<filename>` to other paths. A [UrlScheme] describes
+//! one such path as a template, e.g.
+//! `https://opensource.apple.com/tarballs/{component}/{filename}`, with these
+//! placeholders:
+//!
+//! * `{component}`: the name of the component.
+//! * `{filename}`: the filename of the tarball, e.g. `xnu-7195.81.3.tar.gz`.
+//! * `{name}`: the filename without its `.tar.gz` extension.
+//!
+//! An artifact URL matching one scheme can be translated into the URLs of the
+//! same artifact under every other scheme.
+
+use {
+    anyhow::{anyhow, Context, Result},
+    regex::Regex,
+};
+
+/// Templates of the URLs artifacts have been published under.
+///
+/// The first is the canonical scheme used by the site's pages.
+pub const DEFAULT_SCHEMES: &[&str] = &[
+    "https://opensource.apple.com/tarballs/{component}/{filename}",
+    "https://opensource.apple.com/release/tarballs/{component}/{filename}",
+    "https://github.com/apple-oss-distributions/{component}/archive/refs/tags/{name}.tar.gz",
+];
+
+/// An artifact identified independently of its URL.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Artifact {
+    pub component: String,
+    pub filename: String,
+}
+
+impl Artifact {
+    /// The filename without its extension.
+    pub fn name(&self) -> &str {
+        self.filename
+            .strip_suffix(".tar.gz")
+            .or_else(|| self.filename.strip_suffix(".tgz"))
+            .unwrap_or(&self.filename)
+    }
+}
+
+/// A template of artifact URLs.
+#[derive(Clone, Debug)]
+pub struct UrlScheme {
+    template: String,
+    pattern: Regex,
+}
+
+impl UrlScheme {
+    /// Parse a template.
+    ///
+    /// Templates must have a `{component}` placeholder and either a
+    /// `{filename}` or `{name}` placeholder, each at most once.
+    pub fn new(template: &str) -> Result<Self> {
+        let count = |placeholder| template.matches(placeholder).count();
+
+        if count("{component}") != 1
+            || count("{filename}") + count("{name}") != 1
+            || count("{filename}") > 1
+        {
+            return Err(anyhow!(
+                "URL scheme {} must have one {{component}} and one {{filename}} or {{name}} placeholder",
+                template
+            ));
+        }
+
+        let pattern = regex::escape(template)
+            .replace(r"\{component\}", "(?P<component>[^/]+)")
+            .replace(r"\{filename\}", "(?P<filename>[^/]+)")
+            .replace(r"\{name\}", "(?P<name>[^/]+)");
+
+        Ok(Self {
+            template: template.to_string(),
+            pattern: Regex::new(&format!("^{}$", pattern))
+                .with_context(|| format!("parsing URL scheme {}", template))?,
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Identify the artifact at a URL following this scheme.
+    pub fn parse(&self, url: &str) -> Option<Artifact> {
+        let caps = self.pattern.captures(url)?;
+
+        let filename = match (caps.name("filename"), caps.name("name")) {
+            (Some(filename), _) => filename.as_str().to_string(),
+            (None, Some(name)) => format!("{}.tar.gz", name.as_str()),
+            (None, None) => return None,
+        };
+
+        Some(Artifact {
+            component: caps["component"].to_string(),
+            filename,
+        })
+    }
+
+    /// The URL of an artifact under this scheme.
+    pub fn url(&self, artifact: &Artifact) -> String {
+        self.template
+            .replace("{component}", &artifact.component)
+            .replace("{filename}", &artifact.filename)
+            .replace("{name}", artifact.name())
+    }
+}
+
+/// An ordered set of [UrlScheme]s.
+#[derive(Clone, Debug)]
+pub struct UrlSchemes {
+    schemes: Vec<UrlScheme>,
+}
+
+impl Default for UrlSchemes {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCHEMES).expect("default URL schemes are valid")
+    }
+}
+
+impl UrlSchemes {
+    /// Parse templates, in order of preference.
+    pub fn new(templates: impl IntoIterator<Item = impl AsRef<str>>) -> Result<Self> {
+        Ok(Self {
+            schemes: templates
+                .into_iter()
+                .map(|t| UrlScheme::new(t.as_ref()))
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    pub fn schemes(&self) -> &[UrlScheme] {
+        &self.schemes
+    }
+
+    /// URLs of the artifact at `url` under the other schemes, in order.
+    ///
+    /// Empty if `url` doesn't follow any scheme.
+    pub fn alternate_urls(&self, url: &str) -> Vec<String> {
+        let artifact = match self.schemes.iter().find_map(|s| s.parse(url)) {
+            Some(artifact) => artifact,
+            None => return vec![],
+        };
+
+        let mut urls = Vec::<String>::new();
+
+        for scheme in &self.schemes {
+            let alternate = scheme.url(&artifact);
+
+            if alternate != url && !urls.contains(&alternate) {
+                urls.push(alternate);
+            }
+        }
+
+        urls
+    }
+}
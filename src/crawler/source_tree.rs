@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Browsable source trees.
+//!
+//! Besides tarballs, opensource.apple.com publishes component versions as
+//! directory listings under `/source/<component>/<component>-<version>/`,
+//! with the raw content of each file available by appending `?txt` to its
+//! URL. Some versions are only published this way.
+//!
+//! A source tree is snapshotted file by file into a tarball, so it can be
+//! imported like any other version.
+
+use {
+    super::{VersionsPage, URL_SOURCE},
+    crate::download::ComponentRecord,
+    anyhow::Result,
+    regex::Regex,
+};
+
+/// The URL of the directory listing the source trees of a component.
+pub fn component_url(component: &str) -> String {
+    format!("{}/{}/", URL_SOURCE, component)
+}
+
+/// The URL of the raw content of a file in a source tree directory.
+pub fn file_url(dir_url: &str, name: &str) -> String {
+    format!("{}{}?txt", dir_url, name)
+}
+
+/// Resolve the versions published as source trees from the listing at
+/// [component_url].
+///
+/// Records of source trees have a URL ending with `/`. See
+/// [ComponentRecord::is_source_tree].
+pub fn parse_versions(component: &str, text: &str) -> VersionsPage {
+    let prefix = format!("{}-", component);
+
+    let mut versions = SourceListing::parse_html(text)
+        .directories
+        .into_iter()
+        .filter_map(|dir| {
+            let name = dir.strip_suffix('/')?;
+            let version = name.strip_prefix(&prefix)?;
+
+            Some(ComponentRecord {
+                component: component.to_string(),
+                filename: format!("{}.tar.gz", name),
+                url: format!("{}{}", component_url(component), dir),
+                version: version.to_string(),
+                size: None,
+                last_modified: None,
+            })
+        })
+        .collect::<Vec<_>>();
+    versions.sort();
+
+    VersionsPage {
+        versions,
+        manifests: vec![],
+    }
+}
+
+/// The entries of a source tree directory listing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceListing {
+    /// Names of subdirectories, with a trailing `/`.
+    pub directories: Vec<String>,
+    /// Names of files.
+    pub files: Vec<String>,
+}
+
+impl SourceListing {
+    /// Parse the HTML of a directory listing.
+    ///
+    /// Only links relative to the directory are entries. Links to parent
+    /// directories, sort orders, and other pages are ignored.
+    pub fn parse_html(text: &str) -> Self {
+        let re = Regex::new(r#"<a href="(?P<href>[^"]+)""#).expect("regex is valid");
+        let mut listing = Self::default();
+
+        for caps in re.captures_iter(text) {
+            let href = &caps["href"];
+
+            if href.starts_with(['/', '?', '#'])
+                || href.starts_with("./")
+                || href.starts_with("../")
+                || href.contains("://")
+            {
+                continue;
+            }
+
+            let entries = match href.strip_suffix('/') {
+                Some(name) if !name.contains('/') => &mut listing.directories,
+                None if !href.contains('/') => &mut listing.files,
+                _ => continue,
+            };
+
+            if !entries.iter().any(|e| e == href) {
+                entries.push(href.to_string());
+            }
+        }
+
+        listing
+    }
+}
+
+/// Write the files of a snapshotted source tree to a gzipped tarball.
+///
+/// Paths are relative to the tree and stored under a `prefix` directory, like
+/// the members of published tarballs.
+pub fn tarball(prefix: &str, files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        vec![],
+        flate2::Compression::default(),
+    ));
+
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_entry_type(tar::EntryType::Regular);
+
+        builder.append_data(&mut header, format!("{}/{}", prefix, path), data.as_slice())?;
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
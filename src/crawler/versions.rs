@@ -0,0 +1,181 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Component version index pages.
+
+use {
+    super::URL_TARBALLS,
+    crate::download::{parse_size, ComponentRecord},
+    anyhow::{anyhow, Context, Result},
+    chrono::NaiveDateTime,
+    regex::Regex,
+    std::collections::BTreeMap,
+};
+
+/// Parses component versions from tarball filenames.
+///
+/// By default, filenames are expected to look like `name-version.tar.gz`.
+/// Components not following this convention can be given a regular expression
+/// whose `version` named group captures the version.
+#[derive(Clone, Debug, Default)]
+pub struct VersionParser {
+    overrides: BTreeMap<String, Regex>,
+}
+
+impl VersionParser {
+    /// Parse filenames of a component with a regular expression.
+    pub fn with_override(mut self, component: &str, pattern: &str) -> Result<Self> {
+        let re = Regex::new(pattern)
+            .with_context(|| format!("parsing filename pattern for {}", component))?;
+
+        if !re.capture_names().any(|name| name == Some("version")) {
+            return Err(anyhow!(
+                "filename pattern for {} lacks a version group",
+                component
+            ));
+        }
+
+        self.overrides.insert(component.to_string(), re);
+
+        Ok(self)
+    }
+
+    /// Resolve the version of a component tarball filename.
+    ///
+    /// Returns `None` if the filename isn't a tarball of a version.
+    pub fn parse(&self, component: &str, filename: &str) -> Result<Option<String>> {
+        if let Some(re) = self.overrides.get(component) {
+            return Ok(re
+                .captures(filename)
+                .and_then(|caps| caps.name("version"))
+                .map(|m| m.as_str().to_string()));
+        }
+
+        // The version is the part after the first hyphen and before the .tar.gz.
+        match filename.strip_suffix(".tar.gz") {
+            Some(s) => Ok(Some(
+                s.split_once('-')
+                    .ok_or_else(|| anyhow!("filename does not contain -"))?
+                    .1
+                    .to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Parse the modification time and size following a link in a directory
+/// listing row, like `2021-08-04 19:42  42M` or `04-Aug-2011 19:42  1.2K`.
+fn parse_row_metadata(row: &str) -> (Option<NaiveDateTime>, Option<u64>) {
+    let tags = Regex::new("<[^>]*>").expect("regex is valid");
+    let re = Regex::new(
+        r"(?P<date>\d{4}-\d{2}-\d{2} \d{2}:\d{2}|\d{2}-[A-Za-z]{3}-\d{4} \d{2}:\d{2})\s+(?P<size>\d+(?:\.\d+)?[KMGT]?\b)?",
+    )
+    .expect("regex is valid");
+
+    let text = tags.replace_all(row, " ");
+    let caps = match re.captures(&text) {
+        Some(caps) => caps,
+        None => return (None, None),
+    };
+
+    let date = &caps["date"];
+    let last_modified = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%d-%b-%Y %H:%M"))
+        .ok();
+    let size = caps
+        .name("size")
+        .and_then(|size| parse_size(size.as_str()).ok());
+
+    (last_modified, size)
+}
+
+/// The index of versions of a component.
+#[derive(Clone, Debug, Default)]
+pub struct VersionsPage {
+    /// Versions of the component, sorted.
+    pub versions: Vec<ComponentRecord>,
+    /// URLs of `.plist` manifests in the component's directory.
+    pub manifests: Vec<String>,
+}
+
+impl VersionsPage {
+    /// Parse the HTML of a component's tarballs directory listing.
+    pub fn parse_html(component: &str, text: &str) -> Result<Self> {
+        Self::parse_html_with(component, text, &VersionParser::default())
+    }
+
+    /// Parse the HTML of a component's tarballs directory listing, resolving
+    /// versions with a custom parser.
+    pub fn parse_html_with(component: &str, text: &str, parser: &VersionParser) -> Result<Self> {
+        let re = Regex::new(
+            r#"<tr><td valign="top"><a href="?(?P<filename>[^">]+)"?><img src="?/static/images/icons/gz"#,
+        )?;
+
+        Self::parse_links(component, text, &re, parser)
+    }
+
+    /// Parse the HTML of a component's directory listing on the 2011-era
+    /// site, which used Apache's default directory index.
+    pub fn parse_legacy_html_with(
+        component: &str,
+        text: &str,
+        parser: &VersionParser,
+    ) -> Result<Self> {
+        let re = Regex::new(r#"<a href="(?P<filename>[^"/?]+\.tar\.gz)">"#)?;
+
+        Self::parse_links(component, text, &re, parser)
+    }
+
+    /// Resolve versions from links whose `filename` group is a filename in
+    /// the component's directory.
+    fn parse_links(
+        component: &str,
+        text: &str,
+        re: &Regex,
+        parser: &VersionParser,
+    ) -> Result<Self> {
+        let mut versions = vec![];
+
+        for caps in re.captures_iter(text) {
+            let filename = caps["filename"].to_string();
+            let url = format!("{}/{}/{}", URL_TARBALLS, component, filename);
+
+            // The rest of the listing row may hold the modification time and size.
+            let rest = &text[caps.get(0).expect("match has group 0").end()..];
+            let end = ["\n", "<tr", "<TR"]
+                .iter()
+                .filter_map(|p| rest.find(p))
+                .min()
+                .unwrap_or(rest.len());
+            let (last_modified, size) = parse_row_metadata(&rest[..end]);
+
+            if let Some(version) = parser.parse(component, &filename)? {
+                versions.push(ComponentRecord {
+                    component: component.to_string(),
+                    filename,
+                    url,
+                    version,
+                    size,
+                    last_modified,
+                });
+            }
+        }
+
+        versions.sort();
+
+        let manifest_re = Regex::new(r#"<a href="?(?P<filename>[^">/]+\.plist)"?>"#)?;
+
+        let mut manifests = manifest_re
+            .captures_iter(text)
+            .map(|caps| format!("{}/{}/{}", URL_TARBALLS, component, &caps["filename"]))
+            .collect::<Vec<_>>();
+        manifests.dedup();
+
+        Ok(Self {
+            versions,
+            manifests,
+        })
+    }
+}
@@ -0,0 +1,136 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Source backed by snapshots of opensource.apple.com archived by the
+//! Wayback Machine.
+//!
+//! Records advertise the original opensource.apple.com URLs. Requests are
+//! rewritten to fetch the snapshot closest to a point in time, with the
+//! layout of each page detected so older versions of the site can be parsed.
+
+use {
+    super::{
+        cache, get_request, head_request, ComponentsPage, PageCache, ReleasePage, ReleasesPage,
+        SiteLayout, Source, VersionParser, VersionsPage, URL_MAIN, URL_TARBALLS,
+    },
+    crate::download::ReleaseRecord,
+    anyhow::{anyhow, Context, Result},
+    async_trait::async_trait,
+    reqwest::Client,
+};
+
+const ARCHIVE_URL: &str = "https://web.archive.org/web";
+
+/// A [Source] using snapshots from the Wayback Machine.
+pub struct WaybackSource {
+    client: Client,
+    timestamp: String,
+    version_parser: VersionParser,
+    page_cache: Option<PageCache>,
+}
+
+impl WaybackSource {
+    /// Construct an instance fetching snapshots closest to a timestamp.
+    ///
+    /// Timestamps are in the `YYYYMMDDhhmmss` form, optionally truncated. e.g.
+    /// `2011` or `20170615`.
+    pub fn new(client: Client, timestamp: &str) -> Result<Self> {
+        if timestamp.is_empty()
+            || timestamp.len() > 14
+            || !timestamp.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(anyhow!(
+                "invalid snapshot timestamp {}; expected YYYYMMDDhhmmss or a prefix of it",
+                timestamp
+            ));
+        }
+
+        Ok(Self {
+            client,
+            timestamp: timestamp.to_string(),
+            version_parser: VersionParser::default(),
+            page_cache: None,
+        })
+    }
+
+    /// Resolve versions from tarball filenames with a custom parser.
+    pub fn with_version_parser(mut self, parser: VersionParser) -> Self {
+        self.version_parser = parser;
+        self
+    }
+
+    /// Reuse index pages fetched recently.
+    pub fn with_page_cache(mut self, cache: Option<PageCache>) -> Self {
+        self.page_cache = cache;
+        self
+    }
+
+    /// Resolve the URL of the archived copy of a URL.
+    ///
+    /// The `id_` flag requests content as originally captured, without the
+    /// archive's link rewriting and toolbar.
+    pub fn archive_url(&self, url: &str) -> String {
+        format!("{}/{}id_/{}", ARCHIVE_URL, self.timestamp, url)
+    }
+
+    async fn get_text(&self, url: &str) -> Result<String> {
+        let url = self.archive_url(url);
+
+        cache::cached(self.page_cache.as_ref(), &url, async {
+            Ok(get_request(&self.client, &url, 0).await?.text().await?)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Source for WaybackSource {
+    fn name(&self) -> String {
+        format!("wayback:{}", self.timestamp)
+    }
+
+    async fn get_from(&self, url: &str, offset: u64) -> Result<reqwest::Response> {
+        get_request(&self.client, &self.archive_url(url), offset).await
+    }
+
+    async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        head_request(&self.client, &self.archive_url(url)).await
+    }
+
+    async fn releases(&self) -> Result<ReleasesPage> {
+        let text = self
+            .get_text(URL_MAIN)
+            .await
+            .context("fetching archived releases URL")?;
+
+        SiteLayout::detect(&text).parse_releases(&text)
+    }
+
+    async fn release(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        let text = self
+            .get_text(&record.url)
+            .await
+            .context("fetching archived release components")?;
+
+        SiteLayout::detect(&text).parse_release(record, &text)
+    }
+
+    async fn components(&self) -> Result<ComponentsPage> {
+        let text = self
+            .get_text(&format!("{}/", URL_TARBALLS))
+            .await
+            .context("fetching archived component tarballs URL")?;
+
+        SiteLayout::detect(&text).parse_components(&text)
+    }
+
+    async fn versions(&self, component: &str) -> Result<VersionsPage> {
+        let text = self
+            .get_text(&format!("{}/{}/", URL_TARBALLS, component))
+            .await
+            .context("fetching archived versions of component")?;
+
+        SiteLayout::detect(&text).parse_versions(component, &text, &self.version_parser)
+    }
+}
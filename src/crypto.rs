@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Encryption of artifacts at rest.
+//!
+//! Artifacts are encrypted with AES-256-GCM. Encrypted data consists of
+//! [MAGIC], a random 96-bit nonce and the ciphertext with its authentication
+//! tag, so tampered or truncated data fails to decrypt.
+
+use {
+    aes_gcm::{
+        aead::{Aead, AeadCore, KeyInit, OsRng},
+        Aes256Gcm, Nonce,
+    },
+    anyhow::{anyhow, Context, Result},
+    sha2::{Digest, Sha256},
+    std::path::Path,
+};
+
+/// Header identifying encrypted data.
+pub const MAGIC: &[u8] = b"aosd-aes256gcm-v1\n";
+
+const NONCE_LENGTH: usize = 12;
+
+/// The lowercase hexadecimal SHA-256 digest of data.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Whether data was produced by [EncryptionKey::encrypt].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// A 256-bit key encrypting artifacts.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    /// Construct a key from 32 bytes.
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(key.into()),
+        }
+    }
+
+    /// Parse a key from 64 hexadecimal characters.
+    ///
+    /// Such a key can be generated with `openssl rand -hex 32`.
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(anyhow!("key must be 64 hexadecimal characters"));
+        }
+
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+                .map_err(|_| anyhow!("key must be 64 hexadecimal characters"))?;
+        }
+
+        Ok(Self::new(&key))
+    }
+
+    /// Read a hexadecimal key from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("reading key file {}", path.display()))?;
+
+        Self::from_hex(&s).with_context(|| format!("parsing key file {}", path.display()))
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, data)
+            .map_err(|_| anyhow!("encrypting data"))?;
+
+        let mut res = Vec::with_capacity(MAGIC.len() + NONCE_LENGTH + ciphertext.len());
+        res.extend_from_slice(MAGIC);
+        res.extend_from_slice(&nonce);
+        res.extend_from_slice(&ciphertext);
+
+        Ok(res)
+    }
+
+    /// Decrypt data, failing if it was modified or encrypted with another key.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| anyhow!("data is not encrypted"))?;
+
+        if data.len() < NONCE_LENGTH {
+            return Err(anyhow!("encrypted data is truncated"));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("decrypting data failed; the key is wrong or data is corrupt"))
+    }
+}
@@ -0,0 +1,98 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Cross-reference between macOS, Darwin, and xnu versions.
+
+/// Describes the Darwin and xnu versions shipped in a macOS release.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DarwinRelease {
+    /// The macOS version. e.g. `13.0`.
+    pub macos: &'static str,
+    /// The Darwin version as reported by `uname -r`. e.g. `22.1.0`.
+    pub darwin: &'static str,
+    /// The version of the `xnu` component.
+    pub xnu: &'static str,
+}
+
+impl DarwinRelease {
+    /// URL of the xnu source tarball for this release.
+    pub fn xnu_url(&self) -> String {
+        format!(
+            "https://opensource.apple.com/tarballs/xnu/xnu-{}.tar.gz",
+            self.xnu
+        )
+    }
+}
+
+macro_rules! release {
+    ($macos:expr, $darwin:expr, $xnu:expr) => {
+        DarwinRelease {
+            macos: $macos,
+            darwin: $darwin,
+            xnu: $xnu,
+        }
+    };
+}
+
+/// Known macOS releases, oldest first.
+///
+/// This is maintained by hand and may be incomplete.
+pub static DARWIN_RELEASES: &[DarwinRelease] = &[
+    release!("10.13", "17.0.0", "4570.1.46"),
+    release!("10.13.1", "17.2.0", "4570.20.62"),
+    release!("10.13.2", "17.3.0", "4570.31.3"),
+    release!("10.13.3", "17.4.0", "4570.41.2"),
+    release!("10.13.4", "17.5.0", "4570.51.1"),
+    release!("10.13.5", "17.6.0", "4570.61.1"),
+    release!("10.13.6", "17.7.0", "4570.71.2"),
+    release!("10.14", "18.0.0", "4903.201.2"),
+    release!("10.14.1", "18.2.0", "4903.221.2"),
+    release!("10.14.3", "18.2.0", "4903.241.1"),
+    release!("10.14.4", "18.5.0", "4903.251.3"),
+    release!("10.14.5", "18.6.0", "4903.261.4"),
+    release!("10.14.6", "18.7.0", "4903.270.47"),
+    release!("10.15", "19.0.0", "6153.11.26"),
+    release!("10.15.1", "19.0.0", "6153.41.3"),
+    release!("10.15.2", "19.2.0", "6153.61.1"),
+    release!("10.15.3", "19.3.0", "6153.81.5"),
+    release!("10.15.4", "19.4.0", "6153.101.6"),
+    release!("10.15.5", "19.5.0", "6153.121.1"),
+    release!("10.15.6", "19.6.0", "6153.141.1"),
+    release!("11.0.1", "20.1.0", "7195.50.7"),
+    release!("11.1", "20.2.0", "7195.60.75"),
+    release!("11.2", "20.3.0", "7195.81.3"),
+    release!("11.3", "20.4.0", "7195.101.1"),
+    release!("11.4", "20.5.0", "7195.121.3"),
+    release!("11.5", "20.6.0", "7195.141.2"),
+    release!("12.0.1", "21.1.0", "8019.41.5"),
+    release!("12.1", "21.2.0", "8019.61.5"),
+    release!("12.2", "21.3.0", "8019.80.24"),
+    release!("12.3", "21.4.0", "8020.101.4"),
+    release!("12.4", "21.5.0", "8020.121.3"),
+    release!("12.5", "21.6.0", "8020.140.41"),
+    release!("13.0", "22.1.0", "8792.41.9"),
+    release!("13.1", "22.2.0", "8792.61.2"),
+    release!("13.2", "22.3.0", "8792.81.2"),
+    release!("13.3", "22.4.0", "8796.101.5"),
+    release!("13.4", "22.5.0", "8796.121.2"),
+    release!("13.5", "22.6.0", "8796.141.3"),
+];
+
+/// Find releases matching a Darwin version.
+///
+/// Some Darwin versions shipped in multiple macOS releases, so multiple
+/// entries may be returned.
+pub fn find_darwin(version: &str) -> Vec<&'static DarwinRelease> {
+    DARWIN_RELEASES
+        .iter()
+        .filter(|release| release.darwin == version)
+        .collect()
+}
+
+/// Find the release corresponding to a macOS version.
+pub fn find_macos(version: &str) -> Option<&'static DarwinRelease> {
+    DARWIN_RELEASES
+        .iter()
+        .find(|release| release.macos == version)
+}
@@ -0,0 +1,176 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Content that must not be imported.
+//!
+//! Some organizations aren't allowed to mirror parts of Apple's source code,
+//! such as cryptography subject to export control. A denylist is a JSON file
+//! of rules, each naming a component pattern, a path pattern, or both, and
+//! the reason for the rule:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     {"component": "OpenSSL*", "reason": "export control"},
+//!     {"component": "xnu", "path": "bsd/crypto/**", "reason": "export control"}
+//!   ]
+//! }
+//! ```
+//!
+//! Rules without a path exclude entire components. Rules with a path exclude
+//! matching files from the components the rule applies to, or from every
+//! component if the rule has no component pattern. Paths are matched like
+//! `--exclude-path`. Excluded files are never written to repositories and
+//! every exclusion is recorded to an [AuditLog].
+
+use {
+    crate::ndjson::NdjsonLog,
+    anyhow::{anyhow, Context, Result},
+    glob::Pattern,
+    serde::{Deserialize, Serialize},
+    std::path::Path,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DenylistFile {
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    component: Option<String>,
+    path: Option<String>,
+    reason: String,
+}
+
+/// A rule of a [Denylist].
+#[derive(Clone, Debug)]
+pub struct DenyRule {
+    /// Components the rule applies to. All components if `None`.
+    pub component: Option<Pattern>,
+    /// Paths excluded by the rule. The entire component if `None`.
+    pub path: Option<Pattern>,
+    pub reason: String,
+}
+
+impl DenyRule {
+    fn applies_to(&self, component: &str) -> bool {
+        self.component
+            .as_ref()
+            .map(|p| p.matches(component))
+            .unwrap_or(true)
+    }
+
+    /// Describe the patterns of the rule.
+    pub fn describe(&self) -> String {
+        match (&self.component, &self.path) {
+            (Some(component), Some(path)) => format!("{}:{}", component, path),
+            (Some(component), None) => component.to_string(),
+            (None, Some(path)) => format!("*:{}", path),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// A path pattern excluding archive members, with the reason for excluding
+/// them.
+#[derive(Clone, Debug)]
+pub struct DenyPattern {
+    pub pattern: Pattern,
+    pub reason: String,
+}
+
+/// An archive member excluded by a [DenyPattern].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeniedMember {
+    /// The path of the member in the tree.
+    pub path: String,
+    pub reason: String,
+}
+
+/// Rules excluding components and paths from imports.
+#[derive(Clone, Debug, Default)]
+pub struct Denylist {
+    rules: Vec<DenyRule>,
+}
+
+impl Denylist {
+    pub fn new(rules: Vec<DenyRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Parse a denylist from JSON.
+    pub fn from_json(data: &[u8]) -> Result<Self> {
+        let file: DenylistFile = serde_json::from_slice(data)?;
+
+        let pattern = |v: &str| Pattern::new(v).with_context(|| format!("parsing pattern {}", v));
+
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|rule| {
+                if rule.component.is_none() && rule.path.is_none() {
+                    return Err(anyhow!("denylist rule must have a component or a path"));
+                }
+
+                Ok(DenyRule {
+                    component: rule.component.as_deref().map(pattern).transpose()?,
+                    path: rule.path.as_deref().map(pattern).transpose()?,
+                    reason: rule.reason,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Read a denylist from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("reading denylist {}", path.display()))?;
+
+        Self::from_json(&data).with_context(|| format!("parsing denylist {}", path.display()))
+    }
+
+    pub fn rules(&self) -> &[DenyRule] {
+        &self.rules
+    }
+
+    /// The reason an entire component is excluded, if it is.
+    pub fn component_reason(&self, component: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.path.is_none() && rule.applies_to(component))
+            .map(|rule| rule.reason.as_str())
+    }
+
+    /// The patterns of paths excluded from a component.
+    pub fn path_patterns(&self, component: &str) -> Vec<DenyPattern> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.applies_to(component))
+            .filter_map(|rule| {
+                rule.path.as_ref().map(|pattern| DenyPattern {
+                    pattern: pattern.clone(),
+                    reason: rule.reason.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Describes content excluded by a denylist.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AuditRecord {
+    pub component: String,
+    pub version: Option<String>,
+    /// The excluded path. The entire component was excluded if `None`.
+    pub path: Option<String>,
+    pub reason: String,
+}
+
+/// An append-only log of exclusions.
+pub type AuditLog = NdjsonLog<AuditRecord>;
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Templates of per-component repository paths.
+//!
+//! Mirroring every component in one directory yields thousands of
+//! repositories side by side. Templates like
+//! `{dest}/{component[0]}/{component}.git` shard them into subdirectories.
+
+use {
+    anyhow::{anyhow, Result},
+    std::{
+        ffi::OsString,
+        fmt::{Display, Formatter},
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Literal(String),
+    Dest,
+    Component,
+    /// A character of the component name, by index.
+    ComponentChar(usize),
+}
+
+/// A template resolving the path of a component's repository.
+///
+/// `{dest}` is replaced with the destination directory, `{component}` with
+/// the component name, and `{component[N]}` with the `N`th character of the
+/// component name, or `_` if the name is shorter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DestinationTemplate {
+    template: String,
+    tokens: Vec<Token>,
+}
+
+impl Default for DestinationTemplate {
+    fn default() -> Self {
+        Self::from_str("{dest}/{component}").expect("default template is valid")
+    }
+}
+
+impl Display for DestinationTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl FromStr for DestinationTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut tokens = vec![];
+        let mut rest = s;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                tokens.push(Token::Literal(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("unterminated placeholder in destination template {}", s))?
+                + start;
+
+            let name = &rest[start + 1..end];
+            tokens.push(match name {
+                "dest" => Token::Dest,
+                "component" => Token::Component,
+                _ => {
+                    let index = name
+                        .strip_prefix("component[")
+                        .and_then(|v| v.strip_suffix(']'))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "unknown placeholder {{{}}} in destination template {}",
+                                name,
+                                s
+                            )
+                        })?;
+
+                    Token::ComponentChar(index)
+                }
+            });
+
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            tokens.push(Token::Literal(rest.to_string()));
+        }
+
+        if !tokens.contains(&Token::Component) {
+            return Err(anyhow!(
+                "destination template {} must contain {{component}}",
+                s
+            ));
+        }
+
+        Ok(Self {
+            template: s.to_string(),
+            tokens,
+        })
+    }
+}
+
+impl DestinationTemplate {
+    /// Resolve the repository path of a component.
+    pub fn resolve(&self, dest: &Path, component: &str) -> PathBuf {
+        let mut path = OsString::new();
+
+        for token in &self.tokens {
+            match token {
+                Token::Literal(s) => path.push(s),
+                Token::Dest => path.push(dest),
+                Token::Component => path.push(component),
+                Token::ComponentChar(index) => {
+                    path.push(component.chars().nth(*index).unwrap_or('_').to_string())
+                }
+            }
+        }
+
+        PathBuf::from(path)
+    }
+}
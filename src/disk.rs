@@ -0,0 +1,307 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Disk space accounting for imports.
+
+use {
+    crate::ndjson::NdjsonLog,
+    anyhow::{anyhow, Context, Result},
+    git2::{Commit, ObjectType, Oid, Repository, Tree},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Ratio of on-disk repository size to compressed archive size used until
+/// imports have been recorded in a [DiskUsageLog].
+///
+/// Source tarballs are typically gzip compressed by a factor of 4-5. Loose Git
+/// objects are individually zlib compressed, which is less efficient. This is
+/// a rough heuristic that errs on the side of overestimating.
+pub const DEFAULT_EXPANSION_RATIO: f64 = 3.0;
+
+/// Disk space used by a completed import.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ImportSize {
+    /// Path of the repository.
+    pub repository: PathBuf,
+    /// Total advertised size of the imported archives.
+    pub archive_bytes: u64,
+    /// Growth of the repository on disk.
+    pub disk_bytes: u64,
+}
+
+/// An append-only log of the disk space used by imports.
+pub type DiskUsageLog = NdjsonLog<ImportSize>;
+
+/// Ratio of disk space used to archive size across recorded imports.
+///
+/// Returns `None` if no archive bytes were recorded.
+pub fn expansion_ratio(imports: &[ImportSize]) -> Option<f64> {
+    let archive_bytes = imports.iter().map(|i| i.archive_bytes).sum::<u64>();
+    let disk_bytes = imports.iter().map(|i| i.disk_bytes).sum::<u64>();
+
+    if archive_bytes == 0 {
+        None
+    } else {
+        Some(disk_bytes as f64 / archive_bytes as f64)
+    }
+}
+
+/// Estimate the disk space needed to import archives of the given total size.
+pub fn estimate_required_space(archive_bytes: u64, expansion_ratio: f64) -> u64 {
+    (archive_bytes as f64 * expansion_ratio) as u64
+}
+
+/// Obtain the free space available on the filesystem holding a path.
+///
+/// The path doesn't need to exist: its nearest existing ancestor is used.
+pub fn available_space(path: &Path) -> Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+
+    fs2::available_space(existing)
+        .with_context(|| format!("resolving free space of {}", existing.display()))
+}
+
+/// Compute the total size of files under a directory.
+pub fn directory_size(path: &Path) -> Result<u64> {
+    let mut total = 0;
+
+    for entry in std::fs::read_dir(path).with_context(|| format!("reading {}", path.display()))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            total += directory_size(&entry.path())?;
+        } else if file_type.is_file() {
+            total += entry.metadata()?.len();
+        }
+    }
+
+    Ok(total)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Disk space reserved by imports running concurrently.
+///
+/// Clones share reservations. Imports hold their reservation until they
+/// finish, so space they have already written counts against free space
+/// twice and the check errs on the side of refusing.
+#[derive(Clone, Debug, Default)]
+pub struct SpaceReservations {
+    reserved: Arc<Mutex<u64>>,
+}
+
+impl SpaceReservations {
+    /// Total bytes currently reserved.
+    pub fn reserved(&self) -> u64 {
+        *self.reserved.lock().unwrap()
+    }
+}
+
+/// Disk space reserved for an import, released when dropped.
+#[derive(Debug)]
+pub struct SpaceReservation {
+    reservations: SpaceReservations,
+    archive_bytes: u64,
+    required: u64,
+}
+
+impl SpaceReservation {
+    /// Total advertised size of the archives the space is reserved for.
+    pub fn archive_bytes(&self) -> u64 {
+        self.archive_bytes
+    }
+}
+
+impl Drop for SpaceReservation {
+    fn drop(&mut self) {
+        *self.reservations.reserved.lock().unwrap() -= self.required;
+    }
+}
+
+/// Verify there is room to import archives totaling `archive_bytes` into
+/// `path`, alongside the imports holding `reservations`.
+///
+/// `max_disk_usage` limits the whole repository, like [DiskUsage::enforce],
+/// so the space `path` already uses counts towards it.
+///
+/// On success, the estimated space is reserved until the returned value is
+/// dropped.
+pub fn preflight(
+    path: &Path,
+    archive_bytes: u64,
+    expansion_ratio: f64,
+    max_disk_usage: Option<u64>,
+    reservations: &SpaceReservations,
+) -> Result<SpaceReservation> {
+    let required = estimate_required_space(archive_bytes, expansion_ratio);
+
+    if let Some(max) = max_disk_usage {
+        let used = if path.exists() {
+            directory_size(path)?
+        } else {
+            0
+        };
+
+        if required.saturating_add(used) > max {
+            return Err(anyhow!(
+                "import into {} is estimated to require {} in addition to the {} it uses, exceeding the disk usage limit of {}",
+                path.display(),
+                format_bytes(required),
+                format_bytes(used),
+                format_bytes(max)
+            ));
+        }
+    }
+
+    // Held while checking so concurrent imports see each other's estimates.
+    let mut reserved = reservations.reserved.lock().unwrap();
+    let available = available_space(path)?;
+
+    if required.saturating_add(*reserved) > available {
+        return Err(anyhow!(
+            "insufficient disk space for {}: an estimated {} is required in addition to {} reserved by concurrent imports but only {} is available",
+            path.display(),
+            format_bytes(required),
+            format_bytes(*reserved),
+            format_bytes(available)
+        ));
+    }
+
+    *reserved += required;
+
+    Ok(SpaceReservation {
+        reservations: reservations.clone(),
+        archive_bytes,
+        required,
+    })
+}
+
+/// Tracks the disk space used by a repository as commits are added to it.
+///
+/// The repository is measured once. Afterwards only the loose objects each
+/// commit adds are measured, so checking usage after every commit doesn't
+/// walk the whole repository.
+pub struct DiskUsage {
+    path: PathBuf,
+    objects: PathBuf,
+    initial: u64,
+    used: u64,
+    counted: HashSet<Oid>,
+}
+
+impl DiskUsage {
+    /// Measure the repository at `path`.
+    pub fn new(repo: &Repository, path: &Path) -> Result<Self> {
+        let used = directory_size(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            objects: repo.path().join("objects"),
+            initial: used,
+            used,
+            counted: HashSet::new(),
+        })
+    }
+
+    /// Bytes used by the repository.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Bytes used by the repository when tracking started.
+    pub fn initial(&self) -> u64 {
+        self.initial
+    }
+
+    /// Account for the objects a commit added.
+    ///
+    /// Subtrees the commit shares with its first parent aren't visited.
+    pub fn add_commit(&mut self, repo: &Repository, commit: &Commit) -> Result<()> {
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+
+        self.add_object(commit.id());
+        self.add_tree(repo, &commit.tree()?, parent_tree.as_ref())
+    }
+
+    /// Account for a loose object, returning whether it wasn't seen before.
+    pub fn add_object(&mut self, oid: Oid) -> bool {
+        if !self.counted.insert(oid) {
+            return false;
+        }
+
+        let hex = oid.to_string();
+
+        // Objects already packed aren't new.
+        if let Ok(metadata) = std::fs::metadata(self.objects.join(&hex[0..2]).join(&hex[2..])) {
+            self.used += metadata.len();
+        }
+
+        true
+    }
+
+    fn add_tree(&mut self, repo: &Repository, tree: &Tree, old: Option<&Tree>) -> Result<()> {
+        // The contents of trees already seen were counted along with them.
+        if !self.add_object(tree.id()) {
+            return Ok(());
+        }
+
+        for entry in tree.iter() {
+            let old_entry = match (old, entry.name()) {
+                (Some(old), Some(name)) => old.get_name(name),
+                _ => None,
+            };
+
+            if old_entry.as_ref().map(|e| e.id()) == Some(entry.id()) {
+                continue;
+            }
+
+            match entry.kind() {
+                Some(ObjectType::Tree) => {
+                    let old_tree = match old_entry {
+                        Some(e) if e.kind() == Some(ObjectType::Tree) => {
+                            Some(repo.find_tree(e.id())?)
+                        }
+                        _ => None,
+                    };
+
+                    self.add_tree(repo, &repo.find_tree(entry.id())?, old_tree.as_ref())?;
+                }
+                Some(ObjectType::Blob) => {
+                    self.add_object(entry.id());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Error if the repository uses more than `max_disk_usage` bytes.
+    pub fn enforce(&self, max_disk_usage: u64) -> Result<()> {
+        if self.used > max_disk_usage {
+            Err(anyhow!(
+                "{} uses {}, exceeding the disk usage limit of {}",
+                self.path.display(),
+                format_bytes(self.used),
+                format_bytes(max_disk_usage)
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
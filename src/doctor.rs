@@ -0,0 +1,314 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Diagnosis of the environment imports run in.
+//!
+//! [diagnose] checks the things mirrors commonly trip over, such as network
+//! access, TLS, cache permissions, and disk space, and suggests how to fix
+//! problems it finds.
+
+use {
+    crate::{
+        crawler::{github::API_URL, URL_MAIN},
+        disk::available_space,
+    },
+    anyhow::Result,
+    reqwest::Client,
+    serde::Serialize,
+    std::{
+        error::Error,
+        path::Path,
+        time::{Duration, Instant},
+    },
+};
+
+/// Free space below which disk space is reported as low.
+pub const LOW_DISK_SPACE: u64 = 10 * 1024 * 1024 * 1024;
+
+/// How long requests checking connectivity may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Outcome of a check.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// The result of checking one aspect of the environment.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found.
+    pub detail: String,
+    /// How to fix a problem, if there is one.
+    pub advice: Option<String>,
+}
+
+impl Check {
+    fn ok(name: impl ToString, detail: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Ok,
+            detail: detail.to_string(),
+            advice: None,
+        }
+    }
+
+    fn problem(
+        name: impl ToString,
+        status: CheckStatus,
+        detail: impl ToString,
+        advice: impl ToString,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.to_string(),
+            advice: Some(advice.to_string()),
+        }
+    }
+}
+
+/// The results of every check.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Diagnosis {
+    pub checks: Vec<Check>,
+}
+
+impl Diagnosis {
+    /// The worst status of any check.
+    pub fn status(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok)
+    }
+
+    /// Render as plain text.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![];
+
+        for check in &self.checks {
+            lines.push(format!(
+                "{:<8} {}: {}",
+                check.status.as_str(),
+                check.name,
+                check.detail
+            ));
+
+            if let Some(advice) = &check.advice {
+                lines.push(format!("         {}", advice));
+            }
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Render as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+/// Whether an error, or an error causing it, concerns TLS.
+fn is_tls_error(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+
+    while let Some(e) = current {
+        let message = e.to_string().to_lowercase();
+        if ["certificate", "tls", "handshake"]
+            .iter()
+            .any(|word| message.contains(word))
+        {
+            return true;
+        }
+
+        current = e.source();
+    }
+
+    false
+}
+
+/// Check that `url` can be requested.
+///
+/// Any HTTP response shows the host is reachable and TLS works.
+pub async fn check_url(client: &Client, name: &str, url: &str) -> Check {
+    let start = Instant::now();
+
+    match client.head(url).timeout(REQUEST_TIMEOUT).send().await {
+        Ok(response) if response.status().is_server_error() => Check::problem(
+            name,
+            CheckStatus::Warning,
+            format!("{} responded with HTTP {}", url, response.status()),
+            "The server is having problems; retry later",
+        ),
+        Ok(response) => Check::ok(
+            name,
+            format!(
+                "{} responded with HTTP {} in {:.1}s",
+                url,
+                response.status().as_u16(),
+                start.elapsed().as_secs_f64()
+            ),
+        ),
+        Err(e) if is_tls_error(&e) => Check::problem(
+            name,
+            CheckStatus::Error,
+            format!("TLS with {} failed: {}", url, e),
+            "Check the system clock and whether a proxy intercepts TLS connections",
+        ),
+        Err(e) if e.is_timeout() => Check::problem(
+            name,
+            CheckStatus::Error,
+            format!("{} didn't respond within {}s", url, REQUEST_TIMEOUT.as_secs()),
+            "Check network connectivity; --ipv4 helps on networks with broken IPv6",
+        ),
+        Err(e) => Check::problem(
+            name,
+            CheckStatus::Error,
+            format!("requesting {} failed: {}", url, e),
+            "Check network connectivity and firewalls, or route requests with --socks5 or --resolve",
+        ),
+    }
+}
+
+/// Check that files can be created in a directory, creating it if needed.
+pub fn check_writable_dir(name: &str, dir: &Path) -> Check {
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+
+    let res = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b"probe"))
+        .and_then(|_| std::fs::remove_file(&probe));
+
+    match res {
+        Ok(()) => Check::ok(name, format!("{} is writable", dir.display())),
+        Err(e) => Check::problem(
+            name,
+            CheckStatus::Error,
+            format!("{} isn't writable: {}", dir.display(), e),
+            "Fix the directory's ownership or permissions, or use another directory",
+        ),
+    }
+}
+
+/// Check that the filesystem holding `path` has at least `minimum` bytes free.
+pub fn check_disk_space(name: &str, path: &Path, minimum: u64) -> Check {
+    match available_space(path) {
+        Ok(available) if available < minimum => Check::problem(
+            name,
+            CheckStatus::Warning,
+            format!(
+                "{} MiB free for {}",
+                available / 1024 / 1024,
+                path.display()
+            ),
+            format!(
+                "Imports of large components need several GiB; keep at least {} MiB free",
+                minimum / 1024 / 1024
+            ),
+        ),
+        Ok(available) => Check::ok(
+            name,
+            format!(
+                "{} MiB free for {}",
+                available / 1024 / 1024,
+                path.display()
+            ),
+        ),
+        Err(e) => Check::problem(
+            name,
+            CheckStatus::Warning,
+            format!("{:#}", e),
+            "Disk space checks before imports won't work; pass --skip-space-check",
+        ),
+    }
+}
+
+/// Check the capabilities libgit2 was built with.
+pub fn check_libgit2() -> Check {
+    let version = git2::Version::get();
+    let (major, minor, rev) = version.libgit2_version();
+    let detail = format!(
+        "libgit2 {}.{}.{} (threads: {}, https: {}, ssh: {})",
+        major,
+        minor,
+        rev,
+        version.threads(),
+        version.https(),
+        version.ssh()
+    );
+
+    if version.threads() {
+        Check::ok("libgit2", detail)
+    } else {
+        Check::problem(
+            "libgit2",
+            CheckStatus::Error,
+            detail,
+            "libgit2 must be built with thread support for concurrent imports",
+        )
+    }
+}
+
+/// Check that the `git` executable is available.
+///
+/// It is needed by `--optimize` and for publishing mirrors.
+pub fn check_git_executable() -> Check {
+    match std::process::Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            Check::ok("git", String::from_utf8_lossy(&output.stdout).trim())
+        }
+        Ok(output) => Check::problem(
+            "git",
+            CheckStatus::Warning,
+            format!("git --version failed with {}", output.status),
+            "Repair the git installation; --optimize and publishing mirrors run git",
+        ),
+        Err(e) => Check::problem(
+            "git",
+            CheckStatus::Warning,
+            format!("git couldn't be run: {}", e),
+            "Install git to use --optimize and to publish mirrors",
+        ),
+    }
+}
+
+/// Check the environment.
+///
+/// `dest` is where repositories are created and `cache_dir` is the directory
+/// caching artifacts, if any.
+pub async fn diagnose(client: &Client, dest: &Path, cache_dir: Option<&Path>) -> Diagnosis {
+    let (apple, github) = futures::join!(
+        check_url(client, "opensource.apple.com", URL_MAIN),
+        check_url(client, "GitHub", API_URL),
+    );
+
+    let mut checks = vec![apple, github];
+
+    if let Some(dir) = cache_dir {
+        checks.push(check_writable_dir("cache directory", dir));
+        checks.push(check_disk_space("cache disk space", dir, LOW_DISK_SPACE));
+    }
+
+    checks.push(check_disk_space("disk space", dest, LOW_DISK_SPACE));
+    checks.push(check_libgit2());
+    checks.push(check_git_executable());
+
+    Diagnosis { checks }
+}
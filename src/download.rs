@@ -3,77 +3,243 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    crate::{
+        crawler::{
+            cache::cached,
+            source_tree::{self, file_url, SourceListing},
+            ComponentManifest, HtmlSource, PageCache, ReleasePage, Source, UrlSchemes,
+        },
+        error::HttpStatusError,
+        identity::CommitIdentity,
+        middleware::{
+            CoalesceLayer, Fetch, Layer, RateLimitLayer, Request, SchemeFallbackLayer, Service,
+        },
+        platform::{EntityAliases, Platform},
+        tasks::TaskSet,
+    },
     anyhow::{anyhow, Context, Result},
+    chrono::{DateTime, NaiveDate, NaiveDateTime},
+    futures::StreamExt,
     regex::Regex,
-    reqwest::{Client, ClientBuilder},
+    reqwest::{Client, ClientBuilder, Proxy},
     std::{
         cmp::Ordering,
-        collections::{BTreeMap, BTreeSet},
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
         str::FromStr,
+        sync::{Arc, Mutex},
         time::Duration,
     },
 };
 
-const URL_MAIN: &str = "https://opensource.apple.com/";
-const URL_TARBALLS: &str = "https://opensource.apple.com/tarballs";
-
 const USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:95.0) Gecko/20100101 Firefox/95.0";
 
 /// Compare ordering of a version string.
 ///
+/// Dot delimited parts are compared numerically when both are integers and
+/// lexically otherwise, with missing parts treated as `0`. Versions that
+/// compare equal this way are ordered by their string value so the result
+/// is a total order.
+///
 /// This is super hacky and is likely wrong in many edge cases!
-fn compare_version_string(a: &str, b: &str) -> Option<Ordering> {
-    let a_parts = a.split('.').collect::<Vec<_>>();
-    let b_parts = b.split('.').collect::<Vec<_>>();
+pub(crate) fn compare_version_string(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
 
-    for (i, a_part) in a_parts.into_iter().enumerate() {
-        let b_part = b_parts.get(i).unwrap_or(&"0");
+    loop {
+        let (a_part, b_part) = match (a_parts.next(), b_parts.next()) {
+            (None, None) => break,
+            (a_part, b_part) => (a_part.unwrap_or("0"), b_part.unwrap_or("0")),
+        };
 
-        let a_int = u32::from_str(a_part);
-        let b_int = u32::from_str(b_part);
+        let ord = match (u64::from_str(a_part), u64::from_str(b_part)) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            // Numeric parts sort before textual ones.
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a_part.cmp(b_part),
+        };
 
-        if let (Ok(a), Ok(b)) = (a_int, b_int) {
-            match a.cmp(&b) {
-                Ordering::Equal => continue,
-                ord => {
-                    return Some(ord);
-                }
-            }
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+
+    a.cmp(b)
+}
+
+/// How the versions of a component are ordered, when
+/// [compare_version_string] gets it wrong, e.g. because the versioning
+/// scheme changed.
+#[derive(Clone, Debug)]
+pub enum VersionOrder {
+    /// Versions in the order listed. Versions not listed follow.
+    Explicit(Vec<String>),
+    /// Versions matching an earlier regular expression precede versions
+    /// matching a later one. Versions not matching any follow.
+    Schemes(Vec<Regex>),
+}
+
+impl VersionOrder {
+    fn rank(&self, version: &str) -> usize {
+        match self {
+            Self::Explicit(versions) => versions.iter().position(|v| v == version),
+            Self::Schemes(schemes) => schemes.iter().position(|re| re.is_match(version)),
         }
+        .unwrap_or(usize::MAX)
     }
 
-    a.partial_cmp(b)
+    /// Compare ordering of versions.
+    ///
+    /// Versions of the same rank are ordered by [compare_version_string].
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.rank(a)
+            .cmp(&self.rank(b))
+            .then_with(|| compare_version_string(a, b))
+    }
 }
 
-fn is_macos(s: &str) -> bool {
-    matches!(s, "macos" | "os-x" | "mac-os-x")
+/// Orders of versions of components, keyed by component.
+///
+/// Components without an order use [compare_version_string].
+#[derive(Clone, Debug, Default)]
+pub struct VersionOrders {
+    orders: BTreeMap<String, VersionOrder>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Ord)]
+impl VersionOrders {
+    /// Order versions of a component as listed.
+    pub fn with_versions(mut self, component: &str, versions: &[String]) -> Self {
+        self.orders.insert(
+            component.to_string(),
+            VersionOrder::Explicit(versions.to_vec()),
+        );
+        self
+    }
+
+    /// Order versions of a component by the first of several regular
+    /// expressions they match.
+    pub fn with_schemes(mut self, component: &str, patterns: &[String]) -> Result<Self> {
+        let schemes = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("parsing version scheme for {}", component))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.orders
+            .insert(component.to_string(), VersionOrder::Schemes(schemes));
+
+        Ok(self)
+    }
+
+    /// Compare ordering of versions of a component.
+    pub fn compare(&self, component: &str, a: &str, b: &str) -> Ordering {
+        match self.orders.get(component) {
+            Some(order) => order.compare(a, b),
+            None => compare_version_string(a, b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ReleaseRecord {
     pub entity: String,
     pub version: String,
     pub url: String,
+    /// When the release was published, if known.
+    pub date: Option<NaiveDate>,
+    /// The entity name as published, if it was canonicalized.
+    pub original_entity: Option<String>,
+}
+
+impl Ord for ReleaseRecord {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Aliases of a platform sort together so their versions interleave.
+        // Not every release is dated, so releases are ordered by version.
+        // [crate::chronology::order_releases] orders them chronologically.
+        self.platform()
+            .as_str()
+            .cmp(other.platform().as_str())
+            .then_with(|| compare_version_string(&self.version, &other.version))
+            .then_with(|| self.entity.cmp(&other.entity))
+            .then_with(|| self.url.cmp(&other.url))
+    }
 }
 
 impl PartialOrd for ReleaseRecord {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if self.matches_entity(&other.entity) {
-            compare_version_string(&self.version, &other.version)
-        } else {
-            match self.entity.cmp(&other.entity) {
-                Ordering::Equal => compare_version_string(&self.version, &other.version),
-                ord => Some(ord),
-            }
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl ReleaseRecord {
+    /// The platform this release belongs to.
+    pub fn platform(&self) -> Platform {
+        Platform::from_entity(&self.entity)
+    }
+
+    /// Commit timestamp for this release.
+    ///
+    /// This is midnight UTC of the release date or a fixed date in the
+    /// absence of a release date. See [CommitIdentity].
+    pub fn timestamp(&self) -> i64 {
+        CommitIdentity::default().timestamp(self.date)
+    }
+
+    /// The entity name as published.
+    pub fn published_entity(&self) -> &str {
+        self.original_entity.as_deref().unwrap_or(&self.entity)
+    }
+
     /// Whether this record belongs to the named entity.
+    ///
+    /// Aliases of the same [Platform] match each other.
     pub fn matches_entity(&self, s: &str) -> bool {
-        s == self.entity || (is_macos(s) && is_macos(&self.entity))
+        s == self.entity || Platform::from_entity(s) == self.platform()
+    }
+}
+
+/// The type of a downloadable artifact linked from a release page.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ArtifactKind {
+    /// A gzipped tarball of source code.
+    Tarball,
+    /// An installer package (`.pkg`).
+    Package,
+    /// A disk image (`.dmg`).
+    DiskImage,
+    /// A zip archive.
+    Zip,
+}
+
+impl ArtifactKind {
+    /// Resolve the artifact type from a URL path, if it looks like an artifact.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_ascii_lowercase();
+
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(Self::Tarball)
+        } else if path.ends_with(".pkg") {
+            Some(Self::Package)
+        } else if path.ends_with(".dmg") {
+            Some(Self::DiskImage)
+        } else if path.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tarball => "tarball",
+            Self::Package => "pkg",
+            Self::DiskImage => "dmg",
+            Self::Zip => "zip",
+        }
     }
 }
 
@@ -82,122 +248,802 @@ pub struct ReleaseComponentRecord {
     pub entity: String,
     pub component: String,
     pub url: String,
+    pub kind: ArtifactKind,
+}
+
+impl ReleaseComponentRecord {
+    /// The filename of the artifact, as derived from its URL.
+    pub fn filename(&self) -> &str {
+        self.url
+            .rsplit_once('/')
+            .map(|(_, x)| x)
+            .unwrap_or(&self.url)
+    }
+
+    /// The component version, as derived from the tarball filename.
+    ///
+    /// e.g. `xnu-7195.141.2.tar.gz` yields `7195.141.2`. Component names may
+    /// contain hyphens, so only the `<component>-` prefix is stripped.
+    pub fn version(&self) -> Option<&str> {
+        self.filename()
+            .strip_suffix(".tar.gz")?
+            .strip_prefix(self.component.as_str())?
+            .strip_prefix('-')
+    }
+}
+
+/// Assign artifacts distinct file names to save them under.
+///
+/// Names are the last segment of the URL, with characters other than ASCII
+/// alphanumerics, `.`, `-`, `_`, and `+` replaced by `_`. Records with the
+/// same URL get the same name. When the names of different URLs collide,
+/// ignoring case, later ones get a `-2`, `-3`, ... suffix before their
+/// extension.
+pub fn artifact_filenames(records: &[ReleaseComponentRecord]) -> Vec<String> {
+    let mut by_url = HashMap::<&str, String>::new();
+    let mut taken = HashSet::new();
+
+    records
+        .iter()
+        .map(|record| {
+            if let Some(name) = by_url.get(record.url.as_str()) {
+                return name.clone();
+            }
+
+            let mut name = record
+                .filename()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+') {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>();
+            if name.trim_matches('.').is_empty() {
+                name = "artifact".to_string();
+            }
+
+            let (stem, extension) = [".tar.gz", ".tar.bz2", ".tar.xz"]
+                .into_iter()
+                .find_map(|extension| Some((name.strip_suffix(extension)?, extension)))
+                .or_else(|| name.rfind('.').filter(|&i| i > 0).map(|i| name.split_at(i)))
+                .unwrap_or((name.as_str(), ""));
+
+            let mut candidate = name.clone();
+            let mut n = 1;
+            while !taken.insert(candidate.to_ascii_lowercase()) {
+                n += 1;
+                candidate = format!("{}-{}{}", stem, n, extension);
+            }
+
+            by_url.insert(&record.url, candidate.clone());
+
+            candidate
+        })
+        .collect()
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Ord)]
+/// How a component differs between two software releases.
+#[derive(Clone, Debug)]
+pub enum ComponentChange {
+    Added(ReleaseComponentRecord),
+    Removed(ReleaseComponentRecord),
+    Changed {
+        old: ReleaseComponentRecord,
+        new: ReleaseComponentRecord,
+    },
+}
+
+impl ComponentChange {
+    pub fn component(&self) -> &str {
+        match self {
+            Self::Added(record) | Self::Removed(record) => &record.component,
+            Self::Changed { new, .. } => &new.component,
+        }
+    }
+}
+
+/// Compute which components differ between two sets of release components.
+///
+/// Components are keyed by name and are considered changed if their artifact
+/// URL differs. Results are sorted by component name.
+pub fn diff_release_components(
+    old: &[ReleaseComponentRecord],
+    new: &[ReleaseComponentRecord],
+) -> Vec<ComponentChange> {
+    let old = old
+        .iter()
+        .map(|record| (record.component.as_str(), record))
+        .collect::<BTreeMap<_, _>>();
+    let new = new
+        .iter()
+        .map(|record| (record.component.as_str(), record))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut changes = vec![];
+
+    for (component, old_record) in &old {
+        match new.get(component) {
+            Some(new_record) if new_record.url != old_record.url => {
+                changes.push(ComponentChange::Changed {
+                    old: (*old_record).clone(),
+                    new: (*new_record).clone(),
+                });
+            }
+            Some(_) => {}
+            None => changes.push(ComponentChange::Removed((*old_record).clone())),
+        }
+    }
+
+    for (component, new_record) in &new {
+        if !old.contains_key(component) {
+            changes.push(ComponentChange::Added((*new_record).clone()));
+        }
+    }
+
+    changes.sort_by(|a, b| a.component().cmp(b.component()));
+
+    changes
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ComponentRecord {
     pub component: String,
     pub filename: String,
     pub url: String,
     pub version: String,
+    /// Size of the archive in bytes, if known.
+    ///
+    /// Sizes listed on index pages are rounded.
+    pub size: Option<u64>,
+    /// When the archive was last modified, if known.
+    ///
+    /// Times from `Last-Modified` headers are UTC. Times listed on index
+    /// pages are in the server's time zone.
+    pub last_modified: Option<NaiveDateTime>,
+}
+
+impl ComponentRecord {
+    /// Whether this version is a browsable source tree rather than an archive.
+    pub fn is_source_tree(&self) -> bool {
+        self.url.ends_with('/')
+    }
+
+    /// Fill in size and modification time from artifact metadata, keeping
+    /// values that are already known.
+    pub fn enrich(&mut self, metadata: &ArtifactMetadata) {
+        self.size = self.size.or(metadata.size);
+        self.last_modified = self.last_modified.or(metadata.last_modified);
+    }
+}
+
+/// Whether an error is due to an HTTP 404.
+pub(crate) fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<HttpStatusError>(), Some(e) if e.status == 404)
+}
+
+/// Maximum number of concurrent `HEAD` requests when enriching records.
+pub const METADATA_CONCURRENCY: usize = 8;
+
+/// Maximum number of concurrent requests for the files of a source tree.
+pub const SOURCE_TREE_CONCURRENCY: usize = 8;
+
+/// Metadata of an artifact obtained from the headers of a `HEAD` request.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ArtifactMetadata {
+    /// The `Content-Length`.
+    pub size: Option<u64>,
+    /// The `Last-Modified` time, in UTC.
+    pub last_modified: Option<NaiveDateTime>,
+}
+
+impl ArtifactMetadata {
+    /// Obtain metadata from the headers of a response.
+    pub fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+        Self {
+            size: header(reqwest::header::CONTENT_LENGTH).and_then(|v| u64::from_str(v).ok()),
+            last_modified: header(reqwest::header::LAST_MODIFIED)
+                .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+                .map(|dt| dt.naive_utc()),
+        }
+    }
+
+    fn to_json(self) -> serde_json::Value {
+        serde_json::json!({
+            "size": self.size,
+            "last_modified": self.last_modified.map(|t| t.to_string()),
+        })
+    }
+
+    fn from_json(text: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+
+        Ok(Self {
+            size: value["size"].as_u64(),
+            last_modified: value["last_modified"]
+                .as_str()
+                .map(|t| NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S"))
+                .transpose()?,
+        })
+    }
+}
+
+impl Ord for ComponentRecord {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.component
+            .cmp(&other.component)
+            .then_with(|| compare_version_string(&self.version, &other.version))
+            .then_with(|| self.filename.cmp(&other.filename))
+            .then_with(|| self.url.cmp(&other.url))
+    }
 }
 
 impl PartialOrd for ComponentRecord {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match self.component.cmp(&other.component) {
-            Ordering::Equal => compare_version_string(&self.version, &other.version),
-            ord => Some(ord),
+        Some(self.cmp(other))
+    }
+}
+
+/// Parse a byte size like `10GB`, `4GiB`, or `500k` into bytes.
+///
+/// Units ending in `B` are decimal (`MB` is 1000²) unless binary ones are
+/// spelled out (`MiB` is 1024²). Bare units like `M` are powers of 1024, as
+/// with curl.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let value = s.trim();
+    let (value, base) = match value.strip_suffix(['B', 'b']) {
+        Some(value) => match value.strip_suffix(['i', 'I']) {
+            Some(value) => (value, 1024u64),
+            None => (value, 1000),
+        },
+        None => (value.strip_suffix(['i', 'I']).unwrap_or(value), 1024),
+    };
+
+    let (number, exponent) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 1),
+        Some('m' | 'M') => (&value[..value.len() - 1], 2),
+        Some('g' | 'G') => (&value[..value.len() - 1], 3),
+        Some('t' | 'T') => (&value[..value.len() - 1], 4),
+        _ => (value, 0),
+    };
+    let multiplier = base.pow(exponent);
+
+    let number = f64::from_str(number.trim()).with_context(|| format!("parsing size {}", s))?;
+
+    // NaN fails this too.
+    if !(number > 0.0 && number.is_finite()) {
+        return Err(anyhow!("size must be a positive number: {}", s));
+    }
+
+    match (number * multiplier as f64) as u64 {
+        0 => Err(anyhow!("size must be at least 1 byte: {}", s)),
+        bytes => Ok(bytes),
+    }
+}
+
+/// Parse a duration like `90`, `30m`, `12h`, or `7d`.
+///
+/// Numbers without a unit are seconds. Zero is accepted; callers for which
+/// it is meaningless reject it themselves.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let value = s.trim();
+
+    let (number, multiplier) = match value.chars().last() {
+        Some('s') => (&value[..value.len() - 1], 1),
+        Some('m') => (&value[..value.len() - 1], 60),
+        Some('h') => (&value[..value.len() - 1], 60 * 60),
+        Some('d') => (&value[..value.len() - 1], 24 * 60 * 60),
+        _ => (value, 1),
+    };
+
+    let number = u64::from_str(number.trim()).with_context(|| format!("parsing duration {}", s))?;
+
+    let seconds = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("duration {} is too large", s))?;
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parse a transfer rate like `5MB/s` or `500k` into bytes per second.
+pub fn parse_rate(s: &str) -> Result<u64> {
+    let value = s.trim();
+
+    parse_size(value.strip_suffix("/s").unwrap_or(value))
+}
+
+/// Parse a SOCKS5 proxy address like `127.0.0.1:9050`.
+///
+/// Host names are resolved by the proxy, so DNS lookups are routed through
+/// it as well, as Tor requires.
+pub fn parse_socks5(s: &str) -> Result<Proxy> {
+    let (host, port) = s
+        .trim()
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("SOCKS5 proxy {} must be of the form host:port", s))?;
+
+    if host.is_empty() {
+        return Err(anyhow!("SOCKS5 proxy {} has no host", s));
+    }
+    u16::from_str(port).with_context(|| format!("parsing port of SOCKS5 proxy {}", s))?;
+
+    Proxy::all(format!("socks5h://{}:{}", host, port))
+        .with_context(|| format!("configuring SOCKS5 proxy {}", s))
+}
+
+/// The IP versions connections may use.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IpFamily {
+    /// Whichever addresses the host resolves to.
+    #[default]
+    Any,
+    /// Only IPv4 addresses.
+    V4,
+    /// Only IPv6 addresses.
+    V6,
+}
+
+/// An address to connect to for a host instead of resolving it, like curl's
+/// `--resolve`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolveOverride {
+    pub host: String,
+    pub addr: IpAddr,
+}
+
+/// Parse a DNS override like `opensource.apple.com:17.253.1.1`.
+///
+/// IPv6 addresses may be enclosed in brackets.
+pub fn parse_resolve(s: &str) -> Result<ResolveOverride> {
+    let (host, addr) = s
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| anyhow!("DNS override {} must be of the form host:addr", s))?;
+
+    if host.is_empty() {
+        return Err(anyhow!("DNS override {} has no host", s));
+    }
+
+    let addr = addr.trim_start_matches('[').trim_end_matches(']');
+
+    Ok(ResolveOverride {
+        host: host.to_string(),
+        addr: IpAddr::from_str(addr)
+            .with_context(|| format!("parsing address of DNS override {}", s))?,
+    })
+}
+
+/// A version listed in a versions file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VersionEntry {
+    pub version: String,
+    /// URL of the archive of the version, if it isn't discovered.
+    pub url: Option<String>,
+}
+
+/// Parse a versions file, listing a version per line in the order to import
+/// them.
+///
+/// Each line has a version, optionally followed by whitespace and the URL of
+/// its archive. Empty lines and lines starting with `#` are ignored.
+pub fn parse_versions_file(text: &str) -> Result<Vec<VersionEntry>> {
+    let mut entries: Vec<VersionEntry> = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let version = fields.next().expect("line isn't empty").to_string();
+        let url = fields.next().map(|url| url.to_string());
+
+        if fields.next().is_some() {
+            return Err(anyhow!(
+                "line {} must be a version optionally followed by a URL",
+                i + 1
+            ));
+        }
+
+        if entries.iter().any(|entry| entry.version == version) {
+            return Err(anyhow!("line {} repeats version {}", i + 1, version));
+        }
+
+        entries.push(VersionEntry { version, url });
+    }
+
+    Ok(entries)
+}
+
+/// Settings of the HTTP client.
+#[derive(Clone, Default)]
+struct NetworkOptions {
+    proxy: Option<Proxy>,
+    resolve: Vec<ResolveOverride>,
+    ip_family: IpFamily,
+}
+
+impl NetworkOptions {
+    fn client(&self) -> Result<Client> {
+        let mut builder = ClientBuilder::new()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(120));
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        for o in &self.resolve {
+            // The port is ignored; connections use the port of the URL.
+            builder = builder.resolve(&o.host, SocketAddr::new(o.addr, 0));
+        }
+
+        // Binding to an unspecified address of a family limits connections
+        // to addresses of that family.
+        builder = match self.ip_family {
+            IpFamily::Any => builder,
+            IpFamily::V4 => builder.local_address(IpAddr::from(Ipv4Addr::UNSPECIFIED)),
+            IpFamily::V6 => builder.local_address(IpAddr::from(Ipv6Addr::UNSPECIFIED)),
+        };
+
+        builder.build().context("building HTTP client")
+    }
+}
+
+/// Builds a [Downloader].
+///
+/// Layers wrap the service that downloads artifacts. The first layer added is
+/// the outermost, so it sees each request first. Concurrent requests for the
+/// same URL are coalesced by a [CoalesceLayer] around all layers.
+pub struct DownloaderBuilder {
+    client: Client,
+    network: NetworkOptions,
+    source: Option<Arc<dyn Source>>,
+    max_rate: Option<u64>,
+    layers: Vec<Box<dyn Layer>>,
+    entity_aliases: EntityAliases,
+    page_cache: Option<PageCache>,
+    source_trees: bool,
+    url_schemes: Option<UrlSchemes>,
+    version_orders: VersionOrders,
+}
+
+impl DownloaderBuilder {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: NetworkOptions::default().client()?,
+            network: NetworkOptions::default(),
+            source: None,
+            max_rate: None,
+            layers: vec![],
+            entity_aliases: EntityAliases::default(),
+            page_cache: None,
+            source_trees: false,
+            url_schemes: Some(UrlSchemes::default()),
+            version_orders: VersionOrders::default(),
+        })
+    }
+
+    /// The HTTP client the downloader will use.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Route all requests through a proxy, such as one from [parse_socks5].
+    ///
+    /// Like the other network settings, this replaces the
+    /// [client](Self::client), so sources constructed by callers must be
+    /// given the client afterwards.
+    pub fn proxy(mut self, proxy: Option<Proxy>) -> Result<Self> {
+        self.network.proxy = proxy;
+        self.client = self.network.client()?;
+        Ok(self)
+    }
+
+    /// Connect to fixed addresses instead of resolving some hosts.
+    pub fn resolve(mut self, overrides: Vec<ResolveOverride>) -> Result<Self> {
+        self.network.resolve = overrides;
+        self.client = self.network.client()?;
+        Ok(self)
+    }
+
+    /// Limit connections to an IP version.
+    pub fn ip_family(mut self, family: IpFamily) -> Result<Self> {
+        self.network.ip_family = family;
+        self.client = self.network.client()?;
+        Ok(self)
+    }
+
+    /// Use a different source for discovering available software.
+    pub fn source(mut self, source: Arc<dyn Source>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Limit the aggregate rate at which artifacts are downloaded.
+    pub fn max_rate(mut self, bytes_per_second: Option<u64>) -> Self {
+        self.max_rate = bytes_per_second;
+        self
+    }
+
+    /// Canonicalize the entity names of releases.
+    ///
+    /// Known aliases of platforms are canonicalized by default.
+    pub fn entity_aliases(mut self, aliases: EntityAliases) -> Self {
+        self.entity_aliases = aliases;
+        self
+    }
+
+    /// Cache index pages fetched by sources on disk.
+    ///
+    /// Sources constructed by callers must be given the cache themselves.
+    pub fn page_cache(mut self, cache: Option<PageCache>) -> Self {
+        self.page_cache = cache;
+        self
+    }
+
+    /// Also resolve component versions only published as browsable source
+    /// trees, snapshotting them file by file when downloaded.
+    pub fn source_trees(mut self, enabled: bool) -> Self {
+        self.source_trees = enabled;
+        self
+    }
+
+    /// The URL schemes artifacts that aren't found are looked up under.
+    ///
+    /// [DEFAULT_SCHEMES](crate::crawler::schemes::DEFAULT_SCHEMES) are used
+    /// by default. `None` disables the fallback.
+    pub fn url_schemes(mut self, schemes: Option<UrlSchemes>) -> Self {
+        self.url_schemes = schemes;
+        self
+    }
+
+    /// Order the versions of some components other than by version number.
+    pub fn version_orders(mut self, orders: VersionOrders) -> Self {
+        self.version_orders = orders;
+        self
+    }
+
+    /// Add a layer around artifact downloads.
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn build(self) -> Downloader {
+        let mut service: Arc<dyn Service> = Arc::new(Fetch);
+
+        if let Some(bytes_per_second) = self.max_rate {
+            service = RateLimitLayer::new(bytes_per_second).layer(service);
+        }
+
+        if let Some(schemes) = self.url_schemes {
+            service = SchemeFallbackLayer::new(schemes).layer(service);
+        }
+
+        for layer in self.layers.iter().rev() {
+            service = layer.layer(service);
+        }
+
+        service = CoalesceLayer.layer(service);
+
+        Downloader {
+            source: self.source.unwrap_or_else(|| {
+                Arc::new(
+                    HtmlSource::new(self.client.clone()).with_page_cache(self.page_cache.clone()),
+                )
+            }),
+            client: self.client,
+            service,
+            entity_aliases: Arc::new(self.entity_aliases),
+            version_orders: Arc::new(self.version_orders),
+            page_cache: self.page_cache,
+            source_trees: self.source_trees,
+            metadata: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Downloader {
     client: Client,
+    source: Arc<dyn Source>,
+    service: Arc<dyn Service>,
+    entity_aliases: Arc<EntityAliases>,
+    version_orders: Arc<VersionOrders>,
+    page_cache: Option<PageCache>,
+    source_trees: bool,
+    /// Artifact metadata obtained by this instance, keyed by URL.
+    metadata: Arc<Mutex<HashMap<String, ArtifactMetadata>>>,
 }
 
 impl Downloader {
     pub fn new() -> Result<Self> {
-        let client = ClientBuilder::new()
-            .user_agent(USER_AGENT)
-            .timeout(Duration::from_secs(120))
-            .build()
-            .context("building HTTP client")?;
+        Ok(Self::builder()?.build())
+    }
+
+    pub fn builder() -> Result<DownloaderBuilder> {
+        DownloaderBuilder::new()
+    }
 
-        Ok(Self { client })
+    /// The HTTP client used for requests.
+    pub fn client(&self) -> &Client {
+        &self.client
     }
 
-    async fn get_request(&self, url: &str) -> Result<reqwest::Response> {
-        let res = self.client.get(url).send().await?;
+    /// The source software is discovered from.
+    pub fn source(&self) -> Arc<dyn Source> {
+        self.source.clone()
+    }
 
-        if res.status().is_success() {
-            Ok(res)
-        } else {
-            Err(anyhow!("HTTP {} from {}", res.status(), url))
+    /// The [name](Source::name) of the source software is discovered from.
+    pub fn source_name(&self) -> String {
+        self.source.name()
+    }
+
+    /// Compare ordering of versions of a component, as configured.
+    pub fn compare_versions(&self, component: &str, a: &str, b: &str) -> Ordering {
+        self.version_orders.compare(component, a, b)
+    }
+
+    /// The cache of index pages, if any.
+    pub fn page_cache(&self) -> Option<&PageCache> {
+        self.page_cache.as_ref()
+    }
+
+    /// Use a different source for discovering available software.
+    pub fn with_source(mut self, source: Arc<dyn Source>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Download the content of a URL advertised by a source.
+    async fn download(&self, source: &dyn Source, url: &str) -> Result<Vec<u8>> {
+        self.service.call(Request::new(source, url)).await
+    }
+
+    /// Obtain the size of a URL's content without downloading it.
+    ///
+    /// Returns `None` if the server doesn't advertise a `Content-Length`.
+    pub async fn get_content_length(&self, url: &str) -> Result<Option<u64>> {
+        Ok(self.get_artifact_metadata(url).await?.size)
+    }
+
+    /// Obtain the size and modification time of a URL's content without
+    /// downloading it.
+    ///
+    /// Artifacts don't change, so metadata is remembered by this instance and
+    /// stored in the index cache, if any.
+    pub async fn get_artifact_metadata(&self, url: &str) -> Result<ArtifactMetadata> {
+        if let Some(metadata) = self.metadata.lock().unwrap().get(url) {
+            return Ok(*metadata);
         }
+
+        let key = format!("HEAD {}", url);
+        let text = cached(self.page_cache.as_ref(), &key, async {
+            Ok(serde_json::to_string(
+                &self.fetch_artifact_metadata(url).await?.to_json(),
+            )?)
+        })
+        .await?;
+        let metadata = ArtifactMetadata::from_json(&text)
+            .with_context(|| format!("parsing cached metadata of {}", url))?;
+
+        self.metadata
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), metadata);
+
+        Ok(metadata)
     }
 
-    /// Obtain records describing Apple software releases.
-    pub async fn get_releases(&self) -> Result<Vec<ReleaseRecord>> {
-        let res = self
-            .get_request(URL_MAIN)
+    async fn fetch_artifact_metadata(&self, url: &str) -> Result<ArtifactMetadata> {
+        self.service
+            .head(Request::new(self.source.as_ref(), url))
             .await
-            .context("fetching main releases URL")?;
-        let text = res.text().await?;
+    }
 
-        let re = Regex::new(r#"<a href="/release/(?P<entity>[^"]+)">(?P<version>[^<]+)</a>"#)?;
+    /// Obtain the sum of the sizes of multiple URLs.
+    ///
+    /// URLs whose size can't be determined are ignored.
+    pub async fn get_content_lengths(&self, urls: impl Iterator<Item = &str>) -> u64 {
+        let mut tasks = TaskSet::new();
 
-        let mut records = vec![];
+        for url in urls {
+            let downloader = self.clone();
+            let url = url.to_string();
 
-        for caps in re.captures_iter(&text) {
-            let version = &caps["version"];
+            tasks.spawn(url.clone(), async move {
+                downloader.get_content_length(&url).await
+            });
+        }
 
-            let url = format!("{}release/{}", URL_MAIN, &caps["entity"]);
+        tasks
+            .join()
+            .await
+            .into_iter()
+            .filter_map(|outcome| outcome.result.ok().flatten())
+            .sum()
+    }
 
-            let s = caps["entity"]
-                .strip_suffix(".html")
-                .ok_or_else(|| anyhow!("{} does not end in .html", &caps["entity"]))?;
+    /// Obtain records describing Apple software releases.
+    ///
+    /// Entity names are canonicalized and records of the same release page
+    /// are only returned once.
+    pub async fn get_releases(&self) -> Result<Vec<ReleaseRecord>> {
+        Ok(self
+            .entity_aliases
+            .normalize(self.source.releases().await?.releases))
+    }
 
-            // The version component is the part after the final hyphen. e.g.
-            // `iphone-sdkb8` or `developer-tools-91`.
-            let name = s
-                .rsplit_once('-')
-                .ok_or_else(|| anyhow!("{} does not contain a -", s))?
-                .0;
+    /// Obtain records of releases, including updates only linked from the
+    /// pages of other releases.
+    ///
+    /// This fetches the page of every release. Pages that can't be fetched are
+    /// reported and skipped.
+    pub async fn get_releases_with_updates(&self) -> Result<Vec<ReleaseRecord>> {
+        let releases = self.get_releases().await?;
+
+        let mut tasks = TaskSet::with_limit(8);
+        for record in &releases {
+            let downloader = self.clone();
+            let record = record.clone();
 
-            records.push(ReleaseRecord {
-                entity: name.to_string(),
-                version: version.to_string(),
-                url,
+            tasks.spawn(record.url.clone(), async move {
+                Ok(downloader.get_release_page(&record).await?.updates)
             });
         }
 
-        records.sort();
+        let mut records = releases;
+        for outcome in tasks.join().await {
+            match outcome.result {
+                Ok(updates) => records.extend(updates),
+                Err(e) => println!(
+                    "warning: unable to discover updates from {}: {:?}",
+                    outcome.label, e
+                ),
+            }
+        }
 
-        Ok(records)
+        Ok(self.entity_aliases.normalize(records))
+    }
+
+    /// Find the record for a specific version of a software release.
+    pub async fn find_release(&self, entity: &str, version: &str) -> Result<ReleaseRecord> {
+        self.get_releases()
+            .await?
+            .into_iter()
+            .find(|record| record.matches_entity(entity) && record.version == version)
+            .ok_or_else(|| anyhow!("failed to find version {} of {}", version, entity))
+    }
+
+    /// Obtain the page describing a given Apple software release.
+    pub async fn get_release_page(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        self.source.release(record).await
     }
 
     /// Obtain the software components in a given Apple software release.
+    ///
+    /// By default only component source tarballs are returned. If `all_artifacts`
+    /// is true, every link on the release page that looks like a downloadable
+    /// artifact (installer packages, disk images, etc) is returned as well.
     pub async fn get_release_components(
         &self,
         record: &ReleaseRecord,
+        all_artifacts: bool,
     ) -> Result<Vec<ReleaseComponentRecord>> {
-        let res = self
-            .get_request(&record.url)
-            .await
-            .context("fetching release components")?;
-        let text = res.text().await?;
-
-        let re = Regex::new(r#"<a href="/tarballs/(?P<path>[^"]+)">"#)?;
-
-        let mut records = vec![];
+        let page = self.get_release_page(record).await?;
 
-        for caps in re.captures_iter(&text) {
-            let path = &caps["path"];
+        let mut records = page.components;
 
-            let url = format!("{}tarballs/{}", URL_MAIN, path);
-
-            if let Some(s) = path.strip_suffix(".tar.gz") {
-                let component = s
-                    .split_once('/')
-                    .ok_or_else(|| anyhow!("{} does not have a /", s))?
-                    .0;
-
-                records.push(ReleaseComponentRecord {
-                    entity: record.entity.clone(),
-                    component: component.to_string(),
-                    url,
-                })
-            }
+        if all_artifacts {
+            records.extend(page.other_artifacts);
         }
 
         Ok(records)
@@ -207,99 +1053,171 @@ impl Downloader {
     ///
     /// Values are names of Apple's open sourced components. e.g. `hfs` and `AppleFileSystemDriver`.
     pub async fn get_components(&self) -> Result<BTreeSet<String>> {
-        let res = self
-            .get_request(URL_TARBALLS)
-            .await
-            .context("fetching component tarballs URL")?;
-
-        let text = res.text().await?;
-
-        // One does not use regular expressions to parse HTML. Meh.
-        let re = Regex::new(
-            r#"<tr><td valign="top"><a href="(?P<component>[^/]+)/"><img src="/static/images/icons/folder.png""#,
-        )?;
-
-        Ok(BTreeSet::from_iter(
-            re.captures_iter(&text)
-                .map(|caps| caps["component"].to_string()),
-        ))
+        Ok(self.source.components().await?.components)
     }
 
     /// Obtain the available versions of a component.
     ///
     /// This obtains records for each component version and doesn't fetch the archive itself.
     pub async fn get_component_versions(&self, component: &str) -> Result<Vec<ComponentRecord>> {
-        let url = format!("{}/{}/", URL_TARBALLS, component);
+        let mut versions = self.source.versions(component).await?.versions;
 
-        let res = self
-            .get_request(&url)
-            .await
-            .context("fetching versions of component")?;
-        let text = res.text().await?;
-
-        let re = Regex::new(
-            r#"<tr><td valign="top"><a href="?(?P<filename>[^">]+)"?><img src="?/static/images/icons/gz"#,
-        )?;
-
-        let mut records = vec![];
+        if self.source_trees {
+            let trees = match self.source.source_trees(component).await {
+                Ok(page) => page.versions,
+                // Most components don't have source trees.
+                Err(e) if is_not_found(&e) => vec![],
+                Err(e) => return Err(e.context("fetching source trees")),
+            };
 
-        for caps in re.captures_iter(&text) {
-            let filename = caps["filename"].to_string();
-            let url = format!("{}/{}/{}", URL_TARBALLS, component, filename);
+            for record in trees {
+                if !versions.iter().any(|v| v.version == record.version) {
+                    versions.push(record);
+                }
+            }
+            versions.sort();
+        }
 
-            // The version is the part after the first hyphen and before the .tar.gz.
+        Ok(versions)
+    }
 
-            if let Some(s) = filename.strip_suffix(".tar.gz") {
-                let version = s
-                    .split_once('-')
-                    .ok_or_else(|| anyhow!("filename does not contain -"))?
-                    .1
-                    .to_string();
+    /// Obtain the `.plist` manifests published alongside a component's tarballs.
+    pub async fn get_component_manifests(&self, component: &str) -> Result<Vec<ComponentManifest>> {
+        let mut manifests = vec![];
 
-                records.push(ComponentRecord {
-                    component: component.to_string(),
-                    filename,
-                    url,
-                    version,
-                });
-            }
+        for url in self.source.versions(component).await?.manifests {
+            let data = self.get_artifact(&url).await?;
+            manifests.push(ComponentManifest::parse(&url, &data)?);
         }
 
-        records.sort();
-
-        Ok(records)
+        Ok(manifests)
     }
 
     /// Obtain metadata about all versions of all components.
+    ///
+    /// Components without any published versions map to an empty list.
     pub async fn get_components_versions(&self) -> Result<BTreeMap<String, Vec<ComponentRecord>>> {
         let components = self.get_components().await.context("fetching components")?;
 
-        let mut res = BTreeMap::new();
+        let mut tasks = TaskSet::new();
 
-        for records in
-            futures::future::join_all(components.iter().map(|c| self.get_component_versions(c)))
-                .await
-        {
-            let records = records?;
+        for component in components {
+            let downloader = self.clone();
 
-            if let Some(record) = records.iter().next() {
-                res.insert(record.component.clone(), records);
+            tasks.spawn(component.clone(), async move {
+                let records = downloader.get_component_versions(&component).await?;
+
+                Ok((component, records))
+            });
+        }
+
+        Ok(tasks.try_join().await?.into_iter().collect())
+    }
+
+    /// Fill in the sizes and modification times of component records that
+    /// index pages didn't list, using `HEAD` requests.
+    ///
+    /// At most [METADATA_CONCURRENCY] requests are in flight at once. Records
+    /// whose metadata can't be obtained are left as is.
+    pub async fn enrich_component_records<'a>(
+        &self,
+        records: impl IntoIterator<Item = &'a mut ComponentRecord>,
+    ) {
+        let records = records
+            .into_iter()
+            .filter(|r| r.size.is_none() || r.last_modified.is_none())
+            .collect::<Vec<_>>();
+
+        let mut tasks = TaskSet::with_limit(METADATA_CONCURRENCY);
+        for record in &records {
+            let downloader = self.clone();
+            let url = record.url.clone();
+
+            tasks.spawn(url.clone(), async move {
+                downloader.get_artifact_metadata(&url).await
+            });
+        }
+
+        for (record, outcome) in records.into_iter().zip(tasks.join().await) {
+            match outcome.result {
+                Ok(metadata) => record.enrich(&metadata),
+                Err(e) => eprintln!("warning: fetching metadata of {}: {:#}", record.url, e),
             }
         }
+    }
 
-        Ok(res)
+    /// Obtain the content of an arbitrary artifact URL.
+    pub async fn get_artifact(&self, url: &str) -> Result<Vec<u8>> {
+        self.download(self.source.as_ref(), url).await
     }
 
     /// Get data for a given [ComponentRecord].
     ///
     /// This likely evaluates to a gzipped compressed tarball.
     pub async fn get_component_record(&self, record: &ComponentRecord) -> Result<Vec<u8>> {
-        let res = self
-            .get_request(&record.url)
+        self.get_component_record_from(self.source.as_ref(), record)
+            .await
+    }
+
+    /// Get data for a [ComponentRecord] advertised by a specific [Source].
+    ///
+    /// Source trees are snapshotted to a gzipped tarball.
+    pub async fn get_component_record_from(
+        &self,
+        source: &dyn Source,
+        record: &ComponentRecord,
+    ) -> Result<Vec<u8>> {
+        if record.is_source_tree() {
+            return self
+                .snapshot_source_tree(source, record)
+                .await
+                .with_context(|| format!("snapshotting source tree {}", record.url));
+        }
+
+        self.download(source, &record.url)
             .await
-            .context("fetching component tarball")?;
+            .context("fetching component tarball")
+    }
+
+    /// Download the files of a source tree, walking its directory listings.
+    async fn snapshot_source_tree(
+        &self,
+        source: &dyn Source,
+        record: &ComponentRecord,
+    ) -> Result<Vec<u8>> {
+        let mut files = vec![];
+        let mut pending = vec![String::new()];
+
+        while let Some(dir) = pending.pop() {
+            let dir_url = format!("{}{}", record.url, dir);
+            let listing = SourceListing::parse_html(&String::from_utf8_lossy(
+                &self.download(source, &dir_url).await?,
+            ));
+
+            pending.extend(listing.directories.iter().map(|d| format!("{}{}", dir, d)));
 
-        Ok(res.bytes().await?.to_vec())
+            let contents = futures::stream::iter(listing.files.iter())
+                .map(|name| {
+                    let url = file_url(&dir_url, name);
+                    async move { self.download(source, &url).await }
+                })
+                .buffered(SOURCE_TREE_CONCURRENCY)
+                .collect::<Vec<_>>()
+                .await;
+
+            for (name, data) in listing.files.iter().zip(contents) {
+                files.push((format!("{}{}", dir, name), data?));
+            }
+        }
+
+        files.sort();
+
+        let prefix = record
+            .filename
+            .strip_suffix(".tar.gz")
+            .unwrap_or(&record.filename);
+
+        source_tree::tarball(prefix, &files)
     }
 
     /// Obtain payload for a release component from its record.
@@ -307,15 +1225,6 @@ impl Downloader {
         &self,
         record: &ReleaseComponentRecord,
     ) -> Result<Vec<u8>> {
-        let res = self
-            .get_request(&record.url)
-            .await
-            .with_context(|| format!("fetching {}", record.url))?;
-
-        Ok(res
-            .bytes()
-            .await
-            .with_context(|| format!("reading response body from {}", record.url))?
-            .to_vec())
+        self.download(self.source.as_ref(), &record.url).await
     }
 }
@@ -0,0 +1,227 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Error types and process exit codes.
+
+use std::fmt::{Display, Formatter};
+
+/// Exit code when a command succeeds.
+pub const EXIT_SUCCESS: i32 = 0;
+
+/// Exit code when a command fails outright.
+pub const EXIT_FATAL: i32 = 1;
+
+/// Exit code when a command completes but some of its work failed.
+pub const EXIT_PARTIAL_FAILURE: i32 = 2;
+
+/// Exit code when the command line is invalid.
+pub const EXIT_USAGE: i32 = 3;
+
+/// Exit code when a command stops early because it was asked to shut down.
+pub const EXIT_INTERRUPTED: i32 = 4;
+
+/// Error indicating that some, but not all, units of work failed.
+///
+/// Details of individual failures are reported as they occur. This error
+/// conveys that the overall operation should be considered incomplete.
+#[derive(Clone, Debug)]
+pub struct PartialFailure {
+    /// What was being processed. e.g. `components`.
+    pub noun: &'static str,
+    /// Number of units that failed.
+    pub failed: usize,
+    /// Total number of units attempted.
+    pub total: usize,
+}
+
+impl Display for PartialFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} {} failed", self.failed, self.total, self.noun)
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+impl PartialFailure {
+    /// Obtain a result reflecting whether any units failed.
+    ///
+    /// If every unit failed, the error is fatal rather than partial.
+    pub fn check(noun: &'static str, failed: usize, total: usize) -> anyhow::Result<()> {
+        if failed == 0 {
+            Ok(())
+        } else if failed == total {
+            Err(anyhow::anyhow!("all {} {} failed", total, noun))
+        } else {
+            Err(Self {
+                noun,
+                failed,
+                total,
+            }
+            .into())
+        }
+    }
+}
+
+/// Error for an HTTP request that returned a non-success status.
+#[derive(Clone, Debug)]
+pub struct HttpStatusError {
+    pub status: u16,
+    pub url: String,
+}
+
+impl Display for HttpStatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {} from {}", self.status, self.url)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Error for a download whose size differs from its advertised `Content-Length`.
+#[derive(Clone, Debug)]
+pub struct IncompleteDownloadError {
+    pub url: String,
+    pub expected: u64,
+    pub received: u64,
+}
+
+impl Display for IncompleteDownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "received {} of {} bytes from {}",
+            self.received, self.expected, self.url
+        )
+    }
+}
+
+impl std::error::Error for IncompleteDownloadError {}
+
+/// Error for downloaded content failing an integrity check.
+#[derive(Clone, Debug)]
+pub struct CorruptDownloadError {
+    pub url: String,
+}
+
+impl Display for CorruptDownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "corrupt content downloaded from {}", self.url)
+    }
+}
+
+impl std::error::Error for CorruptDownloadError {}
+
+/// Error for content whose SHA-256 digest isn't the expected digest.
+#[derive(Clone, Debug)]
+pub struct DigestMismatchError {
+    pub url: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Display for DigestMismatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SHA-256 of {} is {}, expected {}",
+            self.url, self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for DigestMismatchError {}
+
+/// Error for a component without any published versions.
+///
+/// Some component directories only contain a `.plist` file or nothing at all.
+#[derive(Clone, Debug)]
+pub struct NoVersionsError {
+    pub component: String,
+}
+
+impl Display for NoVersionsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no versions of {} are available", self.component)
+    }
+}
+
+impl std::error::Error for NoVersionsError {}
+
+/// Error for a concurrent task that panicked.
+#[derive(Clone, Debug)]
+pub struct TaskPanicError {
+    pub task: String,
+    pub message: String,
+}
+
+impl Display for TaskPanicError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task {} panicked: {}", self.task, self.message)
+    }
+}
+
+impl std::error::Error for TaskPanicError {}
+
+/// Error for a concurrent task that was cancelled before completing.
+#[derive(Clone, Debug)]
+pub struct TaskCancelledError {
+    pub task: String,
+}
+
+impl Display for TaskCancelledError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "task {} was cancelled", self.task)
+    }
+}
+
+impl std::error::Error for TaskCancelledError {}
+
+/// Error for an import that stopped early because it was asked to shut down.
+///
+/// Work completed before stopping is kept, so importing again resumes.
+#[derive(Clone, Debug)]
+pub struct InterruptedError;
+
+impl Display for InterruptedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "interrupted before completing")
+    }
+}
+
+impl std::error::Error for InterruptedError {}
+
+/// Error context identifying the artifact being processed.
+///
+/// This is attached to errors so consumers can recover which component
+/// version failed.
+#[derive(Clone, Debug)]
+pub struct ArtifactContext {
+    pub component: String,
+    pub version: Option<String>,
+    pub url: String,
+}
+
+impl Display for ArtifactContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(version) = &self.version {
+            write!(
+                f,
+                "processing {} {} from {}",
+                self.component, version, self.url
+            )
+        } else {
+            write!(f, "processing {} from {}", self.component, self.url)
+        }
+    }
+}
+
+/// Resolve the process exit code for the result of a command.
+pub fn exit_code(res: &anyhow::Result<()>) -> i32 {
+    match res {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) if e.downcast_ref::<InterruptedError>().is_some() => EXIT_INTERRUPTED,
+        Err(e) if e.downcast_ref::<PartialFailure>().is_some() => EXIT_PARTIAL_FAILURE,
+        Err(_) => EXIT_FATAL,
+    }
+}
@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Export of listings in machine readable formats.
+//!
+//! Listings are tables of optional strings. They can be written as JSON (an
+//! array of objects), CSV with a header row or Parquet with a nullable string
+//! column per field, for loading into tools like pandas or DuckDB.
+
+use {
+    anyhow::{anyhow, Result},
+    parquet::{
+        basic::{Compression, LogicalType, Repetition, Type as PhysicalType},
+        data_type::{ByteArray, ByteArrayType},
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::types::Type,
+    },
+    std::{io::Write, str::FromStr, sync::Arc},
+};
+
+/// Output format of listing commands.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ExportFormat {
+    /// Human readable, tab separated lines.
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub const NAMES: &'static [&'static str] = &["text", "json", "csv", "parquet"];
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "parquet" => Ok(Self::Parquet),
+            _ => Err(anyhow!("unknown format: {}", s)),
+        }
+    }
+}
+
+/// Rows of optional string values under named columns.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Table {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+impl Table {
+    pub fn new(columns: &[&'static str]) -> Self {
+        Self {
+            columns: columns.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    /// Add a row. Panics if the number of values doesn't match the columns.
+    pub fn push(&mut self, row: Vec<Option<String>>) {
+        assert_eq!(row.len(), self.columns.len(), "row has wrong length");
+        self.rows.push(row);
+    }
+
+    /// Write the table in a format.
+    ///
+    /// The text format writes the values present in each row separated by
+    /// tabs.
+    pub fn write(&self, format: ExportFormat, writer: &mut impl Write) -> Result<()> {
+        match format {
+            ExportFormat::Text => self.write_text(writer),
+            ExportFormat::Json => self.write_json(writer),
+            ExportFormat::Csv => self.write_csv(writer),
+            ExportFormat::Parquet => {
+                let mut data = vec![];
+                self.write_parquet(&mut data)?;
+                writer.write_all(&data)?;
+
+                Ok(())
+            }
+        }
+    }
+
+    fn write_text(&self, writer: &mut impl Write) -> Result<()> {
+        for row in &self.rows {
+            let values = row.iter().flatten().map(|v| v.as_str()).collect::<Vec<_>>();
+            writeln!(writer, "{}", values.join("\t"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write an array of objects keyed by column.
+    pub fn write_json(&self, writer: &mut impl Write) -> Result<()> {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| (column.to_string(), serde_json::json!(value)))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::to_writer_pretty(&mut *writer, &rows)?;
+        writeln!(writer)?;
+
+        Ok(())
+    }
+
+    /// Write RFC 4180 CSV with a header row. Missing values are empty.
+    pub fn write_csv(&self, writer: &mut impl Write) -> Result<()> {
+        fn field(value: &str) -> String {
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.to_string()
+            }
+        }
+
+        let header = self.columns.iter().map(|c| field(c)).collect::<Vec<_>>();
+        write!(writer, "{}\r\n", header.join(","))?;
+
+        for row in &self.rows {
+            let values = row
+                .iter()
+                .map(|v| v.as_deref().map(field).unwrap_or_default())
+                .collect::<Vec<_>>();
+            write!(writer, "{}\r\n", values.join(","))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a Parquet file with a nullable string column per column.
+    pub fn write_parquet(&self, writer: &mut (impl Write + Send)) -> Result<()> {
+        let fields = self
+            .columns
+            .iter()
+            .map(|name| {
+                Ok(Arc::new(
+                    Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                        .with_repetition(Repetition::OPTIONAL)
+                        .with_logical_type(Some(LogicalType::String))
+                        .build()?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let schema = Arc::new(
+            Type::group_type_builder("schema")
+                .with_fields(fields)
+                .build()?,
+        );
+        let properties = Arc::new(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+        );
+
+        let mut file = SerializedFileWriter::new(writer, schema, properties)?;
+        let mut row_group = file.next_row_group()?;
+
+        for i in 0..self.columns.len() {
+            let values = self
+                .rows
+                .iter()
+                .filter_map(|row| row[i].as_deref().map(ByteArray::from))
+                .collect::<Vec<_>>();
+            let definition_levels = self
+                .rows
+                .iter()
+                .map(|row| i16::from(row[i].is_some()))
+                .collect::<Vec<_>>();
+
+            let mut column = row_group
+                .next_column()?
+                .expect("schema has a column for each column");
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&values, Some(&definition_levels), None)?;
+            column.close()?;
+        }
+
+        row_group.close()?;
+        file.close()?;
+
+        Ok(())
+    }
+}
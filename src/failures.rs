@@ -0,0 +1,187 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Machine-readable logging of failed operations.
+//!
+//! Failures are written as newline-delimited JSON so they can be inspected
+//! with standard tools and consumed by the `retry-failed` command.
+
+use {
+    crate::{
+        download::Downloader,
+        error::{ArtifactContext, HttpStatusError, PartialFailure},
+        git::{create_component_repository, create_release_repository, ImportOptions},
+        ndjson::NdjsonLog,
+    },
+    anyhow::{anyhow, Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// The kind of operation that failed.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// Importing a component into its own Git repository.
+    ComponentToGit,
+    /// Importing a component of a software release into a release repository.
+    ReleaseComponent,
+    /// Downloading an artifact to a file.
+    Download,
+}
+
+/// Describes a single failed operation.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FailureRecord {
+    pub operation: Operation,
+    pub component: String,
+    pub version: Option<String>,
+    pub url: Option<String>,
+    /// Name of the software release the component belongs to.
+    pub release: Option<String>,
+    /// Version of the software release the component belongs to.
+    pub release_version: Option<String>,
+    /// Destination repository or file.
+    pub dest: PathBuf,
+    /// Whether the destination repository is bare.
+    pub bare: Option<bool>,
+    /// HTTP status code, if the failure was due to an HTTP error.
+    pub http_status: Option<u16>,
+    /// The chain of error messages, outermost first.
+    pub errors: Vec<String>,
+}
+
+impl FailureRecord {
+    /// Construct an instance from an error.
+    ///
+    /// The version, URL, and HTTP status are resolved from the error's context
+    /// when available.
+    pub fn from_error(
+        operation: Operation,
+        component: &str,
+        dest: &Path,
+        err: &anyhow::Error,
+    ) -> Self {
+        let artifact = err.downcast_ref::<ArtifactContext>();
+
+        Self {
+            operation,
+            component: component.to_string(),
+            version: artifact.and_then(|a| a.version.clone()),
+            url: artifact.map(|a| a.url.clone()),
+            release: None,
+            release_version: None,
+            dest: dest.to_path_buf(),
+            bare: None,
+            http_status: err.downcast_ref::<HttpStatusError>().map(|e| e.status),
+            errors: err.chain().map(|e| e.to_string()).collect(),
+        }
+    }
+}
+
+/// An append-only log of failures.
+pub type FailureLog = NdjsonLog<FailureRecord>;
+
+async fn retry_failure(
+    downloader: &Downloader,
+    record: &FailureRecord,
+    options: &ImportOptions,
+) -> Result<()> {
+    match record.operation {
+        Operation::ComponentToGit => {
+            let options = ImportOptions {
+                bare: record.bare.unwrap_or(options.bare),
+                resume: true,
+                ..options.clone()
+            };
+
+            create_component_repository(downloader, &record.dest, &record.component, &options).await
+        }
+        Operation::Download => {
+            let url = record
+                .url
+                .as_ref()
+                .ok_or_else(|| anyhow!("failure record has no URL"))?;
+
+            let data = downloader.get_artifact(url).await?;
+            std::fs::write(&record.dest, data)
+                .with_context(|| format!("writing {}", record.dest.display()))?;
+            println!("wrote {}", record.dest.display());
+
+            Ok(())
+        }
+        Operation::ReleaseComponent => {
+            let release = record
+                .release
+                .as_ref()
+                .ok_or_else(|| anyhow!("failure record has no release"))?;
+
+            let options = ImportOptions {
+                bare: record.bare.unwrap_or(options.bare),
+                resume: true,
+                ..options.clone()
+            };
+
+            create_release_repository(downloader, &record.dest, release, &options).await
+        }
+    }
+}
+
+/// Re-attempt the operations described by failure records.
+///
+/// Repositories are appended to rather than recreated: component repositories
+/// resume after their last imported version and release repositories only
+/// download the components that previously failed. Since release repositories
+/// are processed as a whole, records for the same repository are retried once.
+///
+/// Operations that fail again are recorded in the failure log of `options`.
+pub async fn retry_failures(
+    downloader: &Downloader,
+    records: &[FailureRecord],
+    options: &ImportOptions,
+) -> Result<()> {
+    let mut pending = vec![];
+    let mut releases = BTreeMap::new();
+
+    for record in records {
+        if record.operation == Operation::ReleaseComponent {
+            releases
+                .entry((record.dest.clone(), record.release.clone()))
+                .or_insert(record);
+        } else {
+            pending.push(record);
+        }
+    }
+
+    pending.extend(releases.into_values());
+
+    let mut failures = 0;
+
+    for record in &pending {
+        println!(
+            "retrying {} {}",
+            record.component,
+            record.version.as_deref().unwrap_or_default()
+        );
+
+        if let Err(e) = retry_failure(downloader, record, options).await {
+            println!("error retrying {}: {:?}", record.component, e);
+
+            // Component imports record their own failures.
+            if record.operation == Operation::Download {
+                let mut failure = (*record).clone();
+                failure.http_status = e.downcast_ref::<HttpStatusError>().map(|e| e.status);
+                failure.errors = e.chain().map(|e| e.to_string()).collect();
+                options.record_failure(failure);
+            }
+
+            failures += 1;
+        }
+    }
+
+    PartialFailure::check("retries", failures, pending.len())
+}
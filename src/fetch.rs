@@ -0,0 +1,87 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Verified fetching of individual artifacts.
+//!
+//! External pipelines can use this crate to fetch Apple artifacts by URL or
+//! by SHA-256 digest, served from the artifact cache when possible and
+//! verified against the digest they expect.
+
+use {
+    crate::{
+        crypto::sha256_hex, download::Downloader, error::DigestMismatchError,
+        middleware::CacheLayer,
+    },
+    anyhow::{anyhow, Result},
+};
+
+/// Normalize a hexadecimal SHA-256 digest, rejecting malformed digests.
+pub fn parse_sha256(s: &str) -> Result<String> {
+    let digest = s.trim().to_ascii_lowercase();
+
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("{} is not a hexadecimal SHA-256 digest", s));
+    }
+
+    Ok(digest)
+}
+
+/// Error unless data has a SHA-256 digest.
+pub fn verify_sha256(url: &str, data: &[u8], expected: &str) -> Result<()> {
+    let actual = sha256_hex(data);
+
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(DigestMismatchError {
+            url: url.to_string(),
+            expected: expected.to_string(),
+            actual,
+        }
+        .into())
+    }
+}
+
+/// Fetch an artifact by URL, verifying its digest if one is given.
+pub async fn fetch_by_url(
+    downloader: &Downloader,
+    url: &str,
+    sha256: Option<&str>,
+) -> Result<Vec<u8>> {
+    let data = downloader.get_artifact(url).await?;
+
+    if let Some(expected) = sha256 {
+        verify_sha256(url, &data, expected)?;
+    }
+
+    Ok(data)
+}
+
+/// Fetch an artifact by SHA-256 digest.
+///
+/// The digest is resolved to a URL with the digest index of the cache and
+/// the artifact is fetched through the downloader, so it is downloaded again
+/// if it was evicted from the cache. Content cached before digests were
+/// indexed is found by reading every cache entry.
+///
+/// Returns the URL of the artifact, if known, with its content.
+pub async fn fetch_by_digest(
+    downloader: &Downloader,
+    cache: &CacheLayer,
+    sha256: &str,
+) -> Result<(Option<String>, Vec<u8>)> {
+    if let Some(url) = cache.url_for_digest(sha256)? {
+        let data = fetch_by_url(downloader, &url, Some(sha256)).await?;
+
+        return Ok((Some(url), data));
+    }
+
+    match cache.scan_for_digest(sha256)? {
+        Some(data) => Ok((None, data)),
+        None => Err(anyhow!(
+            "no artifact with SHA-256 {} is known; fetch it by URL first",
+            sha256
+        )),
+    }
+}
@@ -3,422 +3,2586 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::download::{Downloader, ReleaseComponentRecord},
+    crate::{
+        chronology::{
+            chronological_releases, interleave_releases, releases_since, OrderVerification,
+        },
+        crawler::{ComponentManifest, Source},
+        crypto::sha256_hex,
+        denylist::{AuditLog, AuditRecord, Denylist},
+        destination::DestinationTemplate,
+        disk::{DiskUsage, DiskUsageLog, ImportSize, SpaceReservation, SpaceReservations},
+        download::{
+            ComponentRecord, Downloader, ReleaseComponentRecord, ReleaseRecord, VersionEntry,
+        },
+        error::{
+            ArtifactContext, InterruptedError, NoVersionsError, PartialFailure, TaskPanicError,
+        },
+        failures::{FailureLog, FailureRecord, Operation},
+        hooks::{Hooks, ImportEvent},
+        identity::{CommitIdentity, SIGNATURE_EMAIL},
+        inventory::{InventoryLog, VersionInventory},
+        lock::ImportLock,
+        maintenance,
+        memory::{MemoryBudget, MemoryReservation},
+        mirror::{Provenance, RepositoryKind, RepositoryMetadata},
+        platform::stitch_aliases,
+        progress::{report, MultiTaskProgress},
+        report::ReleaseReport,
+        tasks::{CancelHandle, LocalTaskSet, TaskOutcome, TaskSet},
+        tree::{tar_data_to_tree, TreeConverter, TreeOptions, GIT_TREE_MODE},
+    },
     anyhow::{anyhow, Context, Result},
-    async_compression::tokio::bufread::GzipDecoder,
+    chrono::NaiveDate,
     futures::StreamExt,
-    git2::{Commit, Oid, Repository, RepositoryInitOptions, Signature, TreeBuilder},
-    std::{collections::HashMap, io::Cursor, path::Path, pin::Pin},
-    tokio::io::AsyncReadExt,
-    tokio_tar::Archive,
+    git2::{Commit, Delta, ObjectType, Odb, Oid, Repository, RepositoryInitOptions, Tree},
+    glob::Pattern,
+    indicatif::ProgressBar,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        path::{Path, PathBuf},
+        rc::Rc,
+        sync::Arc,
+    },
 };
 
-const GIT_TREE_MODE: i32 = 0o40000;
+/// Prefix of references to original archives stored in repositories.
+pub const ARCHIVE_REFS_PREFIX: &str = "refs/apple-oss/archives/";
 
-/// Write content in a tar archive to a Git repository.
+/// Prefix of references to the histories of the components of release
+/// repositories.
+pub const COMPONENT_REFS_PREFIX: &str = "refs/components/";
+
+/// Prefix of references in component repositories to the version shipped
+/// in a software release, named `<entity>-<version>`.
+pub const RELEASE_REFS_PREFIX: &str = "refs/releases/";
+
+/// Default number of repositories to create concurrently.
+pub const DEFAULT_JOBS: usize = 8;
+
+/// Default number of component versions to download and convert ahead of the
+/// version being committed.
+pub const DEFAULT_PREFETCH: usize = 4;
+
+/// File at the root of release commits holding the release page as Markdown.
+pub const RELEASE_NOTES_FILENAME: &str = "RELEASE_NOTES.md";
+
+/// File at the root of release commits holding the HTML of the release page.
+pub const RELEASE_HTML_FILENAME: &str = "RELEASE.html";
+
+/// File at the root of release commits describing the imported components.
+pub const RELEASE_MANIFEST_FILENAME: &str = "MANIFEST.json";
+
+/// A component included in a release commit.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ManifestComponent {
+    pub component: String,
+    pub version: Option<String>,
+    pub url: String,
+    /// SHA-256 of the archive, if known.
+    pub sha256: Option<String>,
+}
+
+/// A component of a release that couldn't be imported.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ManifestFailure {
+    pub component: String,
+    pub version: Option<String>,
+    pub url: String,
+    pub error: String,
+}
+
+/// The content of [RELEASE_MANIFEST_FILENAME], making each release commit
+/// self-describing.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ReleaseManifest {
+    pub entity: String,
+    pub version: String,
+    /// URL of the release page.
+    pub url: String,
+    /// When the release was published, as `YYYY-MM-DD`.
+    pub date: Option<String>,
+    /// Components in the commit, sorted by name.
+    pub components: Vec<ManifestComponent>,
+    /// Components missing from the commit because they failed to import.
+    pub failed: Vec<ManifestFailure>,
+}
+
+impl ReleaseManifest {
+    fn new(record: &ReleaseRecord) -> Self {
+        Self {
+            entity: record.entity.clone(),
+            version: record.version.clone(),
+            url: record.url.clone(),
+            date: record.date.map(|date| date.to_string()),
+            components: vec![],
+            failed: vec![],
+        }
+    }
+
+    /// Read the manifest of a release commit, if it has one.
+    pub fn from_commit(repo: &Repository, commit: &Commit) -> Result<Option<Self>> {
+        let tree = commit.tree()?;
+        let entry = match tree.get_name(RELEASE_MANIFEST_FILENAME) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let blob = repo.find_blob(entry.id())?;
+
+        Ok(Some(
+            serde_json::from_slice(blob.content()).context("parsing release manifest")?,
+        ))
+    }
+
+    /// The message of the release commit, listing skipped components.
+    fn commit_message(&self) -> String {
+        let mut message = format!("{} {}", self.entity, self.version);
+
+        if !self.failed.is_empty() {
+            message.push_str("\n\nSkipped components that failed to download:\n\n");
+
+            for failure in &self.failed {
+                message.push_str(&format!("  {} {}\n", failure.component, failure.url));
+            }
+        }
+
+        message
+    }
+}
+
+/// Append trailers as the last paragraph of a commit message.
+fn append_trailers(message: &mut String, trailers: &str) {
+    let len = message.trim_end().len();
+    message.truncate(len);
+    message.push_str("\n\n");
+    message.push_str(trailers);
+}
+
+/// How to handle a component version whose tree is identical to the previous version.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IdenticalVersionPolicy {
+    /// Create a commit without changes.
+    #[default]
+    Commit,
+    /// Don't create a commit and tag the previous version's commit.
+    Tag,
+    /// Create a commit without changes whose message notes the identical version.
+    Annotate,
+}
+
+/// How to handle components of a software release that fail to download.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MissingComponentPolicy {
+    /// Commit the release without the component, noting it in the commit
+    /// message and [RELEASE_MANIFEST_FILENAME].
+    #[default]
+    Skip,
+    /// Fail the import.
+    Fail,
+    /// Commit the release with a directory for the component containing
+    /// [MISSING_FILENAME], explaining why it is missing.
+    ///
+    /// This keeps the layout of release trees stable across commits.
+    Placeholder,
+}
+
+/// Name of the file explaining why a release component is missing.
+pub const MISSING_FILENAME: &str = "MISSING.txt";
+
+/// Options controlling how content is imported into Git repositories.
+#[derive(Clone, Default)]
+pub struct ImportOptions {
+    /// Whether to create bare repositories.
+    pub bare: bool,
+    /// Hooks receiving events as content is imported.
+    pub hooks: Hooks,
+    /// Whether to verify there is sufficient disk space before downloading.
+    pub check_disk_space: bool,
+    /// Abort once a repository uses more than this many bytes on disk.
+    pub max_disk_usage: Option<u64>,
+    /// Ratio of disk space used to archive size to estimate required space
+    /// with.
+    ///
+    /// Defaults to [crate::disk::DEFAULT_EXPANSION_RATIO].
+    pub expansion_ratio: Option<f64>,
+    /// Log receiving the disk space used by completed imports.
+    pub disk_usage_log: Option<Arc<DiskUsageLog>>,
+    /// Disk space reserved by imports sharing it.
+    pub space_reservations: SpaceReservations,
+    /// Limits the total size of archives buffered at once, across all
+    /// imports sharing it.
+    pub memory_budget: Option<MemoryBudget>,
+    /// Maximum number of repositories to create concurrently.
+    ///
+    /// Defaults to [DEFAULT_JOBS].
+    pub jobs: Option<usize>,
+    /// Number of component versions to download and convert ahead of the
+    /// version being committed.
+    ///
+    /// Defaults to [DEFAULT_PREFETCH].
+    pub prefetch: Option<usize>,
+    /// Log receiving records of failed imports.
+    pub failure_log: Option<Arc<FailureLog>>,
+    /// Report receiving the classified files of every imported version.
+    pub content_report: Option<Arc<InventoryLog>>,
+    /// Rules excluding components and paths from imports.
+    pub denylist: Option<Arc<Denylist>>,
+    /// Log receiving records of content excluded by [Self::denylist].
+    pub deny_audit: Option<Arc<AuditLog>>,
+    /// Reuse content already present in existing repositories.
+    ///
+    /// Component versions that are already tagged are not imported again and
+    /// release components already present in tagged release commits are not
+    /// downloaded again.
+    pub resume: bool,
+    /// Converts downloaded archives to Git trees.
+    pub converter: TreeConverter,
+    /// Additional sources whose versions of a component are merged into
+    /// component repositories.
+    ///
+    /// Versions already provided by the primary source are ignored. This allows
+    /// continuing history past the versions the primary source knows about.
+    pub history_sources: Vec<Arc<dyn Source>>,
+    /// Store original archives as blobs referenced under [ARCHIVE_REFS_PREFIX].
+    pub archive_refs: bool,
+    /// Maintain a history of each component of release repositories under
+    /// [COMPONENT_REFS_PREFIX].
+    pub component_refs: bool,
+    /// Directory receiving a Markdown [ReleaseReport] for every imported
+    /// software release.
+    pub release_reports: Option<PathBuf>,
+    /// Reference the version of a component shipped in each software release
+    /// under [RELEASE_REFS_PREFIX] in component repositories.
+    pub release_refs: bool,
+    /// Import exactly these versions of a component, in this order, instead
+    /// of the discovered versions in version order.
+    ///
+    /// Versions with a URL don't need to be discovered.
+    pub versions: Option<Vec<VersionEntry>>,
+    /// Stops imports when cancelled, e.g. on `SIGTERM`.
+    ///
+    /// Imports stop before the next component version or release, keeping
+    /// what was committed so far, and fail with [InterruptedError].
+    pub shutdown: CancelHandle,
+    /// Only import components whose name matches one of these patterns.
+    ///
+    /// This applies to the components of software releases and to
+    /// [create_components_repositories]. All components are imported if empty.
+    pub components: Vec<Pattern>,
+    /// Import into destinations that are neither empty nor repositories
+    /// previously created by an import.
+    pub force: bool,
+    /// How to handle component versions identical to the previous version.
+    pub identical_versions: IdenticalVersionPolicy,
+    /// How to handle components of software releases that fail to download.
+    pub missing_components: MissingComponentPolicy,
+    /// How to handle software releases whose chronological order is ambiguous.
+    pub release_order: OrderVerification,
+    /// Derives the author and date of imported commits.
+    pub identity: CommitIdentity,
+    /// Only import software releases published on or after this date.
+    ///
+    /// Releases without a known date are selected by [releases_since].
+    pub since: Option<NaiveDate>,
+    /// Also import point and security updates only linked from the pages of
+    /// other releases.
+    pub include_updates: bool,
+    /// Resolves the repository paths of [create_components_repositories].
+    pub destination: DestinationTemplate,
+    /// URL repositories can be cloned from, written to their `cloneurl`
+    /// file. `{name}` is replaced with the component or release entity name.
+    pub clone_url: Option<String>,
+    /// End commit messages with trailers naming the version of this tool and
+    /// a hash of the [configuration](Self::configuration) producing them.
+    pub importer_trailers: bool,
+    /// Repository whose original archives stored under [ARCHIVE_REFS_PREFIX]
+    /// are used instead of downloading component versions.
+    pub archives_from: Option<PathBuf>,
+    /// Pack objects and write a multi-pack-index and commit-graph once a
+    /// repository is imported. Requires `git`.
+    pub optimize: bool,
+    /// Allow clones of imported repositories to use partial clone filters.
+    pub partial_clone: bool,
+}
+
+impl ImportOptions {
+    /// Describe the options affecting the content of imported commits.
+    ///
+    /// Options affecting only how an import runs, such as concurrency, are
+    /// excluded. Each line is a `key=value` pair.
+    pub fn configuration(&self) -> String {
+        let tree = self.converter.options();
+
+        [
+            ("duplicates", format!("{:?}", tree.duplicates)),
+            ("apple_metadata", format!("{:?}", tree.apple_metadata)),
+            ("symlinks", format!("{:?}", tree.symlinks)),
+            (
+                "mode_overrides",
+                tree.mode_overrides
+                    .iter()
+                    .map(|o| format!("{}:{:o}", o.pattern.as_str(), o.mode))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            ("strip_components", tree.strip_components.to_string()),
+            (
+                "denylist",
+                self.denylist
+                    .iter()
+                    .flat_map(|denylist| denylist.rules())
+                    .map(|rule| rule.describe())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                "exclude",
+                tree.exclude
+                    .iter()
+                    .map(|p| p.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                "identical_versions",
+                format!("{:?}", self.identical_versions),
+            ),
+            (
+                "missing_components",
+                format!("{:?}", self.missing_components),
+            ),
+            (
+                "commit_date",
+                self.identity
+                    .date()
+                    .map(|d| d.to_string())
+                    .unwrap_or_default(),
+            ),
+            (
+                "since",
+                self.since.map(|d| d.to_string()).unwrap_or_default(),
+            ),
+            ("include_updates", self.include_updates.to_string()),
+            (
+                "components",
+                self.components
+                    .iter()
+                    .map(|p| p.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", key, value))
+        .collect()
+    }
+
+    /// The trailers ending commit messages, if enabled.
+    ///
+    /// The configuration hash is the first 16 hex characters of the SHA-256
+    /// of [Self::configuration].
+    pub fn trailers(&self) -> Option<String> {
+        if !self.importer_trailers {
+            return None;
+        }
+
+        Some(format!(
+            "Importer-Version: {}\nImporter-Config: {}\n",
+            env!("CARGO_PKG_VERSION"),
+            &sha256_hex(self.configuration().as_bytes())[0..16]
+        ))
+    }
+
+    /// Verify there is room to import archives at the given URLs into `path`.
+    ///
+    /// The estimated space is reserved until the returned value is dropped.
+    async fn preflight(
+        &self,
+        downloader: &Downloader,
+        path: &Path,
+        urls: impl Iterator<Item = &str>,
+    ) -> Result<Option<SpaceReservation>> {
+        if !self.check_disk_space {
+            return Ok(None);
+        }
+
+        let archive_bytes = downloader.get_content_lengths(urls).await;
+
+        Ok(Some(crate::disk::preflight(
+            path,
+            archive_bytes,
+            self.expansion_ratio
+                .unwrap_or(crate::disk::DEFAULT_EXPANSION_RATIO),
+            self.max_disk_usage,
+            &self.space_reservations,
+        )?))
+    }
+
+    /// Whether a component of a software release should be imported.
+    fn includes_component(&self, component: &str) -> bool {
+        self.components.is_empty() || self.components.iter().any(|p| p.matches(component))
+    }
+
+    /// Describe a repository for web front-ends in its Git directory and
+    /// record its provenance in its config.
+    fn write_metadata(
+        &self,
+        repo: &Repository,
+        downloader: &Downloader,
+        kind: RepositoryKind,
+        name: &str,
+        last_version: Option<&str>,
+    ) -> Result<()> {
+        let clone_url = self
+            .clone_url
+            .as_ref()
+            .map(|url| url.replace("{name}", name));
+
+        RepositoryMetadata::from_repository(kind, name, repo)?
+            .write(repo, clone_url.as_deref())
+            .context("writing repository metadata")?;
+
+        Provenance::new(&downloader.source_name(), kind, name, last_version)
+            .write(repo)
+            .context("writing repository provenance")
+    }
+
+    /// Record a failure to the failure log, if configured.
+    pub fn record_failure(&self, record: FailureRecord) {
+        if let Some(log) = &self.failure_log {
+            if let Err(e) = log.record(&record) {
+                println!("warning: {:?}", e);
+            }
+        }
+    }
+
+    fn record_component_failure(&self, component: &str, path: &Path, err: &anyhow::Error) {
+        let mut record = FailureRecord::from_error(Operation::ComponentToGit, component, path, err);
+        record.bare = Some(self.bare);
+
+        self.record_failure(record);
+    }
+
+    /// Record content excluded by the denylist.
+    fn record_denial(&self, record: AuditRecord) {
+        println!(
+            "excluding {}{}{}: {}",
+            record.component,
+            record
+                .version
+                .as_deref()
+                .map(|v| format!(" {}", v))
+                .unwrap_or_default(),
+            record
+                .path
+                .as_deref()
+                .map(|p| format!(" {}", p))
+                .unwrap_or_default(),
+            record.reason
+        );
+
+        if let Some(log) = &self.deny_audit {
+            if let Err(e) = log.record(&record) {
+                println!("warning: {:?}", e);
+            }
+        }
+    }
+
+    /// Whether the denylist excludes an entire component.
+    ///
+    /// Exclusions are recorded.
+    fn denies_component(&self, component: &str, version: Option<&str>) -> bool {
+        let reason = match self
+            .denylist
+            .as_ref()
+            .and_then(|denylist| denylist.component_reason(component))
+        {
+            Some(reason) => reason,
+            None => return false,
+        };
+
+        self.record_denial(AuditRecord {
+            component: component.to_string(),
+            version: version.map(|v| v.to_string()),
+            path: None,
+            reason: reason.to_string(),
+        });
+
+        true
+    }
+
+    /// Error if the denylist excludes an entire component.
+    fn check_component_allowed(&self, component: &str) -> Result<()> {
+        if self.denies_component(component, None) {
+            Err(anyhow!(
+                "component {} is excluded by the denylist",
+                component
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Convert an archive of a component to a tree, excluding paths denied
+    /// for the component.
+    async fn convert(
+        &self,
+        repo: &Repository,
+        component: &str,
+        version: Option<&str>,
+        tar_data: Vec<u8>,
+    ) -> Result<Oid> {
+        let deny = self
+            .denylist
+            .as_ref()
+            .map(|denylist| denylist.path_patterns(component))
+            .unwrap_or_default();
+
+        let conversion = self.converter.convert_denying(tar_data, repo, deny).await?;
+
+        for member in conversion.denied {
+            self.record_denial(AuditRecord {
+                component: component.to_string(),
+                version: version.map(|v| v.to_string()),
+                path: Some(member.path),
+                reason: member.reason,
+            });
+        }
+
+        Ok(conversion.tree)
+    }
+
+    /// Record the classified files of an imported version to the content
+    /// report, if configured.
+    fn report_contents(
+        &self,
+        repo: &Repository,
+        component: &str,
+        version: Option<&str>,
+        tree_oid: Oid,
+    ) -> Result<()> {
+        if let Some(log) = &self.content_report {
+            let inventory = VersionInventory::from_tree(repo, tree_oid, component, version)
+                .with_context(|| format!("classifying files of {}", component))?;
+            log.record(&inventory)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the Markdown report of a release commit, if enabled.
+    ///
+    /// Reports are named after the tag of the release, with `/` replaced by
+    /// `-`.
+    fn write_release_report(
+        &self,
+        repo: &Repository,
+        commit: &Commit,
+        previous: Option<&Commit>,
+        tag: &str,
+    ) -> Result<()> {
+        if let Some(dir) = &self.release_reports {
+            let report = ReleaseReport::from_commits(repo, commit, previous)?;
+
+            std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+            let path = dir.join(format!("{}.md", tag.replace('/', "-")));
+            std::fs::write(&path, report.to_markdown())
+                .with_context(|| format!("writing {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Store an original archive in the repository, if enabled.
+    ///
+    /// The archive is written as a blob referenced by `<ARCHIVE_REFS_PREFIX><name>`
+    /// so the exact archive can be recovered from the repository.
+    fn store_archive(&self, repo: &Repository, name: &str, data: &[u8]) -> Result<()> {
+        if self.archive_refs {
+            let blob_oid = repo.blob(data).context("writing archive to blob")?;
+            repo.reference(
+                &format!("{}{}", ARCHIVE_REFS_PREFIX, name),
+                blob_oid,
+                true,
+                "storing original archive",
+            )
+            .with_context(|| format!("creating archive reference for {}", name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Prepare an imported repository for serving clones, as configured.
+    pub(crate) fn optimize_repository(&self, repo: &Repository) -> Result<()> {
+        if self.partial_clone {
+            maintenance::configure_partial_clone(repo)
+                .context("configuring partial clone support")?;
+        }
+
+        if self.optimize {
+            maintenance::optimize(repo.path())?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for memory to buffer the archive at `url` within the memory
+    /// budget, if any.
+    ///
+    /// The archive's size is looked up if `size` isn't known.
+    async fn reserve_memory(
+        &self,
+        downloader: &Downloader,
+        url: &str,
+        size: Option<u64>,
+    ) -> Option<MemoryReservation> {
+        let budget = self.memory_budget.as_ref()?;
+        let size = match size {
+            Some(size) => Some(size),
+            None => downloader.get_content_length(url).await.ok().flatten(),
+        };
+
+        Some(budget.reserve(size).await)
+    }
+
+    /// Start tracking the disk space used by the repository at `path`, if
+    /// it is limited or logged.
+    fn track_disk_usage(&self, repo: &Repository, path: &Path) -> Result<Option<DiskUsage>> {
+        if self.max_disk_usage.is_none() && self.disk_usage_log.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(DiskUsage::new(repo, path)?))
+    }
+
+    /// Account for a new commit, erroring if the repository now exceeds the
+    /// disk usage limit.
+    fn add_commit_disk_usage(
+        &self,
+        usage: Option<&mut DiskUsage>,
+        repo: &Repository,
+        commit: &Commit,
+    ) -> Result<()> {
+        if let Some(usage) = usage {
+            usage.add_commit(repo, commit)?;
+
+            if let Some(max) = self.max_disk_usage {
+                usage.enforce(max)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record the disk space a completed import into `path` used.
+    ///
+    /// Nothing is recorded if the size of the imported archives is unknown.
+    fn record_disk_usage(
+        &self,
+        path: &Path,
+        usage: Option<&DiskUsage>,
+        archive_bytes: Option<u64>,
+    ) -> Result<()> {
+        if let (Some(log), Some(usage), Some(archive_bytes)) =
+            (&self.disk_usage_log, usage, archive_bytes)
+        {
+            log.record(&ImportSize {
+                repository: path.to_path_buf(),
+                archive_bytes,
+                disk_bytes: crate::disk::directory_size(path)?.saturating_sub(usage.initial()),
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read an original archive stored by [ImportOptions::archive_refs].
+pub fn stored_archive(repo_path: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    let repo = Repository::open(repo_path)
+        .with_context(|| format!("opening repository {}", repo_path.display()))?;
+
+    let reference = match repo.find_reference(&format!("{}{}", ARCHIVE_REFS_PREFIX, name)) {
+        Ok(reference) => reference,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let data = reference.peel_to_blob()?.content().to_vec();
+
+    Ok(Some(data))
+}
+
+/// Compute the files that differ between two tar archives.
+///
+/// Archives are converted to Git trees in an in-memory object database and
+/// diffed on the blocking thread pool. Returns the change type and path of
+/// each differing file.
+pub async fn diff_tar_data(old: Vec<u8>, new: Vec<u8>) -> Result<Vec<(Delta, String)>> {
+    tokio::task::spawn_blocking(move || diff_tar_data_blocking(&old, &new))
+        .await
+        .context("joining archive diff task")?
+}
+
+fn diff_tar_data_blocking(old: &[u8], new: &[u8]) -> Result<Vec<(Delta, String)>> {
+    let odb = Odb::new()?;
+    odb.add_new_mempack_backend(1)?;
+    let repo = Repository::from_odb(odb).context("creating in-memory repository")?;
+
+    let options = TreeOptions::default();
+    let old_tree = repo.find_tree(tar_data_to_tree(old, &repo, &options)?)?;
+    let new_tree = repo.find_tree(tar_data_to_tree(new, &repo, &options)?)?;
+
+    let diff = repo
+        .diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)
+        .context("diffing trees")?;
+
+    Ok(diff
+        .deltas()
+        .map(|delta| {
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+
+            (delta.status(), path)
+        })
+        .collect())
+}
+
+/// Resolve the commit a tag points to, if the tag exists.
+pub fn find_tag_commit<'repo>(repo: &'repo Repository, tag: &str) -> Result<Option<Commit<'repo>>> {
+    match repo.find_reference(&format!("refs/tags/{}", tag)) {
+        Ok(reference) => {
+            Ok(Some(reference.peel_to_commit().with_context(|| {
+                format!("resolving commit for tag {}", tag)
+            })?))
+        }
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Verify a destination is suitable for importing into.
+///
+/// Nonexistent and empty directories are suitable, as are repositories whose
+/// head was created by an import. Anything else is only used if `force` is
+/// set, so unrelated files and repositories aren't clobbered.
+fn check_destination(path: &Path, force: bool) -> Result<()> {
+    if force {
+        return Ok(());
+    }
+
+    let mut entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+
+    if entries.next().is_none() {
+        return Ok(());
+    }
+
+    let repo = Repository::open(path).map_err(|_| {
+        anyhow!(
+            "{} is not empty and is not a Git repository; use --force to import into it anyway",
+            path.display()
+        )
+    })?;
+
+    let head = match repo.head() {
+        Ok(head) => head.peel_to_commit()?,
+        Err(e)
+            if e.code() == git2::ErrorCode::UnbornBranch
+                || e.code() == git2::ErrorCode::NotFound =>
+        {
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if head.author().email() == Some(SIGNATURE_EMAIL) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} contains a Git repository not created by an import; use --force to import into it anyway",
+            path.display()
+        ))
+    }
+}
+
+/// Resolve the commit of every tag in a repository.
+///
+/// For component repositories, this maps each imported version to its commit.
+pub fn tag_commits(repo_path: &Path) -> Result<BTreeMap<String, String>> {
+    let repo = Repository::open(repo_path)
+        .with_context(|| format!("opening repository {}", repo_path.display()))?;
+
+    let mut commits = BTreeMap::new();
+
+    for tag in repo.tag_names(None)?.iter().flatten() {
+        if let Some(commit) = find_tag_commit(&repo, tag)? {
+            commits.insert(tag.to_string(), commit.id().to_string());
+        }
+    }
+
+    Ok(commits)
+}
+
+/// Write the files in a tree to a directory.
+///
+/// Returns the number of files written.
+fn write_tree_to_directory(repo: &Repository, tree: &Tree, dest: &Path) -> Result<usize> {
+    std::fs::create_dir_all(dest).with_context(|| format!("creating {}", dest.display()))?;
+
+    let mut count = 0;
+
+    for entry in tree.iter() {
+        let name = entry
+            .name()
+            .ok_or_else(|| anyhow!("tree entry name is not valid UTF-8"))?;
+        let path = dest.join(name);
+
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                let subtree = repo.find_tree(entry.id())?;
+                count += write_tree_to_directory(repo, &subtree, &path)?;
+            }
+            Some(ObjectType::Blob) => {
+                let blob = repo.find_blob(entry.id())?;
+
+                if entry.filemode() == 0o120000 {
+                    write_symlink(blob.content(), &path)?;
+                } else {
+                    std::fs::write(&path, blob.content())
+                        .with_context(|| format!("writing {}", path.display()))?;
+                    set_executable(&path, entry.filemode() == 0o100755)?;
+                }
+
+                count += 1;
+            }
+            // Submodules have no content to write.
+            Some(ObjectType::Commit) => {
+                std::fs::create_dir_all(&path)?;
+            }
+            _ => {
+                return Err(anyhow!("unexpected tree entry {}", path.display()));
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(unix)]
+fn write_symlink(target: &[u8], path: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), path)
+        .with_context(|| format!("creating symlink {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn write_symlink(target: &[u8], path: &Path) -> Result<()> {
+    // Mirror Git's behavior of writing the link target to a regular file.
+    std::fs::write(path, target).with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path, executable: bool) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = if executable { 0o755 } else { 0o644 };
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("setting permissions of {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path, _executable: bool) -> Result<()> {
+    Ok(())
+}
+
+/// Materialize the files of a revision of a repository in a directory.
+///
+/// Files are written directly from the revision's Git tree, so the index and
+/// working directory of the repository are not used or modified. `dest` must
+/// not exist or be empty.
+///
+/// Returns the number of files written.
+pub fn export_tree(repo_path: &Path, revision: &str, dest: &Path) -> Result<usize> {
+    let repo = Repository::open(repo_path)
+        .with_context(|| format!("opening repository {}", repo_path.display()))?;
+
+    let tree = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| format!("resolving tree of {}", revision))?;
+
+    if dest.exists()
+        && std::fs::read_dir(dest)
+            .with_context(|| format!("reading {}", dest.display()))?
+            .next()
+            .is_some()
+    {
+        return Err(anyhow!("{} is not empty", dest.display()));
+    }
+
+    write_tree_to_directory(&repo, &tree, dest)
+}
+
+pub fn reconcile_repo_to_commit(
+    repo: &Repository,
+    branch_name: &str,
+    commit: &Commit,
+) -> Result<()> {
+    if repo.is_bare() {
+        repo.branch(branch_name, commit, true)
+            .context("updating branch to commit")?;
+    } else {
+        repo.set_head_detached(commit.id())
+            .context("marking head as detached")?;
+        repo.branch(branch_name, commit, true)
+            .context("updating branch to commit")?;
+        repo.set_head(&format!("refs/heads/{}", branch_name))
+            .context("setting head to branch")?;
+        repo.reset(commit.as_object(), git2::ResetType::Hard, None)
+            .context("resetting working directory")?;
+    }
+
+    Ok(())
+}
+
+/// Create a Git repository for an Apple opensource component.
+///
+/// The Git repository will have tags corresponding to the versions of the component.
+pub async fn create_component_repository(
+    downloader: &Downloader,
+    path: impl AsRef<Path>,
+    component: &str,
+    options: &ImportOptions,
+) -> Result<()> {
+    let path = path.as_ref();
+
+    let res = import_component_repository(downloader, path, component, options, None).await;
+
+    match &res {
+        Err(e) if !has_no_versions(e) => options.record_component_failure(component, path, e),
+        _ => {}
+    }
+
+    res
+}
+
+/// Append a single component version to a branch of an existing repository.
+///
+/// The version is committed on top of the branch's head, or as a root commit
+/// if the branch doesn't exist yet, and tagged. Returns the tagged commit.
+pub async fn append_component_version(
+    downloader: &Downloader,
+    path: &Path,
+    component: &str,
+    version: &str,
+    branch_name: &str,
+    options: &ImportOptions,
+) -> Result<Oid> {
+    options.check_component_allowed(component)?;
+
+    let _lock = ImportLock::acquire(path)?;
+    let repo =
+        Repository::open(path).with_context(|| format!("opening repository {}", path.display()))?;
+
+    if find_tag_commit(&repo, version)?.is_some() {
+        return Err(anyhow!("version {} is already tagged", version));
+    }
+
+    let parent = match repo.find_branch(branch_name, git2::BranchType::Local) {
+        Ok(branch) => Some(branch.get().peel_to_commit()?),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let parent_version = match &parent {
+        Some(parent) => tag_commits(path)?
+            .into_iter()
+            .find(|(_, commit)| *commit == parent.id().to_string())
+            .map(|(tag, _)| tag),
+        None => None,
+    };
+
+    let record = downloader
+        .get_component_versions(component)
+        .await
+        .context("fetching component versions")?
+        .into_iter()
+        .find(|record| record.version == version)
+        .ok_or_else(|| anyhow!("failed to find version {} of {}", version, component))?;
+
+    let artifact = || ArtifactContext {
+        component: record.component.clone(),
+        version: Some(record.version.clone()),
+        url: record.url.clone(),
+    };
+
+    let _space = options
+        .preflight(downloader, path, std::iter::once(record.url.as_str()))
+        .await?;
+
+    let tar_data = downloader
+        .get_component_record(&record)
+        .await
+        .with_context(artifact)?;
+
+    options.store_archive(&repo, &record.version, &tar_data)?;
+
+    let tree_oid = options
+        .convert(&repo, component, Some(&record.version), tar_data)
+        .await
+        .with_context(artifact)?;
+    options.report_contents(&repo, component, Some(&record.version), tree_oid)?;
+
+    let commit = commit_component_version(
+        &repo,
+        path,
+        &record,
+        tree_oid,
+        parent
+            .as_ref()
+            .map(|commit| (commit, parent_version.as_deref())),
+        component_manifests(downloader, component, None)
+            .await
+            .get(version),
+        options.identical_versions,
+        options,
+        None,
+    )
+    .await?;
+
+    let commit = match (commit, parent) {
+        (Some(commit), _) | (None, Some(commit)) => commit,
+        (None, None) => unreachable!("identical versions require a parent"),
+    };
+
+    if options.release_refs {
+        update_release_refs(downloader, &repo, component, options).await?;
+    }
+
+    reconcile_repo_to_commit(&repo, branch_name, &commit)?;
+    options.write_metadata(
+        &repo,
+        downloader,
+        RepositoryKind::Component,
+        component,
+        Some(version),
+    )?;
+    options.optimize_repository(&repo)?;
+
+    options
+        .hooks
+        .emit(ImportEvent::RepositoryComplete {
+            repository: path.to_path_buf(),
+            branch: branch_name.to_string(),
+            head: Some(commit.id().to_string()),
+        })
+        .await;
+
+    Ok(commit.id())
+}
+
+/// Prefix of references holding rebuilt history while it is compared.
+const REIMPORT_REFS_PREFIX: &str = "refs/apple-oss/reimport/";
+
+/// How a rebuilt component history relates to the existing history.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ReimportOutcome {
+    /// The rebuilt history is the existing history.
+    Identical,
+    /// The rebuilt history extends the existing history with new versions.
+    FastForward { added: Vec<String> },
+    /// Versions were committed differently.
+    Diverged {
+        /// Versions whose commits differ, in version order.
+        changed: Vec<String>,
+        /// The branch the rebuilt history was preserved on, if rewritten.
+        branch: Option<String>,
+    },
+}
+
+/// Rebuild a component repository with the current import logic and compare
+/// it with its existing history.
+///
+/// The component is taken from the repository's [Provenance]. Original
+/// archives stored in the repository are used instead of downloading them
+/// again, and other archives come from the downloader, including its cache.
+/// The history is rebuilt in a scratch repository in the Git directory.
 ///
-/// Returns the Git tree Oid.
-pub async fn tar_data_to_tree(tar_data: &[u8], repo: &Repository) -> Result<Oid> {
-    let reader = GzipDecoder::new(Cursor::new(tar_data));
+/// Nothing changes unless `rewrite` is set. Then, a rebuilt history
+/// extending the existing one is fast-forwarded to, and tags of new versions
+/// are created. A diverging history is preserved on the first free branch
+/// `main-v2`, `main-v3`, ..., with tags `<branch>/<version>`, leaving `main`
+/// and its tags alone.
+pub async fn reimport_component_repository(
+    downloader: &Downloader,
+    path: &Path,
+    rewrite: bool,
+    options: &ImportOptions,
+) -> Result<ReimportOutcome> {
+    let branch_name = "main";
+
+    let _lock = ImportLock::acquire(path)?;
+    let repo =
+        Repository::open(path).with_context(|| format!("opening repository {}", path.display()))?;
+
+    let component = match Provenance::read(&repo)? {
+        Some(provenance) if provenance.kind == RepositoryKind::Component => provenance.name,
+        _ => {
+            return Err(anyhow!(
+                "{} does not record the component it was imported from",
+                path.display()
+            ))
+        }
+    };
+
+    let head = repo
+        .find_branch(branch_name, git2::BranchType::Local)
+        .with_context(|| format!("finding branch {}", branch_name))?
+        .get()
+        .peel_to_commit()?;
+
+    let scratch = repo.path().join("apple-oss-reimport");
+    if scratch.exists() {
+        std::fs::remove_dir_all(&scratch)
+            .with_context(|| format!("removing {}", scratch.display()))?;
+    }
+
+    let rebuild_options = ImportOptions {
+        bare: true,
+        hooks: Hooks::default(),
+        failure_log: None,
+        content_report: None,
+        deny_audit: None,
+        resume: false,
+        force: true,
+        archive_refs: false,
+        archives_from: Some(path.to_path_buf()),
+        optimize: false,
+        partial_clone: false,
+        ..options.clone()
+    };
+
+    println!("rebuilding {} in {}", component, scratch.display());
+    import_component_repository(downloader, &scratch, &component, &rebuild_options, None).await?;
+
+    repo.remote_anonymous(&scratch.to_string_lossy())?
+        .fetch(
+            &[
+                format!("+refs/heads/{}:{}head", branch_name, REIMPORT_REFS_PREFIX),
+                format!("+refs/tags/*:{}tags/*", REIMPORT_REFS_PREFIX),
+            ],
+            None,
+            None,
+        )
+        .context("fetching rebuilt history")?;
+    std::fs::remove_dir_all(&scratch).with_context(|| format!("removing {}", scratch.display()))?;
+
+    let rebuilt = repo
+        .find_reference(&format!("{}head", REIMPORT_REFS_PREFIX))?
+        .peel_to_commit()?;
+
+    let existing_tags = tag_commits(path)?;
+    let mut rebuilt_tags = vec![];
+    for reference in repo.references_glob(&format!("{}tags/*", REIMPORT_REFS_PREFIX))? {
+        let reference = reference?;
+        let name = reference
+            .name()
+            .and_then(|name| name.strip_prefix(&format!("{}tags/", REIMPORT_REFS_PREFIX)))
+            .ok_or_else(|| anyhow!("invalid reference name"))?
+            .to_string();
+
+        let target = reference
+            .target()
+            .ok_or_else(|| anyhow!("symbolic tag reference {}", name))?;
+
+        rebuilt_tags.push((name, target, reference.peel_to_commit()?));
+    }
+    rebuilt_tags.sort_by(|a, b| downloader.compare_versions(&component, &a.0, &b.0));
+
+    let outcome = if rebuilt.id() == head.id() {
+        ReimportOutcome::Identical
+    } else if repo.graph_descendant_of(rebuilt.id(), head.id())? {
+        let added = rebuilt_tags
+            .iter()
+            .filter(|(version, _, _)| !existing_tags.contains_key(version))
+            .map(|(version, _, _)| version.clone())
+            .collect::<Vec<_>>();
+
+        if rewrite {
+            // Tags of new versions are the tag objects an import creates.
+            for (version, target, _) in &rebuilt_tags {
+                if added.contains(version) {
+                    repo.reference(
+                        &format!("refs/tags/{}", version),
+                        *target,
+                        false,
+                        "reimport",
+                    )?;
+                }
+            }
+            reconcile_repo_to_commit(&repo, branch_name, &rebuilt)?;
+            options.write_metadata(
+                &repo,
+                downloader,
+                RepositoryKind::Component,
+                &component,
+                rebuilt_tags.last().map(|(version, _, _)| version.as_str()),
+            )?;
+            options.optimize_repository(&repo)?;
+        }
 
-    let mut archive = Archive::new(reader);
+        ReimportOutcome::FastForward { added }
+    } else {
+        let changed = rebuilt_tags
+            .iter()
+            .filter(|(version, _, commit)| {
+                existing_tags.get(version) != Some(&commit.id().to_string())
+            })
+            .map(|(version, _, _)| version.clone())
+            .collect::<Vec<_>>();
+
+        let branch = if rewrite {
+            let branch = (2..)
+                .map(|n| format!("{}-v{}", branch_name, n))
+                .find(|name| repo.find_branch(name, git2::BranchType::Local).is_err())
+                .expect("a branch name is free");
+
+            repo.branch(&branch, &rebuilt, false)?;
+            for (version, _, commit) in &rebuilt_tags {
+                repo.tag_lightweight(
+                    &format!("{}/{}", branch, version),
+                    commit.as_object(),
+                    false,
+                )?;
+            }
+
+            Some(branch)
+        } else {
+            None
+        };
+
+        ReimportOutcome::Diverged { changed, branch }
+    };
+
+    for reference in repo.references_glob(&format!("{}*", REIMPORT_REFS_PREFIX))? {
+        reference?.delete()?;
+    }
+
+    Ok(outcome)
+}
+
+/// Obtain a component's manifests, keyed by the version they describe.
+///
+/// Manifests are informational, so failures to obtain them are only reported.
+async fn component_manifests(
+    downloader: &Downloader,
+    component: &str,
+    progress: Option<&ProgressBar>,
+) -> HashMap<String, ComponentManifest> {
+    let manifests = match downloader.get_component_manifests(component).await {
+        Ok(manifests) => manifests,
+        Err(e) => {
+            report(
+                progress,
+                format!(
+                    "warning: unable to fetch manifests of {}: {:?}",
+                    component, e
+                ),
+            );
+            return HashMap::new();
+        }
+    };
+
+    let prefix = format!("{}-", component);
+
+    manifests
+        .into_iter()
+        .filter_map(|manifest| {
+            // Manifests without a version may be named after the tarball.
+            let version = manifest.version.clone().or_else(|| {
+                let filename = manifest.url.rsplit('/').next()?;
+                let version = filename.strip_suffix(".plist")?.strip_prefix(&prefix)?;
+                Some(version.to_string())
+            })?;
+
+            Some((version, manifest))
+        })
+        .collect()
+}
+
+/// Whether an error is due to a component not having any versions.
+fn has_no_versions(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<NoVersionsError>().is_some()
+}
+
+fn is_interrupted(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<InterruptedError>().is_some()
+}
+
+/// Commit and tag a converted component version.
+///
+/// `parent` is the commit to build on and the version it holds, if known.
+/// Returns the new commit, or `None` if the version is identical to the parent
+/// and `policy` says to tag the parent commit instead.
+#[allow(clippy::too_many_arguments)]
+async fn commit_component_version<'repo>(
+    repo: &'repo Repository,
+    path: &Path,
+    record: &ComponentRecord,
+    tree_oid: Oid,
+    parent: Option<(&Commit<'repo>, Option<&str>)>,
+    manifest: Option<&ComponentManifest>,
+    policy: IdenticalVersionPolicy,
+    options: &ImportOptions,
+    progress: Option<&ProgressBar>,
+) -> Result<Option<Commit<'repo>>> {
+    let hooks = &options.hooks;
+    let signature = options.identity.component_signature(record)?;
+    let tree = repo.find_tree(tree_oid)?;
+
+    let identical = parent.filter(|(commit, _)| commit.tree_id() == tree_oid);
+
+    if let (Some((parent, _)), IdenticalVersionPolicy::Tag) = (identical, policy) {
+        report(
+            progress,
+            format!(
+                "{} version {} is identical to previous version; tagging {}",
+                record.component,
+                record.version,
+                parent.id()
+            ),
+        );
+
+        repo.tag(
+            &record.version,
+            parent.as_object(),
+            &signature,
+            "tagging",
+            true,
+        )?;
+
+        hooks
+            .emit(ImportEvent::Tag {
+                repository: path.to_path_buf(),
+                tag: record.version.clone(),
+                commit: parent.id().to_string(),
+            })
+            .await;
+
+        return Ok(None);
+    }
+
+    let parents = parent.map(|(commit, _)| vec![commit]).unwrap_or_default();
+
+    let mut message = format!(
+        "{} {}\n\nDownloaded from {}\n",
+        record.component, record.version, record.url
+    );
+
+    if let Some(manifest) = manifest {
+        message.push_str(&manifest.provenance_notes());
+    }
+
+    if let (Some((_, Some(version))), IdenticalVersionPolicy::Annotate) = (identical, policy) {
+        message.push_str(&format!("\nContent is identical to version {}.\n", version));
+    }
+
+    if let Some(trailers) = options.trailers() {
+        append_trailers(&mut message, &trailers);
+    }
+
+    let commit_oid = repo.commit(None, &signature, &signature, &message, &tree, &parents)?;
+
+    report(
+        progress,
+        format!(
+            "Committed {} version {} as {}",
+            record.component, record.version, commit_oid
+        ),
+    );
+
+    hooks
+        .emit(ImportEvent::Commit {
+            repository: path.to_path_buf(),
+            name: record.component.clone(),
+            version: record.version.clone(),
+            commit: commit_oid.to_string(),
+            url: Some(record.url.clone()),
+        })
+        .await;
+
+    let commit = repo.find_commit(commit_oid)?;
+
+    repo.tag(
+        &record.version,
+        commit.as_object(),
+        &signature,
+        "tagging",
+        true,
+    )?;
+
+    hooks
+        .emit(ImportEvent::Tag {
+            repository: path.to_path_buf(),
+            tag: record.version.clone(),
+            commit: commit_oid.to_string(),
+        })
+        .await;
+
+    Ok(Some(commit))
+}
+
+/// A component version and the additional source providing it, if any.
+type SourcedRecord<'a> = (ComponentRecord, Option<&'a Arc<dyn Source>>);
+
+/// Select the records of versions listed in a versions file, in its order.
+///
+/// Versions with a URL get a record for that URL unless a discovered record
+/// has the URL.
+fn select_versions<'a>(
+    component: &str,
+    mut records: Vec<SourcedRecord<'a>>,
+    entries: &[VersionEntry],
+) -> Result<Vec<SourcedRecord<'a>>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let found = records.iter().position(|(record, _)| match &entry.url {
+                Some(url) => record.url == *url,
+                None => record.version == entry.version,
+            });
+
+            match (found, &entry.url) {
+                (Some(index), _) => {
+                    let (mut record, source) = records.remove(index);
+                    record.version = entry.version.clone();
+
+                    Ok((record, source))
+                }
+                (None, Some(url)) => Ok((
+                    ComponentRecord {
+                        component: component.to_string(),
+                        filename: url.rsplit('/').next().unwrap_or(url).to_string(),
+                        url: url.clone(),
+                        version: entry.version.clone(),
+                        size: None,
+                        last_modified: None,
+                    },
+                    None,
+                )),
+                (None, None) => Err(anyhow!(
+                    "version {} of {} isn't known; give the URL of its archive",
+                    entry.version,
+                    component
+                )),
+            }
+        })
+        .collect()
+}
+
+async fn import_component_repository(
+    downloader: &Downloader,
+    path: &Path,
+    component: &str,
+    options: &ImportOptions,
+    progress: Option<&ProgressBar>,
+) -> Result<()> {
+    let hooks = &options.hooks;
+
+    options.check_component_allowed(component)?;
+
+    let _lock = ImportLock::acquire(path)?;
+    check_destination(path, options.force)?;
+
+    // Components only listed in a versions file with URLs needn't be
+    // discoverable.
+    let discover = options
+        .versions
+        .as_ref()
+        .is_none_or(|entries| entries.iter().any(|entry| entry.url.is_none()));
+
+    let mut records = if discover {
+        downloader
+            .get_component_versions(component)
+            .await
+            .context("fetching component versions")?
+            .into_iter()
+            .map(|record| (record, None))
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+
+    for source in &options.history_sources {
+        match source.versions(component).await {
+            Ok(page) => {
+                for record in page.versions {
+                    if !records.iter().any(|(r, _)| r.version == record.version) {
+                        records.push((record, Some(source)));
+                    }
+                }
+            }
+            Err(e) => {
+                report(
+                    progress,
+                    format!(
+                        "warning: unable to fetch additional versions of {}: {:?}",
+                        component, e
+                    ),
+                );
+            }
+        }
+    }
+
+    match &options.versions {
+        Some(entries) => records = select_versions(component, records, entries)?,
+        None => records.sort_by(|a, b| {
+            downloader
+                .compare_versions(component, &a.0.version, &b.0.version)
+                .then_with(|| a.0.cmp(&b.0))
+        }),
+    }
+
+    if records.is_empty() {
+        return Err(NoVersionsError {
+            component: component.to_string(),
+        }
+        .into());
+    }
+
+    let manifests = component_manifests(downloader, component, progress).await;
+
+    let space = options
+        .preflight(
+            downloader,
+            path,
+            records.iter().map(|(r, _)| r.url.as_str()),
+        )
+        .await?;
+
+    let branch_name = "main";
+
+    let repo = Repository::init_opts(
+        path,
+        RepositoryInitOptions::new()
+            .bare(options.bare)
+            .initial_head(branch_name),
+    )
+    .context("initialing repository")?;
+
+    let mut disk_usage = options.track_disk_usage(&repo, path)?;
+
+    let mut parent_commit = None;
+    let mut parent_version = None;
+
+    // Versions already imported, keyed by version.
+    let mut imported = HashMap::new();
+
+    if options.resume {
+        for (record, _) in &records {
+            if let Some(commit) = find_tag_commit(&repo, &record.version)? {
+                imported.insert(record.version.clone(), commit.id());
+            }
+        }
+    }
+
+    // Upcoming versions are downloaded and converted while earlier versions
+    // are committed. Results are yielded in version order.
+    let mut trees = futures::stream::iter(records.iter().map(|(record, source)| {
+        let repo = &repo;
+        let imported = imported.contains_key(&record.version);
+
+        async move {
+            if imported {
+                return Ok((record, *source, None));
+            }
+
+            // Held until the archive is converted.
+            let _reservation = options
+                .reserve_memory(downloader, &record.url, record.size)
+                .await;
+
+            report(progress, format!("fetching {}", record.version));
+
+            let artifact = || ArtifactContext {
+                component: record.component.clone(),
+                version: Some(record.version.clone()),
+                url: record.url.clone(),
+            };
+
+            let stored = match &options.archives_from {
+                Some(archives) => stored_archive(archives, &record.version)?,
+                None => None,
+            };
+
+            let tar_data = match (stored, source) {
+                (Some(data), _) => Ok(data),
+                (None, Some(source)) => {
+                    downloader
+                        .get_component_record_from(source.as_ref(), record)
+                        .await
+                }
+                (None, None) => downloader.get_component_record(record).await,
+            }
+            .context("fetching component tarball")
+            .with_context(artifact)?;
+
+            options.store_archive(repo, &record.version, &tar_data)?;
+
+            let tree_oid = options
+                .convert(repo, &record.component, Some(&record.version), tar_data)
+                .await
+                .with_context(artifact)?;
+            options.report_contents(repo, &record.component, Some(&record.version), tree_oid)?;
+
+            Ok::<_, anyhow::Error>((record, *source, Some(tree_oid)))
+        }
+    }))
+    .buffered(options.prefetch.unwrap_or(DEFAULT_PREFETCH).max(1));
+
+    let mut interrupted = false;
+
+    loop {
+        // Versions being downloaded or converted are abandoned on shutdown.
+        let res = tokio::select! {
+            biased;
+            _ = options.shutdown.cancelled() => {
+                interrupted = true;
+                break;
+            }
+            res = trees.next() => match res {
+                Some(res) => res,
+                None => break,
+            },
+        };
+        let (record, source, tree_oid) = res?;
+
+        let tree_oid = match tree_oid {
+            Some(tree_oid) => tree_oid,
+            None => {
+                report(progress, format!("{} already imported", record.version));
+                parent_commit = Some(repo.find_commit(imported[&record.version])?);
+                parent_version = Some(record.version.as_str());
+                continue;
+            }
+        };
+
+        // Versions from other sources may duplicate content already imported
+        // under a different version, so always reuse the existing commit.
+        let policy = if source.is_some() {
+            IdenticalVersionPolicy::Tag
+        } else {
+            options.identical_versions
+        };
+
+        if let Some(commit) = commit_component_version(
+            &repo,
+            path,
+            record,
+            tree_oid,
+            parent_commit
+                .as_ref()
+                .map(|commit| (commit, parent_version)),
+            manifests.get(&record.version),
+            policy,
+            options,
+            progress,
+        )
+        .await?
+        {
+            options.add_commit_disk_usage(disk_usage.as_mut(), &repo, &commit)?;
+
+            parent_commit = Some(commit);
+            parent_version = Some(record.version.as_str());
+        }
+    }
+
+    drop(trees);
+
+    // Committed versions are kept so importing again resumes.
+    if interrupted {
+        if let Some(parent) = &parent_commit {
+            reconcile_repo_to_commit(&repo, branch_name, parent)?;
+        }
+        options.write_metadata(
+            &repo,
+            downloader,
+            RepositoryKind::Component,
+            component,
+            parent_version,
+        )?;
+
+        return Err(InterruptedError.into());
+    }
+
+    if options.release_refs {
+        update_release_refs(downloader, &repo, component, options).await?;
+    }
+
+    if let Some(parent) = &parent_commit {
+        reconcile_repo_to_commit(&repo, branch_name, parent)?;
+    }
+    options.write_metadata(
+        &repo,
+        downloader,
+        RepositoryKind::Component,
+        component,
+        parent_version,
+    )?;
+    options.optimize_repository(&repo)?;
+
+    // Resumed imports didn't write all the archives they were checked for.
+    if imported.is_empty() {
+        options.record_disk_usage(
+            path,
+            disk_usage.as_ref(),
+            space.as_ref().map(|space| space.archive_bytes()),
+        )?;
+    }
+
+    hooks
+        .emit(ImportEvent::RepositoryComplete {
+            repository: path.to_path_buf(),
+            branch: branch_name.to_string(),
+            head: parent_commit.map(|commit| commit.id().to_string()),
+        })
+        .await;
+
+    Ok(())
+}
+
+pub async fn create_components_repositories(
+    downloader: &Downloader,
+    path: &Path,
+    options: &ImportOptions,
+) -> Result<()> {
+    let components = downloader
+        .get_components()
+        .await
+        .context("resolving components")?
+        .into_iter()
+        .filter(|c| options.includes_component(c) && !options.denies_component(c, None))
+        .collect::<Vec<_>>();
+
+    let progress = Rc::new(MultiTaskProgress::new(components.len(), "components"));
+
+    // Imports hold repository state across awaits, so they run on this thread.
+    let mut tasks = LocalTaskSet::with_limit(options.jobs.unwrap_or(DEFAULT_JOBS));
+
+    for c in &components {
+        let downloader = downloader.clone();
+        let path = options.destination.resolve(path, c);
+        let c = c.clone();
+        let options = options.clone();
+        let progress = progress.clone();
+
+        tasks.spawn(c.clone(), async move {
+            // Components not started before shutdown aren't imported.
+            if options.shutdown.is_cancelled() {
+                return Err(InterruptedError.into());
+            }
+
+            let bar = progress.start(&c);
+            let res =
+                import_component_repository(&downloader, &path, &c, &options, Some(&bar)).await;
+
+            match &res {
+                Err(e) if has_no_versions(e) || is_interrupted(e) => {}
+                Err(e) => {
+                    progress.println(format!("{}: {:?}", c, e));
+                    options.record_component_failure(&c, &path, e);
+                }
+                Ok(()) => {}
+            }
+            progress.finish(bar);
+
+            res
+        });
+    }
+
+    let results = tasks.join().await;
+
+    progress.clear();
+
+    // Tasks that panicked didn't get to record their failure.
+    for outcome in &results {
+        if let Err(e) = &outcome.result {
+            if e.downcast_ref::<TaskPanicError>().is_some() {
+                println!("{}: {:?}", outcome.label, e);
+                options.record_component_failure(
+                    &outcome.label,
+                    &options.destination.resolve(path, &outcome.label),
+                    e,
+                );
+            }
+        }
+    }
+
+    let width = results.iter().map(|o| o.label.len()).max().unwrap_or(0);
+    let empty = results
+        .iter()
+        .filter(|o| matches!(&o.result, Err(e) if has_no_versions(e)))
+        .count();
+    let interrupted = results
+        .iter()
+        .filter(|o| matches!(&o.result, Err(e) if is_interrupted(e)))
+        .count();
+    let failures = results.iter().filter(|o| o.result.is_err()).count() - empty - interrupted;
+
+    println!("{:width$}  status", "component", width = width);
+    for TaskOutcome {
+        label: component,
+        result,
+    } in &results
+    {
+        match result {
+            Ok(()) => println!("{:width$}  ok", component, width = width),
+            Err(e) if has_no_versions(e) => {
+                println!("{:width$}  no versions available", component, width = width)
+            }
+            Err(e) if is_interrupted(e) => {
+                println!("{:width$}  interrupted", component, width = width)
+            }
+            Err(e) => println!("{:width$}  failed: {}", component, e, width = width),
+        }
+    }
+    println!(
+        "{} succeeded, {} failed, {} without versions",
+        results.len() - failures - empty - interrupted,
+        failures,
+        empty
+    );
+
+    if interrupted > 0 {
+        println!("{} interrupted", interrupted);
+        return Err(InterruptedError.into());
+    }
+
+    PartialFailure::check("components", failures, results.len() - empty)
+}
+
+/// Import a downloaded release component into a repository.
+///
+/// Failed downloads are recorded and skipped.
+async fn import_release_component(
+    repo: &Repository,
+    path: &Path,
+    release: &ReleaseRecord,
+    component: ReleaseComponentRecord,
+    download: Result<Vec<u8>>,
+    options: &ImportOptions,
+) -> Result<Option<(ReleaseComponentRecord, Oid)>> {
+    let tar_data = match download {
+        Ok(x) => x,
+        Err(e) => {
+            let mut failure = FailureRecord::from_error(
+                Operation::ReleaseComponent,
+                &component.component,
+                path,
+                &e,
+            );
+            failure.version = component.version().map(|v| v.to_string());
+            failure.url = Some(component.url.clone());
+            failure.release = Some(release.entity.clone());
+            failure.release_version = Some(release.version.clone());
+            failure.bare = Some(options.bare);
+            options.record_failure(failure);
+
+            if options.missing_components == MissingComponentPolicy::Fail {
+                return Err(e.context(format!(
+                    "{} of release {} {} failed to download",
+                    component.url, release.entity, release.version
+                )));
+            }
 
-    let mut dirs: HashMap<Vec<u8>, TreeBuilder> = HashMap::new();
+            println!(
+                "warning: {} failed to download; skipping ({:?})",
+                component.url, e
+            );
 
-    let mut entries = archive.entries().context("reading tar entries")?;
+            return Ok(None);
+        }
+    };
 
-    let mut pinned = Pin::new(&mut entries);
-    while let Some(entry) = pinned.next().await {
-        let mut entry = entry.context("reading tar entry")?;
+    options.store_archive(repo, component.filename(), &tar_data)?;
 
-        if entry.header().entry_type().is_dir() {
-            continue;
-        }
+    let tree_oid = options
+        .convert(repo, &component.component, component.version(), tar_data)
+        .await
+        .with_context(|| format!("converting {} to Git tree", component.url))?;
+    options.report_contents(repo, &component.component, component.version(), tree_oid)?;
 
-        let original_mode = entry.header().mode()? as i32;
+    println!("imported {} to Git", component.url);
 
-        let (mode, buf) = if let Some(link_name) = entry.header().link_name_bytes() {
-            (0o120000, link_name.to_vec())
-        } else {
-            let mode = if original_mode & 0o111 != 0 {
-                0o100755
-            } else if original_mode & 0o444 != 0 {
-                0o100644
-            // This occurs in some archives.
-            } else if original_mode == 0 {
-                0o100644
-            } else {
-                return Err(anyhow!("invalid tar archive mode: {}", original_mode));
-            };
+    Ok(Some((component, tree_oid)))
+}
 
-            let mut buf = vec![];
-            entry.read_to_end(&mut buf).await?;
+/// Write a tree explaining why a release component is missing.
+fn missing_component_tree(repo: &Repository, failure: &ManifestFailure) -> Result<Oid> {
+    let text = format!(
+        "{} was not imported because it failed to download.\n\nURL: {}\nError: {}\n",
+        failure.component, failure.url, failure.error
+    );
 
-            (mode, buf)
-        };
+    let mut builder = repo.treebuilder(None)?;
+    builder.insert(MISSING_FILENAME, repo.blob(text.as_bytes())?, 0o100644)?;
 
-        let blob_oid = repo.blob(&buf).context("writing file data to blob")?;
+    Ok(builder.write()?)
+}
 
-        let path = entry.path_bytes();
+/// Release repository state shared between the releases it imports.
+#[derive(Default)]
+struct ReleaseImportState {
+    /// Trees of imported archives, keyed by URL.
+    seen_trees: HashMap<String, Oid>,
+    /// SHA-256 of archives, keyed by URL.
+    checksums: HashMap<String, String>,
+    /// Number of components downloads were attempted for.
+    attempted: usize,
+    /// Number of components that failed to download.
+    skipped: usize,
+    /// Disk space used by the repository, if tracked.
+    disk_usage: Option<DiskUsage>,
+    /// Total advertised size of the archives imported, if known.
+    archive_bytes: Option<u64>,
+}
 
-        // First directory is ignored.
-        let start_index = if let Some(i) =
-            path.iter()
-                .enumerate()
-                .find_map(|(index, c)| if *c == b'/' { Some(index) } else { None })
-        {
-            i
-        } else {
-            println!(
-                "ignoring tar member {} not in sub-directory",
-                String::from_utf8_lossy(&path)
-            );
-            continue;
-        };
+/// Commit a software release on top of `parent` and tag it as `tag`.
+///
+/// Archives already imported by a previous release are reused.
+#[allow(clippy::too_many_arguments)]
+async fn commit_release<'repo>(
+    downloader: &Downloader,
+    repo: &'repo Repository,
+    path: &Path,
+    record: &ReleaseRecord,
+    tag: &str,
+    parent: Option<&Commit<'repo>>,
+    state: &mut ReleaseImportState,
+    options: &ImportOptions,
+) -> Result<Commit<'repo>> {
+    let hooks = &options.hooks;
+
+    println!("building commit for {} {}", record.entity, record.version);
+
+    let page = downloader.get_release_page(record).await.with_context(|| {
+        format!(
+            "fetching components for release {} {}",
+            record.entity, record.version
+        )
+    })?;
 
-        let path = &path[start_index + 1..];
+    let mut root_builder = repo.treebuilder(None).context("creating tree builder")?;
 
-        let (dir, filename) = if let Some(dir_index) = path
-            .iter()
-            .enumerate()
-            .rev()
-            .find_map(|(index, c)| if *c == b'/' { Some(index) } else { None })
-        {
-            let dir = &path[..dir_index];
-            let filename = &path[dir_index + 1..];
+    // Context such as license notes only exists on the release page, so
+    // preserve the page alongside the components.
+    if let Some(notes) = page.notes(record)? {
+        let html = page.html.as_deref().unwrap_or_default();
 
-            (dir.to_vec(), filename.to_vec())
-        } else {
-            (vec![], path.to_vec())
-        };
+        root_builder.insert(
+            RELEASE_NOTES_FILENAME,
+            repo.blob(notes.as_bytes())?,
+            0o100644,
+        )?;
+        root_builder.insert(RELEASE_HTML_FILENAME, repo.blob(html.as_bytes())?, 0o100644)?;
+    }
 
-        // Ensure parent directories have treebuilders.
-        for (i, c) in dir.iter().enumerate() {
-            if *c == b'/' {
-                let parent = &dir[..i];
+    let components = page
+        .components
+        .into_iter()
+        .filter(|component| {
+            options.includes_component(&component.component)
+                && !options.denies_component(&component.component, component.version())
+        })
+        .collect::<Vec<_>>();
+
+    let signature = options.identity.release_signature(record)?;
+
+    if options.resume {
+        if let Some(commit) = find_tag_commit(repo, tag)? {
+            let tree = commit.tree()?;
+            let mut failed = HashSet::new();
+
+            if let Some(manifest) = ReleaseManifest::from_commit(repo, &commit)? {
+                for component in manifest.components {
+                    if let Some(sha256) = component.sha256 {
+                        state.checksums.entry(component.url).or_insert(sha256);
+                    }
+                }
+
+                failed.extend(manifest.failed.into_iter().map(|failure| failure.url));
+            }
 
-                dirs.entry(Vec::from(parent))
-                    .or_insert_with(|| repo.treebuilder(None).unwrap());
+            // Placeholders of failed components are downloaded again.
+            for component in components.iter().filter(|c| !failed.contains(&c.url)) {
+                if let Some(entry) = tree.get_name(&component.component) {
+                    state
+                        .seen_trees
+                        .entry(component.url.clone())
+                        .or_insert_with(|| entry.id());
+                }
             }
         }
+    }
 
-        dirs.entry(dir)
-            .or_insert_with(|| repo.treebuilder(None).unwrap())
-            .insert(filename, blob_oid, mode)?;
+    let mut manifest = ReleaseManifest::new(record);
+    let mut missing = vec![];
+
+    for component in components {
+        if let Some(tree_oid) = state.seen_trees.get(&component.url) {
+            println!("using already imported archive {}", component.url);
+            root_builder.insert(&component.component, *tree_oid, GIT_TREE_MODE)?;
+            manifest.components.push(ManifestComponent {
+                component: component.component.clone(),
+                version: component.version().map(|v| v.to_string()),
+                url: component.url.clone(),
+                sha256: state.checksums.get(&component.url).cloned(),
+            });
+        } else {
+            missing.push(component);
+        }
     }
 
-    // Ensure root is present, since it is special.
-    dirs.entry(vec![])
-        .or_insert_with(|| repo.treebuilder(None).unwrap());
+    let space = options
+        .preflight(downloader, path, missing.iter().map(|c| c.url.as_str()))
+        .await?;
+    state.archive_bytes = match (state.archive_bytes, &space) {
+        (Some(total), Some(space)) => Some(total + space.archive_bytes()),
+        _ => None,
+    };
+
+    // Memory is reserved in order before downloads start. Conversion needs
+    // the repository, which can't be shared between tasks, so downloads run
+    // in batches converted once complete. A batch waits for memory for its
+    // first archive and only takes later archives while memory is available,
+    // so no download waits on memory held by archives not yet converted.
+    let jobs = options.jobs.unwrap_or(DEFAULT_JOBS);
+    let sizes = match &options.memory_budget {
+        Some(_) => {
+            futures::stream::iter(missing.iter().map(|component| async move {
+                downloader
+                    .get_content_length(&component.url)
+                    .await
+                    .ok()
+                    .flatten()
+            }))
+            .buffered(jobs)
+            .collect::<Vec<_>>()
+            .await
+        }
+        None => vec![None; missing.len()],
+    };
+    let mut missing = missing.into_iter().zip(sizes).peekable();
+
+    while missing.peek().is_some() {
+        let mut batch = vec![];
+        let mut downloads = TaskSet::with_limit(jobs);
+
+        while let Some((_, size)) = missing.peek() {
+            let reservation = match &options.memory_budget {
+                Some(budget) if batch.is_empty() => Some(budget.reserve(*size).await),
+                Some(budget) => match budget.try_reserve(*size) {
+                    Some(reservation) => Some(reservation),
+                    None => break,
+                },
+                None => None,
+            };
+
+            let (component, _) = missing.next().expect("component was peeked");
+
+            {
+                let downloader = downloader.clone();
+                let component = component.clone();
 
-    // dirs now holds each logical directory/tree and its files. We need to walk from
-    // the child-most nodes down to the root to write the tree objects and populate
-    // parents with the just-written tree object.
-    let mut keys = dirs.keys().cloned().collect::<Vec<_>>();
-    keys.sort_by(|a, b| b.len().cmp(&a.len()));
+                downloads.spawn(component.url.clone(), async move {
+                    downloader
+                        .get_release_component_record(&component)
+                        .await
+                        .context("fetching release component record")
+                });
+            }
 
-    for key in &keys {
-        // Finalize this tree.
-        let tree = dirs.get(key).expect("iterating over known keys");
-        let oid = tree.write()?;
+            batch.push((component, reservation));
+        }
 
-        // Record just-written tree in parent if not at root.
-        if let Some(end_index) =
-            key.iter()
-                .enumerate()
-                .rev()
-                .find_map(|(index, c)| if *c == b'/' { Some(index) } else { None })
-        {
-            let parent_path = &key[0..end_index];
-            let tree_path = &key[end_index + 1..];
-
-            dirs.get_mut(parent_path)
-                .expect("parent directory should always be present")
-                .insert(tree_path, oid, GIT_TREE_MODE)?;
-        } else if !key.is_empty() {
-            dirs.get_mut(&vec![])
-                .expect("root directory should always be present")
-                .insert(&key, oid, GIT_TREE_MODE)?;
-        } else {
-            return Ok(oid);
+        for ((component, _reservation), outcome) in batch.into_iter().zip(downloads.join().await) {
+            let result = outcome.result;
+            state.attempted += 1;
+
+            let sha256 = result.as_ref().ok().map(|data| sha256_hex(data));
+            let failure = ManifestFailure {
+                component: component.component.clone(),
+                version: component.version().map(|v| v.to_string()),
+                url: component.url.clone(),
+                error: match &result {
+                    Ok(_) => String::new(),
+                    Err(e) => format!("{:#}", e),
+                },
+            };
+
+            let imported =
+                import_release_component(repo, path, record, component, result, options).await?;
+
+            if let Some((component, tree_oid)) = imported {
+                if let Some(sha256) = &sha256 {
+                    state
+                        .checksums
+                        .insert(component.url.clone(), sha256.clone());
+                }
+                manifest.components.push(ManifestComponent {
+                    component: component.component.clone(),
+                    version: component.version().map(|v| v.to_string()),
+                    url: component.url.clone(),
+                    sha256,
+                });
+
+                state.seen_trees.insert(component.url, tree_oid);
+                root_builder.insert(component.component, tree_oid, GIT_TREE_MODE)?;
+            } else {
+                if options.missing_components == MissingComponentPolicy::Placeholder {
+                    root_builder.insert(
+                        &failure.component,
+                        missing_component_tree(repo, &failure)?,
+                        GIT_TREE_MODE,
+                    )?;
+                }
+
+                manifest.failed.push(failure);
+                state.skipped += 1;
+            }
         }
     }
 
-    panic!("should have emitted root tree in loop above");
-}
+    manifest
+        .components
+        .sort_by(|a, b| a.component.cmp(&b.component));
+    manifest
+        .failed
+        .sort_by(|a, b| a.component.cmp(&b.component));
+    root_builder.insert(
+        RELEASE_MANIFEST_FILENAME,
+        repo.blob(format!("{}\n", serde_json::to_string_pretty(&manifest)?).as_bytes())?,
+        0o100644,
+    )?;
 
-pub fn reconcile_repo_to_commit(
-    repo: &Repository,
-    branch_name: &str,
-    commit: &Commit,
-) -> Result<()> {
-    if repo.is_bare() {
-        repo.branch(branch_name, commit, true)
-            .context("updating branch to commit")?;
-    } else {
-        repo.set_head_detached(commit.id())
-            .context("marking head as detached")?;
-        repo.branch(branch_name, commit, true)
-            .context("updating branch to commit")?;
-        repo.set_head(&format!("refs/heads/{}", branch_name))
-            .context("setting head to branch")?;
-        repo.reset(commit.as_object(), git2::ResetType::Hard, None)
-            .context("resetting working directory")?;
+    let tree_oid = root_builder.write().context("writing root tree object")?;
+    let tree = repo.find_tree(tree_oid)?;
+
+    let parents = parent.into_iter().collect::<Vec<_>>();
+
+    let mut message = manifest.commit_message();
+    if let Some(trailers) = options.trailers() {
+        append_trailers(&mut message, &trailers);
     }
 
-    Ok(())
-}
+    let commit_oid = repo.commit(None, &signature, &signature, &message, &tree, &parents)?;
 
-/// Create a Git repository for an Apple opensource component.
-///
-/// The Git repository will have tags corresponding to the versions of the component.
-pub async fn create_component_repository(
-    path: impl AsRef<Path>,
-    component: &str,
-    bare: bool,
-) -> Result<()> {
-    let downloader = Downloader::new().context("creating downloader")?;
+    println!(
+        "Committed {} version {} as {}",
+        record.entity, record.version, commit_oid
+    );
 
-    let records = downloader
-        .get_component_versions(component)
-        .await
-        .context("fetching component versions")?;
+    hooks
+        .emit(ImportEvent::Commit {
+            repository: path.to_path_buf(),
+            name: record.entity.clone(),
+            version: record.version.clone(),
+            commit: commit_oid.to_string(),
+            url: Some(record.url.clone()),
+        })
+        .await;
 
-    let branch_name = "main";
+    let commit = repo.find_commit(commit_oid)?;
 
-    let repo = Repository::init_opts(
-        path,
-        RepositoryInitOptions::new()
-            .bare(bare)
-            .initial_head(branch_name),
-    )
-    .context("initialing repository")?;
+    repo.tag(tag, commit.as_object(), &signature, "tagging", true)?;
 
-    let mut parent_commit = None;
+    hooks
+        .emit(ImportEvent::Tag {
+            repository: path.to_path_buf(),
+            tag: tag.to_string(),
+            commit: commit_oid.to_string(),
+        })
+        .await;
 
-    let signature = Signature::new(
-        "Apple Open Source",
-        "opensource@apple.com",
-        &git2::Time::new(1609459200, 0),
-    )?;
+    options.add_commit_disk_usage(state.disk_usage.as_mut(), repo, &commit)?;
 
-    for record in records {
-        let tar_data = downloader
-            .get_component_record(&record)
-            .await
-            .context("fetching component tarball")?;
+    Ok(commit)
+}
 
-        let tree_oid = tar_data_to_tree(&tar_data, &repo).await?;
-        let tree = repo.find_tree(tree_oid)?;
+/// Extend the histories of the components of a release commit.
+///
+/// Every component imported by the release whose tree differs from the head
+/// of its `<prefix><component>` reference gets a commit of its tree on top of
+/// the reference, with the author, committer, and message of the release
+/// commit. Components that failed to download are skipped.
+fn update_component_refs(repo: &Repository, commit: &Commit, prefix: &str) -> Result<()> {
+    let manifest = match ReleaseManifest::from_commit(repo, commit)? {
+        Some(manifest) => manifest,
+        None => return Ok(()),
+    };
+    let tree = commit.tree()?;
 
-        let parents = if let Some(parent) = &parent_commit {
-            vec![parent]
-        } else {
-            vec![]
+    for component in manifest.components {
+        let entry = match tree.get_name(&component.component) {
+            Some(entry) => entry,
+            None => continue,
         };
+        let ref_name = format!("{}{}", prefix, component.component);
 
-        let commit_oid = repo.commit(
-            None,
-            &signature,
-            &signature,
-            &format!(
-                "{} {}\n\nDownloaded from {}\n",
-                record.component, record.version, record.url
-            ),
-            &tree,
-            &parents,
-        )?;
-
-        println!(
-            "Committed {} version {} as {}",
-            record.component, record.version, commit_oid
-        );
+        let parent = match repo.find_reference(&ref_name) {
+            Ok(reference) => Some(reference.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
 
-        let commit = repo.find_commit(commit_oid)?;
+        if parent.as_ref().map(|p| p.tree_id()) == Some(entry.id()) {
+            continue;
+        }
 
-        repo.tag(
-            &record.version,
-            commit.as_object(),
-            &signature,
-            "tagging",
-            true,
+        let component_tree = repo.find_tree(entry.id())?;
+        let parents = parent.iter().collect::<Vec<_>>();
+        let oid = repo.commit(
+            None,
+            &commit.author(),
+            &commit.committer(),
+            commit.message().unwrap_or_default(),
+            &component_tree,
+            &parents,
         )?;
 
-        parent_commit = Some(commit);
-    }
-
-    if let Some(parent) = parent_commit {
-        reconcile_repo_to_commit(&repo, branch_name, &parent)?;
+        repo.reference(&ref_name, oid, true, "recording component history")
+            .with_context(|| format!("updating {}", ref_name))?;
     }
 
     Ok(())
 }
 
-pub async fn create_components_repositories(path: &Path, bare: bool) -> Result<()> {
-    let downloader = Downloader::new().context("creating downloader")?;
-
-    let components = downloader
-        .get_components()
-        .await
-        .context("resolving components")?;
-
-    let mut errors = vec![];
-
-    for fs in futures::future::join_all(
-        components
-            .iter()
-            .map(|c| create_component_repository(path.join(c), c, bare)),
-    )
-    .await
-    {
-        if let Err(e) = fs {
-            errors.push(e);
-        }
+/// The versions of a component shipped in software releases.
+///
+/// This fetches the page of every release. Pages that can't be fetched are
+/// reported and skipped.
+pub async fn release_component_versions(
+    downloader: &Downloader,
+    component: &str,
+    options: &ImportOptions,
+) -> Result<Vec<(ReleaseRecord, String)>> {
+    let releases = fetch_releases(downloader, options).await?;
+
+    let mut tasks = TaskSet::with_limit(8);
+    for record in &releases {
+        let downloader = downloader.clone();
+        let record = record.clone();
+
+        tasks.spawn(record.url.clone(), async move {
+            downloader.get_release_components(&record, false).await
+        });
     }
 
-    for err in errors {
-        println!("{:?}", err);
+    let mut versions = vec![];
+    for (record, outcome) in releases.into_iter().zip(tasks.join().await) {
+        match outcome.result {
+            Ok(components) => {
+                if let Some(version) = components
+                    .iter()
+                    .filter(|c| c.component == component)
+                    .find_map(|c| c.version())
+                {
+                    versions.push((record, version.to_string()));
+                }
+            }
+            Err(e) => println!(
+                "warning: unable to find components of {}: {:?}",
+                outcome.label, e
+            ),
+        }
     }
 
-    Ok(())
+    Ok(versions)
 }
 
-async fn import_release_component(
+/// Reference the commits of component versions shipped in software releases.
+///
+/// `<RELEASE_REFS_PREFIX><entity>-<version>` is pointed at the commit tagged
+/// with the version of the component in that release. Releases shipping a
+/// version that isn't tagged are skipped.
+async fn update_release_refs(
     downloader: &Downloader,
     repo: &Repository,
-    component: ReleaseComponentRecord,
-) -> Result<Option<(ReleaseComponentRecord, Oid)>> {
-    let tar_data = match downloader
-        .get_release_component_record(&component)
-        .await
-        .context("fetching release component record")
-    {
-        Ok(x) => x,
-        Err(e) => {
-            println!(
-                "warning: {} failed to download; skipping ({:?})",
-                component.url, e
-            );
-            return Ok(None);
+    component: &str,
+    options: &ImportOptions,
+) -> Result<()> {
+    for (record, version) in release_component_versions(downloader, component, options).await? {
+        let commit = match find_tag_commit(repo, &version)? {
+            Some(commit) => commit,
+            None => continue,
+        };
+
+        let ref_name = format!(
+            "{}{}-{}",
+            RELEASE_REFS_PREFIX, record.entity, record.version
+        );
+        if !git2::Reference::is_valid_name(&ref_name) {
+            println!("warning: {} isn't a valid reference name", ref_name);
+            continue;
         }
-    };
 
-    let tree_oid = tar_data_to_tree(&tar_data, &repo)
-        .await
-        .with_context(|| format!("converting {} to Git tree", component.url))?;
+        repo.reference(&ref_name, commit.id(), true, "recording release version")
+            .with_context(|| format!("updating {}", ref_name))?;
+    }
 
-    println!("imported {} to Git", component.url);
+    Ok(())
+}
 
-    Ok(Some((component, tree_oid)))
+/// Fetch the software releases an import considers.
+async fn fetch_releases(
+    downloader: &Downloader,
+    options: &ImportOptions,
+) -> Result<Vec<ReleaseRecord>> {
+    if options.include_updates {
+        downloader.get_releases_with_updates().await
+    } else {
+        downloader.get_releases().await
+    }
+    .context("fetching releases")
 }
 
-pub async fn create_release_repository(path: &Path, release: &str, bare: bool) -> Result<()> {
-    let downloader = Downloader::new().context("creating downloader")?;
+/// The releases of an entity to import, in chronological order.
+///
+/// Releases published under aliases of the entity form one history.
+fn entity_releases(
+    releases: Vec<ReleaseRecord>,
+    entity: &str,
+    options: &ImportOptions,
+) -> Result<Vec<ReleaseRecord>> {
+    let releases = stitch_aliases(entity, releases);
+
+    match options.since {
+        Some(since) => {
+            chronological_releases(releases_since(releases, since), options.release_order)
+        }
+        None => chronological_releases(releases, options.release_order),
+    }
+}
 
+pub async fn create_release_repository(
+    downloader: &Downloader,
+    path: &Path,
+    release: &str,
+    options: &ImportOptions,
+) -> Result<()> {
+    let hooks = &options.hooks;
     let branch_name = "main";
 
+    let _lock = ImportLock::acquire(path)?;
+    check_destination(path, options.force)?;
+
     let repo = Repository::init_opts(
         path,
         RepositoryInitOptions::new()
-            .bare(bare)
+            .bare(options.bare)
             .initial_head(branch_name),
     )
     .context("initialing repository")?;
 
-    let mut seen_trees: HashMap<String, Oid> = HashMap::new();
+    let mut state = ReleaseImportState {
+        disk_usage: options.track_disk_usage(&repo, path)?,
+        archive_bytes: Some(0),
+        ..Default::default()
+    };
 
     let mut parent_commit = None;
+    let mut last_version = None;
 
-    for record in downloader
-        .get_releases()
-        .await
-        .context("fetching releases")?
-        .into_iter()
-        .filter(|record| record.matches_entity(release))
-    {
-        println!("building commit for {} {}", record.entity, record.version);
+    let releases = entity_releases(fetch_releases(downloader, options).await?, release, options)?;
 
-        let components = downloader
-            .get_release_components(&record)
-            .await
-            .with_context(|| {
-                format!(
-                    "fetching components for release {} {}",
-                    record.entity, record.version
-                )
-            })?;
+    for record in releases {
+        if options.shutdown.is_cancelled() {
+            break;
+        }
 
-        let mut root_builder = repo.treebuilder(None).context("creating tree builder")?;
+        let commit = commit_release(
+            downloader,
+            &repo,
+            path,
+            &record,
+            &record.version,
+            parent_commit.as_ref(),
+            &mut state,
+            options,
+        )
+        .await?;
 
-        let signature = Signature::new(
-            "Apple Open Source",
-            "opensource@apple.com",
-            &git2::Time::new(1609459200, 0),
-        )?;
+        if options.component_refs {
+            update_component_refs(&repo, &commit, COMPONENT_REFS_PREFIX)?;
+        }
+        options.write_release_report(&repo, &commit, parent_commit.as_ref(), &record.version)?;
 
-        let mut missing = vec![];
+        parent_commit = Some(commit);
+        last_version = Some(record.version);
+    }
 
-        for component in components {
-            if let Some(tree_oid) = seen_trees.get(&component.url) {
-                println!("using already imported archive {}", component.url);
-                root_builder.insert(&component.component, tree_oid.clone(), GIT_TREE_MODE)?;
-            } else {
-                missing.push(component);
-            }
+    if let Some(parent) = &parent_commit {
+        reconcile_repo_to_commit(&repo, branch_name, parent)?;
+    }
+    options.write_metadata(
+        &repo,
+        downloader,
+        RepositoryKind::Release,
+        release,
+        last_version.as_deref(),
+    )?;
+
+    // Committed releases are kept.
+    if options.shutdown.is_cancelled() {
+        return Err(InterruptedError.into());
+    }
+
+    options.optimize_repository(&repo)?;
+
+    // Resumed imports didn't write all the archives they were checked for.
+    if !options.resume {
+        options.record_disk_usage(path, state.disk_usage.as_ref(), state.archive_bytes)?;
+    }
+
+    hooks
+        .emit(ImportEvent::RepositoryComplete {
+            repository: path.to_path_buf(),
+            branch: branch_name.to_string(),
+            head: parent_commit.map(|commit| commit.id().to_string()),
+        })
+        .await;
+
+    PartialFailure::check("release components", state.skipped, state.attempted)
+}
+
+/// Import the software releases of several entities into one repository.
+///
+/// Each entity's releases are committed on a branch named after the entity
+/// and tagged `<entity>/<version>`. Releases are imported in chronological
+/// order across entities and archives shared by releases of different
+/// entities are only downloaded and converted once, so the histories share
+/// component trees. The first entity's branch is checked out.
+///
+/// Component histories enabled by [ImportOptions::component_refs] are kept
+/// per entity, under `<COMPONENT_REFS_PREFIX><entity>/`.
+pub async fn create_multi_release_repository(
+    downloader: &Downloader,
+    path: &Path,
+    entities: &[String],
+    options: &ImportOptions,
+) -> Result<()> {
+    let hooks = &options.hooks;
+    let first = entities
+        .first()
+        .ok_or_else(|| anyhow!("no release entities given"))?;
+
+    let _lock = ImportLock::acquire(path)?;
+    check_destination(path, options.force)?;
+
+    let repo = Repository::init_opts(
+        path,
+        RepositoryInitOptions::new()
+            .bare(options.bare)
+            .initial_head(first),
+    )
+    .context("initialing repository")?;
+
+    let mut state = ReleaseImportState {
+        disk_usage: options.track_disk_usage(&repo, path)?,
+        archive_bytes: Some(0),
+        ..Default::default()
+    };
+
+    let releases = fetch_releases(downloader, options).await?;
+    let histories = entities
+        .iter()
+        .map(|entity| entity_releases(releases.clone(), entity, options))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut heads: Vec<Option<Commit>> = vec![None; entities.len()];
+    let mut last_tag = None;
+
+    for (index, record) in interleave_releases(histories) {
+        if options.shutdown.is_cancelled() {
+            break;
         }
 
-        for fs in futures::future::join_all(
-            missing
-                .into_iter()
-                .map(|component| import_release_component(&downloader, &repo, component)),
+        let tag = format!("{}/{}", entities[index], record.version);
+
+        let commit = commit_release(
+            downloader,
+            &repo,
+            path,
+            &record,
+            &tag,
+            heads[index].as_ref(),
+            &mut state,
+            options,
         )
-        .await
-        {
-            if let Some((component, tree_oid)) = fs? {
-                seen_trees.insert(component.url, tree_oid.clone());
-                root_builder.insert(component.component, tree_oid, GIT_TREE_MODE)?;
-            }
+        .await?;
+
+        if options.component_refs {
+            update_component_refs(
+                &repo,
+                &commit,
+                &format!("{}{}/", COMPONENT_REFS_PREFIX, entities[index]),
+            )?;
         }
+        options.write_release_report(&repo, &commit, heads[index].as_ref(), &tag)?;
 
-        let tree_oid = root_builder.write().context("writing root tree object")?;
-        let tree = repo.find_tree(tree_oid)?;
-
-        let parents = if let Some(parent) = &parent_commit {
-            vec![parent]
-        } else {
-            vec![]
-        };
+        heads[index] = Some(commit);
+        last_tag = Some(tag);
+    }
 
-        let commit_oid = repo.commit(
-            None,
-            &signature,
-            &signature,
-            &format!("{} {}", record.entity, record.version),
-            &tree,
-            &parents,
-        )?;
+    // The first entity is reconciled last so its branch is checked out.
+    for (entity, head) in entities.iter().zip(&heads).rev() {
+        if let Some(head) = head {
+            reconcile_repo_to_commit(&repo, entity, head)?;
+        }
+    }
 
-        println!(
-            "Committed {} version {} as {}",
-            record.entity, record.version, commit_oid
-        );
+    let name = entities.join(",");
+    options.write_metadata(
+        &repo,
+        downloader,
+        RepositoryKind::Release,
+        &name,
+        last_tag.as_deref(),
+    )?;
 
-        let commit = repo.find_commit(commit_oid)?;
+    // Committed releases are kept.
+    if options.shutdown.is_cancelled() {
+        return Err(InterruptedError.into());
+    }
 
-        repo.tag(
-            &record.version,
-            commit.as_object(),
-            &signature,
-            "tagging",
-            true,
-        )?;
+    options.optimize_repository(&repo)?;
 
-        parent_commit = Some(commit);
+    // Resumed imports didn't write all the archives they were checked for.
+    if !options.resume {
+        options.record_disk_usage(path, state.disk_usage.as_ref(), state.archive_bytes)?;
     }
 
-    if let Some(parent) = parent_commit {
-        reconcile_repo_to_commit(&repo, branch_name, &parent)?;
+    for (entity, head) in entities.iter().zip(heads) {
+        hooks
+            .emit(ImportEvent::RepositoryComplete {
+                repository: path.to_path_buf(),
+                branch: entity.clone(),
+                head: head.map(|commit| commit.id().to_string()),
+            })
+            .await;
     }
 
-    Ok(())
+    PartialFailure::check("release components", state.skipped, state.attempted)
 }
@@ -0,0 +1,120 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hooks invoked as imports progress.
+
+use {
+    anyhow::{anyhow, Context, Result},
+    async_trait::async_trait,
+    serde::Serialize,
+    std::{path::PathBuf, process::Stdio, sync::Arc},
+    tokio::{io::AsyncWriteExt, process::Command},
+};
+
+/// An event emitted while importing content into a Git repository.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ImportEvent {
+    /// A commit was created for a version of a component or release.
+    Commit {
+        repository: PathBuf,
+        name: String,
+        version: String,
+        commit: String,
+        url: Option<String>,
+    },
+    /// A tag was created pointing at a commit.
+    Tag {
+        repository: PathBuf,
+        tag: String,
+        commit: String,
+    },
+    /// All versions were imported into a repository.
+    RepositoryComplete {
+        repository: PathBuf,
+        branch: String,
+        head: Option<String>,
+    },
+}
+
+/// Receives events as content is imported.
+///
+/// The import waits for each event to be handled before continuing.
+#[async_trait]
+pub trait ImportHook: Send + Sync {
+    async fn on_event(&self, event: &ImportEvent) -> Result<()>;
+}
+
+/// A hook that runs a shell command, passing the event as JSON on stdin.
+pub struct CommandHook {
+    command: String,
+}
+
+impl CommandHook {
+    pub fn new(command: impl ToString) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ImportHook for CommandHook {
+    async fn on_event(&self, event: &ImportEvent) -> Result<()> {
+        let payload = serde_json::to_vec(event)?;
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("running hook {}", self.command))?;
+
+        // The hook may exit without reading the event, so it is waited for
+        // even if writing fails.
+        let written = child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(&payload)
+            .await;
+
+        let status = child
+            .wait()
+            .await
+            .with_context(|| format!("waiting for hook {}", self.command))?;
+
+        match written {
+            Ok(()) if status.success() => Ok(()),
+            Ok(()) => Err(anyhow!("hook {} exited with {}", self.command, status)),
+            Err(e) => Err(anyhow::Error::new(e).context(format!(
+                "writing event to hook {}, which exited with {}",
+                self.command, status
+            ))),
+        }
+    }
+}
+
+/// A collection of hooks receiving import events.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    hooks: Vec<Arc<dyn ImportHook>>,
+}
+
+impl Hooks {
+    pub fn push(&mut self, hook: impl ImportHook + 'static) {
+        self.hooks.push(Arc::new(hook));
+    }
+
+    /// Deliver an event to all registered hooks.
+    ///
+    /// Hook failures are reported but don't interrupt the import.
+    pub async fn emit(&self, event: ImportEvent) {
+        for hook in &self.hooks {
+            if let Err(e) = hook.on_event(&event).await {
+                println!("warning: hook failed: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Authorship of imported commits and tags.
+//!
+//! Imported commits are authored by Apple Open Source. Their dates are
+//! derived from the record being imported, so history is deterministic: the
+//! same content yields the same commit IDs on every machine.
+
+use {
+    crate::download::{ComponentRecord, ReleaseRecord},
+    anyhow::Result,
+    chrono::NaiveDate,
+    git2::Signature,
+};
+
+/// Name of the author and committer of imported commits.
+pub(crate) const SIGNATURE_NAME: &str = "Apple Open Source";
+
+/// Email of the author and committer of imported commits.
+pub(crate) const SIGNATURE_EMAIL: &str = "opensource@apple.com";
+
+/// Commit time of content without a known date: 2021-01-01 00:00:00 UTC.
+pub const DEFAULT_TIMESTAMP: i64 = 1609459200;
+
+/// Derives the signatures of imported commits and tags.
+///
+/// By default, commits are dated by the record being imported, falling back
+/// to [DEFAULT_TIMESTAMP]. A date override applies to every commit.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CommitIdentity {
+    date: Option<NaiveDate>,
+}
+
+impl CommitIdentity {
+    /// An identity dating every commit at midnight UTC of a date.
+    pub fn with_date(date: NaiveDate) -> Self {
+        Self { date: Some(date) }
+    }
+
+    /// The date overriding the dates of records, if any.
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.date
+    }
+
+    /// The commit time of content published on a date, if known.
+    pub fn timestamp(&self, date: Option<NaiveDate>) -> i64 {
+        self.date
+            .or(date)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .map(|dt| dt.and_utc().timestamp())
+            .unwrap_or(DEFAULT_TIMESTAMP)
+    }
+
+    fn signature(&self, date: Option<NaiveDate>) -> Result<Signature<'static>> {
+        Ok(Signature::new(
+            SIGNATURE_NAME,
+            SIGNATURE_EMAIL,
+            &git2::Time::new(self.timestamp(date), 0),
+        )?)
+    }
+
+    /// The signature of the commit and tag of a software release.
+    pub fn release_signature(&self, record: &ReleaseRecord) -> Result<Signature<'static>> {
+        self.signature(record.date)
+    }
+
+    /// The signature of the commit and tag of a component version.
+    ///
+    /// Component versions aren't dated, so only an override applies.
+    pub fn component_signature(&self, _record: &ComponentRecord) -> Result<Signature<'static>> {
+        self.signature(None)
+    }
+}
@@ -0,0 +1,170 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Classification of the files of imported versions.
+//!
+//! Large and binary files dominate the size of repositories. Reporting them
+//! for every imported version helps choosing Git LFS thresholds and
+//! `--exclude-path` patterns. Reports are written as newline-delimited JSON,
+//! a record per version.
+
+use {
+    crate::ndjson::NdjsonLog,
+    anyhow::{Context, Result},
+    git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult},
+    serde::{Deserialize, Serialize},
+    std::collections::BTreeMap,
+};
+
+/// Files at least this many bytes are listed in reports, even if text.
+pub const LARGE_FILE_THRESHOLD: u64 = 1024 * 1024;
+
+/// Number of leading bytes inspected to classify a file, as Git does.
+const SNIFF_LENGTH: usize = 8000;
+
+/// Whether a file holds text or binary data.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileKind {
+    Text,
+    Binary,
+}
+
+impl FileKind {
+    /// Classify file content.
+    ///
+    /// Like Git, content having a NUL byte in its first 8000 bytes is binary.
+    pub fn classify(data: &[u8]) -> Self {
+        if data[..data.len().min(SNIFF_LENGTH)].contains(&0) {
+            Self::Binary
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// A range of file sizes.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizeBucket {
+    /// Under 100 KiB.
+    Small,
+    /// Under 1 MiB.
+    Medium,
+    /// Under 10 MiB.
+    Large,
+    /// 10 MiB or more.
+    Huge,
+}
+
+impl SizeBucket {
+    pub fn from_size(size: u64) -> Self {
+        match size {
+            s if s < 100 * 1024 => Self::Small,
+            s if s < 1024 * 1024 => Self::Medium,
+            s if s < 10 * 1024 * 1024 => Self::Large,
+            _ => Self::Huge,
+        }
+    }
+}
+
+/// A file listed in a report for being binary or large.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ReportedFile {
+    pub path: String,
+    pub size: u64,
+    pub kind: FileKind,
+}
+
+/// The classified files of an imported version.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct VersionInventory {
+    pub component: String,
+    pub version: Option<String>,
+    pub text_files: usize,
+    pub text_bytes: u64,
+    pub binary_files: usize,
+    pub binary_bytes: u64,
+    /// Number of files in each size range.
+    pub sizes: BTreeMap<SizeBucket, usize>,
+    /// Binary files and files of at least [LARGE_FILE_THRESHOLD] bytes,
+    /// largest first.
+    pub files: Vec<ReportedFile>,
+}
+
+impl VersionInventory {
+    /// Classify the files of a tree.
+    ///
+    /// Symlinks and submodules aren't files and are ignored.
+    pub fn from_tree(
+        repo: &Repository,
+        tree: Oid,
+        component: &str,
+        version: Option<&str>,
+    ) -> Result<Self> {
+        let mut inventory = Self {
+            component: component.to_string(),
+            version: version.map(|v| v.to_string()),
+            ..Self::default()
+        };
+
+        let mut error = None;
+
+        repo.find_tree(tree)?
+            .walk(TreeWalkMode::PreOrder, |root, entry| {
+                if entry.kind() != Some(ObjectType::Blob) || entry.filemode() == 0o120000 {
+                    return TreeWalkResult::Ok;
+                }
+
+                match repo.find_blob(entry.id()) {
+                    Ok(blob) => {
+                        let path =
+                            format!("{}{}", root, String::from_utf8_lossy(entry.name_bytes()));
+                        inventory.add(path, blob.content());
+                        TreeWalkResult::Ok
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        TreeWalkResult::Abort
+                    }
+                }
+            })
+            .context("walking tree")?;
+
+        if let Some(e) = error {
+            return Err(e).context("reading blob");
+        }
+
+        inventory
+            .files
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+        Ok(inventory)
+    }
+
+    fn add(&mut self, path: String, data: &[u8]) {
+        let size = data.len() as u64;
+        let kind = FileKind::classify(data);
+
+        match kind {
+            FileKind::Text => {
+                self.text_files += 1;
+                self.text_bytes += size;
+            }
+            FileKind::Binary => {
+                self.binary_files += 1;
+                self.binary_bytes += size;
+            }
+        }
+
+        *self.sizes.entry(SizeBucket::from_size(size)).or_default() += 1;
+
+        if kind == FileKind::Binary || size >= LARGE_FILE_THRESHOLD {
+            self.files.push(ReportedFile { path, size, kind });
+        }
+    }
+}
+
+/// An append-only report of imported versions.
+pub type InventoryLog = NdjsonLog<VersionInventory>;
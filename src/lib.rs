@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Download Apple's open source code from opensource.apple.com.
+//!
+//! This crate powers the `apple-opensource-downloader` CLI. It can also be used
+//! as a library to query the available software and convert it to Git
+//! repositories.
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod catalog;
+pub mod chronology;
+pub mod compare;
+pub mod config;
+pub mod crawler;
+pub mod crypto;
+pub mod darwin;
+pub mod denylist;
+pub mod destination;
+pub mod disk;
+pub mod doctor;
+pub mod download;
+pub mod error;
+pub mod export;
+pub mod failures;
+pub mod fetch;
+pub mod git;
+pub mod hooks;
+pub mod identity;
+pub mod inventory;
+pub mod lock;
+pub mod maintenance;
+pub mod memory;
+pub mod metadata;
+pub mod middleware;
+pub mod mirror;
+pub mod ndjson;
+pub mod path;
+pub mod platform;
+pub mod progress;
+pub mod report;
+pub mod service;
+pub mod split;
+pub mod status;
+pub mod summary;
+pub mod sync;
+pub mod tasks;
+pub mod telemetry;
+#[cfg(feature = "test-util")]
+pub mod testing;
+pub mod tree;
+pub mod whats_new;
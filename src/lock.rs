@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Advisory locking of import destinations.
+//!
+//! Concurrent imports into the same repository corrupt its state. Imports hold
+//! an exclusive lock on a file next to the destination for their duration.
+//! The operating system releases the lock if the holding process dies, so a
+//! lock file left behind by a crashed import is detected as stale and reused.
+
+use {
+    anyhow::{anyhow, Context, Result},
+    fs2::FileExt,
+    std::{
+        fs::{File, OpenOptions},
+        io::{Read, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Resolve the path of the lock file for a destination.
+pub fn lock_path(dest: &Path) -> PathBuf {
+    let mut name = dest
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(".lock");
+
+    dest.with_file_name(name)
+}
+
+/// An exclusive lock on an import destination, released when dropped.
+#[derive(Debug)]
+pub struct ImportLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl ImportLock {
+    /// Lock a destination, failing if another process holds the lock.
+    pub fn acquire(dest: &Path) -> Result<Self> {
+        let path = lock_path(dest);
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("opening lock file {}", path.display()))?;
+
+        let mut holder = String::new();
+        file.read_to_string(&mut holder)
+            .with_context(|| format!("reading lock file {}", path.display()))?;
+        let holder = holder.trim();
+
+        if file.try_lock_exclusive().is_err() {
+            return Err(anyhow!(
+                "another import (process {}) is running against {}; if that is not the case, remove {}",
+                if holder.is_empty() { "unknown" } else { holder },
+                dest.display(),
+                path.display()
+            ));
+        }
+
+        if !holder.is_empty() {
+            println!(
+                "warning: ignoring stale lock of {} left by process {}",
+                dest.display(),
+                holder
+            );
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())
+            .with_context(|| format!("writing lock file {}", path.display()))?;
+
+        Ok(Self { file, path })
+    }
+}
+
+impl Drop for ImportLock {
+    fn drop(&mut self) {
+        // The file is removed while still locked so another process can't
+        // acquire a lock on a file that is about to disappear.
+        let _ = std::fs::remove_file(&self.path);
+        let _ = FileExt::unlock(&self.file);
+    }
+}
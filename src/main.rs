@@ -3,28 +3,801 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    anyhow::{anyhow, Result},
-    clap::{App, AppSettings, Arg, SubCommand},
-    std::path::Path,
+    anyhow::{anyhow, Context, Result},
+    apple_opensource_downloader::{
+        catalog::{Catalog, CatalogSource},
+        chronology::{self, OrderVerification},
+        compare,
+        config::Config,
+        crawler::{self, GitHubSource, HtmlSource, PageCache, PageChange, Source, WaybackSource},
+        crypto::{self, EncryptionKey},
+        darwin,
+        denylist::{AuditLog, Denylist},
+        destination::DestinationTemplate,
+        disk::{self, DiskUsageLog, SpaceReservations},
+        doctor::{self, CheckStatus},
+        download::{self, Downloader, IpFamily, ReleaseRecord},
+        error,
+        export::{ExportFormat, Table},
+        failures::{self, FailureLog, FailureRecord, Operation},
+        fetch,
+        git::{
+            self, IdenticalVersionPolicy, ImportOptions, MissingComponentPolicy, ReimportOutcome,
+        },
+        hooks::{CommandHook, Hooks},
+        identity::CommitIdentity,
+        inventory::InventoryLog,
+        memory::MemoryBudget,
+        middleware::{CacheLayer, ChecksumLayer, LogLayer, Metrics, MetricsLayer},
+        mirror,
+        platform::{EntityAliases, Platform},
+        progress,
+        service::{ServiceDefinition, ServiceManager},
+        split, status,
+        summary::RunSummary,
+        sync,
+        tasks::{CancelHandle, TaskSet},
+        telemetry::{History, RunRecord, TelemetryLog},
+        tree::{AppleMetadataPolicy, DuplicatePolicy, SymlinkPolicy, TreeConverter, TreeOptions},
+        whats_new::WhatsNew,
+    },
+    chrono::NaiveDate,
+    clap::{App, AppSettings, Arg, ArgMatches, SubCommand},
+    glob::Pattern,
+    std::{
+        collections::BTreeMap,
+        io::Write,
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::{Arc, OnceLock},
+        time::{Duration, Instant, SystemTime},
+    },
 };
 
-pub mod download;
-pub mod git;
+fn failure_log_from_args(args: &ArgMatches) -> Result<Option<Arc<FailureLog>>> {
+    args.value_of_os("failure_log")
+        .map(|path| Ok(Arc::new(FailureLog::open(path)?)))
+        .transpose()
+}
+
+fn content_report_from_args(args: &ArgMatches) -> Result<Option<Arc<InventoryLog>>> {
+    args.value_of_os("content_report")
+        .map(|path| Ok(Arc::new(InventoryLog::open(path)?)))
+        .transpose()
+}
+
+fn denylist_from_args(args: &ArgMatches) -> Result<Option<Arc<Denylist>>> {
+    args.value_of_os("denylist")
+        .map(|path| Ok(Arc::new(Denylist::load(Path::new(path))?)))
+        .transpose()
+}
+
+fn deny_audit_from_args(args: &ArgMatches) -> Result<Option<Arc<AuditLog>>> {
+    args.value_of_os("deny_audit")
+        .map(|path| Ok(Arc::new(AuditLog::open(path)?)))
+        .transpose()
+}
+
+fn tree_options_from_args(args: &ArgMatches, config: &Config) -> Result<TreeOptions> {
+    Ok(TreeOptions {
+        duplicates: if args.is_present("strict") {
+            DuplicatePolicy::Error
+        } else {
+            DuplicatePolicy::LastWins
+        },
+        apple_metadata: match args.value_of("apple_metadata") {
+            None | Some("keep") => AppleMetadataPolicy::Keep,
+            Some("strip") => AppleMetadataPolicy::Strip,
+            Some("sidecar") => AppleMetadataPolicy::Sidecar,
+            Some(value) => return Err(anyhow!("unknown --apple-metadata value: {}", value)),
+        },
+        symlinks: match args.value_of("symlinks") {
+            None | Some("keep") => SymlinkPolicy::Keep,
+            Some("dereference") => SymlinkPolicy::Dereference,
+            Some("skip") => SymlinkPolicy::Skip,
+            Some(value) => return Err(anyhow!("unknown --symlinks value: {}", value)),
+        },
+        strip_components: if args.is_present("keep_root") {
+            0
+        } else {
+            args.value_of("strip_components")
+                .map(|v| v.parse::<usize>().context("parsing --strip-components"))
+                .transpose()?
+                .unwrap_or(1)
+        },
+        exclude: args
+            .values_of("exclude_path")
+            .map(|values| {
+                values
+                    .map(|v| Pattern::new(v).with_context(|| format!("parsing pattern {}", v)))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        // Deny rules apply per component and are resolved by imports.
+        deny: vec![],
+        mode_overrides: config.mode_overrides()?,
+    })
+}
+
+fn since_from_args(args: &ArgMatches) -> Result<Option<NaiveDate>> {
+    args.value_of("since")
+        .map(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").context("parsing --since"))
+        .transpose()
+}
+
+fn bare_arg() -> Arg<'static, 'static> {
+    Arg::with_name("bare")
+        .long("--bare")
+        .overrides_with("no_bare")
+        .help("Create bare Git repositories (the default)")
+}
+
+fn no_bare_arg() -> Arg<'static, 'static> {
+    Arg::with_name("no_bare")
+        .long("--no-bare")
+        .overrides_with("bare")
+        .help("Create Git repositories with working trees")
+}
+
+fn commit_date_arg() -> Arg<'static, 'static> {
+    Arg::with_name("commit_date")
+        .long("--commit-date")
+        .takes_value(true)
+        .help("Date all imported commits at this date (YYYY-MM-DD)")
+}
+
+fn importer_trailers_arg() -> Arg<'static, 'static> {
+    Arg::with_name("importer_trailers")
+        .long("--importer-trailers")
+        .help(
+            "End commit messages with trailers naming the importer version and configuration hash",
+        )
+}
+
+fn optimize_arg() -> Arg<'static, 'static> {
+    Arg::with_name("optimize").long("--optimize").help(
+        "Pack objects and write a multi-pack-index and commit-graph after importing (requires git)",
+    )
+}
+
+fn partial_clone_arg() -> Arg<'static, 'static> {
+    Arg::with_name("partial_clone")
+        .long("--partial-clone")
+        .help("Allow clones of imported repositories to use partial clone filters like --filter=blob:none")
+}
+
+/// Resolve the value of `--format` for a command supporting `formats`.
+///
+/// The first format is the default.
+fn format_value(args: &ArgMatches, formats: &[&'static str]) -> Result<&'static str> {
+    match args.value_of("format") {
+        None => Ok(formats[0]),
+        Some(value) => formats
+            .iter()
+            .find(|format| **format == value)
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "unsupported --format value for this command: {} (expected one of {})",
+                    value,
+                    formats.join(", ")
+                )
+            }),
+    }
+}
+
+fn format_from_args(args: &ArgMatches) -> Result<ExportFormat> {
+    format_value(args, ExportFormat::NAMES)?.parse()
+}
+
+/// Resolve a repository or destination path argument, relative to
+/// `--dest-root` if given.
+fn dest_from_args(args: &ArgMatches, name: &str) -> Option<PathBuf> {
+    let dest = Path::new(args.value_of_os(name)?);
+
+    Some(match args.value_of_os("dest_root") {
+        Some(root) => Path::new(root).join(dest),
+        None => dest.to_path_buf(),
+    })
+}
+
+/// Write a listing to stdout.
+fn write_table(table: &Table, format: ExportFormat) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    table.write(format, &mut stdout)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn format_release(record: &ReleaseRecord) -> String {
+    if let Some(date) = record.date {
+        format!("{}\t{}\t{}", record.entity, record.version, date)
+    } else {
+        format!("{}\t{}", record.entity, record.version)
+    }
+}
+
+/// Write the version to commit mapping of a repository as JSON.
+///
+/// A path of `-` writes to stdout.
+fn write_tag_map(repo_path: &Path, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(&git::tag_commits(repo_path)?)?;
+
+    if path == Path::new("-") {
+        println!("{}", json);
+    } else {
+        std::fs::write(path, format!("{}\n", json))
+            .with_context(|| format!("writing {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+fn github_source(args: &ArgMatches, downloader: &Downloader) -> GitHubSource {
+    GitHubSource::new(downloader.client().clone())
+        .with_token(args.value_of("github_token").map(|s| s.to_string()))
+        .with_page_cache(downloader.page_cache().cloned())
+}
+
+/// Cancelled when the process is asked to shut down.
+static SHUTDOWN: OnceLock<CancelHandle> = OnceLock::new();
+
+/// Obtain a handle cancelled once `SIGINT` or `SIGTERM` is received.
+///
+/// Handlers are installed the first time this is called. Imports observing
+/// the handle stop cleanly after the first signal. A second signal exits
+/// immediately.
+fn shutdown_handle() -> Result<CancelHandle> {
+    if let Some(handle) = SHUTDOWN.get() {
+        return Ok(handle.clone());
+    }
+
+    let handle = CancelHandle::default();
+
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .context("installing SIGTERM handler")?;
+
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        loop {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            #[cfg(not(unix))]
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+
+            if shutdown.is_cancelled() {
+                eprintln!("aborting");
+                std::process::exit(error::EXIT_INTERRUPTED);
+            }
+
+            eprintln!("shutting down after committing the current work; signal again to abort");
+            shutdown.cancel();
+        }
+    });
+
+    Ok(SHUTDOWN.get_or_init(|| handle).clone())
+}
+
+/// Whether created repositories are bare.
+///
+/// `--bare` and `--no-bare` are defined before and after the command name
+/// rather than globally, as clap merges global flags from both sides and loses
+/// their order. On each side the last one given wins, and flags after the
+/// command name take precedence.
+fn bare_from_args(matches: &ArgMatches, args: &ArgMatches) -> bool {
+    [args, matches]
+        .into_iter()
+        .find(|m| m.is_present("bare") || m.is_present("no_bare"))
+        .is_none_or(|m| !m.is_present("no_bare"))
+}
+
+fn import_options_from_args(
+    matches: &ArgMatches,
+    args: &ArgMatches,
+    downloader: &Downloader,
+    config: &Config,
+) -> Result<ImportOptions> {
+    let mut hooks = Hooks::default();
+
+    if let Some(commands) = args.values_of("hook") {
+        for command in commands {
+            hooks.push(CommandHook::new(command));
+        }
+    }
 
-#[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<()> {
+    // Space estimates use the expansion of previously logged imports.
+    let (disk_usage_log, expansion_ratio) = match args.value_of_os("disk_usage_log") {
+        Some(path) => {
+            let log = DiskUsageLog::open(path)?;
+            let ratio = disk::expansion_ratio(&DiskUsageLog::read(path)?);
+
+            (Some(Arc::new(log)), ratio)
+        }
+        None => (None, None),
+    };
+
+    Ok(ImportOptions {
+        bare: bare_from_args(matches, args),
+        archive_refs: args.is_present("archive_refs"),
+        component_refs: args.is_present("component_refs"),
+        release_reports: args.value_of_os("release_reports").map(PathBuf::from),
+        release_refs: args.is_present("release_refs"),
+        versions: args
+            .value_of_os("versions_file")
+            .map(|path| {
+                let path = Path::new(path);
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+
+                download::parse_versions_file(&text)
+                    .with_context(|| format!("parsing {}", path.display()))
+            })
+            .transpose()?,
+        since: since_from_args(args)?,
+        include_updates: args.is_present("include_updates"),
+        clone_url: args.value_of("clone_url").map(|s| s.to_string()),
+        importer_trailers: args.is_present("importer_trailers"),
+        optimize: args.is_present("optimize"),
+        partial_clone: args.is_present("partial_clone"),
+        destination: args
+            .value_of("dest_template")
+            .map(DestinationTemplate::from_str)
+            .transpose()?
+            .unwrap_or_default(),
+        components: args
+            .values_of("components")
+            .map(|values| {
+                values
+                    .map(|v| Pattern::new(v).with_context(|| format!("parsing pattern {}", v)))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        history_sources: if args.is_present("stitch_github") {
+            vec![Arc::new(github_source(args, downloader)) as Arc<dyn Source>]
+        } else {
+            vec![]
+        },
+        hooks,
+        check_disk_space: !args.is_present("skip_space_check"),
+        max_disk_usage: args
+            .value_of("max_disk_usage")
+            .map(download::parse_size)
+            .transpose()?,
+        expansion_ratio,
+        disk_usage_log,
+        space_reservations: SpaceReservations::default(),
+        memory_budget: args
+            .value_of("memory_budget")
+            .or(config.memory_budget.as_deref())
+            .map(download::parse_size)
+            .transpose()?
+            .map(MemoryBudget::new),
+        jobs: args
+            .value_of("jobs")
+            .map(|v| v.parse::<usize>().context("parsing --jobs"))
+            .transpose()?,
+        force: args.is_present("force"),
+        missing_components: match args.value_of("missing") {
+            Some("fail") => MissingComponentPolicy::Fail,
+            Some("placeholder") => MissingComponentPolicy::Placeholder,
+            _ if args.is_present("strict") => MissingComponentPolicy::Fail,
+            None | Some("skip") => MissingComponentPolicy::Skip,
+            Some(value) => return Err(anyhow!("unknown --missing value: {}", value)),
+        },
+        identity: args
+            .value_of("commit_date")
+            .map(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").context("parsing --commit-date"))
+            .transpose()?
+            .map(CommitIdentity::with_date)
+            .unwrap_or_default(),
+        release_order: match args.value_of("verify_order") {
+            None | Some("warn") => OrderVerification::Warn,
+            Some("abort") => OrderVerification::Abort,
+            Some(value) => return Err(anyhow!("unknown --verify-order value: {}", value)),
+        },
+        identical_versions: match args.value_of("identical_versions") {
+            None | Some("commit") => IdenticalVersionPolicy::Commit,
+            Some("tag") => IdenticalVersionPolicy::Tag,
+            Some("annotate") => IdenticalVersionPolicy::Annotate,
+            Some(value) => return Err(anyhow!("unknown --identical-versions value: {}", value)),
+        },
+        prefetch: args
+            .value_of("prefetch")
+            .map(|v| v.parse::<usize>().context("parsing --prefetch"))
+            .transpose()?,
+        failure_log: failure_log_from_args(args)?,
+        content_report: content_report_from_args(args)?,
+        denylist: denylist_from_args(args)?,
+        deny_audit: deny_audit_from_args(args)?,
+        converter: args
+            .value_of("conversion_threads")
+            .map(|v| v.parse::<usize>().context("parsing --conversion-threads"))
+            .transpose()?
+            .map(TreeConverter::new)
+            .unwrap_or_default()
+            .with_options(tree_options_from_args(args, config)?),
+        shutdown: shutdown_handle()?,
+        ..ImportOptions::default()
+    })
+}
+
+fn main() {
+    let started = std::time::Instant::now();
+
+    let matches = match app().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(e)
+            if matches!(
+                e.kind,
+                clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed
+            ) =>
+        {
+            e.exit()
+        }
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(error::EXIT_USAGE);
+        }
+    };
+
+    let non_interactive =
+        matches.is_present("non_interactive") || matches.is_present("github_output");
+    progress::set_interactive(!non_interactive);
+
+    let res = run(&matches);
+
+    if let Err(e) = &res {
+        eprintln!("Error: {:?}", e);
+    }
+
+    let mut code = error::exit_code(&res);
+
+    if non_interactive {
+        let summary = RunSummary::new(
+            matches.subcommand_name().unwrap_or_default(),
+            &res,
+            started.elapsed(),
+        );
+        println!("{}", summary.to_json());
+
+        if matches.is_present("github_output") {
+            let written = std::env::var_os("GITHUB_OUTPUT")
+                .ok_or_else(|| anyhow!("GITHUB_OUTPUT is not set"))
+                .and_then(|path| summary.write_github_output(Path::new(&path)));
+
+            if let Err(e) = written {
+                eprintln!("Error: {:?}", e);
+                code = code.max(error::EXIT_FATAL);
+            }
+        }
+    }
+
+    std::process::exit(code);
+}
+
+/// Build the Tokio runtime, sized from arguments or configuration.
+fn runtime_from_args(matches: &ArgMatches, config: &Config) -> Result<tokio::runtime::Runtime> {
+    let threads = matches
+        .value_of("threads")
+        .map(|v| v.parse::<usize>().context("parsing --threads"))
+        .transpose()?
+        .or(config.threads);
+    let blocking_threads = matches
+        .value_of("blocking_threads")
+        .map(|v| v.parse::<usize>().context("parsing --blocking-threads"))
+        .transpose()?
+        .or(config.blocking_threads);
+
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+
+    if let Some(threads) = threads {
+        if threads == 0 {
+            return Err(anyhow!("--threads must be at least 1"));
+        }
+        builder.worker_threads(threads);
+    }
+    if let Some(threads) = blocking_threads {
+        if threads == 0 {
+            return Err(anyhow!("--blocking-threads must be at least 1"));
+        }
+        builder.max_blocking_threads(threads);
+    }
+
+    builder.build().context("building async runtime")
+}
+
+fn run(matches: &ArgMatches<'static>) -> Result<()> {
+    let config = matches
+        .value_of_os("config")
+        .map(|path| Config::load(Path::new(path)))
+        .transpose()?
+        .unwrap_or_default();
+
+    // Runs rendering the telemetry log aren't worth recording in it.
+    let telemetry_log = matches
+        .value_of_os("telemetry_log")
+        .map(PathBuf::from)
+        .or_else(|| config.telemetry_log.clone())
+        .filter(|_| matches.subcommand_name() != Some("stats"));
+    let metrics = telemetry_log.as_ref().map(|_| Arc::new(Metrics::default()));
+
+    let started = SystemTime::now();
+    let start = Instant::now();
+
+    let res = runtime_from_args(matches, &config)?.block_on(run_command(
+        matches,
+        config,
+        metrics.clone(),
+    ));
+
+    if let (Some(path), Some(metrics)) = (telemetry_log, metrics) {
+        let summary = RunSummary::new(
+            matches.subcommand_name().unwrap_or_default(),
+            &res,
+            start.elapsed(),
+        );
+        let record = RunRecord::new(&summary, &metrics, started);
+
+        if let Err(e) = TelemetryLog::open(&path).and_then(|log| log.record(&record)) {
+            eprintln!("warning: {:#}", e);
+        }
+    }
+
+    res
+}
+
+fn app() -> App<'static, 'static> {
     let app = App::new("Apple Open Source Downloader")
         .setting(AppSettings::ArgRequiredElseHelp)
         .version("0.1")
         .author("Gregory Szorc <gregory.szorc@gmail.com>")
         .about("Download Apple open source code");
 
+    // https://no-color.org/
+    let app = if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        app.setting(AppSettings::ColorNever)
+    } else {
+        app
+    };
+
+    let app = app.arg(
+        Arg::with_name("non_interactive")
+            .long("--non-interactive")
+            .global(true)
+            .help("Hide progress displays and print a JSON summary of the run as the last line"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("github_output")
+            .long("--github-output")
+            .global(true)
+            .help("Write the run summary as GitHub Actions step outputs to $GITHUB_OUTPUT (implies --non-interactive)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("config")
+            .long("--config")
+            .takes_value(true)
+            .global(true)
+            .help("Path to JSON configuration file"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("telemetry_log")
+            .long("--telemetry-log")
+            .takes_value(true)
+            .global(true)
+            .help("Append timing and download statistics of the run to this local file"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("memory_budget")
+            .long("--memory-budget")
+            .takes_value(true)
+            .global(true)
+            .help(
+                "Limit the total size of archives buffered at once by imports (e.g. 4GB or 4GiB)",
+            ),
+    );
+
+    let app = app.arg(
+        Arg::with_name("dest_root")
+            .long("--dest-root")
+            .takes_value(true)
+            .global(true)
+            .help("Directory relative destination paths are resolved against"),
+    );
+
+    let app = app.arg(bare_arg()).arg(no_bare_arg());
+
+    let app = app.arg(
+        Arg::with_name("jobs")
+            .long("--jobs")
+            .takes_value(true)
+            .global(true)
+            .help("Number of repositories to create or update concurrently"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("format")
+            .long("--format")
+            .takes_value(true)
+            .global(true)
+            .help("Output format of listings and reports; supported formats depend on the command"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("verbose")
+            .short("v")
+            .long("--verbose")
+            .global(true)
+            .help("Print each download and its outcome"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("raw_entities")
+            .long("--raw-entities")
+            .global(true)
+            .help("Print release entity names as published instead of canonicalizing aliases"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("cache_dir")
+            .long("--cache-dir")
+            .takes_value(true)
+            .global(true)
+            .help("Directory caching downloaded artifacts"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("cache_key_file")
+            .long("--cache-key-file")
+            .takes_value(true)
+            .global(true)
+            .help("File holding a hex AES-256 key encrypting cached artifacts"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("threads")
+            .long("--threads")
+            .takes_value(true)
+            .global(true)
+            .help("Number of async runtime worker threads (defaults to the number of CPUs)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("blocking_threads")
+            .long("--blocking-threads")
+            .takes_value(true)
+            .global(true)
+            .help("Maximum number of threads for blocking work such as Git tree conversion"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("max_rate")
+            .long("--max-rate")
+            .takes_value(true)
+            .global(true)
+            .help("Maximum download rate (e.g. 5MB/s or 5MiB/s)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("socks5")
+            .long("--socks5")
+            .takes_value(true)
+            .global(true)
+            .help("Route all requests through a SOCKS5 proxy, such as Tor or an SSH tunnel (e.g. 127.0.0.1:9050)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("resolve")
+            .long("--resolve")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .global(true)
+            .help("Connect to an address instead of resolving a host (e.g. opensource.apple.com:17.253.1.1); can be repeated"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("ipv4")
+            .long("--ipv4")
+            .global(true)
+            .conflicts_with("ipv6")
+            .help("Only connect to IPv4 addresses"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("ipv6")
+            .long("--ipv6")
+            .global(true)
+            .help("Only connect to IPv6 addresses"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("index_cache_dir")
+            .long("--index-cache-dir")
+            .takes_value(true)
+            .global(true)
+            .help("Directory caching index pages listing releases, components, and versions"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("index_cache_ttl")
+            .long("--index-cache-ttl")
+            .takes_value(true)
+            .global(true)
+            .help("How long cached index pages are used (e.g. 30m or 12h; defaults to 1h)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("source")
+            .long("--source")
+            .takes_value(true)
+            .global(true)
+            .possible_values(&["apple", "github", "wayback"])
+            .help("Where to discover software: opensource.apple.com (apple), the apple-oss-distributions GitHub organization (github) or archived snapshots of opensource.apple.com (wayback)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("catalog")
+            .long("--catalog")
+            .takes_value(true)
+            .global(true)
+            .help("Serve index pages from a catalog written by `catalog export` instead of crawling, verifying artifacts against its digests"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("source_trees")
+            .long("--source-trees")
+            .global(true)
+            .help("Also use component versions only published as browsable /source/ trees"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("snapshot")
+            .long("--snapshot")
+            .takes_value(true)
+            .global(true)
+            .help("Timestamp of Wayback Machine snapshots to use with --source wayback (e.g. 2011 or 20170615)"),
+    );
+
+    let app = app.arg(
+        Arg::with_name("github_token")
+            .long("--github-token")
+            .takes_value(true)
+            .global(true)
+            .env("GITHUB_TOKEN")
+            .hide_env_values(true)
+            .help("Token authenticating requests to the GitHub API"),
+    );
+
     let app = app
         .subcommand(SubCommand::with_name("components").about("Print available component names"));
 
     let app = app.subcommand(
         SubCommand::with_name("component-versions")
             .about("Print available versions of a given component")
+            .arg(
+                Arg::with_name("manifests")
+                    .long("--manifests")
+                    .help("Also print metadata from .plist manifests of each component"),
+            )
+            .arg(
+                Arg::with_name("details")
+                    .long("--details")
+                    .help("Print archive sizes and modification times, fetching them with HEAD requests when not listed"),
+            )
             .arg(
                 Arg::with_name("component")
                     .multiple(true)
@@ -32,171 +805,2259 @@ async fn main() -> Result<()> {
             ),
     );
 
-    let app = app.subcommand(
-        SubCommand::with_name("component-to-git")
-            .about("Fetch an Apple open source component and convert to a Git repository")
-            .arg(
-                Arg::with_name("no_bare")
-                    .long("--no-bare")
-                    .help("Do not create a bare repository"),
-            )
-            .arg(
-                Arg::with_name("component")
-                    .required(true)
-                    .help("component name"),
+    let app = app.subcommand(
+        SubCommand::with_name("component-to-git")
+            .about("Fetch an Apple open source component and convert to a Git repository")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(commit_date_arg())
+            .arg(importer_trailers_arg())
+            .arg(optimize_arg())
+            .arg(partial_clone_arg())
+            .arg(
+                Arg::with_name("map_file")
+                    .long("--map-file")
+                    .takes_value(true)
+                    .help("Write a JSON mapping of versions to commits to this file (- for stdout)"),
+            )
+            .arg(
+                Arg::with_name("split_top_level")
+                    .long("--split-top-level")
+                    .conflicts_with("map_file")
+                    .help("Create a repository per top-level directory of the component under the destination directory"),
+            )
+            .arg(
+                Arg::with_name("apple_metadata")
+                    .long("--apple-metadata")
+                    .takes_value(true)
+                    .possible_values(&["keep", "strip", "sidecar"])
+                    .default_value("keep")
+                    .help("How to handle AppleDouble ._ files and pax header members"),
+            )
+            .arg(
+                Arg::with_name("symlinks")
+                    .long("--symlinks")
+                    .takes_value(true)
+                    .possible_values(&["keep", "dereference", "skip"])
+                    .default_value("keep")
+                    .help("How to handle symlinks in archives"),
+            )
+            .arg(
+                Arg::with_name("conversion_threads")
+                    .long("--conversion-threads")
+                    .takes_value(true)
+                    .help("Number of archives to convert to Git trees concurrently"),
+            )
+            .arg(
+                Arg::with_name("prefetch")
+                    .long("--prefetch")
+                    .takes_value(true)
+                    .help("Number of component versions to download and convert ahead of the version being committed"),
+            )
+            .arg(
+                Arg::with_name("identical_versions")
+                    .long("--identical-versions")
+                    .takes_value(true)
+                    .possible_values(&["commit", "tag", "annotate"])
+                    .default_value("commit")
+                    .help("How to handle versions identical to the previous version: create an empty commit, only tag the previous commit, or create an annotated empty commit"),
+            )
+            .arg(
+                Arg::with_name("failure_log")
+                    .long("--failure-log")
+                    .takes_value(true)
+                    .help("Append records of failures to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("content_report")
+                    .long("--content-report")
+                    .takes_value(true)
+                    .help("Append a report of the binary and large files of each imported version to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("denylist")
+                    .long("--denylist")
+                    .takes_value(true)
+                    .help("JSON file of rules naming components and paths that must not be imported"),
+            )
+            .arg(
+                Arg::with_name("deny_audit")
+                    .long("--deny-audit")
+                    .takes_value(true)
+                    .requires("denylist")
+                    .help("Append records of content excluded by --denylist to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("strip_components")
+                    .long("--strip-components")
+                    .takes_value(true)
+                    .help("Number of leading path components to remove from archive members (default 1)"),
+            )
+            .arg(
+                Arg::with_name("keep_root")
+                    .long("--keep-root")
+                    .conflicts_with("strip_components")
+                    .help("Import archive members at their full paths (same as --strip-components 0)"),
+            )
+            .arg(
+                Arg::with_name("exclude_path")
+                    .long("--exclude-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Leave archive members matching this glob (e.g. 'Tests/**') out of imports"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("--strict")
+                    .help("Fail on archives containing duplicate paths instead of using the last"),
+            )
+            .arg(
+                Arg::with_name("skip_space_check")
+                    .long("--skip-space-check")
+                    .help("Do not verify there is sufficient disk space before importing"),
+            )
+            .arg(
+                Arg::with_name("disk_usage_log")
+                    .long("--disk-usage-log")
+                    .takes_value(true)
+                    .help("Record the disk space imports use to this file and estimate required space from it"),
+            )
+            .arg(
+                Arg::with_name("max_disk_usage")
+                    .long("--max-disk-usage")
+                    .takes_value(true)
+                    .help("Abort if a repository exceeds this size on disk (e.g. 10GB or 10GiB)"),
+            )
+            .arg(
+                Arg::with_name("hook")
+                    .long("--hook")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Shell command to run after each commit, tag, and repository completion"),
+            )
+            .arg(
+                Arg::with_name("archive_refs")
+                    .long("--archive-refs")
+                    .help("Store original archives in the repository under refs/apple-oss/archives/"),
+            )
+            .arg(
+                Arg::with_name("release_refs")
+                    .long("--release-refs")
+                    .help("Reference the version shipped in each software release as refs/releases/<entity>-<version>"),
+            )
+            .arg(
+                Arg::with_name("versions_file")
+                    .long("--versions-file")
+                    .takes_value(true)
+                    .help("File listing the versions to import in order, a version per line optionally followed by the URL of its archive"),
+            )
+            .arg(
+                Arg::with_name("stitch_github")
+                    .long("--stitch-github")
+                    .help("Merge versions tagged in the apple-oss-distributions GitHub repositories"),
+            )
+            .arg(
+                Arg::with_name("clone_url")
+                    .long("--clone-url")
+                    .takes_value(true)
+                    .help("URL to advertise in the repository's cloneurl file ({name} is replaced with the repository name)"),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .long("--force")
+                    .help("Import into destinations that are not empty and not previously imported repositories"),
+            )
+            .arg(
+                Arg::with_name("component")
+                    .required(true)
+                    .help("component name"),
+            )
+            .arg(
+                Arg::with_name("dest")
+                    .required(true)
+                    .help("Destination directory of Git repository"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("component-version-to-git")
+            .about("Append a single version of a component as a commit to an existing Git repository")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(commit_date_arg())
+            .arg(importer_trailers_arg())
+            .arg(optimize_arg())
+            .arg(partial_clone_arg())
+            .arg(
+                Arg::with_name("branch")
+                    .long("--branch")
+                    .takes_value(true)
+                    .default_value("main")
+                    .help("Branch to append the version to"),
+            )
+            .arg(
+                Arg::with_name("apple_metadata")
+                    .long("--apple-metadata")
+                    .takes_value(true)
+                    .possible_values(&["keep", "strip", "sidecar"])
+                    .default_value("keep")
+                    .help("How to handle AppleDouble ._ files and pax header members"),
+            )
+            .arg(
+                Arg::with_name("symlinks")
+                    .long("--symlinks")
+                    .takes_value(true)
+                    .possible_values(&["keep", "dereference", "skip"])
+                    .default_value("keep")
+                    .help("How to handle symlinks in archives"),
+            )
+            .arg(
+                Arg::with_name("identical_versions")
+                    .long("--identical-versions")
+                    .takes_value(true)
+                    .possible_values(&["commit", "tag", "annotate"])
+                    .default_value("commit")
+                    .help("How to handle a version identical to the branch head: create an empty commit, only tag the head, or create an annotated empty commit"),
+            )
+            .arg(
+                Arg::with_name("strip_components")
+                    .long("--strip-components")
+                    .takes_value(true)
+                    .help("Number of leading path components to remove from archive members (default 1)"),
+            )
+            .arg(
+                Arg::with_name("keep_root")
+                    .long("--keep-root")
+                    .conflicts_with("strip_components")
+                    .help("Import archive members at their full paths (same as --strip-components 0)"),
+            )
+            .arg(
+                Arg::with_name("exclude_path")
+                    .long("--exclude-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Leave archive members matching this glob (e.g. 'Tests/**') out of imports"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("--strict")
+                    .help("Fail on archives containing duplicate paths instead of using the last"),
+            )
+            .arg(
+                Arg::with_name("skip_space_check")
+                    .long("--skip-space-check")
+                    .help("Do not verify there is sufficient disk space before importing"),
+            )
+            .arg(
+                Arg::with_name("disk_usage_log")
+                    .long("--disk-usage-log")
+                    .takes_value(true)
+                    .help("Record the disk space imports use to this file and estimate required space from it"),
+            )
+            .arg(
+                Arg::with_name("hook")
+                    .long("--hook")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Shell command to run after each commit, tag, and repository completion"),
+            )
+            .arg(
+                Arg::with_name("archive_refs")
+                    .long("--archive-refs")
+                    .help("Store the original archive in the repository under refs/apple-oss/archives/"),
+            )
+            .arg(
+                Arg::with_name("release_refs")
+                    .long("--release-refs")
+                    .help("Reference the version shipped in each software release as refs/releases/<entity>-<version>"),
+            )
+            .arg(
+                Arg::with_name("component")
+                    .required(true)
+                    .help("Name of component to import"),
+            )
+            .arg(
+                Arg::with_name("version")
+                    .required(true)
+                    .help("Version of the component to import"),
+            )
+            .arg(
+                Arg::with_name("repo")
+                    .required(true)
+                    .help("Path to existing Git repository"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("reimport")
+            .about("Rebuild a component repository with the current import logic and compare it with its history")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(
+                Arg::with_name("rewrite")
+                    .long("--rewrite")
+                    .help("Fast-forward to the rebuilt history, or preserve it on a new main-vN branch if it diverges"),
+            )
+            .arg(commit_date_arg())
+            .arg(importer_trailers_arg())
+            .arg(optimize_arg())
+            .arg(partial_clone_arg())
+            .arg(
+                Arg::with_name("apple_metadata")
+                    .long("--apple-metadata")
+                    .takes_value(true)
+                    .possible_values(&["keep", "strip", "sidecar"])
+                    .default_value("keep")
+                    .help("How to handle AppleDouble ._ files and pax header members"),
+            )
+            .arg(
+                Arg::with_name("symlinks")
+                    .long("--symlinks")
+                    .takes_value(true)
+                    .possible_values(&["keep", "dereference", "skip"])
+                    .default_value("keep")
+                    .help("How to handle symlinks in archives"),
+            )
+            .arg(
+                Arg::with_name("conversion_threads")
+                    .long("--conversion-threads")
+                    .takes_value(true)
+                    .help("Number of archives to convert to Git trees concurrently"),
+            )
+            .arg(
+                Arg::with_name("identical_versions")
+                    .long("--identical-versions")
+                    .takes_value(true)
+                    .possible_values(&["commit", "tag", "annotate"])
+                    .default_value("commit")
+                    .help("How to handle versions identical to the previous version: create an empty commit, only tag the previous commit, or create an annotated empty commit"),
+            )
+            .arg(
+                Arg::with_name("strip_components")
+                    .long("--strip-components")
+                    .takes_value(true)
+                    .help("Number of leading path components to remove from archive members (default 1)"),
+            )
+            .arg(
+                Arg::with_name("keep_root")
+                    .long("--keep-root")
+                    .conflicts_with("strip_components")
+                    .help("Import archive members at their full paths (same as --strip-components 0)"),
+            )
+            .arg(
+                Arg::with_name("exclude_path")
+                    .long("--exclude-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Leave archive members matching this glob (e.g. 'Tests/**') out of imports"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("--strict")
+                    .help("Fail on archives containing duplicate paths instead of using the last"),
+            )
+            .arg(
+                Arg::with_name("repo")
+                    .required(true)
+                    .help("Path of a component repository created by component-to-git"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("components-to-gits")
+            .about("Fetch Apple open source components and convert to Git repositories")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(commit_date_arg())
+            .arg(importer_trailers_arg())
+            .arg(optimize_arg())
+            .arg(partial_clone_arg())
+            .arg(
+                Arg::with_name("apple_metadata")
+                    .long("--apple-metadata")
+                    .takes_value(true)
+                    .possible_values(&["keep", "strip", "sidecar"])
+                    .default_value("keep")
+                    .help("How to handle AppleDouble ._ files and pax header members"),
+            )
+            .arg(
+                Arg::with_name("symlinks")
+                    .long("--symlinks")
+                    .takes_value(true)
+                    .possible_values(&["keep", "dereference", "skip"])
+                    .default_value("keep")
+                    .help("How to handle symlinks in archives"),
+            )
+            .arg(
+                Arg::with_name("conversion_threads")
+                    .long("--conversion-threads")
+                    .takes_value(true)
+                    .help("Number of archives to convert to Git trees concurrently"),
+            )
+            .arg(
+                Arg::with_name("prefetch")
+                    .long("--prefetch")
+                    .takes_value(true)
+                    .help("Number of component versions to download and convert ahead of the version being committed"),
+            )
+            .arg(
+                Arg::with_name("identical_versions")
+                    .long("--identical-versions")
+                    .takes_value(true)
+                    .possible_values(&["commit", "tag", "annotate"])
+                    .default_value("commit")
+                    .help("How to handle versions identical to the previous version: create an empty commit, only tag the previous commit, or create an annotated empty commit"),
+            )
+            .arg(
+                Arg::with_name("failure_log")
+                    .long("--failure-log")
+                    .takes_value(true)
+                    .help("Append records of failures to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("content_report")
+                    .long("--content-report")
+                    .takes_value(true)
+                    .help("Append a report of the binary and large files of each imported version to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("denylist")
+                    .long("--denylist")
+                    .takes_value(true)
+                    .help("JSON file of rules naming components and paths that must not be imported"),
+            )
+            .arg(
+                Arg::with_name("deny_audit")
+                    .long("--deny-audit")
+                    .takes_value(true)
+                    .requires("denylist")
+                    .help("Append records of content excluded by --denylist to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("strip_components")
+                    .long("--strip-components")
+                    .takes_value(true)
+                    .help("Number of leading path components to remove from archive members (default 1)"),
+            )
+            .arg(
+                Arg::with_name("keep_root")
+                    .long("--keep-root")
+                    .conflicts_with("strip_components")
+                    .help("Import archive members at their full paths (same as --strip-components 0)"),
+            )
+            .arg(
+                Arg::with_name("exclude_path")
+                    .long("--exclude-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Leave archive members matching this glob (e.g. 'Tests/**') out of imports"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("--strict")
+                    .help("Fail on archives containing duplicate paths instead of using the last"),
+            )
+            .arg(
+                Arg::with_name("skip_space_check")
+                    .long("--skip-space-check")
+                    .help("Do not verify there is sufficient disk space before importing"),
+            )
+            .arg(
+                Arg::with_name("disk_usage_log")
+                    .long("--disk-usage-log")
+                    .takes_value(true)
+                    .help("Record the disk space imports use to this file and estimate required space from it"),
+            )
+            .arg(
+                Arg::with_name("max_disk_usage")
+                    .long("--max-disk-usage")
+                    .takes_value(true)
+                    .help("Abort if a repository exceeds this size on disk (e.g. 10GB or 10GiB)"),
+            )
+            .arg(
+                Arg::with_name("hook")
+                    .long("--hook")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Shell command to run after each commit, tag, and repository completion"),
+            )
+            .arg(
+                Arg::with_name("archive_refs")
+                    .long("--archive-refs")
+                    .help("Store original archives in the repository under refs/apple-oss/archives/"),
+            )
+            .arg(
+                Arg::with_name("release_refs")
+                    .long("--release-refs")
+                    .help("Reference the version shipped in each software release as refs/releases/<entity>-<version>"),
+            )
+            .arg(
+                Arg::with_name("stitch_github")
+                    .long("--stitch-github")
+                    .help("Merge versions tagged in the apple-oss-distributions GitHub repositories"),
+            )
+            .arg(
+                Arg::with_name("clone_url")
+                    .long("--clone-url")
+                    .takes_value(true)
+                    .help("URL to advertise in the repository's cloneurl file ({name} is replaced with the repository name)"),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .long("--force")
+                    .help("Import into destinations that are not empty and not previously imported repositories"),
+            )
+            .arg(
+                Arg::with_name("dest_template")
+                    .long("--dest-template")
+                    .takes_value(true)
+                    .help("Template of repository paths, e.g. {dest}/{component[0]}/{component}.git (default {dest}/{component})"),
+            )
+            .arg(
+                Arg::with_name("dest")
+                    .required(true)
+                    .help("Destination directory for Git repositories"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("retry-failed")
+            .about("Re-attempt operations recorded in a failure log")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(
+                Arg::with_name("apple_metadata")
+                    .long("--apple-metadata")
+                    .takes_value(true)
+                    .possible_values(&["keep", "strip", "sidecar"])
+                    .default_value("keep")
+                    .help("How to handle AppleDouble ._ files and pax header members"),
+            )
+            .arg(
+                Arg::with_name("symlinks")
+                    .long("--symlinks")
+                    .takes_value(true)
+                    .possible_values(&["keep", "dereference", "skip"])
+                    .default_value("keep")
+                    .help("How to handle symlinks in archives"),
+            )
+            .arg(
+                Arg::with_name("conversion_threads")
+                    .long("--conversion-threads")
+                    .takes_value(true)
+                    .help("Number of archives to convert to Git trees concurrently"),
+            )
+            .arg(
+                Arg::with_name("prefetch")
+                    .long("--prefetch")
+                    .takes_value(true)
+                    .help("Number of component versions to download and convert ahead of the version being committed"),
+            )
+            .arg(
+                Arg::with_name("identical_versions")
+                    .long("--identical-versions")
+                    .takes_value(true)
+                    .possible_values(&["commit", "tag", "annotate"])
+                    .default_value("commit")
+                    .help("How to handle versions identical to the previous version: create an empty commit, only tag the previous commit, or create an annotated empty commit"),
+            )
+            .arg(
+                Arg::with_name("failure_log")
+                    .long("--failure-log")
+                    .takes_value(true)
+                    .help("Append records of failures to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("content_report")
+                    .long("--content-report")
+                    .takes_value(true)
+                    .help("Append a report of the binary and large files of each imported version to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("denylist")
+                    .long("--denylist")
+                    .takes_value(true)
+                    .help("JSON file of rules naming components and paths that must not be imported"),
+            )
+            .arg(
+                Arg::with_name("deny_audit")
+                    .long("--deny-audit")
+                    .takes_value(true)
+                    .requires("denylist")
+                    .help("Append records of content excluded by --denylist to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("hook")
+                    .long("--hook")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Shell command to run after each commit, tag, and repository completion"),
+            )
+            .arg(
+                Arg::with_name("strip_components")
+                    .long("--strip-components")
+                    .takes_value(true)
+                    .help("Number of leading path components to remove from archive members (default 1)"),
+            )
+            .arg(
+                Arg::with_name("keep_root")
+                    .long("--keep-root")
+                    .conflicts_with("strip_components")
+                    .help("Import archive members at their full paths (same as --strip-components 0)"),
+            )
+            .arg(
+                Arg::with_name("exclude_path")
+                    .long("--exclude-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Leave archive members matching this glob (e.g. 'Tests/**') out of imports"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("--strict")
+                    .help("Fail on archives containing duplicate paths instead of using the last"),
+            )
+            .arg(
+                Arg::with_name("skip_space_check")
+                    .long("--skip-space-check")
+                    .help("Do not verify there is sufficient disk space before importing"),
+            )
+            .arg(
+                Arg::with_name("disk_usage_log")
+                    .long("--disk-usage-log")
+                    .takes_value(true)
+                    .help("Record the disk space imports use to this file and estimate required space from it"),
+            )
+            .arg(
+                Arg::with_name("log")
+                    .required(true)
+                    .help("Failure log written by a previous invocation"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("releases")
+            .about("Print available software releases")
+            .arg(
+                Arg::with_name("summary")
+                    .long("--summary")
+                    .help("Group releases by platform and only print the newest of each"),
+            )
+            .arg(
+                Arg::with_name("count")
+                    .long("--count")
+                    .takes_value(true)
+                    .requires("summary")
+                    .help("Number of releases per platform to print with --summary (default 5)"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("--since")
+                    .takes_value(true)
+                    .help("Only print releases published on or after this date (YYYY-MM-DD)"),
+            )
+            .arg(
+                Arg::with_name("include_updates")
+                    .long("--include-updates")
+                    .help("Also print updates only linked from release pages (fetches every release page)"),
+            )
+            .arg(
+                Arg::with_name("platform")
+                    .help("Only print releases of this platform (e.g. macos or ios)"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("release-components")
+            .about("Print available components within a software release")
+            .arg(
+                Arg::with_name("all_artifacts")
+                    .long("--all-artifacts")
+                    .help("Include non-tarball artifacts such as installer packages"),
+            )
+            .arg(
+                Arg::with_name("release")
+                    .required(true)
+                    .help("Name of software release"),
+            )
+            .arg(
+                Arg::with_name("version")
+                    .required(true)
+                    .help("Version of software release"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("download")
+            .about("Download the artifacts of a software release to a directory")
+            .arg(
+                Arg::with_name("failure_log")
+                    .long("--failure-log")
+                    .takes_value(true)
+                    .help("Append records of failures to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("content_report")
+                    .long("--content-report")
+                    .takes_value(true)
+                    .help("Append a report of the binary and large files of each imported version to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("denylist")
+                    .long("--denylist")
+                    .takes_value(true)
+                    .help("JSON file of rules naming components and paths that must not be imported"),
+            )
+            .arg(
+                Arg::with_name("deny_audit")
+                    .long("--deny-audit")
+                    .takes_value(true)
+                    .requires("denylist")
+                    .help("Append records of content excluded by --denylist to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("all_artifacts")
+                    .long("--all-artifacts")
+                    .help("Download non-tarball artifacts such as installer packages"),
+            )
+            .arg(
+                Arg::with_name("release")
+                    .required(true)
+                    .help("Name of software release"),
+            )
+            .arg(
+                Arg::with_name("version")
+                    .required(true)
+                    .help("Version of software release"),
+            )
+            .arg(
+                Arg::with_name("dest")
+                    .required(true)
+                    .help("Directory to write artifacts to"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("index")
+            .about("Crawl all index pages into the index cache and report pages that changed")
+            .arg(
+                Arg::with_name("update")
+                    .long("--update")
+                    .help("Revalidate every cached page regardless of its age, downloading only pages that changed"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("catalog")
+            .about("Share crawled metadata as a catalog file")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("export")
+                    .about("Crawl all metadata and write it to a catalog")
+                    .arg(
+                        Arg::with_name("path")
+                            .required(true)
+                            .help("File to write the catalog to"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("import")
+                    .about("Validate a catalog and verify cached artifacts against its digests")
+                    .arg(
+                        Arg::with_name("path")
+                            .required(true)
+                            .help("Catalog file to validate"),
+                    ),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("whats-new")
+            .about("Report components, versions, and releases published since a prior snapshot")
+            .arg(
+                Arg::with_name("since")
+                    .long("--since")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Catalog written by `catalog export`, or a date as YYYY-MM-DD"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("status")
+            .about("Report whether a component repository has every available version")
+            .arg(
+                Arg::with_name("component")
+                    .required(true)
+                    .help("component name"),
+            )
+            .arg(
+                Arg::with_name("dest")
+                    .required(true)
+                    .help("Directory of Git repository"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("doctor")
+            .about("Check network access, caches, and disk space, suggesting fixes for problems")
+            .arg(
+                Arg::with_name("dest")
+                    .help("Directory repositories are created in (defaults to --dest-root or the current directory)"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("stats")
+            .about("Analyze statistics recorded by --telemetry-log")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("history")
+                    .about("Chart timing and failures of recorded runs over time")
+                    .arg(Arg::with_name("log").long("--log").takes_value(true).help(
+                        "Telemetry log to read (defaults to --telemetry-log or the configured log)",
+                    ))
+                    .arg(
+                        Arg::with_name("command")
+                            .long("--command")
+                            .takes_value(true)
+                            .help("Only show runs of this command"),
+                    )
+                    .arg(
+                        Arg::with_name("last")
+                            .long("--last")
+                            .takes_value(true)
+                            .help("Only show this many of the most recent runs"),
+                    ),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("fetch-by-url")
+            .about("Fetch an artifact by URL, verifying its SHA-256 digest")
+            .arg(
+                Arg::with_name("sha256")
+                    .long("--sha256")
+                    .takes_value(true)
+                    .help("Fail unless the artifact has this SHA-256 digest"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .long("--output")
+                    .takes_value(true)
+                    .required(true)
+                    .help("File to write the artifact to"),
+            )
+            .arg(
+                Arg::with_name("url")
+                    .required(true)
+                    .help("URL of the artifact"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("fetch-by-digest")
+            .about("Fetch a previously fetched artifact by SHA-256 digest")
+            .arg(
+                Arg::with_name("output")
+                    .long("--output")
+                    .takes_value(true)
+                    .required(true)
+                    .help("File to write the artifact to"),
+            )
+            .arg(
+                Arg::with_name("sha256")
+                    .required(true)
+                    .help("SHA-256 digest of the artifact"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("release-diff")
+            .about("Report components that changed between two versions of a software release")
+            .arg(
+                Arg::with_name("files")
+                    .long("--files")
+                    .help("Download changed components and report changed files"),
+            )
+            .arg(
+                Arg::with_name("release")
+                    .required(true)
+                    .help("Name of software release"),
+            )
+            .arg(
+                Arg::with_name("old_version")
+                    .required(true)
+                    .help("Version of software release to compare from"),
+            )
+            .arg(
+                Arg::with_name("new_version")
+                    .help("Version to compare to (defaults to the next release)"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("darwin")
+            .about("Print the macOS release and xnu version for a Darwin version")
+            .arg(
+                Arg::with_name("macos")
+                    .long("--macos")
+                    .help("Interpret the version as a macOS version"),
+            )
+            .arg(
+                Arg::with_name("version")
+                    .required(true)
+                    .help("Darwin version (e.g. 22.1.0)"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("export-tree")
+            .about("Write the files of a version in an imported repository to a directory")
+            .arg(
+                Arg::with_name("repo")
+                    .required(true)
+                    .help("Path to Git repository"),
+            )
+            .arg(
+                Arg::with_name("tag")
+                    .required(true)
+                    .help("Tag (or other revision) to export"),
+            )
+            .arg(
+                Arg::with_name("dest")
+                    .required(true)
+                    .help("Directory to write files to"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("release-to-git")
+            .about("Convert a released entity to a Git repository")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(commit_date_arg())
+            .arg(importer_trailers_arg())
+            .arg(optimize_arg())
+            .arg(partial_clone_arg())
+            .arg(
+                Arg::with_name("apple_metadata")
+                    .long("--apple-metadata")
+                    .takes_value(true)
+                    .possible_values(&["keep", "strip", "sidecar"])
+                    .default_value("keep")
+                    .help("How to handle AppleDouble ._ files and pax header members"),
+            )
+            .arg(
+                Arg::with_name("symlinks")
+                    .long("--symlinks")
+                    .takes_value(true)
+                    .possible_values(&["keep", "dereference", "skip"])
+                    .default_value("keep")
+                    .help("How to handle symlinks in archives"),
+            )
+            .arg(
+                Arg::with_name("conversion_threads")
+                    .long("--conversion-threads")
+                    .takes_value(true)
+                    .help("Number of archives to convert to Git trees concurrently"),
+            )
+            .arg(
+                Arg::with_name("failure_log")
+                    .long("--failure-log")
+                    .takes_value(true)
+                    .help("Append records of failures to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("content_report")
+                    .long("--content-report")
+                    .takes_value(true)
+                    .help("Append a report of the binary and large files of each imported version to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("denylist")
+                    .long("--denylist")
+                    .takes_value(true)
+                    .help("JSON file of rules naming components and paths that must not be imported"),
+            )
+            .arg(
+                Arg::with_name("deny_audit")
+                    .long("--deny-audit")
+                    .takes_value(true)
+                    .requires("denylist")
+                    .help("Append records of content excluded by --denylist to this NDJSON file"),
+            )
+            .arg(
+                Arg::with_name("strip_components")
+                    .long("--strip-components")
+                    .takes_value(true)
+                    .help("Number of leading path components to remove from archive members (default 1)"),
+            )
+            .arg(
+                Arg::with_name("keep_root")
+                    .long("--keep-root")
+                    .conflicts_with("strip_components")
+                    .help("Import archive members at their full paths (same as --strip-components 0)"),
+            )
+            .arg(
+                Arg::with_name("exclude_path")
+                    .long("--exclude-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Leave archive members matching this glob (e.g. 'Tests/**') out of imports"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("--strict")
+                    .help("Fail on archives containing duplicate paths and on components that fail to download"),
+            )
+            .arg(
+                Arg::with_name("missing")
+                    .long("--missing")
+                    .takes_value(true)
+                    .possible_values(&["skip", "fail", "placeholder"])
+                    .help("How to handle components that fail to download (default skip, or fail with --strict)"),
+            )
+            .arg(
+                Arg::with_name("verify_order")
+                    .long("--verify-order")
+                    .takes_value(true)
+                    .possible_values(&["warn", "abort"])
+                    .default_value("warn")
+                    .help("How to handle releases whose versions don't increase chronologically"),
+            )
+            .arg(
+                Arg::with_name("skip_space_check")
+                    .long("--skip-space-check")
+                    .help("Do not verify there is sufficient disk space before importing"),
+            )
+            .arg(
+                Arg::with_name("disk_usage_log")
+                    .long("--disk-usage-log")
+                    .takes_value(true)
+                    .help("Record the disk space imports use to this file and estimate required space from it"),
+            )
+            .arg(
+                Arg::with_name("max_disk_usage")
+                    .long("--max-disk-usage")
+                    .takes_value(true)
+                    .help("Abort if a repository exceeds this size on disk (e.g. 10GB or 10GiB)"),
+            )
+            .arg(
+                Arg::with_name("hook")
+                    .long("--hook")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Shell command to run after each commit, tag, and repository completion"),
+            )
+            .arg(
+                Arg::with_name("archive_refs")
+                    .long("--archive-refs")
+                    .help("Store original archives in the repository under refs/apple-oss/archives/"),
+            )
+            .arg(
+                Arg::with_name("components")
+                    .long("--components")
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .number_of_values(1)
+                    .help("Only import these comma-separated components (glob patterns allowed)"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("--since")
+                    .takes_value(true)
+                    .help("Only import releases published on or after this date (YYYY-MM-DD)"),
+            )
+            .arg(
+                Arg::with_name("include_updates")
+                    .long("--include-updates")
+                    .help("Also import updates only linked from release pages (fetches every release page)"),
+            )
+            .arg(
+                Arg::with_name("clone_url")
+                    .long("--clone-url")
+                    .takes_value(true)
+                    .help("URL to advertise in the repository's cloneurl file ({name} is replaced with the repository name)"),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .long("--force")
+                    .help("Import into destinations that are not empty and not previously imported repositories"),
+            )
+            .arg(
+                Arg::with_name("component_refs")
+                    .long("--component-refs")
+                    .help("Maintain refs/components/<component> references to the history of each component"),
+            )
+            .arg(
+                Arg::with_name("release_reports")
+                    .long("--release-reports")
+                    .takes_value(true)
+                    .help("Directory to write a Markdown summary of each imported release to"),
+            )
+            .arg(
+                Arg::with_name("entities")
+                    .long("--entities")
+                    .takes_value(true)
+                    .help("Comma separated entities whose releases are imported on parallel branches, e.g. macos,ios. Only the destination is then given"),
+            )
+            .arg(
+                Arg::with_name("release")
+                    .required_unless("entities")
+                    .help("Name of released entity"),
+            )
+            .arg(
+                Arg::with_name("dest")
+                    .required_unless("entities")
+                    .help("Destination directory of Git repository"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("compare-repos")
+            .about(
+                "Verify two independently imported repositories have identical trees for every tag",
+            )
+            .arg(
+                Arg::with_name("a")
+                    .required(true)
+                    .help("Path of the first repository"),
+            )
+            .arg(
+                Arg::with_name("b")
+                    .required(true)
+                    .help("Path of the second repository"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("mirror-config")
+            .about("Print cgit or gitolite configuration declaring mirrored repositories")
+            .arg(
+                Arg::with_name("dir")
+                    .required(true)
+                    .help("Directory containing mirrored repositories"),
+            ),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("sync")
+            .visible_alias("update")
+            .about(
+                "Update the components and releases in the sync section of the configuration file",
+            )
+            .arg(bare_arg())
+            .arg(no_bare_arg()),
+    );
+
+    let app = app.subcommand(
+        SubCommand::with_name("watch")
+            .about("Periodically update the components and releases in the sync section of the configuration file")
+            .arg(bare_arg())
+            .arg(no_bare_arg())
+            .arg(
+                Arg::with_name("interval")
+                    .long("--interval")
+                    .takes_value(true)
+                    .default_value("1h")
+                    .help("How long to wait between updates, e.g. 30m"),
+            )
+            .arg(
+                Arg::with_name("install_service")
+                    .long("--install-service")
+                    .takes_value(true)
+                    .possible_values(&["systemd", "launchd"])
+                    .help("Instead of watching, install a service running this command with the current configuration"),
+            )
+            .arg(
+                Arg::with_name("service_path")
+                    .long("--service-path")
+                    .takes_value(true)
+                    .requires("install_service")
+                    .help("Where to write the service definition instead of the per-user location"),
+            ),
+    );
+
+    app
+}
+
+async fn run_command(
+    matches: &ArgMatches<'static>,
+    config: Config,
+    metrics: Option<Arc<Metrics>>,
+) -> Result<()> {
+    let max_rate = matches
+        .value_of("max_rate")
+        .map(download::parse_rate)
+        .transpose()?;
+
+    let proxy = matches
+        .value_of("socks5")
+        .map(download::parse_socks5)
+        .transpose()?;
+
+    let resolve = matches
+        .values_of("resolve")
+        .map(|values| {
+            values
+                .map(download::parse_resolve)
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let ip_family = if matches.is_present("ipv4") {
+        IpFamily::V4
+    } else if matches.is_present("ipv6") {
+        IpFamily::V6
+    } else {
+        IpFamily::Any
+    };
+
+    let mut builder = Downloader::builder()?
+        .proxy(proxy)?
+        .resolve(resolve)?
+        .ip_family(ip_family)?
+        .max_rate(max_rate)
+        .url_schemes(config.url_schemes()?)
+        .version_orders(config.version_orders()?)
+        .entity_aliases(if matches.is_present("raw_entities") {
+            EntityAliases::disabled()
+        } else {
+            config.entity_aliases()
+        });
+
+    let cache_dir = matches
+        .value_of_os("cache_dir")
+        .map(PathBuf::from)
+        .or_else(|| config.cache_dir.clone());
+    let cache_key_file = matches
+        .value_of_os("cache_key_file")
+        .map(PathBuf::from)
+        .or_else(|| config.cache_key_file.clone());
+
+    let catalog = matches
+        .value_of_os("catalog")
+        .map(|path| Catalog::load(Path::new(path)))
+        .transpose()?;
+
+    // Verify artifacts before the cache serves them.
+    if let Some(catalog) = &catalog {
+        builder = builder.layer(ChecksumLayer::new(catalog.checksums.clone()));
+    }
+
+    let cache = match (cache_dir, cache_key_file) {
+        (Some(dir), Some(key_file)) => {
+            Some(CacheLayer::new(dir).with_encryption(EncryptionKey::load(&key_file)?))
+        }
+        (Some(dir), None) => Some(CacheLayer::new(dir)),
+        (None, Some(_)) => {
+            return Err(anyhow!("a cache key requires a cache directory"));
+        }
+        (None, None) => None,
+    };
+
+    if let Some(cache) = &cache {
+        builder = builder.layer(cache.clone());
+    }
+
+    // Cached artifacts aren't downloaded, so aren't logged or measured.
+    if matches.is_present("verbose") {
+        builder = builder.layer(LogLayer);
+    }
+    if let Some(metrics) = metrics {
+        builder = builder.layer(MetricsLayer::new(metrics));
+    }
+
+    let index_cache_ttl = match matches
+        .value_of("index_cache_ttl")
+        .or(config.index_cache_ttl.as_deref())
+    {
+        // Every cached page is revalidated.
+        _ if matches
+            .subcommand_matches("index")
+            .is_some_and(|args| args.is_present("update")) =>
+        {
+            Duration::ZERO
+        }
+        Some(ttl) => download::parse_duration(ttl)?,
+        None => crawler::cache::DEFAULT_TTL,
+    };
+    let page_cache = matches
+        .value_of_os("index_cache_dir")
+        .map(PathBuf::from)
+        .or_else(|| config.index_cache_dir.clone())
+        .map(|dir| PageCache::new(dir, index_cache_ttl));
+
+    let version_parser = config.version_parser()?;
+    let source = HtmlSource::new(builder.client().clone())
+        .with_version_parser(version_parser.clone())
+        .with_page_cache(page_cache.clone());
+    let downloader = builder
+        .source(Arc::new(source))
+        .page_cache(page_cache)
+        .source_trees(matches.is_present("source_trees"))
+        .build();
+
+    if matches.is_present("snapshot") && matches.value_of("source") != Some("wayback") {
+        return Err(anyhow!("--snapshot requires --source wayback"));
+    }
+
+    let downloader = match matches.value_of("source") {
+        Some("github") => {
+            let source = github_source(matches, &downloader);
+
+            downloader.with_source(Arc::new(source))
+        }
+        Some("wayback") => {
+            let timestamp = matches
+                .value_of("snapshot")
+                .ok_or_else(|| anyhow!("--source wayback requires --snapshot"))?;
+            let source = WaybackSource::new(downloader.client().clone(), timestamp)?
+                .with_version_parser(version_parser)
+                .with_page_cache(downloader.page_cache().cloned());
+
+            downloader.with_source(Arc::new(source))
+        }
+        _ => downloader,
+    };
+
+    let downloader = match catalog {
+        Some(catalog) => {
+            let source = CatalogSource::new(catalog, downloader.source());
+
+            downloader.with_source(Arc::new(source))
+        }
+        None => downloader,
+    };
+
+    match matches.subcommand() {
+        ("components", Some(args)) => {
+            let mut table = Table::new(&["component"]);
+            for component in downloader.get_components().await? {
+                table.push(vec![Some(component)]);
+            }
+
+            write_table(&table, format_from_args(args)?)
+        }
+
+        ("component-versions", Some(args)) => {
+            let format = format_from_args(args)?;
+            if format != ExportFormat::Text && args.is_present("manifests") {
+                return Err(anyhow!("--manifests is only supported with --format text"));
+            }
+
+            let mut versions = if let Some(components) = args.values_of("component") {
+                let mut versions = BTreeMap::new();
+
+                for component in components {
+                    versions.insert(
+                        component.to_string(),
+                        downloader.get_component_versions(component).await?,
+                    );
+                }
+
+                versions
+            } else {
+                downloader.get_components_versions().await?
+            };
+
+            let details = args.is_present("details");
+            if details {
+                downloader
+                    .enrich_component_records(versions.values_mut().flatten())
+                    .await;
+            }
+
+            if format != ExportFormat::Text {
+                let mut table = Table::new(&[
+                    "component",
+                    "version",
+                    "filename",
+                    "url",
+                    "size",
+                    "last_modified",
+                ]);
+                for record in versions.into_values().flatten() {
+                    table.push(vec![
+                        Some(record.component),
+                        Some(record.version),
+                        Some(record.filename),
+                        Some(record.url),
+                        record.size.map(|size| size.to_string()),
+                        record.last_modified.map(|t| t.to_string()),
+                    ]);
+                }
+
+                return write_table(&table, format);
+            }
+
+            for (component, records) in versions {
+                if records.is_empty() {
+                    eprintln!("{}: no versions available", component);
+                }
+
+                for record in records {
+                    if details {
+                        println!(
+                            "{}\t{}\t{}\t{}\t{}",
+                            record.component,
+                            record.version,
+                            record.url,
+                            record
+                                .size
+                                .map(|size| size.to_string())
+                                .unwrap_or_else(|| "-".to_string()),
+                            record
+                                .last_modified
+                                .map(|t| t.to_string())
+                                .unwrap_or_else(|| "-".to_string())
+                        );
+                    } else {
+                        println!("{}\t{}\t{}", record.component, record.version, record.url);
+                    }
+                }
+
+                if args.is_present("manifests") {
+                    for manifest in downloader.get_component_manifests(&component).await? {
+                        println!(
+                            "{}\tmanifest\t{}\t{}\t{}",
+                            component,
+                            manifest.project_name.as_deref().unwrap_or("-"),
+                            manifest.version.as_deref().unwrap_or("-"),
+                            manifest.url
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        ("component-to-git", Some(args)) => {
+            let component = args
+                .value_of("component")
+                .expect("component argument is required");
+            let dest = &dest_from_args(args, "dest").expect("dest argument is required");
+            let options = import_options_from_args(matches, args, &downloader, &config)?;
+
+            if args.is_present("split_top_level") {
+                for path in split::create_split_component_repositories(
+                    &downloader,
+                    dest,
+                    component,
+                    &options,
+                )
+                .await?
+                {
+                    println!("created {}", path.display());
+                }
+
+                return Ok(());
+            }
+
+            git::create_component_repository(&downloader, dest, component, &options).await?;
+
+            if let Some(map_file) = args.value_of_os("map_file") {
+                write_tag_map(dest, Path::new(map_file))?;
+            }
+
+            Ok(())
+        }
+
+        ("component-version-to-git", Some(args)) => {
+            let component = args
+                .value_of("component")
+                .expect("component argument is required");
+            let version = args
+                .value_of("version")
+                .expect("version argument is required");
+            let repo = &dest_from_args(args, "repo").expect("repo argument is required");
+
+            let commit = git::append_component_version(
+                &downloader,
+                repo,
+                component,
+                version,
+                args.value_of("branch").expect("branch has default value"),
+                &import_options_from_args(matches, args, &downloader, &config)?,
             )
-            .arg(
-                Arg::with_name("dest")
-                    .required(true)
-                    .help("Destination directory of Git repository"),
-            ),
-    );
+            .await?;
 
-    let app = app.subcommand(
-        SubCommand::with_name("components-to-gits")
-            .about("Fetch Apple open source components and convert to Git repositories")
-            .arg(
-                Arg::with_name("no_bare")
-                    .long("--no-bare")
-                    .help("Do not create bare Git repositories)"),
-            )
-            .arg(
-                Arg::with_name("dest")
-                    .required(true)
-                    .help("Destination directory for Git repositories"),
-            ),
-    );
+            println!("{} {} is commit {}", component, version, commit);
 
-    let app = app
-        .subcommand(SubCommand::with_name("releases").about("Print available software releases"));
+            Ok(())
+        }
 
-    let app = app.subcommand(
-        SubCommand::with_name("release-components")
-            .about("Print available components within a software release")
-            .arg(
-                Arg::with_name("release")
-                    .required(true)
-                    .help("Name of software release"),
+        ("reimport", Some(args)) => {
+            let repo = &dest_from_args(args, "repo").expect("repo argument is required");
+
+            let outcome = git::reimport_component_repository(
+                &downloader,
+                repo,
+                args.is_present("rewrite"),
+                &import_options_from_args(matches, args, &downloader, &config)?,
             )
-            .arg(
-                Arg::with_name("version")
-                    .required(true)
-                    .help("Version of software release"),
-            ),
-    );
+            .await?;
 
-    let app = app.subcommand(
-        SubCommand::with_name("release-to-git")
-            .about("Convert a released entity to a Git repository")
-            .arg(
-                Arg::with_name("no_bare")
-                    .long("--no-bare")
-                    .help("Do not create a bare repository"),
+            match outcome {
+                ReimportOutcome::Identical => {
+                    println!("rebuilt history is identical");
+                }
+                ReimportOutcome::FastForward { added } => {
+                    println!("rebuilt history adds versions: {}", added.join(", "));
+
+                    if !args.is_present("rewrite") {
+                        println!("use --rewrite to fast-forward to it");
+                    }
+                }
+                ReimportOutcome::Diverged { changed, branch } => {
+                    println!(
+                        "rebuilt history differs at versions: {}",
+                        changed.join(", ")
+                    );
+
+                    match branch {
+                        Some(branch) => println!("rebuilt history preserved on branch {}", branch),
+                        None => println!("use --rewrite to preserve it on a new branch"),
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        ("components-to-gits", Some(args)) => {
+            let dest = &dest_from_args(args, "dest").expect("dest argument is required");
+
+            git::create_components_repositories(
+                &downloader,
+                dest,
+                &import_options_from_args(matches, args, &downloader, &config)?,
             )
-            .arg(
-                Arg::with_name("release")
-                    .required(true)
-                    .help("Name of released entity"),
+            .await
+        }
+
+        ("retry-failed", Some(args)) => {
+            let log = args.value_of_os("log").expect("log argument is required");
+            let records = FailureLog::read(log)?;
+
+            failures::retry_failures(
+                &downloader,
+                &records,
+                &import_options_from_args(matches, args, &downloader, &config)?,
             )
-            .arg(
-                Arg::with_name("dest")
-                    .required(true)
-                    .help("Destination directory of Git repository"),
-            ),
-    );
+            .await
+        }
 
-    let matches = app.get_matches();
+        ("releases", Some(args)) => {
+            let format = format_from_args(args)?;
+            let platform = args.value_of("platform").map(Platform::from_entity);
+            let since = since_from_args(args)?;
 
-    match matches.subcommand() {
-        ("components", _) => {
-            let downloader = crate::download::Downloader::new()?;
+            let records = if args.is_present("include_updates") {
+                downloader.get_releases_with_updates().await?
+            } else {
+                downloader.get_releases().await?
+            };
 
-            for component in downloader.get_components().await? {
-                println!("{}", component);
+            let records = chronology::order_platform_releases(
+                records
+                    .into_iter()
+                    .filter(|record| platform.as_ref().is_none_or(|p| *p == record.platform())),
+                since,
+            );
+
+            if format != ExportFormat::Text {
+                if args.is_present("summary") {
+                    return Err(anyhow!("--summary is only supported with --format text"));
+                }
+
+                let mut table = Table::new(&[
+                    "entity",
+                    "original_entity",
+                    "platform",
+                    "version",
+                    "date",
+                    "url",
+                ]);
+                for record in records {
+                    table.push(vec![
+                        Some(record.entity.clone()),
+                        record.original_entity.clone(),
+                        Some(record.platform().to_string()),
+                        Some(record.version),
+                        record.date.map(|date| date.to_string()),
+                        Some(record.url),
+                    ]);
+                }
+
+                return write_table(&table, format);
+            }
+
+            if args.is_present("summary") {
+                let count = args
+                    .value_of("count")
+                    .unwrap_or("5")
+                    .parse::<usize>()
+                    .context("parsing --count")?;
+
+                let mut groups = BTreeMap::<Platform, Vec<_>>::new();
+                for record in records {
+                    groups.entry(record.platform()).or_default().push(record);
+                }
+
+                for (platform, records) in groups {
+                    println!("{} ({} releases)", platform, records.len());
+
+                    for record in records.iter().rev().take(count) {
+                        println!("  {}", format_release(record));
+                    }
+                }
+            } else {
+                for record in records {
+                    println!("{}", format_release(&record));
+                }
             }
 
             Ok(())
         }
 
-        ("component-versions", Some(args)) => {
-            let downloader = crate::download::Downloader::new()?;
+        ("release-components", Some(args)) => {
+            let all_artifacts = args.is_present("all_artifacts");
+            let release = args
+                .value_of("release")
+                .expect("release argument is required");
+            let version = args
+                .value_of("version")
+                .expect("version argument is required");
+
+            let record = downloader.find_release(release, version).await?;
+            let components = downloader
+                .get_release_components(&record, all_artifacts)
+                .await?;
 
-            if let Some(components) = args.values_of("component") {
+            let format = format_from_args(args)?;
+            if format != ExportFormat::Text {
+                let mut table = Table::new(&["entity", "component", "kind", "version", "url"]);
                 for component in components {
-                    for record in downloader.get_component_versions(component).await? {
-                        println!("{}\t{}\t{}", record.component, record.version, record.url);
-                    }
+                    table.push(vec![
+                        Some(component.entity.clone()),
+                        Some(component.component.clone()),
+                        Some(component.kind.as_str().to_string()),
+                        component.version().map(|v| v.to_string()),
+                        Some(component.url),
+                    ]);
                 }
-            } else {
-                for records in downloader.get_components_versions().await?.values() {
-                    for record in records {
-                        println!("{}\t{}\t{}", record.component, record.version, record.url);
+
+                return write_table(&table, format);
+            }
+
+            for component in components {
+                if all_artifacts {
+                    println!(
+                        "{}\t{}\t{}",
+                        component.component,
+                        component.kind.as_str(),
+                        component.url
+                    );
+                } else {
+                    println!("{}\t{}", component.component, component.url);
+                }
+            }
+
+            Ok(())
+        }
+
+        ("index", Some(_)) => {
+            let page_cache = downloader
+                .page_cache()
+                .cloned()
+                .ok_or_else(|| anyhow!("index requires --index-cache-dir"))?;
+
+            Catalog::crawl(downloader.source(), matches.is_present("source_trees")).await?;
+
+            let changes = page_cache.changes();
+            let count = |change| changes.values().filter(|c| **c == change).count();
+
+            for (url, change) in &changes {
+                if *change != PageChange::Unchanged {
+                    println!("{}\t{}", change.as_str(), url);
+                }
+            }
+            println!(
+                "fetched {} pages: {} new, {} changed, {} unchanged",
+                changes.len(),
+                count(PageChange::New),
+                count(PageChange::Changed),
+                count(PageChange::Unchanged)
+            );
+
+            Ok(())
+        }
+
+        ("catalog", Some(args)) => match args.subcommand() {
+            ("export", Some(args)) => {
+                let path = Path::new(args.value_of_os("path").expect("path argument is required"));
+
+                let mut catalog =
+                    Catalog::crawl(downloader.source(), matches.is_present("source_trees")).await?;
+                if let Some(cache) = &cache {
+                    catalog.add_cached_checksums(cache)?;
+                }
+
+                catalog.write(path)?;
+                println!(
+                    "wrote {}: {} releases, {} components, {} digests",
+                    path.display(),
+                    catalog.releases.len(),
+                    catalog.components.len(),
+                    catalog.checksums.len()
+                );
+
+                Ok(())
+            }
+            ("import", Some(args)) => {
+                let path = Path::new(args.value_of_os("path").expect("path argument is required"));
+
+                let catalog = Catalog::load(path)?;
+                println!(
+                    "{} is valid: {} releases, {} components, {} digests",
+                    path.display(),
+                    catalog.releases.len(),
+                    catalog.components.len(),
+                    catalog.checksums.len()
+                );
+
+                if let Some(cache) = &cache {
+                    let verification = catalog.verify_cache(cache)?;
+
+                    for mismatch in &verification.mismatched {
+                        println!(
+                            "{}: cached SHA-256 is {}, catalog has {}",
+                            mismatch.url, mismatch.actual, mismatch.expected
+                        );
+                    }
+                    println!(
+                        "verified {} cached artifacts; {} artifacts aren't cached",
+                        verification.verified, verification.missing
+                    );
+
+                    if !verification.mismatched.is_empty() {
+                        return Err(anyhow!(
+                            "{} cached artifacts don't match the catalog",
+                            verification.mismatched.len()
+                        ));
                     }
                 }
+
+                Ok(())
+            }
+            _ => Err(anyhow!("invalid sub-sub-command")),
+        },
+
+        ("whats-new", Some(args)) => {
+            let since = args.value_of("since").expect("since argument is required");
+
+            let current =
+                Catalog::crawl(downloader.source(), matches.is_present("source_trees")).await?;
+
+            let report = match NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+                Ok(date) => WhatsNew::since_date(&current, date),
+                Err(_) => WhatsNew::between(&Catalog::load(Path::new(since))?, &current, since),
+            };
+
+            match format_value(args, &["text", "json", "markdown"])? {
+                "json" => print!("{}", report.to_json()?),
+                "markdown" => print!("{}", report.to_markdown()),
+                _ => print!("{}", report.to_text()),
             }
 
             Ok(())
         }
 
-        ("component-to-git", Some(args)) => {
-            let bare = !args.is_present("no_bare");
+        ("status", Some(args)) => {
             let component = args
                 .value_of("component")
                 .expect("component argument is required");
-            let dest = Path::new(args.value_of_os("dest").expect("dest argument is required"));
+            let dest = &dest_from_args(args, "dest").expect("dest argument is required");
+
+            let status = status::repo_status(&downloader, dest, component).await?;
+
+            match format_value(args, &["text", "json"])? {
+                "json" => print!("{}", status.to_json()?),
+                _ => print!("{}", status.to_text()),
+            }
 
-            crate::git::create_component_repository(dest, component, bare).await
+            Ok(())
         }
 
-        ("components-to-gits", Some(args)) => {
-            let bare = !args.is_present("no_bare");
-            let dest = Path::new(args.value_of_os("dest").expect("dest argument is required"));
+        ("doctor", Some(args)) => {
+            let dest = dest_from_args(args, "dest")
+                .or_else(|| args.value_of_os("dest_root").map(PathBuf::from))
+                .unwrap_or_else(|| PathBuf::from("."));
+            let cache_dir = matches
+                .value_of_os("cache_dir")
+                .map(PathBuf::from)
+                .or_else(|| config.cache_dir.clone());
+
+            let diagnosis =
+                doctor::diagnose(downloader.client(), &dest, cache_dir.as_deref()).await;
+
+            match format_value(args, &["text", "json"])? {
+                "json" => print!("{}", diagnosis.to_json()?),
+                _ => print!("{}", diagnosis.to_text()),
+            }
 
-            crate::git::create_components_repositories(dest, bare).await
+            if diagnosis.status() == CheckStatus::Error {
+                Err(anyhow!("problems were found"))
+            } else {
+                Ok(())
+            }
         }
 
-        ("releases", _) => {
-            let downloader = crate::download::Downloader::new()?;
+        ("stats", Some(args)) => match args.subcommand() {
+            ("history", Some(args)) => {
+                let log = args
+                    .value_of_os("log")
+                    .or_else(|| matches.value_of_os("telemetry_log"))
+                    .map(PathBuf::from)
+                    .or_else(|| config.telemetry_log.clone())
+                    .ok_or_else(|| {
+                        anyhow!("no telemetry log; pass --log or configure telemetry_log")
+                    })?;
+                let last = args
+                    .value_of("last")
+                    .map(|v| v.parse::<usize>().context("parsing --last"))
+                    .transpose()?;
+
+                let history =
+                    History::new(TelemetryLog::read(&log)?, args.value_of("command"), last);
 
-            for record in downloader.get_releases().await? {
-                println!("{}\t{}", record.entity, record.version);
+                match format_value(args, &["text", "json"])? {
+                    "json" => print!("{}", history.to_json()?),
+                    _ => print!("{}", history.to_text()),
+                }
+
+                Ok(())
             }
+            _ => Err(anyhow!("invalid sub-sub-command")),
+        },
+
+        ("fetch-by-url", Some(args)) => {
+            let url = args.value_of("url").expect("url argument is required");
+            let sha256 = args
+                .value_of("sha256")
+                .map(fetch::parse_sha256)
+                .transpose()?;
+            let output = Path::new(
+                args.value_of_os("output")
+                    .expect("output argument is required"),
+            );
+
+            let data = fetch::fetch_by_url(&downloader, url, sha256.as_deref()).await?;
+
+            std::fs::write(output, &data)
+                .with_context(|| format!("writing {}", output.display()))?;
+            println!(
+                "wrote {} (sha256 {})",
+                output.display(),
+                crypto::sha256_hex(&data)
+            );
 
             Ok(())
         }
 
-        ("release-components", Some(args)) => {
+        ("fetch-by-digest", Some(args)) => {
+            let sha256 = fetch::parse_sha256(
+                args.value_of("sha256")
+                    .expect("sha256 argument is required"),
+            )?;
+            let output = Path::new(
+                args.value_of_os("output")
+                    .expect("output argument is required"),
+            );
+
+            let cache = cache
+                .as_ref()
+                .ok_or_else(|| anyhow!("fetching by digest requires --cache-dir"))?;
+
+            let (url, data) = fetch::fetch_by_digest(&downloader, cache, &sha256).await?;
+
+            std::fs::write(output, &data)
+                .with_context(|| format!("writing {}", output.display()))?;
+            match url {
+                Some(url) => println!(
+                    "wrote {} from {} (sha256 {})",
+                    output.display(),
+                    url,
+                    sha256
+                ),
+                None => println!("wrote {} (sha256 {})", output.display(), sha256),
+            }
+
+            Ok(())
+        }
+
+        ("download", Some(args)) => {
+            let all_artifacts = args.is_present("all_artifacts");
             let release = args
                 .value_of("release")
                 .expect("release argument is required");
             let version = args
                 .value_of("version")
                 .expect("version argument is required");
+            let dest = &dest_from_args(args, "dest").expect("dest argument is required");
+
+            let record = downloader.find_release(release, version).await?;
+
+            let components = downloader
+                .get_release_components(&record, all_artifacts)
+                .await?;
+
+            std::fs::create_dir_all(dest)
+                .with_context(|| format!("creating {}", dest.display()))?;
+
+            let failure_log = failure_log_from_args(args)?;
+
+            // Artifacts linked more than once are only downloaded once.
+            let mut artifacts = vec![];
+            for (component, filename) in components
+                .iter()
+                .zip(download::artifact_filenames(&components))
+            {
+                let path = dest.join(filename);
+                if artifacts.iter().all(|(_, p)| p != &path) {
+                    artifacts.push((component, path));
+                }
+            }
+
+            let mut tasks = TaskSet::new();
+
+            for (component, path) in &artifacts {
+                let downloader = downloader.clone();
+                let component = (*component).clone();
+                let path = path.clone();
+
+                tasks.spawn(component.url.clone(), async move {
+                    let data = downloader.get_release_component_record(&component).await?;
 
-            let downloader = crate::download::Downloader::new()?;
+                    std::fs::write(&path, data)
+                        .with_context(|| format!("writing {}", path.display()))?;
+                    println!("wrote {}", path.display());
 
-            let record = downloader
+                    Ok(())
+                });
+            }
+
+            let results = tasks.join().await;
+
+            let mut failures = 0;
+            for ((component, path), outcome) in artifacts.iter().zip(&results) {
+                if let Err(e) = &outcome.result {
+                    println!("error downloading {}: {:?}", component.url, e);
+                    failures += 1;
+
+                    if let Some(log) = &failure_log {
+                        let mut failure = FailureRecord::from_error(
+                            Operation::Download,
+                            &component.component,
+                            path,
+                            e,
+                        );
+                        failure.version = component.version().map(|v| v.to_string());
+                        failure.url = Some(component.url.clone());
+                        failure.release = Some(record.entity.clone());
+                        failure.release_version = Some(record.version.clone());
+                        log.record(&failure)?;
+                    }
+                }
+            }
+
+            error::PartialFailure::check("downloads", failures, results.len())
+        }
+
+        ("release-diff", Some(args)) => {
+            let files = args.is_present("files");
+            let release = args
+                .value_of("release")
+                .expect("release argument is required");
+            let old_version = args
+                .value_of("old_version")
+                .expect("old_version argument is required");
+
+            let records = downloader
                 .get_releases()
                 .await?
                 .into_iter()
-                .find(|record| record.entity == release && record.version == version)
-                .ok_or_else(|| anyhow!("failed to find version {} of {}", version, release))?;
+                .filter(|record| record.matches_entity(release))
+                .collect::<Vec<_>>();
+
+            let old_index = records
+                .iter()
+                .position(|record| record.version == old_version)
+                .ok_or_else(|| anyhow!("failed to find version {} of {}", old_version, release))?;
+            let old_record = &records[old_index];
+
+            let new_record = if let Some(new_version) = args.value_of("new_version") {
+                records
+                    .iter()
+                    .find(|record| record.version == new_version)
+                    .ok_or_else(|| {
+                        anyhow!("failed to find version {} of {}", new_version, release)
+                    })?
+            } else {
+                records
+                    .get(old_index + 1)
+                    .ok_or_else(|| anyhow!("no release of {} after {}", release, old_version))?
+            };
+
+            println!(
+                "comparing {} {} to {} {}",
+                old_record.entity, old_record.version, new_record.entity, new_record.version
+            );
+
+            let old_components = downloader.get_release_components(old_record, false).await?;
+            let new_components = downloader.get_release_components(new_record, false).await?;
+
+            for change in download::diff_release_components(&old_components, &new_components) {
+                match change {
+                    download::ComponentChange::Added(record) => {
+                        println!(
+                            "added\t{}\t-\t{}",
+                            record.component,
+                            record.version().unwrap_or("-")
+                        );
+                    }
+                    download::ComponentChange::Removed(record) => {
+                        println!(
+                            "removed\t{}\t{}\t-",
+                            record.component,
+                            record.version().unwrap_or("-")
+                        );
+                    }
+                    download::ComponentChange::Changed { old, new } => {
+                        println!(
+                            "changed\t{}\t{}\t{}",
+                            new.component,
+                            old.version().unwrap_or("-"),
+                            new.version().unwrap_or("-")
+                        );
+
+                        if files {
+                            let old_data = downloader.get_release_component_record(&old).await?;
+                            let new_data = downloader.get_release_component_record(&new).await?;
+
+                            for (status, path) in git::diff_tar_data(old_data, new_data).await? {
+                                println!("\t{:?}\t{}", status, path);
+                            }
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        ("darwin", Some(args)) => {
+            let version = args
+                .value_of("version")
+                .expect("version argument is required");
+
+            let releases = if args.is_present("macos") {
+                darwin::find_macos(version).into_iter().collect()
+            } else {
+                darwin::find_darwin(version)
+            };
+
+            if releases.is_empty() {
+                return Err(anyhow!("no known release for version {}", version));
+            }
+
+            let records = downloader.get_releases().await?;
+
+            for release in releases {
+                let release_url = records
+                    .iter()
+                    .find(|record| {
+                        record.matches_entity("macos") && record.version == release.macos
+                    })
+                    .map(|record| record.url.as_str())
+                    .unwrap_or("-");
 
-            for component in downloader.get_release_components(&record).await? {
-                println!("{}\t{}", component.component, component.url);
+                println!(
+                    "macos {}\tdarwin {}\txnu {}\t{}\t{}",
+                    release.macos,
+                    release.darwin,
+                    release.xnu,
+                    release_url,
+                    release.xnu_url()
+                );
             }
 
             Ok(())
         }
 
+        ("export-tree", Some(args)) => {
+            let repo = &dest_from_args(args, "repo").expect("repo argument is required");
+            let tag = args.value_of("tag").expect("tag argument is required");
+            let dest = &dest_from_args(args, "dest").expect("dest argument is required");
+
+            let count = git::export_tree(repo, tag, dest)?;
+            println!("wrote {} files to {}", count, dest.display());
+
+            Ok(())
+        }
+
         ("release-to-git", Some(args)) => {
-            let bare = !args.is_present("no_bare");
+            let options = import_options_from_args(matches, args, &downloader, &config)?;
+
+            if let Some(entities) = args.value_of("entities") {
+                // The destination is the only positional argument.
+                if args.is_present("dest") {
+                    return Err(anyhow!("--entities replaces the release argument"));
+                }
+                let dest = &dest_from_args(args, "release")
+                    .ok_or_else(|| anyhow!("the destination directory is required"))?;
+                let entities = entities
+                    .split(',')
+                    .map(|entity| entity.trim().to_string())
+                    .filter(|entity| !entity.is_empty())
+                    .collect::<Vec<_>>();
+
+                return git::create_multi_release_repository(
+                    &downloader,
+                    dest,
+                    &entities,
+                    &options,
+                )
+                .await;
+            }
+
             let release = args
                 .value_of("release")
                 .expect("release argument is required");
-            let dest = Path::new(args.value_of_os("dest").expect("dest argument is required"));
+            let dest = &dest_from_args(args, "dest").expect("dest argument is required");
+
+            git::create_release_repository(&downloader, dest, release, &options).await
+        }
+
+        ("compare-repos", Some(args)) => {
+            let a = &dest_from_args(args, "a").expect("a argument is required");
+            let b = &dest_from_args(args, "b").expect("b argument is required");
+
+            let comparisons = compare::compare_repositories(a, b)?;
+            write_table(
+                &compare::comparisons_table(&comparisons),
+                format_from_args(args)?,
+            )?;
+
+            let divergent = comparisons
+                .iter()
+                .filter(|c| c.status() != compare::TagStatus::Match)
+                .count();
+
+            if divergent > 0 {
+                Err(anyhow!(
+                    "repositories diverge at {} of {} tags",
+                    divergent,
+                    comparisons.len()
+                ))
+            } else {
+                Ok(())
+            }
+        }
+
+        ("mirror-config", Some(args)) => {
+            let dir = &dest_from_args(args, "dir").expect("dir argument is required");
+            let repos = mirror::find_repositories(dir)?;
+
+            print!(
+                "{}",
+                match format_value(args, &["cgit", "gitolite"])? {
+                    "gitolite" => mirror::gitolite_conf(&repos),
+                    _ => mirror::cgitrc(&repos),
+                }
+            );
+
+            Ok(())
+        }
+
+        ("sync", Some(args)) => {
+            let mut options = import_options_from_args(matches, args, &downloader, &config)?;
+            if config.sync.stitch_github {
+                options.history_sources = vec![Arc::new(github_source(matches, &downloader))];
+            }
+
+            sync::sync(&downloader, &config.sync, &options).await
+        }
+
+        ("watch", Some(args)) => {
+            let interval = download::parse_duration(
+                args.value_of("interval")
+                    .expect("interval argument has default"),
+            )?;
+            if interval.is_zero() {
+                return Err(anyhow!("--interval must be positive"));
+            }
+
+            if let Some(manager) = args.value_of("install_service") {
+                let config_path = matches
+                    .value_of_os("config")
+                    .ok_or_else(|| anyhow!("--install-service requires --config"))?;
+                let config_path = std::fs::canonicalize(config_path)
+                    .with_context(|| format!("resolving {}", Path::new(config_path).display()))?;
+
+                let mut service_args = vec![
+                    "--config".to_string(),
+                    config_path.to_string_lossy().to_string(),
+                    "--non-interactive".to_string(),
+                    "watch".to_string(),
+                    "--interval".to_string(),
+                    args.value_of("interval")
+                        .expect("interval argument has default")
+                        .to_string(),
+                ];
+                if let Some(jobs) = args.value_of("jobs") {
+                    service_args.extend(["--jobs".to_string(), jobs.to_string()]);
+                }
+
+                let service = ServiceDefinition {
+                    manager: ServiceManager::from_str(manager)?,
+                    program: std::env::current_exe().context("resolving executable")?,
+                    args: service_args,
+                };
+                let path = match args.value_of_os("service_path") {
+                    Some(path) => PathBuf::from(path),
+                    None => service.user_path()?,
+                };
+
+                service.install(&path)?;
+                println!("wrote {}", path.display());
+                println!("enable it with: {}", service.enable_instructions(&path));
+
+                return Ok(());
+            }
+
+            let mut options = import_options_from_args(matches, args, &downloader, &config)?;
+            if config.sync.stitch_github {
+                options.history_sources = vec![Arc::new(github_source(matches, &downloader))];
+            }
 
-            crate::git::create_release_repository(dest, release, bare).await
+            sync::watch(&downloader, &config.sync, &options, interval).await
         }
 
         _ => Err(anyhow!("invalid sub-command")),
@@ -0,0 +1,59 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Object layout of repositories served to many clones.
+//!
+//! Imports write loose objects. For mirrors of large histories, packing them
+//! and writing a multi-pack-index and commit-graph makes clones and fetches
+//! much cheaper for the server. libgit2 can't write these files, so `git` is
+//! run for this.
+
+use {
+    anyhow::{anyhow, Context, Result},
+    git2::Repository,
+    std::path::Path,
+};
+
+/// Run a `git` command in a repository.
+fn git(repo_path: &Path, args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(args)
+        .status()
+        .with_context(|| format!("running git {}", args.join(" ")))?;
+
+    if !status.success() {
+        return Err(anyhow!(
+            "git {} in {} failed: {}",
+            args.join(" "),
+            repo_path.display(),
+            status
+        ));
+    }
+
+    Ok(())
+}
+
+/// Pack a repository's objects and write a multi-pack-index and commit-graph.
+pub fn optimize(repo_path: &Path) -> Result<()> {
+    println!("optimizing objects of {}", repo_path.display());
+
+    git(repo_path, &["repack", "-a", "-d", "-q"])?;
+    git(repo_path, &["multi-pack-index", "write"])?;
+    git(repo_path, &["commit-graph", "write", "--reachable"])?;
+
+    Ok(())
+}
+
+/// Allow clones of a repository to use partial clone filters, e.g.
+/// `git clone --filter=blob:none`, and to fetch the objects they omitted.
+pub fn configure_partial_clone(repo: &Repository) -> Result<()> {
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+
+    config.set_bool("uploadpack.allowFilter", true)?;
+    config.set_bool("uploadpack.allowAnySHA1InWant", true)?;
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Memory accounting for imports.
+//!
+//! Archives are buffered in memory while they are downloaded and converted
+//! to Git trees. Concurrent imports of large components can exhaust memory,
+//! so a [MemoryBudget] limits the total size of archives in flight.
+
+use {
+    std::sync::Arc,
+    tokio::sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+/// Size assumed for archives whose size isn't known.
+pub const UNKNOWN_ARCHIVE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Granularity of reservations, in bytes.
+const UNIT: u64 = 1024;
+
+/// Limits the total size of archives buffered at once.
+///
+/// Clones share the same budget. Reservations are granted in the order they
+/// are requested.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    semaphore: Arc<Semaphore>,
+    units: u32,
+}
+
+/// Memory reserved from a [MemoryBudget], returned when dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl MemoryBudget {
+    pub fn new(bytes: u64) -> Self {
+        let units = (bytes / UNIT).clamp(1, u32::MAX as u64) as u32;
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(units as usize)),
+            units,
+        }
+    }
+
+    /// The size of the budget in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.units as u64 * UNIT
+    }
+
+    /// The number of bytes not reserved.
+    pub fn available(&self) -> u64 {
+        self.semaphore.available_permits() as u64 * UNIT
+    }
+
+    /// Wait until memory for an archive of the given size can be reserved.
+    ///
+    /// Archives of unknown size are assumed to be [UNKNOWN_ARCHIVE_SIZE].
+    /// Archives larger than the budget reserve all of it, so they are
+    /// processed alone.
+    pub async fn reserve(&self, bytes: Option<u64>) -> MemoryReservation {
+        MemoryReservation {
+            _permit: self
+                .semaphore
+                .clone()
+                .acquire_many_owned(self.units(bytes))
+                .await
+                .expect("semaphore is never closed"),
+        }
+    }
+
+    /// Reserve memory for an archive of the given size if it is available
+    /// without waiting.
+    pub fn try_reserve(&self, bytes: Option<u64>) -> Option<MemoryReservation> {
+        Some(MemoryReservation {
+            _permit: self
+                .semaphore
+                .clone()
+                .try_acquire_many_owned(self.units(bytes))
+                .ok()?,
+        })
+    }
+
+    fn units(&self, bytes: Option<u64>) -> u32 {
+        bytes
+            .unwrap_or(UNKNOWN_ARCHIVE_SIZE)
+            .div_ceil(UNIT)
+            .clamp(1, self.units as u64) as u32
+    }
+}
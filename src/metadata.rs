@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Apple file metadata carried in tar archives.
+//!
+//! Archives produced on macOS carry extended attributes and resource forks
+//! either as AppleDouble `._<name>` members next to the file they describe or
+//! as `SCHILY.xattr.*` records in pax extended headers. Archivers unaware of
+//! pax headers additionally leave `PaxHeader` members behind.
+
+use {
+    crate::path::RepoPath,
+    anyhow::{anyhow, Result},
+    serde::Serialize,
+    std::collections::BTreeMap,
+};
+
+/// Filename of the sidecar file recording decoded metadata in a tree's root.
+pub const SIDECAR_FILENAME: &str = ".apple-metadata.json";
+
+const APPLE_DOUBLE_MAGIC: u32 = 0x0005_1607;
+const ENTRY_RESOURCE_FORK: u32 = 2;
+const ENTRY_FINDER_INFO: u32 = 9;
+const FINDER_INFO_SIZE: usize = 32;
+const ATTR_MAGIC: &[u8] = b"ATTR";
+
+/// A tar member holding Apple metadata rather than file content.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MetadataMember {
+    /// AppleDouble file describing the file at the given path.
+    AppleDouble(RepoPath),
+    /// Pax extended header written as a regular member.
+    PaxHeader,
+}
+
+impl MetadataMember {
+    /// Identify a tar member path holding Apple metadata.
+    pub fn from_path(path: &RepoPath) -> Option<Self> {
+        if path
+            .components()
+            .any(|c| c == b"PaxHeader" || c.starts_with(b"PaxHeaders."))
+        {
+            return Some(Self::PaxHeader);
+        }
+
+        let (dir, filename) = path.split()?;
+
+        if filename.starts_with(b"._") && filename.len() > 2 {
+            Some(Self::AppleDouble(dir.join(&filename[2..])))
+        } else {
+            None
+        }
+    }
+}
+
+/// Metadata recorded for a file in the sidecar file.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FileMetadata {
+    /// Extended attributes, with hex encoded values.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, String>,
+    /// Hex encoded Finder info, if not all zeros.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finder_info: Option<String>,
+    /// Hex encoded resource fork, if not empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_fork: Option<String>,
+}
+
+impl FileMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.xattrs.is_empty() && self.finder_info.is_none() && self.resource_fork.is_none()
+    }
+
+    /// Record extended attributes from `SCHILY.xattr.*` pax extension records.
+    pub fn add_pax_xattrs<'a>(&mut self, records: impl Iterator<Item = (&'a str, &'a [u8])>) {
+        for (key, value) in records {
+            if let Some(name) = key.strip_prefix("SCHILY.xattr.") {
+                self.xattrs.insert(name.to_string(), hex(value));
+            }
+        }
+    }
+
+    /// Record the content of an AppleDouble file.
+    pub fn add_apple_double(&mut self, data: &[u8]) -> Result<()> {
+        let read_u16 = |offset: usize| -> Result<u16> {
+            data.get(offset..offset + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or_else(|| anyhow!("truncated AppleDouble data"))
+        };
+        let read_u32 = |offset: usize| -> Result<u32> {
+            data.get(offset..offset + 4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| anyhow!("truncated AppleDouble data"))
+        };
+        let slice = |offset: usize, length: usize| -> Result<&[u8]> {
+            data.get(offset..offset + length)
+                .ok_or_else(|| anyhow!("truncated AppleDouble data"))
+        };
+
+        if read_u32(0)? != APPLE_DOUBLE_MAGIC {
+            return Err(anyhow!("not an AppleDouble file"));
+        }
+
+        for i in 0..read_u16(24)? as usize {
+            let entry = 26 + i * 12;
+            let id = read_u32(entry)?;
+            let offset = read_u32(entry + 4)? as usize;
+            let length = read_u32(entry + 8)? as usize;
+
+            match id {
+                ENTRY_RESOURCE_FORK if length > 0 => {
+                    self.resource_fork = Some(hex(slice(offset, length)?));
+                }
+                ENTRY_FINDER_INFO => {
+                    let info = slice(offset, length.min(FINDER_INFO_SIZE))?;
+                    if info.iter().any(|b| *b != 0) {
+                        self.finder_info = Some(hex(info));
+                    }
+
+                    // macOS stores extended attributes in an `ATTR` block
+                    // following the Finder info, possibly after 2 bytes of
+                    // padding.
+                    let attr = [offset + FINDER_INFO_SIZE, offset + FINDER_INFO_SIZE + 2]
+                        .into_iter()
+                        .find(|start| data.get(*start..*start + 4) == Some(ATTR_MAGIC));
+
+                    if let Some(attr) = attr {
+                        self.add_attr_block(data, attr)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record extended attributes from an `ATTR` block starting at `start`.
+    fn add_attr_block(&mut self, data: &[u8], start: usize) -> Result<()> {
+        let truncated = || anyhow!("truncated AppleDouble attribute data");
+        let read_u32 = |offset: usize| -> Result<u32> {
+            data.get(offset..offset + 4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(truncated)
+        };
+
+        // magic, debug tag, total size, data start, data length, 3 reserved,
+        // then 16-bit flags and attribute count.
+        let count = data
+            .get(start + 34..start + 36)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(truncated)?;
+
+        let mut entry = start + 36;
+
+        for _ in 0..count {
+            let offset = read_u32(entry)? as usize;
+            let length = read_u32(entry + 4)? as usize;
+            let name_length = *data.get(entry + 10).ok_or_else(truncated)? as usize;
+            let name = data
+                .get(entry + 11..entry + 11 + name_length)
+                .ok_or_else(truncated)?;
+            let name = name.strip_suffix(b"\0").unwrap_or(name);
+            let value = data.get(offset..offset + length).ok_or_else(truncated)?;
+
+            self.xattrs
+                .insert(String::from_utf8_lossy(name).to_string(), hex(value));
+
+            // Entries are 4 byte aligned.
+            entry = (entry + 11 + name_length + 3) & !3;
+        }
+
+        Ok(())
+    }
+}
+
+/// Serialize metadata for files to the content of a sidecar file.
+pub fn sidecar_data(files: &BTreeMap<String, FileMetadata>) -> Result<Vec<u8>> {
+    let mut data = serde_json::to_vec_pretty(files)?;
+    data.push(b'\n');
+
+    Ok(data)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,1006 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Composable layers around artifact downloads.
+//!
+//! Downloads are performed by a stack of [Service]s. The innermost service
+//! fetches content from a [Source]. [Layer]s wrap a service to add behavior
+//! such as retries, caching, logging, or metrics, in the style of `tower`.
+
+use {
+    crate::{
+        crawler::{Source, UrlSchemes},
+        crypto::{self, EncryptionKey},
+        download::ArtifactMetadata,
+        error::{
+            CorruptDownloadError, DigestMismatchError, HttpStatusError, IncompleteDownloadError,
+        },
+    },
+    anyhow::{anyhow, Context, Result},
+    async_trait::async_trait,
+    flate2::read::MultiGzDecoder,
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        future::Future,
+        path::{Path, PathBuf},
+        pin::Pin,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::sync::OnceCell,
+};
+
+/// Maximum number of times an interrupted download is resumed.
+const MAX_RESUME_ATTEMPTS: usize = 5;
+
+/// Verify the integrity of downloaded gzip data.
+///
+/// Decompressing the data validates the CRC and size stored in the gzip
+/// trailer, detecting truncated or corrupted archives before they are
+/// imported. Data that isn't gzip compressed is accepted as is.
+fn verify_gzip(data: &[u8]) -> Result<()> {
+    if !data.starts_with(&[0x1f, 0x8b]) {
+        return Ok(());
+    }
+
+    let mut decoder = MultiGzDecoder::new(data);
+    std::io::copy(&mut decoder, &mut std::io::sink()).context("verifying gzip data")?;
+
+    Ok(())
+}
+
+struct RateLimiterState {
+    tokens: f64,
+    last: Instant,
+}
+
+/// A token bucket limiting the aggregate rate of response body reads.
+///
+/// Readers consume tokens as data arrives. Once the bucket is exhausted, readers
+/// go into debt and sleep until the debt is repaid, so concurrent transfers share
+/// the configured rate.
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    state: Mutex<RateLimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second as f64;
+
+        Self {
+            bytes_per_second,
+            state: Mutex::new(RateLimiterState {
+                tokens: bytes_per_second,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    async fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.last).as_secs_f64();
+            state.last = now;
+
+            state.tokens = (state.tokens + elapsed * self.bytes_per_second)
+                .min(self.bytes_per_second)
+                - bytes as f64;
+
+            if state.tokens < 0.0 {
+                Duration::from_secs_f64(-state.tokens / self.bytes_per_second)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// A request to download the content of a URL.
+#[derive(Clone)]
+pub struct Request<'a> {
+    /// The source advertising the URL.
+    pub source: &'a dyn Source,
+    pub url: &'a str,
+    /// Rate limiters response body reads must honor.
+    rate_limiters: Vec<Arc<RateLimiter>>,
+}
+
+impl<'a> Request<'a> {
+    pub fn new(source: &'a dyn Source, url: &'a str) -> Self {
+        Self {
+            source,
+            url,
+            rate_limiters: vec![],
+        }
+    }
+}
+
+/// Something that downloads the content of URLs.
+#[async_trait]
+pub trait Service: Send + Sync {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>>;
+
+    /// Obtain metadata of the content of a URL without downloading it.
+    ///
+    /// By default, a `HEAD` request is sent to the request's source. Services
+    /// wrapping another service forward to it instead.
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        let res = request
+            .source
+            .head(request.url)
+            .await
+            .with_context(|| format!("requesting metadata of {}", request.url))?;
+
+        Ok(ArtifactMetadata::from_headers(res.headers()))
+    }
+}
+
+/// A kind of request handled by a [Service], letting layers treat downloads
+/// and metadata requests alike.
+trait Method {
+    type Output: Send;
+
+    /// Describes requests of this kind in messages.
+    const NAME: &'static str;
+
+    fn send<'a>(
+        service: &'a dyn Service,
+        request: Request<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>> + Send + 'a>>;
+}
+
+/// Requests handled by [Service::call].
+struct Get;
+
+impl Method for Get {
+    type Output = Vec<u8>;
+
+    const NAME: &'static str = "download";
+
+    fn send<'a>(
+        service: &'a dyn Service,
+        request: Request<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>> + Send + 'a>> {
+        service.call(request)
+    }
+}
+
+/// Requests handled by [Service::head].
+struct Head;
+
+impl Method for Head {
+    type Output = ArtifactMetadata;
+
+    const NAME: &'static str = "HEAD request";
+
+    fn send<'a>(
+        service: &'a dyn Service,
+        request: Request<'a>,
+    ) -> Pin<Box<dyn Future<Output = Result<Self::Output>> + Send + 'a>> {
+        service.head(request)
+    }
+}
+
+/// Wraps a [Service] to add behavior.
+pub trait Layer: Send + Sync {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service>;
+}
+
+/// The innermost [Service], fetching content from the request's source.
+///
+/// If the transfer is interrupted, it is resumed from the bytes already
+/// received with an HTTP `Range` request rather than starting over.
+///
+/// The received content is validated against the advertised `Content-Length`
+/// and, for gzip data, the gzip trailer. Invalid content is downloaded again.
+#[derive(Default)]
+pub struct Fetch;
+
+impl Fetch {
+    /// Read a response body into `data`, honoring rate limits.
+    async fn read_body(
+        request: &Request<'_>,
+        res: &mut reqwest::Response,
+        data: &mut Vec<u8>,
+    ) -> Result<()> {
+        while let Some(chunk) = res.chunk().await? {
+            for limiter in &request.rate_limiters {
+                limiter.acquire(chunk.len()).await;
+            }
+
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Service for Fetch {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        let url = request.url;
+
+        let mut res = request
+            .source
+            .get(url)
+            .await
+            .with_context(|| format!("fetching {}", url))?;
+
+        let mut expected = res.content_length();
+        let mut data = Vec::with_capacity(expected.unwrap_or(0) as usize);
+        let mut attempts = 0;
+
+        loop {
+            let err = match Self::read_body(&request, &mut res, &mut data).await {
+                Ok(()) => match expected {
+                    Some(expected) if data.len() as u64 != expected => IncompleteDownloadError {
+                        url: url.to_string(),
+                        expected,
+                        received: data.len() as u64,
+                    }
+                    .into(),
+                    _ => match verify_gzip(&data) {
+                        Ok(()) => return Ok(data),
+                        Err(e) => {
+                            // There's no telling where the corruption is.
+                            data.clear();
+                            e.context(CorruptDownloadError {
+                                url: url.to_string(),
+                            })
+                        }
+                    },
+                },
+                Err(e) => e,
+            };
+
+            if attempts >= MAX_RESUME_ATTEMPTS {
+                return Err(err).with_context(|| format!("reading response body from {}", url));
+            }
+
+            attempts += 1;
+
+            println!(
+                "warning: download of {} failed after {} bytes; retrying: {}",
+                url,
+                data.len(),
+                err
+            );
+
+            let offset = data.len() as u64;
+
+            res = request
+                .source
+                .get_from(url, offset)
+                .await
+                .with_context(|| format!("resuming download of {}", url))?;
+
+            if res.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                expected = res.content_length().map(|length| offset + length);
+            } else {
+                // The server ignored the range, so start over.
+                data.clear();
+                expected = res.content_length();
+            }
+        }
+    }
+}
+
+/// Limits the aggregate rate at which response bodies are read.
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            limiter: Arc::new(RateLimiter::new(bytes_per_second)),
+        }
+    }
+}
+
+impl Layer for RateLimitLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        })
+    }
+}
+
+struct RateLimitService {
+    inner: Arc<dyn Service>,
+    limiter: Arc<RateLimiter>,
+}
+
+#[async_trait]
+impl Service for RateLimitService {
+    async fn call(&self, mut request: Request<'_>) -> Result<Vec<u8>> {
+        // Limiting happens as the body is read by the innermost service.
+        request.rate_limiters.push(self.limiter.clone());
+
+        self.inner.call(request).await
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.inner.head(request).await
+    }
+}
+
+/// Retries failed downloads with exponential backoff.
+///
+/// Client errors other than timeouts and rate limiting are not retried, as
+/// they won't succeed on a subsequent attempt.
+pub struct RetryLayer {
+    attempts: usize,
+    delay: Duration,
+}
+
+impl RetryLayer {
+    /// Retry up to `attempts` times, initially waiting `delay` between attempts.
+    pub fn new(attempts: usize, delay: Duration) -> Self {
+        Self { attempts, delay }
+    }
+}
+
+impl Layer for RetryLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(RetryService {
+            inner,
+            attempts: self.attempts,
+            delay: self.delay,
+        })
+    }
+}
+
+struct RetryService {
+    inner: Arc<dyn Service>,
+    attempts: usize,
+    delay: Duration,
+}
+
+fn is_retryable(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<HttpStatusError>() {
+        Some(e) => !(400..500).contains(&e.status) || e.status == 408 || e.status == 429,
+        None => true,
+    }
+}
+
+impl RetryService {
+    async fn send<M: Method>(&self, request: Request<'_>) -> Result<M::Output> {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+
+        loop {
+            match M::send(self.inner.as_ref(), request.clone()).await {
+                Ok(output) => return Ok(output),
+                Err(e) if attempt < self.attempts && is_retryable(&e) => {
+                    attempt += 1;
+
+                    println!(
+                        "warning: {} of {} failed; retrying in {}s ({}/{}): {}",
+                        M::NAME,
+                        request.url,
+                        delay.as_secs_f64(),
+                        attempt,
+                        self.attempts,
+                        e
+                    );
+
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Service for RetryService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        self.send::<Get>(request).await
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.send::<Head>(request).await
+    }
+}
+
+/// Falls back to other [UrlSchemes] when an artifact isn't found.
+///
+/// When a download fails with HTTP 404 and its URL follows a known scheme,
+/// the same artifact is requested under the other schemes. The scheme that
+/// last succeeded is tried first, so once the site is reorganized, later
+/// downloads don't need to probe every scheme.
+///
+/// If no scheme has the artifact, the original error is returned.
+pub struct SchemeFallbackLayer {
+    schemes: Arc<UrlSchemes>,
+}
+
+impl SchemeFallbackLayer {
+    pub fn new(schemes: UrlSchemes) -> Self {
+        Self {
+            schemes: Arc::new(schemes),
+        }
+    }
+}
+
+impl Layer for SchemeFallbackLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(SchemeFallbackService {
+            inner,
+            schemes: self.schemes.clone(),
+            preferred: Mutex::new(None),
+        })
+    }
+}
+
+struct SchemeFallbackService {
+    inner: Arc<dyn Service>,
+    schemes: Arc<UrlSchemes>,
+    /// Template of the scheme that last succeeded as a fallback.
+    preferred: Mutex<Option<String>>,
+}
+
+impl SchemeFallbackService {
+    async fn send<M: Method>(&self, request: Request<'_>) -> Result<M::Output> {
+        let err = match M::send(self.inner.as_ref(), request.clone()).await {
+            Ok(output) => return Ok(output),
+            Err(e) if crate::download::is_not_found(&e) => e,
+            Err(e) => return Err(e),
+        };
+
+        let mut alternates = self.schemes.alternate_urls(request.url);
+
+        if let Some(preferred) = self.preferred.lock().unwrap().clone() {
+            if let Some(scheme) = self
+                .schemes
+                .schemes()
+                .iter()
+                .find(|s| s.template() == preferred)
+            {
+                alternates.sort_by_key(|url| scheme.parse(url).is_none());
+            }
+        }
+
+        for url in &alternates {
+            let mut alternate = request.clone();
+            alternate.url = url;
+
+            match M::send(self.inner.as_ref(), alternate).await {
+                Ok(output) => {
+                    println!("warning: {} not found; used {} instead", request.url, url);
+
+                    if let Some(scheme) = self
+                        .schemes
+                        .schemes()
+                        .iter()
+                        .find(|s| s.parse(url).is_some())
+                    {
+                        *self.preferred.lock().unwrap() = Some(scheme.template().to_string());
+                    }
+
+                    return Ok(output);
+                }
+                Err(e) if crate::download::is_not_found(&e) => {}
+                Err(e) => {
+                    println!(
+                        "warning: {} of alternate URL {} failed: {}",
+                        M::NAME,
+                        url,
+                        e
+                    );
+                }
+            }
+        }
+
+        Err(err)
+    }
+}
+
+#[async_trait]
+impl Service for SchemeFallbackService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        self.send::<Get>(request).await
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.send::<Head>(request).await
+    }
+}
+
+/// Caches downloaded content in a directory, keyed by URL.
+///
+/// Content is only written to the cache once fully downloaded, so the cache
+/// never holds partial downloads.
+///
+/// With an encryption key, cached content is encrypted when written and
+/// decrypted when read. Unencrypted entries are then downloaded again.
+///
+/// Downloaded content is also indexed by its SHA-256 digest in the
+/// [DIGEST_INDEX_DIRNAME] directory of the cache, so it can be found by digest
+/// with [CacheLayer::url_for_digest].
+#[derive(Clone)]
+pub struct CacheLayer {
+    dir: PathBuf,
+    key: Option<EncryptionKey>,
+}
+
+impl CacheLayer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            key: None,
+        }
+    }
+
+    /// Encrypt cached content with a key.
+    pub fn with_encryption(mut self, key: EncryptionKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// The URL of content downloaded through the cache having a SHA-256
+    /// digest, if any.
+    pub fn url_for_digest(&self, sha256: &str) -> Result<Option<String>> {
+        let path = self.dir.join(DIGEST_INDEX_DIRNAME).join(sha256);
+
+        match std::fs::read_to_string(&path) {
+            Ok(url) => Ok(Some(url.trim().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// The SHA-256 digests of content downloaded through the cache, keyed by
+    /// URL.
+    pub fn digests(&self) -> Result<BTreeMap<String, String>> {
+        let dir = self.dir.join(DIGEST_INDEX_DIRNAME);
+
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+        };
+
+        let mut digests = BTreeMap::new();
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if let Some(digest) = path.file_name().and_then(|name| name.to_str()) {
+                let url = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+
+                digests.insert(url.trim().to_string(), digest.to_string());
+            }
+        }
+
+        Ok(digests)
+    }
+
+    /// Find cached content having a SHA-256 digest by reading every entry.
+    ///
+    /// This finds content cached before it was indexed by digest. Entries
+    /// that can't be decrypted are skipped.
+    pub fn scan_for_digest(&self, sha256: &str) -> Result<Option<Vec<u8>>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", self.dir.display())),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+
+            if !entry.file_type()?.is_file()
+                || path.extension().and_then(|e| e.to_str()) == Some("partial")
+            {
+                continue;
+            }
+
+            let data =
+                std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+            let data = match (&self.key, crypto::is_encrypted(&data)) {
+                (Some(key), true) => match key.decrypt(&data) {
+                    Ok(data) => data,
+                    Err(_) => continue,
+                },
+                (None, true) => continue,
+                (_, false) => data,
+            };
+
+            if crypto::sha256_hex(&data) == sha256 {
+                return Ok(Some(data));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Layer for CacheLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(CacheService {
+            inner,
+            dir: self.dir.clone(),
+            key: self.key.clone(),
+        })
+    }
+}
+
+struct CacheService {
+    inner: Arc<dyn Service>,
+    dir: PathBuf,
+    key: Option<EncryptionKey>,
+}
+
+/// Name of the directory of a cache holding a file per SHA-256 digest of
+/// downloaded content, containing the URL of the content.
+pub const DIGEST_INDEX_DIRNAME: &str = "sha256";
+
+/// Derive the name of a file caching the content of a URL.
+pub(crate) fn cache_filename(url: &str) -> String {
+    url.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Derive the path a file is written to before being renamed to `path`.
+///
+/// The suffix is appended rather than replacing the extension, so files
+/// differing only in their last extension don't share a temporary file.
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".partial");
+
+    path.with_file_name(file_name)
+}
+
+impl CacheService {
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(cache_filename(url))
+    }
+}
+
+#[async_trait]
+impl Service for CacheService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        let url = request.url;
+        let path = self.path(url);
+
+        if let Ok(data) = tokio::fs::read(&path).await {
+            match (&self.key, crypto::is_encrypted(&data)) {
+                (Some(key), true) => {
+                    return key
+                        .decrypt(&data)
+                        .with_context(|| format!("decrypting {}", path.display()));
+                }
+                (None, true) => {
+                    return Err(anyhow!(
+                        "{} is encrypted but no key was configured",
+                        path.display()
+                    ));
+                }
+                (None, false) => return Ok(data),
+                (Some(_), false) => {}
+            }
+        }
+
+        let data = self.inner.call(request).await?;
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("creating cache directory {}", self.dir.display()))?;
+
+        let stored = match &self.key {
+            Some(key) => Cow::Owned(key.encrypt(&data)?),
+            None => Cow::Borrowed(&data),
+        };
+
+        let temp_path = partial_path(&path);
+        tokio::fs::write(&temp_path, stored.as_ref())
+            .await
+            .with_context(|| format!("writing {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, &path)
+            .await
+            .with_context(|| format!("renaming {}", temp_path.display()))?;
+
+        let index_dir = self.dir.join(DIGEST_INDEX_DIRNAME);
+        tokio::fs::create_dir_all(&index_dir)
+            .await
+            .with_context(|| format!("creating {}", index_dir.display()))?;
+        let index_path = index_dir.join(crypto::sha256_hex(&data));
+        tokio::fs::write(&index_path, format!("{}\n", url))
+            .await
+            .with_context(|| format!("writing {}", index_path.display()))?;
+
+        Ok(data)
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.inner.head(request).await
+    }
+}
+
+/// Coalesces concurrent downloads of the same URL.
+///
+/// While a URL is being downloaded, further requests for it wait for that
+/// download and share its content instead of downloading it again. If the
+/// download fails, a waiting request downloads the URL itself. Completed
+/// downloads aren't remembered; see [CacheLayer] for that.
+#[derive(Default)]
+pub struct CoalesceLayer;
+
+impl Layer for CoalesceLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(CoalesceService {
+            inner,
+            in_flight: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+struct CoalesceService {
+    inner: Arc<dyn Service>,
+    /// Content of downloads in progress, keyed by URL.
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<Vec<u8>>>>>,
+}
+
+#[async_trait]
+impl Service for CoalesceService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        let url = request.url;
+        let cell = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+
+        let res = cell
+            .get_or_try_init(|| self.inner.call(request))
+            .await
+            .cloned();
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(url)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(url);
+        }
+
+        res
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.inner.head(request).await
+    }
+}
+
+/// Prints a line for each download and its outcome.
+#[derive(Default)]
+pub struct LogLayer;
+
+impl Layer for LogLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(LogService { inner })
+    }
+}
+
+struct LogService {
+    inner: Arc<dyn Service>,
+}
+
+#[async_trait]
+impl Service for LogService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        let url = request.url;
+        let start = Instant::now();
+
+        println!("downloading {}", url);
+
+        let res = self.inner.call(request).await;
+
+        match &res {
+            Ok(data) => println!(
+                "downloaded {} ({} bytes in {:.1}s)",
+                url,
+                data.len(),
+                start.elapsed().as_secs_f64()
+            ),
+            Err(e) => println!("failed to download {}: {}", url, e),
+        }
+
+        res
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        let url = request.url;
+
+        let res = self.inner.head(request).await;
+
+        match &res {
+            Ok(metadata) => println!(
+                "requested metadata of {} ({} bytes)",
+                url,
+                metadata
+                    .size
+                    .map_or_else(|| "unknown".to_string(), |size| size.to_string())
+            ),
+            Err(e) => println!("failed to request metadata of {}: {}", url, e),
+        }
+
+        res
+    }
+}
+
+/// Counters describing downloads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+    failures: AtomicU64,
+    bytes: AtomicU64,
+    download_micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Number of downloads attempted.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of downloads that failed.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Total bytes of successfully downloaded content.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent in downloads.
+    ///
+    /// Concurrent downloads each contribute their full duration.
+    pub fn download_time(&self) -> Duration {
+        Duration::from_micros(self.download_micros.load(Ordering::Relaxed))
+    }
+}
+
+/// Verifies downloaded content against known SHA-256 digests.
+///
+/// Content of URLs without a known digest passes through unverified.
+/// Content that doesn't match fails with a [DigestMismatchError].
+pub struct ChecksumLayer {
+    checksums: Arc<BTreeMap<String, String>>,
+}
+
+impl ChecksumLayer {
+    /// Verify content with the given digests, keyed by URL.
+    pub fn new(checksums: BTreeMap<String, String>) -> Self {
+        Self {
+            checksums: Arc::new(checksums),
+        }
+    }
+}
+
+impl Layer for ChecksumLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(ChecksumService {
+            inner,
+            checksums: self.checksums.clone(),
+        })
+    }
+}
+
+struct ChecksumService {
+    inner: Arc<dyn Service>,
+    checksums: Arc<BTreeMap<String, String>>,
+}
+
+#[async_trait]
+impl Service for ChecksumService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        let url = request.url;
+        let data = self.inner.call(request).await?;
+
+        if let Some(expected) = self.checksums.get(url) {
+            let actual = crypto::sha256_hex(&data);
+
+            if &actual != expected {
+                return Err(DigestMismatchError {
+                    url: url.to_string(),
+                    expected: expected.clone(),
+                    actual,
+                }
+                .into());
+            }
+        }
+
+        Ok(data)
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.inner.head(request).await
+    }
+}
+
+/// Records [Metrics] for downloads.
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl Layer for MetricsLayer {
+    fn layer(&self, inner: Arc<dyn Service>) -> Arc<dyn Service> {
+        Arc::new(MetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+struct MetricsService {
+    inner: Arc<dyn Service>,
+    metrics: Arc<Metrics>,
+}
+
+#[async_trait]
+impl Service for MetricsService {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+        let start = Instant::now();
+
+        let res = self.inner.call(request).await;
+
+        self.metrics
+            .download_micros
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+        match &res {
+            Ok(data) => {
+                self.metrics
+                    .bytes
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        res
+    }
+
+    /// Metadata requests aren't downloads, so they aren't counted.
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.inner.head(request).await
+    }
+}
@@ -0,0 +1,402 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Metadata for serving mirrored repositories.
+//!
+//! Web front-ends like cgit and gitweb read a repository's `description` and
+//! `cloneurl` files from its Git directory. Imports write these along with
+//! [METADATA_FILENAME], describing what the repository mirrors. The
+//! [Provenance] of the import is recorded in the repository's Git config.
+//!
+//! [find_repositories] discovers mirrored repositories from these files so
+//! configuration for cgit and gitolite can be generated.
+
+use {
+    crate::{download::compare_version_string, platform::Platform},
+    anyhow::{Context, Result},
+    chrono::{DateTime, SecondsFormat, Utc},
+    git2::Repository,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+/// Name of the file in a Git directory describing what the repository mirrors.
+pub const METADATA_FILENAME: &str = "apple-oss.json";
+
+/// Section of the Git config recording the [Provenance] of a repository.
+pub const CONFIG_SECTION: &str = "apple-oss";
+
+/// The content of the `description` file of new repositories.
+const DEFAULT_DESCRIPTION_PREFIX: &str = "Unnamed repository;";
+
+/// What a repository mirrors.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepositoryKind {
+    /// The versions of a component.
+    Component,
+    /// The software releases of an entity.
+    Release,
+}
+
+/// Describes a mirrored repository.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RepositoryMetadata {
+    pub kind: RepositoryKind,
+    /// The component or release entity name.
+    pub name: String,
+    pub description: String,
+    /// Tagged versions, sorted.
+    pub versions: Vec<String>,
+    /// When the repository was last updated, in RFC 3339 format.
+    pub last_sync: String,
+}
+
+impl RepositoryMetadata {
+    /// Describe the current state of a repository.
+    pub fn from_repository(kind: RepositoryKind, name: &str, repo: &Repository) -> Result<Self> {
+        let mut versions = repo
+            .tag_names(None)?
+            .iter()
+            .flatten()
+            .map(|tag| tag.to_string())
+            .collect::<Vec<_>>();
+        versions.sort_by(|a, b| compare_version_string(a, b));
+
+        let description = match kind {
+            RepositoryKind::Component => format!("Apple open source component {}", name),
+            RepositoryKind::Release => format!("Apple open source releases of {}", name),
+        };
+
+        Ok(Self {
+            kind,
+            name: name.to_string(),
+            description,
+            versions,
+            last_sync: DateTime::<Utc>::from(SystemTime::now())
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        })
+    }
+
+    /// Read the metadata from a Git directory.
+    pub fn read(git_dir: &Path) -> Result<Self> {
+        let path = git_dir.join(METADATA_FILENAME);
+        let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+
+        serde_json::from_slice(&data).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Write the metadata, `description`, and `cloneurl` files to a
+    /// repository's Git directory.
+    ///
+    /// A `description` that was edited is left alone.
+    pub fn write(&self, repo: &Repository, clone_url: Option<&str>) -> Result<()> {
+        let git_dir = repo.path();
+
+        let path = git_dir.join(METADATA_FILENAME);
+        std::fs::write(&path, format!("{}\n", serde_json::to_string_pretty(self)?))
+            .with_context(|| format!("writing {}", path.display()))?;
+
+        let path = git_dir.join("description");
+        let edited = match std::fs::read_to_string(&path) {
+            Ok(existing) => {
+                !existing.starts_with(DEFAULT_DESCRIPTION_PREFIX)
+                    && existing.trim_end() != self.description
+            }
+            Err(_) => false,
+        };
+        if !edited {
+            std::fs::write(&path, format!("{}\n", self.description))
+                .with_context(|| format!("writing {}", path.display()))?;
+        }
+
+        if let Some(url) = clone_url {
+            let path = git_dir.join("cloneurl");
+            std::fs::write(&path, format!("{}\n", url))
+                .with_context(|| format!("writing {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How a repository was imported.
+///
+/// This is recorded in the `[apple-oss]` section of the repository's Git
+/// config, e.g.:
+///
+/// ```text
+/// [apple-oss]
+///     source = apple
+///     component = xnu
+///     lastVersion = 7195.81.3
+///     importerVersion = 0.1.0
+/// ```
+///
+/// Release repositories have an `entity` key instead of `component`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Provenance {
+    /// The [name](crate::crawler::Source::name) of the source software was
+    /// discovered from.
+    pub source: String,
+    pub kind: RepositoryKind,
+    /// The component or release entity name.
+    pub name: String,
+    /// The version most recently committed to the main branch.
+    pub last_version: Option<String>,
+    /// The version of this tool that performed the import.
+    pub importer_version: String,
+}
+
+impl Provenance {
+    /// Describe an import performed by this version of the tool.
+    pub fn new(source: &str, kind: RepositoryKind, name: &str, last_version: Option<&str>) -> Self {
+        Self {
+            source: source.to_string(),
+            kind,
+            name: name.to_string(),
+            last_version: last_version.map(|v| v.to_string()),
+            importer_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    fn name_key(kind: RepositoryKind) -> String {
+        match kind {
+            RepositoryKind::Component => format!("{}.component", CONFIG_SECTION),
+            RepositoryKind::Release => format!("{}.entity", CONFIG_SECTION),
+        }
+    }
+
+    /// Read the provenance from a repository's config, if recorded.
+    pub fn read(repo: &Repository) -> Result<Option<Self>> {
+        let config = repo.config()?.snapshot()?;
+        let get = |key: &str| {
+            config
+                .get_string(&format!("{}.{}", CONFIG_SECTION, key))
+                .ok()
+        };
+
+        let source = match get("source") {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+
+        let (kind, name) = match (
+            config.get_string(&Self::name_key(RepositoryKind::Component)),
+            config.get_string(&Self::name_key(RepositoryKind::Release)),
+        ) {
+            (Ok(name), _) => (RepositoryKind::Component, name),
+            (_, Ok(name)) => (RepositoryKind::Release, name),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            source,
+            kind,
+            name,
+            last_version: get("lastVersion"),
+            importer_version: get("importerVersion").unwrap_or_default(),
+        }))
+    }
+
+    /// Write the provenance to a repository's local config.
+    ///
+    /// A version that isn't known leaves a previously recorded one alone.
+    pub fn write(&self, repo: &Repository) -> Result<()> {
+        let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+        let key = |key: &str| format!("{}.{}", CONFIG_SECTION, key);
+
+        config.set_str(&key("source"), &self.source)?;
+        config.set_str(&Self::name_key(self.kind), &self.name)?;
+        if let Some(version) = &self.last_version {
+            config.set_str(&key("lastVersion"), version)?;
+        }
+        config.set_str(&key("importerVersion"), &self.importer_version)?;
+
+        Ok(())
+    }
+}
+
+/// A repository found by [find_repositories].
+#[derive(Clone, Debug)]
+pub struct MirroredRepository {
+    /// Path of the repository, relative to the searched directory and
+    /// without a `.git` suffix. e.g. `x/xnu`.
+    pub name: String,
+    /// Path of the repository's Git directory.
+    pub git_dir: PathBuf,
+    pub metadata: RepositoryMetadata,
+    /// The content of the `cloneurl` file, if present.
+    pub clone_url: Option<String>,
+}
+
+impl MirroredRepository {
+    /// The section grouping this repository: components or a platform.
+    pub fn section(&self) -> (String, String) {
+        match self.metadata.kind {
+            RepositoryKind::Component => ("components".to_string(), "Components".to_string()),
+            RepositoryKind::Release => {
+                let platform = Platform::from_entity(&self.metadata.name);
+
+                (
+                    platform.as_str().to_string(),
+                    platform.display_name().to_string(),
+                )
+            }
+        }
+    }
+}
+
+/// Find repositories described by [METADATA_FILENAME] under a directory.
+///
+/// Bare and non-bare repositories are found at any depth, so sharded
+/// destinations are supported. Repositories are sorted by name.
+pub fn find_repositories(root: &Path) -> Result<Vec<MirroredRepository>> {
+    let mut repos = vec![];
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let git_dir = [dir.clone(), dir.join(".git")]
+            .into_iter()
+            .find(|d| d.join(METADATA_FILENAME).is_file());
+
+        if let Some(git_dir) = git_dir {
+            // A repository searched directly is named after its directory.
+            let relative = match dir.strip_prefix(root) {
+                Ok(relative) if relative.as_os_str().is_empty() => {
+                    Path::new(root.file_name().unwrap_or_default())
+                }
+                Ok(relative) => relative,
+                Err(_) => &dir,
+            };
+            let name = relative
+                .to_string_lossy()
+                .replace(std::path::MAIN_SEPARATOR, "/");
+
+            repos.push(MirroredRepository {
+                name: name.strip_suffix(".git").unwrap_or(&name).to_string(),
+                metadata: RepositoryMetadata::read(&git_dir)?,
+                clone_url: std::fs::read_to_string(git_dir.join("cloneurl"))
+                    .ok()
+                    .map(|url| url.trim().to_string()),
+                git_dir,
+            });
+
+            continue;
+        }
+
+        for entry in
+            std::fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))?
+        {
+            let entry = entry?;
+
+            if entry.file_type()?.is_dir() {
+                pending.push(entry.path());
+            }
+        }
+    }
+
+    repos.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(repos)
+}
+
+/// Group repositories by section, with components first.
+fn sections(
+    repos: &[MirroredRepository],
+) -> BTreeMap<(bool, String, String), Vec<&MirroredRepository>> {
+    let mut sections = BTreeMap::new();
+
+    for repo in repos {
+        let (key, title) = repo.section();
+
+        sections
+            .entry((repo.metadata.kind == RepositoryKind::Release, key, title))
+            .or_insert_with(Vec::new)
+            .push(repo);
+    }
+
+    sections
+}
+
+/// Generate a cgitrc fragment declaring repositories.
+///
+/// Repositories are grouped into a section for components and one for each
+/// platform.
+pub fn cgitrc(repos: &[MirroredRepository]) -> String {
+    let mut out = String::new();
+
+    for ((_, _, title), repos) in sections(repos) {
+        writeln!(out, "section={}\n", title).unwrap();
+
+        for repo in repos {
+            writeln!(out, "repo.url={}", repo.name).unwrap();
+            writeln!(out, "repo.path={}", repo.git_dir.display()).unwrap();
+            writeln!(out, "repo.desc={}", repo.metadata.description).unwrap();
+            if let Some(url) = &repo.clone_url {
+                writeln!(out, "repo.clone-url={}", url).unwrap();
+            }
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Generate a gitolite.conf fragment declaring repositories.
+///
+/// Each section becomes a group, like `@apple-components`, which everyone
+/// can read.
+pub fn gitolite_conf(repos: &[MirroredRepository]) -> String {
+    let mut out = String::new();
+    let sections = sections(repos);
+
+    if sections.is_empty() {
+        return out;
+    }
+
+    for ((_, key, _), repos) in &sections {
+        writeln!(
+            out,
+            "@apple-{} = {}",
+            key,
+            repos
+                .iter()
+                .map(|repo| repo.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        out,
+        "\nrepo {}\n    R = @all\n",
+        sections
+            .keys()
+            .map(|(_, key, _)| format!("@apple-{}", key))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+    .unwrap();
+
+    for repos in sections.values() {
+        for repo in repos {
+            writeln!(
+                out,
+                "repo {}\n    config gitweb.description = \"{}\"\n",
+                repo.name,
+                repo.metadata.description.replace('"', "'")
+            )
+            .unwrap();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,86 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Append-only logs of newline-delimited JSON records.
+//!
+//! Failures, content reports, denylist audits, and run statistics are all
+//! recorded as one JSON object per line, so they can be inspected with
+//! standard tools and appended to by concurrent imports.
+
+use {
+    anyhow::{Context, Result},
+    serde::{de::DeserializeOwned, Serialize},
+    std::{
+        fs::{File, OpenOptions},
+        io::{BufRead, BufReader, Write},
+        marker::PhantomData,
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+};
+
+/// An append-only log of records of type `T`.
+///
+/// Records are written whole, so concurrent writers don't interleave lines.
+pub struct NdjsonLog<T> {
+    path: PathBuf,
+    file: Mutex<File>,
+    records: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize + DeserializeOwned> NdjsonLog<T> {
+    /// Open a log for appending, creating it if necessary.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening log {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            records: PhantomData,
+        })
+    }
+
+    /// Append a record to the log.
+    pub fn record(&self, record: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(&line)
+            .with_context(|| format!("writing to log {}", self.path.display()))
+    }
+
+    /// Read all records from a log file.
+    ///
+    /// Blank lines are ignored.
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<T>> {
+        let path = path.as_ref();
+        let fh = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+
+        let mut records = vec![];
+
+        for (i, line) in BufReader::new(fh).lines().enumerate() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            records.push(
+                serde_json::from_str(&line)
+                    .with_context(|| format!("parsing {} line {}", path.display(), i + 1))?,
+            );
+        }
+
+        Ok(records)
+    }
+}
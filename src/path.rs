@@ -0,0 +1,157 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Paths of files in repositories.
+//!
+//! Tar archives store member paths as bytes that needn't be UTF-8. [RepoPath]
+//! holds such a path in normalized form and provides the operations needed to
+//! place archive members in a hierarchy of Git trees.
+
+use {
+    anyhow::{anyhow, Result},
+    std::fmt::{Display, Formatter},
+};
+
+/// A `/` separated path of bytes relative to the root of a repository.
+///
+/// Paths are normalized on construction: empty components, such as those from
+/// leading, trailing, or repeated separators, and `.` components are removed.
+/// The empty path is the root.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RepoPath(Vec<u8>);
+
+impl RepoPath {
+    /// Construct a normalized path from bytes.
+    pub fn new(path: &[u8]) -> Self {
+        let mut normalized = Vec::with_capacity(path.len());
+
+        for component in path
+            .split(|c| *c == b'/')
+            .filter(|c| !c.is_empty() && *c != b".")
+        {
+            if !normalized.is_empty() {
+                normalized.push(b'/');
+            }
+            normalized.extend_from_slice(component);
+        }
+
+        Self(normalized)
+    }
+
+    /// The root of the repository.
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Whether this is the root of the repository.
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The components of the path, outermost first.
+    ///
+    /// The root has no components.
+    pub fn components(&self) -> impl DoubleEndedIterator<Item = &[u8]> + Clone {
+        self.0
+            .split(|c| *c == b'/')
+            .filter(|component| !component.is_empty())
+    }
+
+    /// Split into the parent directory and the final component.
+    ///
+    /// Returns `None` for the root.
+    pub fn split(&self) -> Option<(RepoPath, &[u8])> {
+        if self.is_root() {
+            return None;
+        }
+
+        Some(match self.0.iter().rposition(|c| *c == b'/') {
+            Some(index) => (Self(self.0[..index].to_vec()), &self.0[index + 1..]),
+            None => (Self::root(), self.0.as_slice()),
+        })
+    }
+
+    /// The directory containing this path.
+    pub fn parent(&self) -> Option<RepoPath> {
+        self.split().map(|(parent, _)| parent)
+    }
+
+    /// The final component of the path.
+    pub fn file_name(&self) -> Option<&[u8]> {
+        self.components().next_back()
+    }
+
+    /// The directories containing this path, innermost first and ending with
+    /// the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = RepoPath> {
+        std::iter::successors(self.parent(), |path| path.parent())
+    }
+
+    /// Append a path to this one.
+    pub fn join(&self, path: &[u8]) -> RepoPath {
+        let mut joined = self.0.clone();
+        joined.push(b'/');
+        joined.extend_from_slice(path);
+
+        Self::new(&joined)
+    }
+
+    /// Remove `count` leading components.
+    ///
+    /// The final component is never removed, so paths having no more than
+    /// `count` components become their file name.
+    pub fn strip_components(&self, count: usize) -> RepoPath {
+        let mut path = self.0.as_slice();
+
+        for _ in 0..count {
+            match path.iter().position(|c| *c == b'/') {
+                Some(index) => path = &path[index + 1..],
+                None => break,
+            }
+        }
+
+        Self(path.to_vec())
+    }
+
+    /// Ensure every component of the path can be stored in a Git tree.
+    ///
+    /// `..` components, components naming a `.git` directory, and NUL bytes
+    /// are rejected.
+    pub fn validate(&self) -> Result<()> {
+        for component in self.components() {
+            if component == b".." {
+                return Err(anyhow!("path {} has a relative component", self));
+            }
+            if component.eq_ignore_ascii_case(b".git") {
+                return Err(anyhow!("path {} names a .git directory", self));
+            }
+            if component.contains(&0) {
+                return Err(anyhow!("path {} contains a NUL byte", self));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The path as a string, with invalid UTF-8 replaced.
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.0).to_string()
+    }
+}
+
+impl Display for RepoPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl From<&str> for RepoPath {
+    fn from(path: &str) -> Self {
+        Self::new(path.as_bytes())
+    }
+}
@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Platforms that Apple software releases belong to.
+
+use {
+    crate::download::ReleaseRecord,
+    std::{
+        collections::{BTreeMap, HashMap},
+        fmt::{Display, Formatter},
+    },
+};
+
+/// The platform (or release train) of a software release.
+///
+/// Release entity names have varied over time. e.g. macOS releases have been
+/// published as `mac-os-x`, `os-x`, and `macos`. Parsing an entity name maps
+/// all such aliases to the same platform.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Platform {
+    MacOs,
+    MacOsServer,
+    Ios,
+    IpadOs,
+    TvOs,
+    WatchOs,
+    DeveloperTools,
+    /// A platform not otherwise known, holding the entity name.
+    Other(String),
+}
+
+impl Platform {
+    /// Resolve the platform of a release entity name.
+    ///
+    /// Names are matched case insensitively and spaces are treated as
+    /// hyphens, so `Mac OS X` is `mac-os-x`.
+    pub fn from_entity(entity: &str) -> Self {
+        let entity = entity.to_ascii_lowercase();
+
+        match entity.replace(' ', "-").as_str() {
+            "macos" | "os-x" | "mac-os-x" | "mac-os" => Self::MacOs,
+            "macos-server" | "os-x-server" | "mac-os-x-server" => Self::MacOsServer,
+            "ios" | "iphone-os" | "iphone-sdk" => Self::Ios,
+            "ipados" => Self::IpadOs,
+            "tvos" | "apple-tv" => Self::TvOs,
+            "watchos" => Self::WatchOs,
+            "developer-tools" | "xcode" => Self::DeveloperTools,
+            _ => Self::Other(entity),
+        }
+    }
+
+    /// All known platforms.
+    pub fn known() -> [Self; 7] {
+        [
+            Self::MacOs,
+            Self::MacOsServer,
+            Self::Ios,
+            Self::IpadOs,
+            Self::TvOs,
+            Self::WatchOs,
+            Self::DeveloperTools,
+        ]
+    }
+
+    /// The canonical entity name of this platform.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::MacOs => "macos",
+            Self::MacOsServer => "macos-server",
+            Self::Ios => "ios",
+            Self::IpadOs => "ipados",
+            Self::TvOs => "tvos",
+            Self::WatchOs => "watchos",
+            Self::DeveloperTools => "developer-tools",
+            Self::Other(entity) => entity,
+        }
+    }
+
+    /// The human readable name of this platform.
+    pub fn display_name(&self) -> &str {
+        match self {
+            Self::MacOs => "macOS",
+            Self::MacOsServer => "macOS Server",
+            Self::Ios => "iOS",
+            Self::IpadOs => "iPadOS",
+            Self::TvOs => "tvOS",
+            Self::WatchOs => "watchOS",
+            Self::DeveloperTools => "Developer Tools",
+            Self::Other(entity) => entity,
+        }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.display_name())
+    }
+}
+
+/// Canonicalizes the entity names of release records.
+///
+/// By default, aliases of a known [Platform] are renamed to its canonical
+/// entity name. e.g. `mac-os-x` and `os-x` become `macos`. Additional aliases
+/// can be configured and take precedence. The original entity name is kept in
+/// [ReleaseRecord::original_entity].
+#[derive(Clone, Debug)]
+pub struct EntityAliases {
+    enabled: bool,
+    aliases: BTreeMap<String, String>,
+}
+
+impl Default for EntityAliases {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl EntityAliases {
+    /// Aliases leaving entity names as published.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            aliases: BTreeMap::new(),
+        }
+    }
+
+    /// Rename an entity to a canonical name.
+    pub fn with_alias(mut self, alias: &str, canonical: &str) -> Self {
+        self.aliases
+            .insert(alias.to_ascii_lowercase(), canonical.to_string());
+        self
+    }
+
+    /// Resolve the canonical name of an entity.
+    pub fn canonical(&self, entity: &str) -> String {
+        if !self.enabled {
+            return entity.to_string();
+        }
+
+        if let Some(canonical) = self.aliases.get(&entity.to_ascii_lowercase()) {
+            return canonical.clone();
+        }
+
+        match Platform::from_entity(entity) {
+            Platform::Other(_) => entity.to_string(),
+            platform => platform.as_str().to_string(),
+        }
+    }
+
+    /// Canonicalize the entities of records.
+    ///
+    /// Records for the same page are listed once, keeping the first record
+    /// with a date. The result is sorted.
+    pub fn normalize(&self, records: Vec<ReleaseRecord>) -> Vec<ReleaseRecord> {
+        let mut by_url = HashMap::<String, usize>::new();
+        let mut res: Vec<ReleaseRecord> = vec![];
+
+        for mut record in records {
+            let canonical = self.canonical(&record.entity);
+            if canonical != record.entity {
+                let original = std::mem::replace(&mut record.entity, canonical);
+                record.original_entity.get_or_insert(original);
+            }
+
+            if let Some(&index) = by_url.get(&record.url) {
+                if res[index].date.is_none() {
+                    res[index].date = record.date;
+                }
+                continue;
+            }
+
+            by_url.insert(record.url.clone(), res.len());
+            res.push(record);
+        }
+
+        res.sort();
+
+        res
+    }
+}
+
+/// Stitch the releases of a platform's aliases into one history.
+///
+/// Only releases of the platform of `entity` are kept. A version published
+/// under several aliases is listed once, keeping a dated record and
+/// preferring the one published under the canonical entity name. The result
+/// is not sorted; see [crate::chronology].
+pub fn stitch_aliases(
+    entity: &str,
+    records: impl IntoIterator<Item = ReleaseRecord>,
+) -> Vec<ReleaseRecord> {
+    let platform = Platform::from_entity(entity);
+    let mut by_version = HashMap::<String, usize>::new();
+    let mut res: Vec<ReleaseRecord> = vec![];
+
+    let rank = |record: &ReleaseRecord| {
+        (
+            record.date.is_some(),
+            record.published_entity() == platform.as_str(),
+        )
+    };
+
+    for record in records
+        .into_iter()
+        .filter(|record| record.matches_entity(entity))
+    {
+        match by_version.get(&record.version) {
+            Some(&index) => {
+                if rank(&record) > rank(&res[index]) {
+                    res[index] = record;
+                }
+            }
+            None => {
+                by_version.insert(record.version.clone(), res.len());
+                res.push(record);
+            }
+        }
+    }
+
+    res
+}
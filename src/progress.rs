@@ -0,0 +1,103 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Progress reporting for concurrent operations.
+
+use {
+    indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle},
+    std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    },
+};
+
+/// Whether progress displays are drawn.
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+/// Set whether progress displays are drawn.
+///
+/// When disabled, progress displays are hidden and their messages are printed
+/// as plain lines, which suits logs of unattended runs.
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+/// Emit a status message for a task.
+///
+/// The message is displayed on the task's progress bar if it has one and is
+/// printed otherwise. Messages of hidden progress bars are printed prefixed
+/// with the task's name.
+pub fn report(bar: Option<&ProgressBar>, message: impl Into<String>) {
+    match bar {
+        Some(bar) if bar.is_hidden() => println!("{}: {}", bar.prefix(), message.into()),
+        Some(bar) => bar.set_message(message.into()),
+        None => println!("{}", message.into()),
+    }
+}
+
+/// Tracks many concurrently running tasks.
+///
+/// Each in-flight task gets its own line, beneath which is an overall
+/// completed/total count.
+pub struct MultiTaskProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+}
+
+impl MultiTaskProgress {
+    pub fn new(total: usize, noun: &str) -> Self {
+        let multi = if INTERACTIVE.load(Ordering::Relaxed) {
+            MultiProgress::new()
+        } else {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        };
+
+        let overall = multi.add(ProgressBar::new(total as u64));
+        overall.set_style(
+            ProgressStyle::with_template(&format!(
+                "[{{elapsed_precise}}] {{bar:40}} {{pos}}/{{len}} {}",
+                noun
+            ))
+            .expect("progress template is valid"),
+        );
+
+        Self { multi, overall }
+    }
+
+    /// Register a newly started task, returning its progress bar.
+    pub fn start(&self, name: &str) -> ProgressBar {
+        let bar = self
+            .multi
+            .insert_before(&self.overall, ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {prefix}: {wide_msg}")
+                .expect("progress template is valid"),
+        );
+        bar.set_prefix(name.to_string());
+        bar.enable_steady_tick(Duration::from_millis(250));
+
+        bar
+    }
+
+    /// Record that a task has finished.
+    pub fn finish(&self, bar: ProgressBar) {
+        bar.finish_and_clear();
+        self.multi.remove(&bar);
+        self.overall.inc(1);
+    }
+
+    /// Print a line above the progress display.
+    pub fn println(&self, message: impl AsRef<str>) {
+        // Printing only fails if the display is hidden, in which case fall back
+        // to stdout.
+        if self.multi.println(message.as_ref()).is_err() || self.multi.is_hidden() {
+            println!("{}", message.as_ref());
+        }
+    }
+
+    /// Remove the progress display.
+    pub fn clear(&self) {
+        self.overall.finish_and_clear();
+    }
+}
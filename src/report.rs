@@ -0,0 +1,249 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Human readable summaries of imported software releases.
+//!
+//! A report is derived from the [ReleaseManifest] of a release commit and of
+//! its parent, listing every component with its version, archive URL, and
+//! size, and highlighting components that were added, updated, or removed.
+//! Reports are Markdown, suitable for publishing as a GitHub Release or blog
+//! post alongside a mirrored repository.
+
+use {
+    crate::git::{ManifestFailure, ReleaseManifest},
+    anyhow::{anyhow, Result},
+    git2::{Commit, ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult},
+    indicatif::HumanBytes,
+    std::collections::BTreeMap,
+};
+
+/// Escape text for a Markdown table cell.
+pub fn markdown_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+/// How a component of a release differs from the previous release.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ComponentChange {
+    /// The previous release doesn't have the component.
+    Added,
+    /// The previous release has another version of the component.
+    Updated { from: Option<String> },
+    /// The previous release has the same archive of the component.
+    Unchanged,
+}
+
+/// A component in a [ReleaseReport].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReportComponent {
+    pub component: String,
+    pub version: Option<String>,
+    pub url: String,
+    /// Total size of the files of the component.
+    pub size: u64,
+    pub change: ComponentChange,
+}
+
+/// A summary of a release commit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReleaseReport {
+    pub entity: String,
+    pub version: String,
+    pub url: String,
+    pub date: Option<String>,
+    /// Components, sorted by name.
+    pub components: Vec<ReportComponent>,
+    /// Components of the previous release that aren't in this release, with
+    /// their version.
+    pub removed: Vec<(String, Option<String>)>,
+    pub failed: Vec<ManifestFailure>,
+}
+
+/// Total size of the blobs of a tree.
+fn tree_size(repo: &Repository, tree: Oid) -> Result<u64> {
+    let odb = repo.odb()?;
+    let mut size = 0;
+    let mut error = None;
+
+    repo.find_tree(tree)?
+        .walk(TreeWalkMode::PreOrder, |_, entry| {
+            if entry.kind() != Some(ObjectType::Blob) {
+                return TreeWalkResult::Ok;
+            }
+
+            match odb.read_header(entry.id()) {
+                Ok((len, _)) => {
+                    size += len as u64;
+                    TreeWalkResult::Ok
+                }
+                Err(e) => {
+                    error = Some(e);
+                    TreeWalkResult::Abort
+                }
+            }
+        })?;
+
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(size),
+    }
+}
+
+impl ReleaseReport {
+    /// Summarize a release commit, comparing it with the previous release.
+    pub fn from_commits(
+        repo: &Repository,
+        commit: &Commit,
+        previous: Option<&Commit>,
+    ) -> Result<Self> {
+        let manifest = ReleaseManifest::from_commit(repo, commit)?
+            .ok_or_else(|| anyhow!("commit {} has no release manifest", commit.id()))?;
+        let previous = match previous {
+            Some(previous) => ReleaseManifest::from_commit(repo, previous)?,
+            None => None,
+        };
+
+        let mut previous_components = previous
+            .iter()
+            .flat_map(|manifest| &manifest.components)
+            .map(|component| (component.component.as_str(), component))
+            .collect::<BTreeMap<_, _>>();
+
+        let tree = commit.tree()?;
+        let mut components = vec![];
+
+        for component in &manifest.components {
+            let size = match tree.get_name(&component.component) {
+                Some(entry) => tree_size(repo, entry.id())?,
+                None => 0,
+            };
+
+            let change = match previous_components.remove(component.component.as_str()) {
+                None => ComponentChange::Added,
+                Some(previous) if previous.url == component.url => ComponentChange::Unchanged,
+                Some(previous) => ComponentChange::Updated {
+                    from: previous.version.clone(),
+                },
+            };
+
+            components.push(ReportComponent {
+                component: component.component.clone(),
+                version: component.version.clone(),
+                url: component.url.clone(),
+                size,
+                change,
+            });
+        }
+
+        let removed = previous_components
+            .into_values()
+            .map(|component| (component.component.clone(), component.version.clone()))
+            .collect();
+
+        Ok(Self {
+            entity: manifest.entity,
+            version: manifest.version,
+            url: manifest.url,
+            date: manifest.date,
+            components,
+            removed,
+            failed: manifest.failed,
+        })
+    }
+
+    /// Render as Markdown.
+    pub fn to_markdown(&self) -> String {
+        let count = |f: fn(&ComponentChange) -> bool| {
+            self.components
+                .iter()
+                .filter(|component| f(&component.change))
+                .count()
+        };
+        let version = |v: &Option<String>| v.as_deref().unwrap_or("unknown version").to_string();
+
+        let mut lines = vec![format!("# {} {}", self.entity, self.version), String::new()];
+
+        lines.push(match &self.date {
+            Some(date) => format!("Released {}. [Release page]({})", date, self.url),
+            None => format!("[Release page]({})", self.url),
+        });
+        lines.push(String::new());
+        lines.push(format!(
+            "{} components: {} added, {} updated, {} unchanged, {} removed.",
+            self.components.len(),
+            count(|c| *c == ComponentChange::Added),
+            count(|c| matches!(c, ComponentChange::Updated { .. })),
+            count(|c| *c == ComponentChange::Unchanged),
+            self.removed.len()
+        ));
+
+        let mut changes = vec![];
+        for component in &self.components {
+            match &component.change {
+                ComponentChange::Added => changes.push(format!(
+                    "- Added `{}` {}",
+                    component.component,
+                    version(&component.version)
+                )),
+                ComponentChange::Updated { from } => changes.push(format!(
+                    "- Updated `{}` {} → {}",
+                    component.component,
+                    version(from),
+                    version(&component.version)
+                )),
+                ComponentChange::Unchanged => {}
+            }
+        }
+        for (component, v) in &self.removed {
+            changes.push(format!("- Removed `{}` {}", component, version(v)));
+        }
+
+        if !changes.is_empty() {
+            lines.push(String::new());
+            lines.push("## Changes".to_string());
+            lines.push(String::new());
+            lines.extend(changes);
+        }
+
+        lines.push(String::new());
+        lines.push("## Components".to_string());
+        lines.push(String::new());
+        lines.push("| Component | Version | Size | Change |".to_string());
+        lines.push("| --- | --- | --- | --- |".to_string());
+        for component in &self.components {
+            lines.push(format!(
+                "| {} | [{}]({}) | {} | {} |",
+                markdown_escape(&component.component),
+                markdown_escape(&version(&component.version)),
+                component.url,
+                HumanBytes(component.size),
+                match &component.change {
+                    ComponentChange::Added => "added".to_string(),
+                    ComponentChange::Updated { from } =>
+                        format!("updated from {}", markdown_escape(&version(from))),
+                    ComponentChange::Unchanged => String::new(),
+                }
+            ));
+        }
+
+        if !self.failed.is_empty() {
+            lines.push(String::new());
+            lines.push("## Failed Components".to_string());
+            lines.push(String::new());
+            lines
+                .push("These components failed to download and aren't in the release.".to_string());
+            lines.push(String::new());
+            lines.extend(
+                self.failed
+                    .iter()
+                    .map(|failure| format!("- [`{}`]({})", failure.component, failure.url)),
+            );
+        }
+
+        lines.join("\n") + "\n"
+    }
+}
@@ -0,0 +1,196 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Service definitions running `watch` under a service manager.
+//!
+//! [ServiceDefinition] renders a systemd user unit or a launchd agent
+//! property list invoking the tool, so continuous mirrors start at boot and
+//! are restarted if they fail.
+
+use {
+    crate::error::EXIT_INTERRUPTED,
+    anyhow::{anyhow, Context, Result},
+    plist::{Dictionary, Value},
+    std::{
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+/// Name of the systemd unit.
+pub const SYSTEMD_UNIT_NAME: &str = "apple-opensource-downloader-watch.service";
+
+/// Label of the launchd agent.
+pub const LAUNCHD_LABEL: &str = "com.github.indygreg.apple-opensource-downloader.watch";
+
+/// A service manager.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ServiceManager {
+    Systemd,
+    Launchd,
+}
+
+impl FromStr for ServiceManager {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "systemd" => Ok(Self::Systemd),
+            "launchd" => Ok(Self::Launchd),
+            _ => Err(anyhow!("unknown service manager: {}", s)),
+        }
+    }
+}
+
+impl ServiceManager {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Systemd => "systemd",
+            Self::Launchd => "launchd",
+        }
+    }
+}
+
+/// Quote an argument of a systemd `ExecStart` command line.
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+
+    if escaped.is_empty() || escaped.contains(char::is_whitespace) || escaped != arg {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// A service running the tool.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServiceDefinition {
+    pub manager: ServiceManager,
+    /// Path of the executable.
+    pub program: PathBuf,
+    /// Arguments passed to the executable.
+    pub args: Vec<String>,
+}
+
+impl ServiceDefinition {
+    /// Where the definition is installed for the current user.
+    pub fn user_path(&self) -> Result<PathBuf> {
+        let home = || {
+            std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .ok_or_else(|| anyhow!("HOME is not set"))
+        };
+
+        Ok(match self.manager {
+            ServiceManager::Systemd => {
+                let config = match std::env::var_os("XDG_CONFIG_HOME") {
+                    Some(dir) => PathBuf::from(dir),
+                    None => home()?.join(".config"),
+                };
+
+                config.join("systemd").join("user").join(SYSTEMD_UNIT_NAME)
+            }
+            ServiceManager::Launchd => home()?
+                .join("Library")
+                .join("LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+        })
+    }
+
+    /// Render the definition.
+    pub fn render(&self) -> Result<String> {
+        match self.manager {
+            ServiceManager::Systemd => Ok(self.systemd_unit()),
+            ServiceManager::Launchd => self.launchd_plist(),
+        }
+    }
+
+    fn command_line(&self) -> Vec<String> {
+        std::iter::once(self.program.to_string_lossy().to_string())
+            .chain(self.args.iter().cloned())
+            .collect()
+    }
+
+    fn systemd_unit(&self) -> String {
+        let exec = self
+            .command_line()
+            .iter()
+            .map(|arg| systemd_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!(
+            "[Unit]\n\
+             Description=Mirror Apple open source software\n\
+             Wants=network-online.target\n\
+             After=network-online.target\n\
+             \n\
+             [Service]\n\
+             ExecStart={}\n\
+             Restart=on-failure\n\
+             RestartSec=5min\n\
+             # Imports stop cleanly on SIGTERM, which may take a while.\n\
+             SuccessExitStatus={}\n\
+             TimeoutStopSec=15min\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            exec, EXIT_INTERRUPTED
+        )
+    }
+
+    fn launchd_plist(&self) -> Result<String> {
+        let mut keep_alive = Dictionary::new();
+        keep_alive.insert("SuccessfulExit".to_string(), Value::Boolean(false));
+
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "Label".to_string(),
+            Value::String(LAUNCHD_LABEL.to_string()),
+        );
+        dict.insert(
+            "ProgramArguments".to_string(),
+            Value::Array(self.command_line().into_iter().map(Value::String).collect()),
+        );
+        dict.insert("RunAtLoad".to_string(), Value::Boolean(true));
+        dict.insert("KeepAlive".to_string(), Value::Dictionary(keep_alive));
+        dict.insert(
+            "ProcessType".to_string(),
+            Value::String("Background".to_string()),
+        );
+
+        let mut data = vec![];
+        Value::Dictionary(dict).to_writer_xml(&mut data)?;
+
+        Ok(String::from_utf8(data)? + "\n")
+    }
+
+    /// Write the definition to `path`, creating parent directories.
+    pub fn install(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+
+        std::fs::write(path, self.render()?)
+            .with_context(|| format!("writing {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// The commands enabling the installed service.
+    pub fn enable_instructions(&self, path: &Path) -> String {
+        match self.manager {
+            ServiceManager::Systemd => format!(
+                "systemctl --user daemon-reload && systemctl --user enable --now {}",
+                SYSTEMD_UNIT_NAME
+            ),
+            ServiceManager::Launchd => format!("launchctl load -w {}", path.display()),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Splitting of component repositories by top-level directory.
+//!
+//! The histories of components like WebKit are too large to clone
+//! comfortably. Splitting produces a repository per top-level directory of
+//! the component, each having a commit for every version of the component so
+//! all repositories have the same tags. Files at the top level go to a
+//! repository named [ROOT_REPOSITORY].
+
+use {
+    crate::{
+        download::Downloader,
+        git::{create_component_repository, reconcile_repo_to_commit, tag_commits, ImportOptions},
+        hooks::Hooks,
+    },
+    anyhow::{anyhow, Context, Result},
+    git2::{ObjectType, Oid, Repository, RepositoryInitOptions},
+    std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Name of the repository holding files at the top level of a component.
+pub const ROOT_REPOSITORY: &str = "_root";
+
+/// Name of the directory the complete history is imported into before it is
+/// split.
+const SCRATCH_DIRNAME: &str = ".apple-oss-full";
+
+/// Copy the objects of a tree and its subtrees between repositories.
+///
+/// Objects already present in the destination are skipped.
+fn copy_tree(src: &Repository, dst: &Repository, tree: Oid) -> Result<()> {
+    let src_odb = src.odb()?;
+    let dst_odb = dst.odb()?;
+    let mut pending = vec![tree];
+
+    while let Some(oid) = pending.pop() {
+        if dst_odb.exists(oid) {
+            continue;
+        }
+
+        let object = src_odb.read(oid)?;
+
+        if object.kind() == ObjectType::Tree {
+            for entry in src.find_tree(oid)?.iter() {
+                match entry.kind() {
+                    Some(ObjectType::Tree) | Some(ObjectType::Blob) => pending.push(entry.id()),
+                    // Submodule commits aren't part of the repository.
+                    _ => {}
+                }
+            }
+        }
+
+        dst_odb.write(object.kind(), object.data())?;
+    }
+
+    Ok(())
+}
+
+/// The top-level content of a tree, keyed by repository name.
+///
+/// Directories map to their tree. Files at the top level are collected into
+/// a tree for [ROOT_REPOSITORY].
+fn top_level_trees(repo: &Repository, tree: &git2::Tree) -> Result<BTreeMap<String, Oid>> {
+    let mut trees = BTreeMap::new();
+    let mut root = repo.treebuilder(None)?;
+
+    for entry in tree.iter() {
+        let name = entry
+            .name()
+            .ok_or_else(|| anyhow!("non UTF-8 path {:?}", entry.name_bytes()))?;
+
+        if entry.kind() == Some(ObjectType::Tree) {
+            trees.insert(name.to_string(), entry.id());
+        } else {
+            root.insert(name, entry.id(), entry.filemode())?;
+        }
+    }
+
+    if !root.is_empty() {
+        trees.insert(ROOT_REPOSITORY.to_string(), root.write()?);
+    }
+
+    Ok(trees)
+}
+
+/// Split the history of a component repository into a repository per
+/// top-level directory under `dest`.
+///
+/// Every commit on the `main` branch is replayed in each repository with the
+/// same author, committer, and message, committing the directory's content
+/// (or an empty tree if the directory doesn't exist in that version). Each
+/// version's tag is recreated on the corresponding commits.
+///
+/// Returns the paths of the created repositories.
+pub fn split_top_level(source: &Path, dest: &Path, bare: bool) -> Result<Vec<PathBuf>> {
+    let branch_name = "main";
+    let src = Repository::open(source)
+        .with_context(|| format!("opening repository {}", source.display()))?;
+
+    let head = src
+        .find_branch(branch_name, git2::BranchType::Local)?
+        .get()
+        .peel_to_commit()?;
+
+    let mut walk = src.revwalk()?;
+    walk.push(head.id())?;
+    walk.simplify_first_parent()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    let commits = walk
+        .map(|oid| Ok(src.find_commit(oid?)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Versions tagged at each commit.
+    let mut tags: HashMap<Oid, Vec<String>> = HashMap::new();
+    for (version, commit) in tag_commits(source)? {
+        tags.entry(Oid::from_str(&commit)?)
+            .or_default()
+            .push(version);
+    }
+
+    let trees = commits
+        .iter()
+        .map(|commit| top_level_trees(&src, &commit.tree()?))
+        .collect::<Result<Vec<_>>>()?;
+    let names = trees
+        .iter()
+        .flat_map(|trees| trees.keys().cloned())
+        .collect::<BTreeSet<_>>();
+
+    let mut paths = vec![];
+
+    for name in names {
+        let path = dest.join(&name);
+        println!("splitting {} into {}", name, path.display());
+
+        let repo = Repository::init_opts(
+            &path,
+            RepositoryInitOptions::new()
+                .bare(bare)
+                .initial_head(branch_name),
+        )
+        .context("initialing repository")?;
+
+        let empty = repo.treebuilder(None)?.write()?;
+        let mut parent = None;
+
+        for (commit, trees) in commits.iter().zip(&trees) {
+            let tree_oid = match trees.get(&name) {
+                Some(oid) => {
+                    copy_tree(&src, &repo, *oid)?;
+                    *oid
+                }
+                None => empty,
+            };
+            let tree = repo.find_tree(tree_oid)?;
+
+            let parents = parent.iter().collect::<Vec<_>>();
+            let oid = repo.commit(
+                None,
+                &commit.author(),
+                &commit.committer(),
+                commit.message().unwrap_or_default(),
+                &tree,
+                &parents,
+            )?;
+            let split = repo.find_commit(oid)?;
+
+            for version in tags.get(&commit.id()).into_iter().flatten() {
+                repo.tag(
+                    version,
+                    split.as_object(),
+                    &commit.committer(),
+                    "tagging",
+                    true,
+                )?;
+            }
+
+            parent = Some(split);
+        }
+
+        if let Some(parent) = &parent {
+            reconcile_repo_to_commit(&repo, branch_name, parent)?;
+        }
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Import a component into a repository per top-level directory under `dest`.
+///
+/// The complete history is imported into a scratch repository in `dest` and
+/// then split with [split_top_level]. The scratch repository is removed
+/// afterwards, so splitting again imports everything again.
+pub async fn create_split_component_repositories(
+    downloader: &Downloader,
+    dest: &Path,
+    component: &str,
+    options: &ImportOptions,
+) -> Result<Vec<PathBuf>> {
+    let scratch = dest.join(SCRATCH_DIRNAME);
+    let scratch_options = ImportOptions {
+        bare: true,
+        force: true,
+        hooks: Hooks::default(),
+        optimize: false,
+        partial_clone: false,
+        ..options.clone()
+    };
+
+    create_component_repository(downloader, &scratch, component, &scratch_options).await?;
+
+    let paths = split_top_level(&scratch, dest, options.bare)?;
+
+    for path in &paths {
+        options.optimize_repository(&Repository::open(path)?)?;
+    }
+
+    std::fs::remove_dir_all(&scratch).with_context(|| format!("removing {}", scratch.display()))?;
+
+    Ok(paths)
+}
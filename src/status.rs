@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Whether mirrored repositories are up to date.
+//!
+//! [repo_status] compares the versions tagged in a component repository
+//! with the versions available, so automation can decide whether to sync
+//! without importing anything.
+
+use {
+    crate::{download::Downloader, git::tag_commits},
+    anyhow::{Context, Result},
+    serde::Serialize,
+    std::path::Path,
+};
+
+/// How a component repository compares with the available versions.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RepoStatus {
+    pub component: String,
+    /// Available versions tagged in the repository, oldest first.
+    pub imported_versions: Vec<String>,
+    /// Available versions not tagged in the repository, oldest first.
+    pub missing_versions: Vec<String>,
+    /// Number of missing versions newer than the newest imported version.
+    ///
+    /// These are the versions an incremental import appends. Missing
+    /// versions older than the newest imported version are gaps in the
+    /// history.
+    pub behind_by: usize,
+}
+
+impl RepoStatus {
+    /// Whether every available version is imported.
+    pub fn is_up_to_date(&self) -> bool {
+        self.missing_versions.is_empty()
+    }
+
+    /// Render as plain text.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!(
+            "{}: {} versions imported, {} missing, behind by {}",
+            self.component,
+            self.imported_versions.len(),
+            self.missing_versions.len(),
+            self.behind_by
+        )];
+
+        lines.extend(
+            self.missing_versions
+                .iter()
+                .map(|v| format!("  missing {}", v)),
+        );
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Render as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+/// Compare the repository of a component at `path` with the available
+/// versions of the component.
+///
+/// A repository that doesn't exist is missing every version.
+pub async fn repo_status(
+    downloader: &Downloader,
+    path: &Path,
+    component: &str,
+) -> Result<RepoStatus> {
+    let tags = if path.exists() {
+        tag_commits(path)?
+    } else {
+        Default::default()
+    };
+
+    let mut versions = downloader
+        .get_component_versions(component)
+        .await
+        .context("fetching component versions")?
+        .into_iter()
+        .map(|record| record.version)
+        .collect::<Vec<_>>();
+    versions.sort_by(|a, b| downloader.compare_versions(component, a, b));
+    versions.dedup();
+
+    let (imported_versions, missing_versions): (Vec<_>, Vec<_>) =
+        versions.into_iter().partition(|v| tags.contains_key(v));
+
+    let behind_by = match imported_versions.last() {
+        Some(newest) => missing_versions
+            .iter()
+            .filter(|v| downloader.compare_versions(component, v, newest).is_gt())
+            .count(),
+        None => missing_versions.len(),
+    };
+
+    Ok(RepoStatus {
+        component: component.to_string(),
+        imported_versions,
+        missing_versions,
+        behind_by,
+    })
+}
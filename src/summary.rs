@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Summaries of command runs for automation.
+//!
+//! Scheduled jobs keeping mirrors fresh need to know how a run went without
+//! parsing its log. With `--non-interactive`, a [RunSummary] is printed as the
+//! last line of output. With `--github-output`, it is also written as GitHub
+//! Actions step outputs.
+
+use {
+    crate::error::{
+        exit_code, PartialFailure, EXIT_INTERRUPTED, EXIT_PARTIAL_FAILURE, EXIT_SUCCESS,
+    },
+    anyhow::{Context, Result},
+    serde::Serialize,
+    std::{fs::OpenOptions, io::Write, path::Path, time::Duration},
+};
+
+/// The outcome of running a command.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// Name of the subcommand.
+    pub command: String,
+    /// `success`, `partial-failure`, `interrupted`, or `failure`.
+    pub status: &'static str,
+    pub exit_code: i32,
+    pub duration_secs: u64,
+    /// The error the command failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl RunSummary {
+    pub fn new(command: &str, res: &Result<()>, duration: Duration) -> Self {
+        let code = exit_code(res);
+
+        Self {
+            command: command.to_string(),
+            status: match code {
+                EXIT_SUCCESS => "success",
+                EXIT_PARTIAL_FAILURE => "partial-failure",
+                EXIT_INTERRUPTED => "interrupted",
+                _ => "failure",
+            },
+            exit_code: code,
+            duration_secs: duration.as_secs(),
+            error: res
+                .as_ref()
+                .err()
+                .map(|e| match e.downcast_ref::<PartialFailure>() {
+                    Some(partial) => partial.to_string(),
+                    None => format!("{:#}", e),
+                }),
+        }
+    }
+
+    /// The summary as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("summary is serializable")
+    }
+
+    /// The summary in the format of GitHub Actions step outputs.
+    ///
+    /// Values spanning lines use the heredoc syntax.
+    pub fn github_output(&self) -> String {
+        let mut outputs = vec![
+            ("command", self.command.clone()),
+            ("status", self.status.to_string()),
+            ("exit-code", self.exit_code.to_string()),
+            ("duration-secs", self.duration_secs.to_string()),
+            ("summary", self.to_json()),
+        ];
+        if let Some(error) = &self.error {
+            outputs.push(("error", error.clone()));
+        }
+
+        outputs
+            .into_iter()
+            .map(|(key, value)| {
+                if value.contains('\n') {
+                    format!("{}<<EOF_{}\n{}\nEOF_{}\n", key, key, value, key)
+                } else {
+                    format!("{}={}\n", key, value)
+                }
+            })
+            .collect()
+    }
+
+    /// Append the summary as step outputs to a GitHub Actions output file.
+    pub fn write_github_output(&self, path: &Path) -> Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut fh| fh.write_all(self.github_output().as_bytes()))
+            .with_context(|| format!("writing GitHub outputs to {}", path.display()))
+    }
+}
@@ -0,0 +1,287 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Keeping mirrors up to date from configuration.
+//!
+//! The `sync` section of the configuration file describes which components
+//! and releases are mirrored to Git repositories, where the repositories
+//! live, and where they are published. [sync] brings every repository up to
+//! date, importing only what is new, so it can run unattended. [watch] does
+//! so periodically.
+
+use {
+    crate::{
+        destination::DestinationTemplate,
+        download::Downloader,
+        error::{InterruptedError, PartialFailure},
+        failures::FailureLog,
+        git::{self, ImportOptions},
+        hooks::CommandHook,
+    },
+    anyhow::{anyhow, Context, Result},
+    glob::Pattern,
+    serde::Deserialize,
+    std::{
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::Arc,
+        time::Duration,
+    },
+};
+
+/// What `sync` mirrors, read from the `sync` section of a configuration file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    /// Glob patterns of components to mirror. `*` mirrors every component.
+    pub components: Vec<String>,
+
+    /// Directory holding a repository per mirrored component.
+    pub components_dest: Option<PathBuf>,
+
+    /// Template of component repository paths, like
+    /// `{dest}/{component[0]}/{component}.git`. See [DestinationTemplate].
+    pub components_dest_template: Option<String>,
+
+    /// Release entities to mirror, e.g. `macos`.
+    pub releases: Vec<String>,
+
+    /// Directory holding a repository per mirrored release entity.
+    pub releases_dest: Option<PathBuf>,
+
+    /// Create repositories with working trees instead of bare repositories.
+    pub no_bare: bool,
+
+    /// Merge versions tagged in the apple-oss-distributions GitHub
+    /// repositories into component repositories.
+    pub stitch_github: bool,
+
+    /// Also mirror point and security updates only linked from release pages.
+    pub include_updates: bool,
+
+    /// Shell commands run after each commit, tag, and repository completion.
+    pub hooks: Vec<String>,
+
+    /// Append records of failures to this NDJSON file.
+    pub failure_log: Option<PathBuf>,
+
+    /// URL advertised in each repository's `cloneurl` file. `{name}` is
+    /// replaced with the component or release entity name.
+    pub clone_url: Option<String>,
+
+    /// Git remote URLs repositories are mirrored to once updated.
+    ///
+    /// `{name}` is replaced with the name of the component or release
+    /// entity. e.g. `git@example.com:apple/{name}.git`.
+    pub publish: Vec<String>,
+
+    /// Pack objects and write a multi-pack-index and commit-graph once a
+    /// repository is updated, before publishing it.
+    pub optimize: bool,
+
+    /// Allow clones of repositories to use partial clone filters.
+    pub partial_clone: bool,
+}
+
+impl SyncConfig {
+    /// Whether anything is configured to be mirrored.
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty() && self.releases.is_empty()
+    }
+
+    /// Apply the import settings of this configuration to options.
+    pub fn import_options(&self, options: ImportOptions) -> Result<ImportOptions> {
+        let mut hooks = options.hooks;
+        for command in &self.hooks {
+            hooks.push(CommandHook::new(command));
+        }
+
+        Ok(ImportOptions {
+            bare: options.bare && !self.no_bare,
+            hooks,
+            include_updates: self.include_updates,
+            optimize: self.optimize || options.optimize,
+            partial_clone: self.partial_clone || options.partial_clone,
+            clone_url: self.clone_url.clone().or(options.clone_url),
+            failure_log: match &self.failure_log {
+                Some(path) => Some(Arc::new(FailureLog::open(path)?)),
+                None => options.failure_log,
+            },
+            // Existing repositories are brought up to date.
+            resume: true,
+            ..options
+        })
+    }
+
+    fn component_patterns(&self) -> Result<Vec<Pattern>> {
+        self.components
+            .iter()
+            .map(|v| Pattern::new(v).with_context(|| format!("parsing pattern {}", v)))
+            .collect()
+    }
+}
+
+/// Mirror a repository to the configured publish targets.
+fn publish(path: &Path, name: &str, targets: &[String]) -> Result<()> {
+    for target in targets {
+        let url = target.replace("{name}", name);
+
+        println!("publishing {} to {}", path.display(), url);
+
+        let status = std::process::Command::new("git")
+            .arg("push")
+            .arg("--mirror")
+            .arg(&url)
+            .current_dir(path)
+            .status()
+            .context("running git push")?;
+
+        if !status.success() {
+            return Err(anyhow!("git push to {} failed: {}", url, status));
+        }
+    }
+
+    Ok(())
+}
+
+/// Bring the repositories described by a [SyncConfig] up to date.
+///
+/// Failures of individual repositories don't stop others from being
+/// updated; a [PartialFailure] is returned if any repository failed.
+pub async fn sync(
+    downloader: &Downloader,
+    config: &SyncConfig,
+    options: &ImportOptions,
+) -> Result<()> {
+    if config.is_empty() {
+        return Err(anyhow!(
+            "nothing to sync; configure components or releases in the sync section of the configuration file"
+        ));
+    }
+
+    let options = config.import_options(options.clone())?;
+
+    let mut targets = 0;
+    let mut failures = 0;
+
+    if !config.components.is_empty() {
+        let dest = config
+            .components_dest
+            .as_ref()
+            .ok_or_else(|| anyhow!("syncing components requires components_dest"))?;
+
+        let patterns = config.component_patterns()?;
+        let components = downloader
+            .get_components()
+            .await
+            .context("resolving components")?
+            .into_iter()
+            .filter(|c| patterns.iter().any(|p| p.matches(c)))
+            .collect::<Vec<_>>();
+
+        let component_options = ImportOptions {
+            components: patterns,
+            destination: config
+                .components_dest_template
+                .as_deref()
+                .map(DestinationTemplate::from_str)
+                .transpose()?
+                .unwrap_or_default(),
+            ..options.clone()
+        };
+
+        targets += 1;
+        if let Err(e) =
+            git::create_components_repositories(downloader, dest, &component_options).await
+        {
+            println!("error syncing components: {:?}", e);
+            failures += 1;
+        }
+
+        for component in &components {
+            if options.shutdown.is_cancelled() {
+                break;
+            }
+
+            let path = component_options.destination.resolve(dest, component);
+
+            // Components without versions don't have a repository.
+            if config.publish.is_empty() || !path.exists() {
+                continue;
+            }
+
+            targets += 1;
+            if let Err(e) = publish(&path, component, &config.publish) {
+                println!("error publishing {}: {:?}", component, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if !config.releases.is_empty() {
+        let dest = config
+            .releases_dest
+            .as_ref()
+            .ok_or_else(|| anyhow!("syncing releases requires releases_dest"))?;
+
+        for entity in &config.releases {
+            if options.shutdown.is_cancelled() {
+                break;
+            }
+
+            let path = dest.join(entity);
+
+            targets += 1;
+            let res = git::create_release_repository(downloader, &path, entity, &options)
+                .await
+                .and_then(|()| publish(&path, entity, &config.publish));
+
+            if let Err(e) = res {
+                println!("error syncing release {}: {:?}", entity, e);
+                failures += 1;
+            }
+        }
+    }
+
+    // Targets not started are left for the next sync.
+    if options.shutdown.is_cancelled() {
+        return Err(InterruptedError.into());
+    }
+
+    PartialFailure::check("sync targets", failures, targets)
+}
+
+/// Bring the repositories described by a [SyncConfig] up to date every
+/// `interval`, until [ImportOptions::shutdown] is cancelled.
+///
+/// Failed syncs are reported and retried at the next interval.
+pub async fn watch(
+    downloader: &Downloader,
+    config: &SyncConfig,
+    options: &ImportOptions,
+    interval: Duration,
+) -> Result<()> {
+    if config.is_empty() {
+        return Err(anyhow!(
+            "nothing to watch; configure components or releases in the sync section of the configuration file"
+        ));
+    }
+
+    loop {
+        if let Err(e) = sync(downloader, config, options).await {
+            println!("error syncing: {:?}", e);
+        }
+
+        if options.shutdown.is_cancelled() {
+            return Err(InterruptedError.into());
+        }
+
+        println!("next sync in {}s", interval.as_secs());
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = options.shutdown.cancelled() => return Err(InterruptedError.into()),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Supervised execution of concurrent tasks.
+//!
+//! [TaskSet] runs tasks on the Tokio runtime and [LocalTaskSet] runs tasks
+//! that can't be sent between threads, such as those holding `git2` objects.
+//! Both limit how many tasks run at once, turn panics into errors, can be
+//! cancelled and report the outcome of every task in the order they were
+//! spawned.
+
+use {
+    crate::error::{TaskCancelledError, TaskPanicError},
+    anyhow::{Context, Result},
+    futures::FutureExt,
+    std::{
+        any::Any,
+        future::Future,
+        panic::AssertUnwindSafe,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    },
+    tokio::{
+        sync::{Notify, Semaphore},
+        task::{JoinSet, LocalSet},
+    },
+};
+
+/// The outcome of a task.
+#[derive(Debug)]
+pub struct TaskOutcome<T> {
+    /// Label the task was spawned with.
+    pub label: String,
+    pub result: Result<T>,
+}
+
+/// Cancels the tasks of a task set.
+///
+/// Handles can be cloned and used from any task or thread. Tasks that haven't
+/// completed when the set is cancelled are aborted at their next await point
+/// and report [TaskCancelledError].
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.notify.notified();
+
+            if self.is_cancelled() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
+/// State shared by [TaskSet] and [LocalTaskSet].
+struct Tasks<T> {
+    set: JoinSet<(usize, Result<T>)>,
+    labels: Vec<String>,
+    semaphore: Option<Arc<Semaphore>>,
+    cancel: CancelHandle,
+}
+
+impl<T: 'static> Tasks<T> {
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            set: JoinSet::new(),
+            labels: vec![],
+            semaphore: limit.map(|limit| Arc::new(Semaphore::new(limit.max(1)))),
+            cancel: CancelHandle::default(),
+        }
+    }
+
+    /// Wrap a task so it waits for a permit and reports its index and panics.
+    fn supervise<F>(&mut self, label: String, fut: F) -> impl Future<Output = (usize, Result<T>)>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let index = self.labels.len();
+        self.labels.push(label.clone());
+        let semaphore = self.semaphore.clone();
+
+        async move {
+            let _permit = match semaphore {
+                Some(semaphore) => Some(
+                    semaphore
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore is never closed"),
+                ),
+                None => None,
+            };
+
+            let result = match AssertUnwindSafe(fut).catch_unwind().await {
+                Ok(result) => result,
+                Err(payload) => Err(TaskPanicError {
+                    task: label,
+                    message: panic_message(payload),
+                }
+                .into()),
+            };
+
+            (index, result)
+        }
+    }
+
+    async fn join(&mut self, fail_fast: bool) -> Vec<TaskOutcome<T>> {
+        let mut results = self.labels.iter().map(|_| None).collect::<Vec<_>>();
+        let mut aborted = false;
+
+        loop {
+            tokio::select! {
+                res = self.set.join_next() => match res {
+                    Some(Ok((index, result))) => {
+                        if fail_fast && result.is_err() {
+                            self.cancel.cancel();
+                        }
+                        results[index] = Some(result);
+                    }
+                    // Aborted tasks don't report their index and are
+                    // recorded as cancelled below.
+                    Some(Err(_)) => {}
+                    None => break,
+                },
+                _ = self.cancel.cancelled(), if !aborted => {
+                    aborted = true;
+                    self.set.abort_all();
+                }
+            }
+        }
+
+        self.labels
+            .drain(..)
+            .zip(results)
+            .map(|(label, result)| TaskOutcome {
+                result: result.unwrap_or_else(|| {
+                    Err(TaskCancelledError {
+                        task: label.clone(),
+                    }
+                    .into())
+                }),
+                label,
+            })
+            .collect()
+    }
+
+    async fn try_join(&mut self) -> Result<Vec<T>> {
+        let outcomes = self.join(true).await;
+
+        // Report the failure that caused cancellation rather than the tasks
+        // cancelled because of it.
+        let first_error = outcomes.iter().position(|outcome| {
+            matches!(&outcome.result, Err(e) if e.downcast_ref::<TaskCancelledError>().is_none())
+        });
+
+        let mut values = vec![];
+        for (index, outcome) in outcomes.into_iter().enumerate() {
+            match outcome.result {
+                Ok(value) => values.push(value),
+                Err(e) if first_error.is_none() || first_error == Some(index) => {
+                    return Err(e).with_context(|| format!("running task {}", outcome.label));
+                }
+                Err(_) => {}
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+/// Render the payload of a panic.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// A set of tasks running on the Tokio runtime.
+pub struct TaskSet<T> {
+    tasks: Tasks<T>,
+}
+
+impl<T: Send + 'static> TaskSet<T> {
+    /// Create a set running any number of tasks at once.
+    pub fn new() -> Self {
+        Self {
+            tasks: Tasks::new(None),
+        }
+    }
+
+    /// Create a set running at most `limit` tasks at once.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            tasks: Tasks::new(Some(limit)),
+        }
+    }
+
+    /// Obtain a handle that cancels the tasks of this set.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.tasks.cancel.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.labels.is_empty()
+    }
+
+    /// Spawn a task identified by `label` in outcomes and errors.
+    pub fn spawn<F>(&mut self, label: impl Into<String>, fut: F)
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let fut = self.tasks.supervise(label.into(), fut);
+        self.tasks.set.spawn(fut);
+    }
+
+    /// Wait for all tasks, obtaining their outcomes in the order spawned.
+    pub async fn join(mut self) -> Vec<TaskOutcome<T>> {
+        self.tasks.join(false).await
+    }
+
+    /// Wait for all tasks, cancelling the remaining tasks if one fails.
+    pub async fn try_join(mut self) -> Result<Vec<T>> {
+        self.tasks.try_join().await
+    }
+}
+
+impl<T: Send + 'static> Default for TaskSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A set of tasks running on the current thread.
+///
+/// Tasks only make progress while the set is being joined.
+pub struct LocalTaskSet<T> {
+    tasks: Tasks<T>,
+    local: LocalSet,
+}
+
+impl<T: 'static> LocalTaskSet<T> {
+    /// Create a set running any number of tasks at once.
+    pub fn new() -> Self {
+        Self {
+            tasks: Tasks::new(None),
+            local: LocalSet::new(),
+        }
+    }
+
+    /// Create a set running at most `limit` tasks at once.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            tasks: Tasks::new(Some(limit)),
+            local: LocalSet::new(),
+        }
+    }
+
+    /// Obtain a handle that cancels the tasks of this set.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.tasks.cancel.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.labels.is_empty()
+    }
+
+    /// Spawn a task identified by `label` in outcomes and errors.
+    pub fn spawn<F>(&mut self, label: impl Into<String>, fut: F)
+    where
+        F: Future<Output = Result<T>> + 'static,
+    {
+        let fut = self.tasks.supervise(label.into(), fut);
+        self.tasks.set.spawn_local_on(fut, &self.local);
+    }
+
+    /// Wait for all tasks, obtaining their outcomes in the order spawned.
+    pub async fn join(mut self) -> Vec<TaskOutcome<T>> {
+        let tasks = &mut self.tasks;
+        self.local.run_until(tasks.join(false)).await
+    }
+
+    /// Wait for all tasks, cancelling the remaining tasks if one fails.
+    pub async fn try_join(mut self) -> Result<Vec<T>> {
+        let tasks = &mut self.tasks;
+        self.local.run_until(tasks.try_join()).await
+    }
+}
+
+impl<T: 'static> Default for LocalTaskSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Local statistics of runs over time.
+//!
+//! With `--telemetry-log`, every run appends a [RunRecord] of its timing and
+//! download statistics to a newline-delimited JSON file. Nothing is sent
+//! anywhere. The `stats history` command renders the log so trends across
+//! scheduled runs, such as the server getting slower, become visible.
+
+use {
+    crate::{middleware::Metrics, ndjson::NdjsonLog, summary::RunSummary},
+    anyhow::Result,
+    serde::{Deserialize, Serialize},
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Width of the bars of the history.
+const BAR_WIDTH: usize = 20;
+
+/// Timing and download statistics of a run.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RunRecord {
+    /// When the run started, in seconds since the Unix epoch.
+    pub started: u64,
+    /// Name of the subcommand.
+    pub command: String,
+    /// `success`, `partial-failure`, `interrupted`, or `failure`.
+    pub status: String,
+    pub exit_code: i32,
+    pub duration_secs: u64,
+    /// Number of downloads attempted.
+    pub requests: u64,
+    /// Number of downloads that failed.
+    pub failures: u64,
+    /// Total bytes of successfully downloaded content.
+    pub bytes: u64,
+    /// Total time spent in downloads.
+    pub download_secs: f64,
+}
+
+impl RunRecord {
+    pub fn new(summary: &RunSummary, metrics: &Metrics, started: SystemTime) -> Self {
+        Self {
+            started: started
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            command: summary.command.clone(),
+            status: summary.status.to_string(),
+            exit_code: summary.exit_code,
+            duration_secs: summary.duration_secs,
+            requests: metrics.requests(),
+            failures: metrics.failures(),
+            bytes: metrics.bytes(),
+            download_secs: metrics.download_time().as_secs_f64(),
+        }
+    }
+
+    /// Fraction of downloads that failed.
+    pub fn failure_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.requests as f64
+        }
+    }
+
+    /// Mean duration of a download in seconds.
+    pub fn mean_request_secs(&self) -> Option<f64> {
+        if self.requests == 0 {
+            None
+        } else {
+            Some(self.download_secs / self.requests as f64)
+        }
+    }
+}
+
+/// An append-only log of runs.
+pub type TelemetryLog = NdjsonLog<RunRecord>;
+
+/// Runs recorded in a telemetry log, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct History {
+    pub runs: Vec<RunRecord>,
+}
+
+impl History {
+    /// Select runs of a command, keeping only the `last` most recent.
+    pub fn new(mut runs: Vec<RunRecord>, command: Option<&str>, last: Option<usize>) -> Self {
+        runs.retain(|run| command.is_none_or(|command| run.command == command));
+        runs.sort_by_key(|run| run.started);
+
+        if let Some(last) = last {
+            runs.drain(..runs.len().saturating_sub(last));
+        }
+
+        Self { runs }
+    }
+
+    /// Render as a table with a bar charting the mean download time of each
+    /// run relative to the slowest.
+    pub fn to_text(&self) -> String {
+        if self.runs.is_empty() {
+            return "no runs recorded\n".to_string();
+        }
+
+        let slowest = self
+            .runs
+            .iter()
+            .filter_map(|run| run.mean_request_secs())
+            .fold(0.0, f64::max);
+
+        let mut lines = vec![format!(
+            "{:<16}  {:<16}  {:<15}  {:>8}  {:>8}  {:>7}  {:>9}  {:>9}",
+            "started", "command", "status", "duration", "requests", "failed", "MB", "s/request"
+        )];
+
+        for run in &self.runs {
+            let started = chrono::DateTime::from_timestamp(run.started as i64, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+
+            let (mean, bar) = match run.mean_request_secs() {
+                Some(mean) => {
+                    let width = if slowest > 0.0 {
+                        (mean / slowest * BAR_WIDTH as f64).round() as usize
+                    } else {
+                        0
+                    };
+
+                    (format!("{:.2}", mean), "#".repeat(width))
+                }
+                None => ("-".to_string(), String::new()),
+            };
+
+            lines.push(
+                format!(
+                    "{:<16}  {:<16}  {:<15}  {:>7}s  {:>8}  {:>6.1}%  {:>9.1}  {:>9}  {}",
+                    started,
+                    run.command,
+                    run.status,
+                    run.duration_secs,
+                    run.requests,
+                    run.failure_rate() * 100.0,
+                    run.bytes as f64 / 1_000_000.0,
+                    mean,
+                    bar
+                )
+                .trim_end()
+                .to_string(),
+            );
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Render as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
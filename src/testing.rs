@@ -0,0 +1,370 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Utilities for testing code using this crate without network access.
+//!
+//! This module requires the `test-util` feature.
+
+use {
+    crate::{
+        crawler::{
+            source_tree, ComponentsPage, ReleasePage, ReleasesPage, Source, VersionsPage, URL_MAIN,
+            URL_TARBALLS,
+        },
+        download::{ArtifactKind, ComponentRecord, ReleaseComponentRecord, ReleaseRecord},
+        error::HttpStatusError,
+    },
+    anyhow::{anyhow, Result},
+    async_trait::async_trait,
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+/// Build a gzipped tarball with files under a `prefix` directory.
+///
+/// Files are `(path, content)` pairs and are written with mode `0644`.
+pub fn tarball(prefix: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        vec![],
+        flate2::Compression::fast(),
+    ));
+
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_cksum();
+
+        builder
+            .append_data(&mut header, format!("{}/{}", prefix, path), *data)
+            .expect("writing to memory should not fail");
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .expect("writing to memory should not fail")
+}
+
+/// A [Source] serving canned data from memory.
+///
+/// Component versions are served as tiny tarballs at the URLs they would
+/// have on opensource.apple.com.
+#[derive(Clone, Debug, Default)]
+pub struct TestSource {
+    releases: Vec<ReleaseRecord>,
+    release_components: BTreeMap<String, Vec<ReleaseComponentRecord>>,
+    versions: BTreeMap<String, Vec<ComponentRecord>>,
+    content: BTreeMap<String, Vec<u8>>,
+    release_html: BTreeMap<String, String>,
+    release_updates: BTreeMap<String, Vec<ReleaseRecord>>,
+}
+
+impl TestSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A source with a few components and a release of them.
+    ///
+    /// Components `hello` (versions `1.0` and `2.0`) and `world` (version
+    /// `1.0`) are available. Release `macos` `11.0` contains `hello` `2.0`
+    /// and `world` `1.0`.
+    pub fn sample() -> Self {
+        Self::new()
+            .with_component_version("hello", "1.0", &[("hello.c", b"v1\n")])
+            .with_component_version(
+                "hello",
+                "2.0",
+                &[("hello.c", b"v2\n"), ("README", b"hello\n")],
+            )
+            .with_component_version("world", "1.0", &[("world.c", b"world\n")])
+            .with_release("macos", "11.0", &[("hello", "2.0"), ("world", "1.0")])
+    }
+
+    /// Add a component without any versions.
+    pub fn with_component(mut self, component: &str) -> Self {
+        self.versions.entry(component.to_string()).or_default();
+        self
+    }
+
+    /// Add a component version whose tarball contains the given files.
+    pub fn with_component_version(
+        mut self,
+        component: &str,
+        version: &str,
+        files: &[(&str, &[u8])],
+    ) -> Self {
+        let prefix = format!("{}-{}", component, version);
+        let filename = format!("{}.tar.gz", prefix);
+        let url = format!("{}/{}/{}", URL_TARBALLS, component, filename);
+
+        let data = tarball(&prefix, files);
+        let size = Some(data.len() as u64);
+        self.content.insert(url.clone(), data);
+
+        let versions = self.versions.entry(component.to_string()).or_default();
+        versions.push(ComponentRecord {
+            component: component.to_string(),
+            filename,
+            url,
+            version: version.to_string(),
+            size,
+            last_modified: None,
+        });
+        versions.sort();
+
+        self
+    }
+
+    /// Add a software release consisting of `(component, version)` pairs.
+    ///
+    /// Panics if a component version hasn't been added.
+    pub fn with_release(
+        mut self,
+        entity: &str,
+        version: &str,
+        components: &[(&str, &str)],
+    ) -> Self {
+        let record = self.add_release_page(entity, version, components);
+        self.releases.push(record);
+        self.releases.sort();
+
+        self
+    }
+
+    /// Add an update of a release that is only linked from the release's page.
+    ///
+    /// Panics if the release or a component version hasn't been added.
+    pub fn with_release_update(
+        mut self,
+        entity: &str,
+        version: &str,
+        update_version: &str,
+        components: &[(&str, &str)],
+    ) -> Self {
+        let parent = self
+            .releases
+            .iter()
+            .find(|r| r.entity == entity && r.version == version)
+            .unwrap_or_else(|| panic!("release {} {} not added", entity, version))
+            .url
+            .clone();
+
+        let record = self.add_release_page(entity, update_version, components);
+        self.release_updates.entry(parent).or_default().push(record);
+
+        self
+    }
+
+    /// Register the page of a release, returning its record.
+    fn add_release_page(
+        &mut self,
+        entity: &str,
+        version: &str,
+        components: &[(&str, &str)],
+    ) -> ReleaseRecord {
+        let url = format!(
+            "{}release/{}-{}.html",
+            URL_MAIN,
+            entity,
+            version.replace('.', "")
+        );
+
+        let components = components
+            .iter()
+            .map(|(component, component_version)| {
+                let record = self
+                    .versions
+                    .get(*component)
+                    .and_then(|versions| versions.iter().find(|r| r.version == *component_version))
+                    .unwrap_or_else(|| {
+                        panic!("component {} {} not added", component, component_version)
+                    });
+
+                ReleaseComponentRecord {
+                    entity: entity.to_string(),
+                    component: component.to_string(),
+                    url: record.url.clone(),
+                    kind: ArtifactKind::Tarball,
+                }
+            })
+            .collect();
+
+        self.release_components.insert(url.clone(), components);
+
+        ReleaseRecord {
+            entity: entity.to_string(),
+            version: version.to_string(),
+            url,
+            date: None,
+            original_entity: None,
+        }
+    }
+
+    /// Set the HTML of the page of a previously added release.
+    ///
+    /// Panics if the release hasn't been added.
+    pub fn with_release_html(mut self, entity: &str, version: &str, html: &str) -> Self {
+        let url = self
+            .releases
+            .iter()
+            .find(|r| r.entity == entity && r.version == version)
+            .unwrap_or_else(|| panic!("release {} {} not added", entity, version))
+            .url
+            .clone();
+
+        self.release_html.insert(url, html.to_string());
+        self
+    }
+
+    /// Serve arbitrary content at a URL.
+    pub fn with_content(mut self, url: &str, data: Vec<u8>) -> Self {
+        self.content.insert(url.to_string(), data);
+        self
+    }
+
+    /// Stop serving the content of a URL, so requests for it fail with a 404.
+    pub fn without_content(mut self, url: &str) -> Self {
+        self.content.remove(url);
+        self
+    }
+
+    /// Publish a component version as a browsable source tree.
+    ///
+    /// The tree's directory listings and files are served as content. The
+    /// version isn't added to the component's tarball versions.
+    pub fn with_source_tree(
+        mut self,
+        component: &str,
+        version: &str,
+        files: &[(&str, &[u8])],
+    ) -> Self {
+        let name = format!("{}-{}", component, version);
+        let tree_url = format!("{}{}/", source_tree::component_url(component), name);
+
+        // Entries of each directory, keyed by path relative to the tree.
+        let mut dirs = BTreeMap::<String, BTreeSet<String>>::new();
+        dirs.insert(String::new(), BTreeSet::new());
+
+        for (path, data) in files {
+            let (dir, filename) = match path.rsplit_once('/') {
+                Some((dir, filename)) => (format!("{}/", dir), filename),
+                None => (String::new(), *path),
+            };
+
+            self.content.insert(
+                source_tree::file_url(&format!("{}{}", tree_url, dir), filename),
+                data.to_vec(),
+            );
+
+            dirs.entry(dir.clone())
+                .or_default()
+                .insert(filename.to_string());
+
+            // Register the directory in each of its ancestors.
+            let mut child = dir;
+            while let Some(parent) = child.trim_end_matches('/').rsplit_once('/') {
+                let parent_dir = format!("{}/", parent.0);
+                dirs.entry(parent_dir.clone())
+                    .or_default()
+                    .insert(format!("{}/", parent.1));
+                child = parent_dir;
+            }
+            if !child.is_empty() {
+                dirs.entry(String::new()).or_default().insert(child);
+            }
+        }
+
+        for (dir, entries) in dirs {
+            let mut html = "<a href=\"../\">Parent Directory</a>\n".to_string();
+            for entry in entries {
+                html.push_str(&format!("<a href=\"{}\">{}</a>\n", entry, entry));
+            }
+
+            self.content
+                .insert(format!("{}{}", tree_url, dir), html.into_bytes());
+        }
+
+        self.content
+            .entry(source_tree::component_url(component))
+            .or_insert_with(|| b"<a href=\"/source/\">Parent Directory</a>\n".to_vec())
+            .extend(format!("<a href=\"{}/\">{}/</a>\n", name, name).as_bytes());
+
+        self
+    }
+}
+
+#[async_trait]
+impl Source for TestSource {
+    fn name(&self) -> String {
+        "test".to_string()
+    }
+
+    async fn get_from(&self, url: &str, offset: u64) -> Result<reqwest::Response> {
+        let data = self.content.get(url).ok_or_else(|| HttpStatusError {
+            status: 404,
+            url: url.to_string(),
+        })?;
+
+        let data = data.get(offset as usize..).unwrap_or_default().to_vec();
+        let status = if offset > 0 { 206 } else { 200 };
+
+        let res = http::Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_LENGTH, data.len())
+            .body(data)?;
+
+        Ok(res.into())
+    }
+
+    async fn head(&self, url: &str) -> Result<reqwest::Response> {
+        let data = self.content.get(url).ok_or_else(|| HttpStatusError {
+            status: 404,
+            url: url.to_string(),
+        })?;
+
+        let res = http::Response::builder()
+            .header(http::header::CONTENT_LENGTH, data.len())
+            .body(vec![])?;
+
+        Ok(res.into())
+    }
+
+    async fn releases(&self) -> Result<ReleasesPage> {
+        Ok(ReleasesPage {
+            releases: self.releases.clone(),
+        })
+    }
+
+    async fn release(&self, record: &ReleaseRecord) -> Result<ReleasePage> {
+        Ok(ReleasePage {
+            components: self
+                .release_components
+                .get(&record.url)
+                .cloned()
+                .ok_or_else(|| anyhow!("release {} not found", record.url))?,
+            other_artifacts: vec![],
+            html: self.release_html.get(&record.url).cloned(),
+            updates: self
+                .release_updates
+                .get(&record.url)
+                .cloned()
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn components(&self) -> Result<ComponentsPage> {
+        Ok(ComponentsPage {
+            components: self.versions.keys().cloned().collect(),
+        })
+    }
+
+    async fn versions(&self, component: &str) -> Result<VersionsPage> {
+        Ok(VersionsPage {
+            versions: self.versions.get(component).cloned().unwrap_or_default(),
+            manifests: vec![],
+        })
+    }
+}
@@ -0,0 +1,1171 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Conversion of tar archives to Git trees.
+
+use {
+    crate::{
+        denylist::{DeniedMember, DenyPattern},
+        metadata::{sidecar_data, FileMetadata, MetadataMember, SIDECAR_FILENAME},
+        path::RepoPath,
+    },
+    anyhow::{anyhow, Context, Result},
+    async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder},
+    flate2::read::GzDecoder,
+    futures::StreamExt,
+    git2::{Oid, Repository},
+    glob::{MatchOptions, Pattern},
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        io::{Read, Write},
+        pin::Pin,
+        sync::Arc,
+    },
+    tokio::{
+        io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt},
+        sync::Semaphore,
+    },
+};
+
+pub(crate) const GIT_TREE_MODE: i32 = 0o40000;
+
+/// Entries larger than this many bytes are streamed into blobs.
+const BLOB_STREAMING_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Compression format of an archive.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Identify the compression format from the leading bytes of data.
+    pub fn sniff(data: &[u8]) -> Self {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if data.starts_with(b"BZh") {
+            Self::Bzip2
+        } else if data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else {
+            Self::None
+        }
+    }
+
+    /// Wrap a reader of data in this format to read it decompressed.
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> Box<dyn Read + 'a> {
+        match self {
+            Self::None => Box::new(reader),
+            Self::Gzip => Box::new(GzDecoder::new(reader)),
+            Self::Bzip2 => Box::new(bzip2::read::BzDecoder::new(reader)),
+            Self::Xz => Box::new(xz2::read::XzDecoder::new(reader)),
+        }
+    }
+
+    /// Async equivalent of [Compression::decoder].
+    pub fn async_decoder<'a>(
+        self,
+        reader: impl AsyncBufRead + Unpin + Send + 'a,
+    ) -> Box<dyn AsyncRead + Unpin + Send + 'a> {
+        match self {
+            Self::None => Box::new(reader),
+            Self::Gzip => Box::new(GzipDecoder::new(reader)),
+            Self::Bzip2 => Box::new(BzDecoder::new(reader)),
+            Self::Xz => Box::new(XzDecoder::new(reader)),
+        }
+    }
+}
+
+/// Whether a tar mode of a regular file is unusual.
+///
+/// Modes are unusual if they have special bits set, aren't readable, or are
+/// executable by only some of the classes able to read them, which suggests
+/// the executable bits aren't meaningful.
+fn is_unusual_mode(original_mode: u32) -> bool {
+    let read = original_mode & 0o444;
+    let execute = original_mode & 0o111;
+
+    original_mode & 0o7000 != 0 || read == 0 || (execute != 0 && execute != read >> 2)
+}
+
+/// Resolve the Git file mode for a regular file from its tar mode.
+fn file_mode(original_mode: i32) -> Result<i32> {
+    // A mode of 0 occurs in some archives.
+    if original_mode & 0o111 != 0 {
+        Ok(0o100755)
+    } else if original_mode & 0o444 != 0 || original_mode == 0 {
+        Ok(0o100644)
+    } else {
+        Err(anyhow!("invalid tar archive mode: {}", original_mode))
+    }
+}
+
+/// Write the content of a tar entry to a blob.
+///
+/// Large entries are streamed to the object database in chunks so memory use is
+/// bounded. Repositories not backed by the filesystem don't support streaming,
+/// so entries are buffered for them.
+fn write_entry_blob(repo: &Repository, entry: &mut impl Read) -> Result<Oid> {
+    let mut buf = vec![];
+    let size = entry
+        .by_ref()
+        .take(BLOB_STREAMING_THRESHOLD + 1)
+        .read_to_end(&mut buf)?;
+
+    if size as u64 <= BLOB_STREAMING_THRESHOLD {
+        return repo.blob(&buf).context("writing file data to blob");
+    }
+
+    match repo.blob_writer(None) {
+        Ok(mut writer) => {
+            writer.write_all(&buf)?;
+            drop(buf);
+            std::io::copy(entry, &mut writer).context("streaming file data to blob")?;
+
+            writer.commit().context("writing file data to blob")
+        }
+        Err(_) => {
+            entry.read_to_end(&mut buf)?;
+
+            repo.blob(&buf).context("writing file data to blob")
+        }
+    }
+}
+
+/// Async equivalent of [write_entry_blob].
+async fn write_async_entry_blob(
+    repo: &Repository,
+    entry: &mut (impl AsyncRead + Unpin),
+) -> Result<Oid> {
+    let mut buf = vec![];
+    let size = entry
+        .take(BLOB_STREAMING_THRESHOLD + 1)
+        .read_to_end(&mut buf)
+        .await?;
+
+    if size as u64 <= BLOB_STREAMING_THRESHOLD {
+        return repo.blob(&buf).context("writing file data to blob");
+    }
+
+    match repo.blob_writer(None) {
+        Ok(mut writer) => {
+            writer.write_all(&buf)?;
+
+            loop {
+                buf.clear();
+                if entry
+                    .take(BLOB_STREAMING_THRESHOLD)
+                    .read_to_end(&mut buf)
+                    .await?
+                    == 0
+                {
+                    break;
+                }
+                writer.write_all(&buf)?;
+            }
+
+            writer.commit().context("writing file data to blob")
+        }
+        Err(_) => {
+            entry.read_to_end(&mut buf).await?;
+
+            repo.blob(&buf).context("writing file data to blob")
+        }
+    }
+}
+
+/// How to handle archives containing the same path more than once.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// The last occurrence of a path in the archive is used.
+    #[default]
+    LastWins,
+    /// Conversion fails.
+    Error,
+}
+
+/// How to handle AppleDouble and pax header members in archives.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AppleMetadataPolicy {
+    /// Members are imported verbatim as regular files.
+    #[default]
+    Keep,
+    /// Members are not imported.
+    Strip,
+    /// Members are not imported and their metadata, along with extended
+    /// attributes in pax headers, is recorded in a sidecar file.
+    ///
+    /// See [crate::metadata::SIDECAR_FILENAME].
+    Sidecar,
+}
+
+/// How to handle symlinks in archives.
+///
+/// Hard links aren't affected: they are always imported as copies of the
+/// files they link to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Symlinks are imported as symlinks.
+    #[default]
+    Keep,
+    /// Symlinks are replaced by the files or directories they point to.
+    ///
+    /// Symlinks whose targets aren't in the archive are not imported.
+    Dereference,
+    /// Symlinks are not imported.
+    Skip,
+}
+
+/// A Git file mode forced on regular files at paths matching a pattern.
+#[derive(Clone, Debug)]
+pub struct ModeOverride {
+    pub pattern: Pattern,
+    /// Either `0o100644` or `0o100755`.
+    pub mode: i32,
+}
+
+impl ModeOverride {
+    /// Construct an instance from a pattern and an octal Git file mode.
+    pub fn new(pattern: &str, mode: &str) -> Result<Self> {
+        let pattern =
+            Pattern::new(pattern).with_context(|| format!("parsing pattern {}", pattern))?;
+
+        let mode = match mode {
+            "100644" | "644" => 0o100644,
+            "100755" | "755" => 0o100755,
+            _ => return Err(anyhow!("file mode must be 100644 or 100755: {}", mode)),
+        };
+
+        Ok(Self { pattern, mode })
+    }
+}
+
+/// How patterns of paths are matched against paths in trees.
+fn path_match_options() -> MatchOptions {
+    MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    }
+}
+
+/// Options controlling how tar archives are converted to trees.
+#[derive(Clone, Debug)]
+pub struct TreeOptions {
+    /// How to handle paths occurring multiple times in an archive.
+    pub duplicates: DuplicatePolicy,
+    /// How to handle members carrying Apple file metadata.
+    pub apple_metadata: AppleMetadataPolicy,
+    /// How to handle symlinks.
+    pub symlinks: SymlinkPolicy,
+    /// Number of leading path components to remove from archive members.
+    ///
+    /// Defaults to 1, as archives typically contain a single top-level
+    /// `<name>-<version>` directory. Members having no more than this many
+    /// path components are imported at the root of the tree.
+    pub strip_components: usize,
+    /// Patterns of paths to leave out of the tree.
+    ///
+    /// Patterns are matched against paths in the tree, after leading
+    /// components are stripped. `*` doesn't match `/`, so `Tests/**` excludes
+    /// everything under a top-level `Tests` directory and `**/*.o` excludes
+    /// object files anywhere.
+    pub exclude: Vec<Pattern>,
+    /// Patterns of paths to leave out of the tree for a reason.
+    ///
+    /// These are matched like [Self::exclude]. Excluded members are reported
+    /// by [TreeConverter::convert_denying].
+    pub deny: Vec<DenyPattern>,
+    /// File modes forced on regular files, instead of deriving them from
+    /// their modes in the archive.
+    ///
+    /// Patterns are matched like [Self::exclude]. The first matching override
+    /// applies. Files whose modes in the archive are unusual and aren't
+    /// overridden are reported.
+    pub mode_overrides: Vec<ModeOverride>,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            duplicates: DuplicatePolicy::default(),
+            apple_metadata: AppleMetadataPolicy::default(),
+            symlinks: SymlinkPolicy::default(),
+            strip_components: 1,
+            exclude: vec![],
+            deny: vec![],
+            mode_overrides: vec![],
+        }
+    }
+}
+
+/// What to do with a member of a tar archive.
+enum Disposition {
+    /// Import the member's content.
+    Content,
+    /// Ignore the member.
+    Skip,
+    /// Decode the member as AppleDouble metadata for the file at a path.
+    AppleDouble(RepoPath),
+}
+
+/// A member of a tar archive, independent of the crate reading the archive.
+struct Member {
+    path: RepoPath,
+    kind: MemberKind,
+    /// Pax extension records, if metadata is recorded.
+    pax: Vec<(String, Vec<u8>)>,
+}
+
+enum MemberKind {
+    /// Directories and global pax headers, which don't become tree entries.
+    Ignored,
+    /// A file with a tar mode.
+    File(u32),
+    Symlink(Vec<u8>),
+    /// A hard link to the path of an earlier member.
+    HardLink(Vec<u8>),
+}
+
+impl MemberKind {
+    /// Classify a member from its header fields.
+    ///
+    /// The mode is only needed by files.
+    fn new(
+        ignored: bool,
+        symlink: bool,
+        link_name: Option<Cow<[u8]>>,
+        mode: std::io::Result<u32>,
+    ) -> Result<Self> {
+        Ok(match link_name {
+            _ if ignored => Self::Ignored,
+            Some(target) if symlink => Self::Symlink(target.into_owned()),
+            Some(target) => Self::HardLink(target.into_owned()),
+            None => Self::File(mode?),
+        })
+    }
+}
+
+/// What to do with the content of a member.
+enum MemberContent {
+    /// The content isn't needed.
+    Skip,
+    /// Record the content as AppleDouble metadata for the file at a path.
+    AppleDouble(RepoPath),
+    /// Write the content to a blob with a file mode and pass it to
+    /// [TreeAccumulator::add_file].
+    Blob(i32),
+}
+
+/// Maximum number of symlinks followed resolving a path.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// An entry of a directory in a tree being accumulated.
+#[derive(Clone)]
+enum TreeEntry {
+    /// A blob with a file mode.
+    Blob(Oid, i32),
+    Dir(DirNode),
+    /// A symlink to be dereferenced.
+    Symlink(Vec<u8>),
+}
+
+/// A directory in a tree being accumulated.
+#[derive(Clone, Default)]
+struct DirNode {
+    entries: BTreeMap<Vec<u8>, TreeEntry>,
+}
+
+impl DirNode {
+    /// Obtain the directory at `path` beneath this one, creating directories
+    /// as needed.
+    ///
+    /// Blobs in the way are replaced by directories and their names recorded
+    /// in `replaced`.
+    fn dir_mut<'a>(
+        &mut self,
+        mut components: impl Iterator<Item = &'a [u8]>,
+        replaced: &mut Vec<Vec<u8>>,
+    ) -> &mut DirNode {
+        let Some(name) = components.next() else {
+            return self;
+        };
+
+        let entry = self
+            .entries
+            .entry(name.to_vec())
+            .or_insert_with(|| TreeEntry::Dir(DirNode::default()));
+
+        if !matches!(entry, TreeEntry::Dir(_)) {
+            replaced.push(name.to_vec());
+            *entry = TreeEntry::Dir(DirNode::default());
+        }
+
+        match entry {
+            TreeEntry::Dir(dir) => dir.dir_mut(components, replaced),
+            _ => unreachable!("other entries were replaced above"),
+        }
+    }
+
+    /// Write this directory and every directory beneath it, returning the
+    /// Oid of this directory's tree.
+    fn write(&self, repo: &Repository) -> Result<Oid> {
+        let mut builder = repo.treebuilder(None)?;
+
+        for (name, entry) in &self.entries {
+            match entry {
+                TreeEntry::Blob(oid, mode) => {
+                    builder.insert(name, *oid, *mode)?;
+                }
+                TreeEntry::Dir(dir) => {
+                    builder.insert(name, dir.write(repo)?, GIT_TREE_MODE)?;
+                }
+                TreeEntry::Symlink(_) => {
+                    return Err(anyhow!(
+                        "symlink {} should have been dereferenced",
+                        String::from_utf8_lossy(name)
+                    ));
+                }
+            }
+        }
+
+        Ok(builder.write()?)
+    }
+}
+
+/// The entry a symlink points to.
+enum Resolved<'a> {
+    Blob(Oid, i32),
+    Dir(&'a DirNode),
+}
+
+/// Replaces symlinks in a tree by the entries they point to.
+struct SymlinkResolver<'a> {
+    root: &'a DirNode,
+    /// Symlinks that couldn't be resolved.
+    unresolved: Vec<RepoPath>,
+}
+
+impl<'a> SymlinkResolver<'a> {
+    /// Resolve the target of a symlink in directory `dir` to components of a
+    /// path relative to the root.
+    ///
+    /// Returns `None` for targets outside the tree.
+    fn target_path(dir: &[Vec<u8>], target: &[u8]) -> Option<Vec<Vec<u8>>> {
+        if target.starts_with(b"/") {
+            return None;
+        }
+
+        let mut path = dir.to_vec();
+
+        for component in target.split(|c| *c == b'/') {
+            match component {
+                b"" | b"." => {}
+                b".." => {
+                    path.pop()?;
+                }
+                _ => path.push(component.to_vec()),
+            }
+        }
+
+        Some(path)
+    }
+
+    /// Find the entry at a path, following symlinks.
+    ///
+    /// Returns the path of the entry with symlinks resolved along with the
+    /// entry.
+    fn lookup(&self, path: &[Vec<u8>], depth: usize) -> Option<(Vec<Vec<u8>>, Resolved<'a>)> {
+        let mut dir = self.root;
+
+        for (index, name) in path.iter().enumerate() {
+            match dir.entries.get(name)? {
+                TreeEntry::Dir(child) => dir = child,
+                TreeEntry::Blob(oid, mode) if index + 1 == path.len() => {
+                    return Some((path.to_vec(), Resolved::Blob(*oid, *mode)));
+                }
+                TreeEntry::Blob(..) => return None,
+                TreeEntry::Symlink(target) => {
+                    if depth >= MAX_SYMLINK_DEPTH {
+                        return None;
+                    }
+
+                    let mut resolved = Self::target_path(&path[..index], target)?;
+                    resolved.extend_from_slice(&path[index + 1..]);
+
+                    return self.lookup(&resolved, depth + 1);
+                }
+            }
+        }
+
+        Some((path.to_vec(), Resolved::Dir(dir)))
+    }
+
+    /// Copy the directory at `path`, replacing symlinks by their targets.
+    ///
+    /// `stack` holds the paths of directories being copied, so symlinks to
+    /// them or their parents, which would copy a directory into itself, are
+    /// left unresolved.
+    fn dereference_dir(
+        &mut self,
+        path: &[Vec<u8>],
+        dir: &'a DirNode,
+        stack: &mut Vec<Vec<Vec<u8>>>,
+    ) -> DirNode {
+        stack.push(path.to_vec());
+
+        let mut copy = DirNode::default();
+
+        for (name, entry) in &dir.entries {
+            let mut entry_path = path.to_vec();
+            entry_path.push(name.clone());
+
+            let resolved = match entry {
+                TreeEntry::Blob(oid, mode) => Some(TreeEntry::Blob(*oid, *mode)),
+                TreeEntry::Dir(child) => Some(TreeEntry::Dir(self.dereference_dir(
+                    &entry_path,
+                    child,
+                    stack,
+                ))),
+                TreeEntry::Symlink(target) => match Self::target_path(path, target)
+                    .and_then(|target| self.lookup(&target, 1))
+                {
+                    Some((_, Resolved::Blob(oid, mode))) => Some(TreeEntry::Blob(oid, mode)),
+                    Some((target, Resolved::Dir(child)))
+                        if !stack.iter().any(|dir| dir.starts_with(&target)) =>
+                    {
+                        Some(TreeEntry::Dir(self.dereference_dir(&target, child, stack)))
+                    }
+                    _ => None,
+                },
+            };
+
+            match resolved {
+                Some(entry) => {
+                    copy.entries.insert(name.clone(), entry);
+                }
+                None => self.unresolved.push(RepoPath::new(&entry_path.join(&b'/'))),
+            }
+        }
+
+        stack.pop();
+
+        copy
+    }
+
+    /// Copy a tree, replacing symlinks by their targets.
+    ///
+    /// Returns the copy and the paths of symlinks that couldn't be resolved
+    /// and were left out.
+    fn dereference(root: &'a DirNode) -> (DirNode, Vec<RepoPath>) {
+        let mut resolver = Self {
+            root,
+            unresolved: vec![],
+        };
+        let copy = resolver.dereference_dir(&[], root, &mut vec![]);
+
+        (copy, resolver.unresolved)
+    }
+}
+
+/// Accumulates blobs at paths into a hierarchy of Git trees.
+struct TreeAccumulator<'repo, 'options> {
+    repo: &'repo Repository,
+    options: &'options TreeOptions,
+    root: DirNode,
+    /// Blobs and modes of imported files, keyed by path in the archive, for
+    /// resolving hard links.
+    files: HashMap<RepoPath, (Oid, i32)>,
+    duplicates: Vec<RepoPath>,
+    /// Hard links whose target wasn't an imported file.
+    unresolved_links: Vec<RepoPath>,
+    unusual_modes: Vec<(RepoPath, u32)>,
+    metadata: BTreeMap<String, FileMetadata>,
+    denied: Vec<DeniedMember>,
+}
+
+impl<'repo, 'options> TreeAccumulator<'repo, 'options> {
+    fn new(repo: &'repo Repository, options: &'options TreeOptions) -> Self {
+        Self {
+            repo,
+            options,
+            root: DirNode::default(),
+            files: HashMap::new(),
+            duplicates: vec![],
+            unresolved_links: vec![],
+            unusual_modes: vec![],
+            metadata: BTreeMap::new(),
+            denied: vec![],
+        }
+    }
+
+    /// Resolve the path in the tree of a path in a tar archive.
+    ///
+    /// Members having too few path components to strip are placed at the root
+    /// of the tree.
+    fn tree_path(&self, path: &RepoPath) -> RepoPath {
+        path.strip_components(self.options.strip_components)
+    }
+
+    /// Whether a path in a tar archive matches an exclude or deny pattern.
+    ///
+    /// Members matching a deny pattern are recorded.
+    fn is_excluded(&mut self, path: &RepoPath) -> bool {
+        if self.options.exclude.is_empty() && self.options.deny.is_empty() {
+            return false;
+        }
+
+        let path = self.tree_path(path).to_string_lossy();
+        let options = path_match_options();
+
+        if self
+            .options
+            .exclude
+            .iter()
+            .any(|pattern| pattern.matches_with(&path, options))
+        {
+            return true;
+        }
+
+        match self
+            .options
+            .deny
+            .iter()
+            .find(|deny| deny.pattern.matches_with(&path, options))
+        {
+            Some(deny) => {
+                self.denied.push(DeniedMember {
+                    path,
+                    reason: deny.reason.clone(),
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Determine how to handle a tar member at a path.
+    fn disposition(&mut self, path: &RepoPath) -> Disposition {
+        if self.is_excluded(path) {
+            return Disposition::Skip;
+        }
+
+        match (self.options.apple_metadata, MetadataMember::from_path(path)) {
+            (AppleMetadataPolicy::Keep, _) | (_, None) => Disposition::Content,
+            (AppleMetadataPolicy::Strip, Some(_))
+            | (AppleMetadataPolicy::Sidecar, Some(MetadataMember::PaxHeader)) => Disposition::Skip,
+            (AppleMetadataPolicy::Sidecar, Some(MetadataMember::AppleDouble(target))) => {
+                Disposition::AppleDouble(target)
+            }
+        }
+    }
+
+    /// Whether metadata for imported files should be recorded.
+    fn records_metadata(&self) -> bool {
+        self.options.apple_metadata == AppleMetadataPolicy::Sidecar
+    }
+
+    /// Obtain the metadata record for a path in a tar archive.
+    fn metadata_mut(&mut self, path: &RepoPath) -> &mut FileMetadata {
+        let path = self.tree_path(path);
+
+        self.metadata.entry(path.to_string_lossy()).or_default()
+    }
+
+    /// Record the content of an AppleDouble member describing `target`.
+    fn add_apple_double(&mut self, target: &RepoPath, data: &[u8]) {
+        if let Err(e) = self.metadata_mut(target).add_apple_double(data) {
+            println!("warning: ignoring AppleDouble data for {}: {}", target, e);
+        }
+    }
+
+    /// Record extended attributes from pax extension records of a member.
+    fn add_pax_xattrs(&mut self, path: &RepoPath, records: &[(String, Vec<u8>)]) {
+        if records.is_empty() {
+            return;
+        }
+
+        self.metadata_mut(path)
+            .add_pax_xattrs(records.iter().map(|(k, v)| (k.as_str(), v.as_slice())));
+    }
+
+    /// Resolve the Git file mode of a regular file at a path in a tar archive.
+    fn file_mode(&mut self, path: &RepoPath, original_mode: u32) -> Result<i32> {
+        let tree_path = self.tree_path(path);
+
+        if !self.options.mode_overrides.is_empty() {
+            let name = tree_path.to_string_lossy();
+
+            if let Some(o) = self
+                .options
+                .mode_overrides
+                .iter()
+                .find(|o| o.pattern.matches_with(&name, path_match_options()))
+            {
+                return Ok(o.mode);
+            }
+        }
+
+        if is_unusual_mode(original_mode) {
+            self.unusual_modes.push((tree_path, original_mode));
+        }
+
+        file_mode(original_mode as i32)
+    }
+
+    /// Record a member of a tar archive, returning what to do with its
+    /// content.
+    ///
+    /// This holds the handling of members common to every way of reading
+    /// archives.
+    fn add_member(&mut self, member: &Member) -> Result<MemberContent> {
+        match &member.kind {
+            MemberKind::Ignored => return Ok(MemberContent::Skip),
+            MemberKind::Symlink(_) if self.options.symlinks == SymlinkPolicy::Skip => {
+                return Ok(MemberContent::Skip);
+            }
+            _ => {}
+        }
+
+        match self.disposition(&member.path) {
+            Disposition::Content => {}
+            Disposition::Skip => return Ok(MemberContent::Skip),
+            Disposition::AppleDouble(target) => return Ok(MemberContent::AppleDouble(target)),
+        }
+
+        if self.records_metadata() {
+            self.add_pax_xattrs(&member.path, &member.pax);
+        }
+
+        match &member.kind {
+            MemberKind::Ignored => {}
+            MemberKind::File(mode) => {
+                return Ok(MemberContent::Blob(self.file_mode(&member.path, *mode)?));
+            }
+            MemberKind::Symlink(target) => self.insert_link(&member.path, target)?,
+            MemberKind::HardLink(target) => self.insert_hard_link(&member.path, target)?,
+        }
+
+        Ok(MemberContent::Skip)
+    }
+
+    /// Record the blob of a file whose content [Self::add_member] requested.
+    fn add_file(&mut self, path: &RepoPath, blob_oid: Oid, mode: i32) -> Result<()> {
+        self.files.insert(path.clone(), (blob_oid, mode));
+        self.insert(path, blob_oid, mode)
+    }
+
+    /// Record a hard link to an earlier member as a copy of its file.
+    ///
+    /// Links to members that aren't files or weren't imported are skipped.
+    fn insert_hard_link(&mut self, path: &RepoPath, target: &[u8]) -> Result<()> {
+        match self.files.get(&RepoPath::new(target)).copied() {
+            Some((blob_oid, mode)) => self.add_file(path, blob_oid, mode),
+            None => {
+                self.unresolved_links.push(self.tree_path(path));
+                Ok(())
+            }
+        }
+    }
+
+    /// Record a blob at a path in a tar archive.
+    ///
+    /// A member at the path of a previous member, or at the path of a
+    /// directory holding previous members, is a duplicate.
+    fn insert(&mut self, path: &RepoPath, blob_oid: Oid, mode: i32) -> Result<()> {
+        self.insert_entry(path, TreeEntry::Blob(blob_oid, mode))
+    }
+
+    /// Record a symlink to `target` at a path in a tar archive.
+    fn insert_link(&mut self, path: &RepoPath, target: &[u8]) -> Result<()> {
+        if self.options.symlinks == SymlinkPolicy::Dereference {
+            return self.insert_entry(path, TreeEntry::Symlink(target.to_vec()));
+        }
+
+        let blob_oid = self
+            .repo
+            .blob(target)
+            .context("writing symlink target to blob")?;
+
+        self.insert(path, blob_oid, 0o120000)
+    }
+
+    fn insert_entry(&mut self, path: &RepoPath, entry: TreeEntry) -> Result<()> {
+        let path = self.tree_path(path);
+        path.validate().context("validating tar member path")?;
+
+        let (dir, filename) = path
+            .split()
+            .ok_or_else(|| anyhow!("tar member has an empty path"))?;
+
+        let mut replaced = vec![];
+        let node = self.root.dir_mut(dir.components(), &mut replaced);
+        let existing = node.entries.insert(filename.to_vec(), entry);
+
+        if existing.is_some() || !replaced.is_empty() {
+            match self.options.duplicates {
+                DuplicatePolicy::LastWins => {
+                    self.duplicates.push(path);
+                }
+                DuplicatePolicy::Error => {
+                    return Err(anyhow!("duplicate tar member {}", path));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write all trees, returning the Oid of the root tree.
+    fn write(mut self) -> Result<Oid> {
+        let repo = self.repo;
+
+        if !self.duplicates.is_empty() {
+            println!(
+                "warning: archive contains duplicate members; using last occurrence of: {}",
+                self.duplicates
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        if !self.unresolved_links.is_empty() {
+            println!(
+                "warning: ignoring hard links to members that aren't imported files: {}",
+                self.unresolved_links
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        self.metadata.retain(|_, metadata| !metadata.is_empty());
+
+        if !self.metadata.is_empty() {
+            let blob_oid = repo
+                .blob(&sidecar_data(&self.metadata)?)
+                .context("writing metadata sidecar to blob")?;
+
+            self.root.entries.insert(
+                SIDECAR_FILENAME.as_bytes().to_vec(),
+                TreeEntry::Blob(blob_oid, 0o100644),
+            );
+        }
+
+        if !self.unusual_modes.is_empty() {
+            println!(
+                "warning: archive contains files with unusual modes: {}",
+                self.unusual_modes
+                    .iter()
+                    .map(|(p, mode)| format!("{} ({:o})", p, mode))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        if self.options.symlinks == SymlinkPolicy::Dereference {
+            let (root, unresolved) = SymlinkResolver::dereference(&self.root);
+
+            if !unresolved.is_empty() {
+                println!(
+                    "warning: ignoring symlinks to paths outside the archive: {}",
+                    unresolved
+                        .iter()
+                        .map(|p| p.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+
+            self.root = root;
+        }
+
+        self.root.write(repo)
+    }
+}
+
+/// Collect pax extension records having UTF-8 keys.
+fn pax_records(
+    records: impl Iterator<Item = std::io::Result<(Result<String, std::str::Utf8Error>, Vec<u8>)>>,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut res = vec![];
+
+    for record in records {
+        if let (Ok(key), value) = record.context("reading pax extensions")? {
+            res.push((key, value));
+        }
+    }
+
+    Ok(res)
+}
+
+/// Write content in a tar archive to a Git repository.
+///
+/// The compression format (gzip, bzip2, xz, or none) is detected from the
+/// leading bytes of the data.
+///
+/// This is CPU bound and blocks. Async callers should use [TreeConverter].
+///
+/// Returns the Git tree Oid.
+pub fn tar_data_to_tree(tar_data: &[u8], repo: &Repository, options: &TreeOptions) -> Result<Oid> {
+    Ok(convert_tar_data(tar_data, repo, options)?.tree)
+}
+
+/// The result of converting an archive to a tree.
+#[derive(Clone, Debug)]
+pub struct Conversion {
+    pub tree: Oid,
+    /// Members excluded by [TreeOptions::deny].
+    pub denied: Vec<DeniedMember>,
+}
+
+fn convert_tar_data(
+    tar_data: &[u8],
+    repo: &Repository,
+    options: &TreeOptions,
+) -> Result<Conversion> {
+    let mut tree = accumulate_tar(
+        Compression::sniff(tar_data).decoder(tar_data),
+        repo,
+        options,
+    )?;
+
+    let denied = std::mem::take(&mut tree.denied);
+
+    Ok(Conversion {
+        tree: tree.write()?,
+        denied,
+    })
+}
+
+/// Write content from a reader of an uncompressed tar archive to a Git repository.
+///
+/// Returns the Git tree Oid.
+pub fn tar_reader_to_tree(
+    reader: impl Read,
+    repo: &Repository,
+    options: &TreeOptions,
+) -> Result<Oid> {
+    accumulate_tar(reader, repo, options)?.write()
+}
+
+/// Accumulate the members of an uncompressed tar archive.
+fn accumulate_tar<'repo, 'options>(
+    reader: impl Read,
+    repo: &'repo Repository,
+    options: &'options TreeOptions,
+) -> Result<TreeAccumulator<'repo, 'options>> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut tree = TreeAccumulator::new(repo, options);
+
+    for entry in archive.entries().context("reading tar entries")? {
+        let mut entry = entry.context("reading tar entry")?;
+
+        let entry_type = entry.header().entry_type();
+        let member = Member {
+            path: RepoPath::new(&entry.path_bytes()),
+            kind: MemberKind::new(
+                entry_type.is_dir() || entry_type.is_pax_global_extensions(),
+                entry_type.is_symlink(),
+                entry.link_name_bytes(),
+                entry.header().mode(),
+            )?,
+            pax: if tree.records_metadata() {
+                pax_records(entry.pax_extensions()?.into_iter().flatten().map(|e| {
+                    e.map(|e| (e.key().map(|k| k.to_string()), e.value_bytes().to_vec()))
+                }))?
+            } else {
+                vec![]
+            },
+        };
+
+        match tree.add_member(&member)? {
+            MemberContent::Skip => {}
+            MemberContent::AppleDouble(target) => {
+                let mut data = vec![];
+                entry.read_to_end(&mut data)?;
+                tree.add_apple_double(&target, &data);
+            }
+            MemberContent::Blob(mode) => {
+                let blob_oid = write_entry_blob(repo, &mut entry)?;
+                tree.add_file(&member.path, blob_oid, mode)?;
+            }
+        }
+    }
+
+    Ok(tree)
+}
+
+/// Write content from an async stream of an uncompressed tar archive to a Git repository.
+///
+/// This allows converting archives from arbitrary data sources (local files,
+/// network streams, etc) without buffering the entire archive in memory.
+///
+/// Returns the Git tree Oid.
+pub async fn tar_stream_to_tree(
+    reader: impl AsyncBufRead + Unpin + Send,
+    repo: &Repository,
+    options: &TreeOptions,
+) -> Result<Oid> {
+    let mut archive = tokio_tar::Archive::new(reader);
+
+    let mut tree = TreeAccumulator::new(repo, options);
+
+    let mut entries = archive.entries().context("reading tar entries")?;
+
+    let mut pinned = Pin::new(&mut entries);
+    while let Some(entry) = pinned.next().await {
+        let mut entry = entry.context("reading tar entry")?;
+
+        let entry_type = entry.header().entry_type();
+        let member = Member {
+            path: RepoPath::new(&entry.path_bytes()),
+            kind: MemberKind::new(
+                entry_type.is_dir() || entry_type.is_pax_global_extensions(),
+                entry_type.is_symlink(),
+                entry.link_name_bytes(),
+                entry.header().mode(),
+            )?,
+            pax: if tree.records_metadata() {
+                pax_records(
+                    entry
+                        .pax_extensions()
+                        .await?
+                        .into_iter()
+                        .flatten()
+                        .map(|e| {
+                            e.map(|e| (e.key().map(|k| k.to_string()), e.value_bytes().to_vec()))
+                        }),
+                )?
+            } else {
+                vec![]
+            },
+        };
+
+        match tree.add_member(&member)? {
+            MemberContent::Skip => {}
+            MemberContent::AppleDouble(target) => {
+                let mut data = vec![];
+                entry.read_to_end(&mut data).await?;
+                tree.add_apple_double(&target, &data);
+            }
+            MemberContent::Blob(mode) => {
+                let blob_oid = write_async_entry_blob(repo, &mut entry).await?;
+                tree.add_file(&member.path, blob_oid, mode)?;
+            }
+        }
+    }
+
+    tree.write()
+}
+
+/// Write content from an async stream of a possibly compressed tar archive to a Git repository.
+///
+/// The compression format (gzip, bzip2, xz, or none) is detected from the
+/// leading bytes of the stream.
+///
+/// Returns the Git tree Oid.
+pub async fn compressed_tar_stream_to_tree(
+    mut reader: impl AsyncBufRead + Unpin + Send,
+    repo: &Repository,
+    options: &TreeOptions,
+) -> Result<Oid> {
+    let compression = Compression::sniff(reader.fill_buf().await?);
+
+    tar_stream_to_tree(
+        tokio::io::BufReader::new(compression.async_decoder(reader)),
+        repo,
+        options,
+    )
+    .await
+}
+
+/// Converts tar archives to Git trees on Tokio's blocking thread pool.
+///
+/// Decompression and tar parsing are CPU bound, so conversions are run off the
+/// async runtime's worker threads. The number of concurrent conversions is
+/// bounded and is shared by all clones of an instance.
+#[derive(Clone)]
+pub struct TreeConverter {
+    permits: Arc<Semaphore>,
+    options: Arc<TreeOptions>,
+}
+
+impl Default for TreeConverter {
+    fn default() -> Self {
+        Self::new(
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+        )
+    }
+}
+
+impl TreeConverter {
+    /// Construct an instance running up to `threads` conversions concurrently.
+    pub fn new(threads: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(threads.max(1))),
+            options: Arc::new(TreeOptions::default()),
+        }
+    }
+
+    /// Set the options used for conversions.
+    pub fn with_options(mut self, options: TreeOptions) -> Self {
+        self.options = Arc::new(options);
+        self
+    }
+
+    /// The options used for conversions.
+    pub fn options(&self) -> &TreeOptions {
+        &self.options
+    }
+
+    /// Convert a tar archive to a tree in a repository.
+    ///
+    /// The repository is reopened on the blocking thread, so it must be backed
+    /// by the filesystem.
+    pub async fn convert(&self, tar_data: Vec<u8>, repo: &Repository) -> Result<Oid> {
+        Ok(self.convert_denying(tar_data, repo, vec![]).await?.tree)
+    }
+
+    /// Convert a tar archive to a tree, also excluding members matching
+    /// `deny`.
+    ///
+    /// The excluded members are returned with the tree.
+    pub async fn convert_denying(
+        &self,
+        tar_data: Vec<u8>,
+        repo: &Repository,
+        deny: Vec<DenyPattern>,
+    ) -> Result<Conversion> {
+        let _permit = self.permits.acquire().await?;
+
+        let repo_path = repo.path().to_path_buf();
+        let options = if deny.is_empty() {
+            self.options.clone()
+        } else {
+            Arc::new(TreeOptions {
+                deny: self.options.deny.iter().cloned().chain(deny).collect(),
+                ..(*self.options).clone()
+            })
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let repo = Repository::open(&repo_path)
+                .with_context(|| format!("opening repository {}", repo_path.display()))?;
+
+            convert_tar_data(&tar_data, &repo, &options)
+        })
+        .await
+        .context("joining tar conversion task")?
+    }
+}
@@ -0,0 +1,247 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reports of software published since a prior snapshot.
+//!
+//! A report compares a [Catalog] with a catalog written earlier, or lists
+//! what was published since a date, for posting updates about new
+//! components, component versions, and releases.
+
+use {
+    crate::{
+        catalog::{Catalog, CatalogVersion},
+        download::compare_version_string,
+        report::markdown_escape,
+    },
+    anyhow::Result,
+    chrono::NaiveDate,
+    serde::Serialize,
+    std::collections::BTreeSet,
+};
+
+/// A component version in a [WhatsNew] report.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct NewVersion {
+    pub component: String,
+    pub version: String,
+    pub url: String,
+    pub last_modified: Option<String>,
+}
+
+/// A software release in a [WhatsNew] report.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct NewRelease {
+    pub entity: String,
+    pub version: String,
+    pub url: String,
+    pub date: Option<String>,
+}
+
+/// Components, versions, and releases published since a prior snapshot.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct WhatsNew {
+    /// Describes the snapshot the report is relative to.
+    pub since: String,
+    /// Components all of whose versions are new.
+    pub components: Vec<String>,
+    /// New versions, ordered by component and version.
+    pub versions: Vec<NewVersion>,
+    pub releases: Vec<NewRelease>,
+}
+
+impl WhatsNew {
+    /// Report what `current` has that `previous` doesn't.
+    ///
+    /// Versions and releases are identified by URL.
+    pub fn between(previous: &Catalog, current: &Catalog, since: &str) -> Self {
+        let versions = previous
+            .components
+            .values()
+            .flat_map(|component| component.versions.iter().map(|v| v.url.as_str()))
+            .collect::<BTreeSet<_>>();
+        let releases = previous
+            .releases
+            .iter()
+            .map(|release| release.url.as_str())
+            .collect::<BTreeSet<_>>();
+
+        Self::from_catalog(
+            current,
+            since,
+            |version| !versions.contains(version.url.as_str()),
+            |release| !releases.contains(release.url.as_str()),
+        )
+    }
+
+    /// Report what `current` has that was published on or after a date.
+    ///
+    /// Versions are dated by their modification time and releases by their
+    /// publication date. Versions and releases without a date aren't
+    /// reported.
+    pub fn since_date(current: &Catalog, date: NaiveDate) -> Self {
+        let date = date.format("%Y-%m-%d").to_string();
+
+        Self::from_catalog(
+            current,
+            &date,
+            // Dates and times are formatted so they sort lexically.
+            |version| {
+                version
+                    .last_modified
+                    .as_deref()
+                    .is_some_and(|t| t >= date.as_str())
+            },
+            |release| release.date.as_deref().is_some_and(|d| d >= date.as_str()),
+        )
+    }
+
+    fn from_catalog(
+        current: &Catalog,
+        since: &str,
+        new_version: impl Fn(&CatalogVersion) -> bool,
+        new_release: impl Fn(&NewRelease) -> bool,
+    ) -> Self {
+        let mut components = vec![];
+        let mut versions = vec![];
+
+        for (name, component) in &current.components {
+            let mut new = component
+                .versions
+                .iter()
+                .filter(|version| new_version(version))
+                .map(|version| NewVersion {
+                    component: name.clone(),
+                    version: version.version.clone(),
+                    url: version.url.clone(),
+                    last_modified: version.last_modified.clone(),
+                })
+                .collect::<Vec<_>>();
+
+            if !new.is_empty() && new.len() == component.versions.len() {
+                components.push(name.clone());
+            }
+
+            new.sort_by(|a, b| compare_version_string(&a.version, &b.version));
+            versions.extend(new);
+        }
+
+        let releases = current
+            .releases
+            .iter()
+            .map(|release| NewRelease {
+                entity: release.entity.clone(),
+                version: release.version.clone(),
+                url: release.url.clone(),
+                date: release.date.clone(),
+            })
+            .filter(|release| new_release(release))
+            .collect();
+
+        Self {
+            since: since.to_string(),
+            components,
+            versions,
+            releases,
+        }
+    }
+
+    /// Whether nothing new was found.
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty() && self.versions.is_empty() && self.releases.is_empty()
+    }
+
+    /// Render as plain text.
+    pub fn to_text(&self) -> String {
+        if self.is_empty() {
+            return format!("Nothing new since {}.\n", self.since);
+        }
+
+        let mut lines = vec![format!("New since {}:", self.since)];
+
+        if !self.components.is_empty() {
+            lines.push(String::new());
+            lines.push("New components:".to_string());
+            lines.extend(self.components.iter().map(|c| format!("  {}", c)));
+        }
+
+        if !self.versions.is_empty() {
+            lines.push(String::new());
+            lines.push("New component versions:".to_string());
+            lines.extend(
+                self.versions
+                    .iter()
+                    .map(|v| format!("  {} {}", v.component, v.version)),
+            );
+        }
+
+        if !self.releases.is_empty() {
+            lines.push(String::new());
+            lines.push("New releases:".to_string());
+            lines.extend(self.releases.iter().map(|r| match &r.date {
+                Some(date) => format!("  {} {} ({})", r.entity, r.version, date),
+                None => format!("  {} {}", r.entity, r.version),
+            }));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Render as Markdown.
+    pub fn to_markdown(&self) -> String {
+        let mut lines = vec![format!("# What's New Since {}", self.since)];
+
+        if self.is_empty() {
+            lines.push(String::new());
+            lines.push("Nothing new.".to_string());
+        }
+
+        if !self.components.is_empty() {
+            lines.push(String::new());
+            lines.push("## New Components".to_string());
+            lines.push(String::new());
+            lines.extend(self.components.iter().map(|c| format!("- `{}`", c)));
+        }
+
+        if !self.versions.is_empty() {
+            lines.push(String::new());
+            lines.push("## New Component Versions".to_string());
+            lines.push(String::new());
+            lines.push("| Component | Version | Modified |".to_string());
+            lines.push("| --- | --- | --- |".to_string());
+            lines.extend(self.versions.iter().map(|v| {
+                format!(
+                    "| {} | [{}]({}) | {} |",
+                    markdown_escape(&v.component),
+                    markdown_escape(&v.version),
+                    v.url,
+                    v.last_modified.as_deref().unwrap_or("")
+                )
+            }));
+        }
+
+        if !self.releases.is_empty() {
+            lines.push(String::new());
+            lines.push("## New Releases".to_string());
+            lines.push(String::new());
+            lines.push("| Release | Version | Date |".to_string());
+            lines.push("| --- | --- | --- |".to_string());
+            lines.extend(self.releases.iter().map(|r| {
+                format!(
+                    "| {} | [{}]({}) | {} |",
+                    markdown_escape(&r.entity),
+                    markdown_escape(&r.version),
+                    r.url,
+                    r.date.as_deref().unwrap_or("")
+                )
+            }));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Render as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for the blocking facade.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        blocking, download, git::tag_commits, git::ImportOptions, testing::TestSource,
+    },
+    std::sync::Arc,
+};
+
+fn downloader() -> Result<blocking::Downloader> {
+    blocking::Downloader::from_async(
+        download::Downloader::builder()?
+            .source(Arc::new(TestSource::sample()))
+            .build(),
+    )
+}
+
+#[test]
+fn queries() -> Result<()> {
+    let downloader = downloader()?;
+
+    assert_eq!(
+        downloader.get_components()?.into_iter().collect::<Vec<_>>(),
+        vec!["hello", "world"]
+    );
+    assert_eq!(
+        downloader
+            .get_component_versions("hello")?
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["1.0", "2.0"]
+    );
+
+    let release = downloader.find_release("macos", "11.0")?;
+    assert_eq!(downloader.get_release_components(&release, false)?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn repositories() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("aosd-blocking-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    downloader()?.create_component_repository(&path, "hello", &ImportOptions::default())?;
+    assert_eq!(
+        tag_commits(&path)?.keys().collect::<Vec<_>>(),
+        vec!["1.0", "2.0"]
+    );
+    assert!(downloader()?.repo_status(&path, "hello")?.is_up_to_date());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
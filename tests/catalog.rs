@@ -0,0 +1,167 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for catalogs of crawled metadata.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        catalog::{Catalog, CatalogSource},
+        crawler::URL_TARBALLS,
+        crypto::sha256_hex,
+        download::Downloader,
+        error::DigestMismatchError,
+        middleware::ChecksumLayer,
+        testing::TestSource,
+        whats_new::WhatsNew,
+    },
+    std::sync::Arc,
+};
+
+fn source() -> TestSource {
+    TestSource::sample().with_release_update("macos", "11.0", "11.0.1", &[("hello", "2.0")])
+}
+
+fn downloader(source: TestSource) -> Result<Downloader> {
+    Ok(Downloader::builder()?.source(Arc::new(source)).build())
+}
+
+#[tokio::test]
+async fn catalogs_serve_crawled_metadata() -> Result<()> {
+    let catalog = Catalog::crawl(Arc::new(source()), false).await?;
+
+    assert_eq!(catalog.source, "test");
+    assert_eq!(catalog.releases.len(), 2);
+    assert!(catalog.releases[0].listed);
+    assert!(!catalog.releases[1].listed);
+    assert_eq!(
+        catalog.releases[0].updates,
+        vec![catalog.releases[1].url.clone()]
+    );
+
+    let catalog = Catalog::from_json(&catalog.to_json()?)?;
+
+    // Metadata is served from the catalog without the original source.
+    let crawled = downloader(source())?;
+    let served = Downloader::builder()?
+        .source(Arc::new(CatalogSource::new(
+            catalog,
+            Arc::new(TestSource::new()),
+        )))
+        .build();
+
+    assert_eq!(served.source_name(), "test");
+    assert_eq!(
+        served.get_releases_with_updates().await?,
+        crawled.get_releases_with_updates().await?
+    );
+    assert_eq!(
+        served.get_components_versions().await?,
+        crawled.get_components_versions().await?
+    );
+
+    let release = served.find_release("macos", "11.0").await?;
+    let components = served
+        .get_release_components(&release, false)
+        .await?
+        .into_iter()
+        .map(|c| c.url)
+        .collect::<Vec<_>>();
+    let expected = crawled
+        .get_release_components(&release, false)
+        .await?
+        .into_iter()
+        .map(|c| c.url)
+        .collect::<Vec<_>>();
+    assert_eq!(components, expected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn catalog_digests_are_verified() -> Result<()> {
+    let url = format!("{}/hello/hello-1.0.tar.gz", URL_TARBALLS);
+    let data = downloader(source())?.get_artifact(&url).await?;
+
+    let mut catalog = Catalog::crawl(Arc::new(source()), false).await?;
+    catalog.checksums.insert(url.clone(), sha256_hex(&data));
+    catalog.validate()?;
+
+    let verified = |checksums| -> Result<Downloader> {
+        Ok(Downloader::builder()?
+            .layer(ChecksumLayer::new(checksums))
+            .source(Arc::new(source()))
+            .build())
+    };
+
+    assert_eq!(
+        verified(catalog.checksums.clone())?
+            .get_artifact(&url)
+            .await?,
+        data
+    );
+
+    let mut tampered = catalog.checksums.clone();
+    tampered.insert(url.clone(), sha256_hex(b"other"));
+    let err = verified(tampered)?.get_artifact(&url).await.unwrap_err();
+    assert!(err.downcast_ref::<DigestMismatchError>().is_some());
+
+    // Malformed digests are rejected.
+    catalog.checksums.insert(url, "abc".to_string());
+    assert!(Catalog::from_json(&catalog.to_json()?).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn whats_new_reports_additions() -> Result<()> {
+    let previous = Catalog::crawl(Arc::new(TestSource::sample()), false).await?;
+    let current = Catalog::crawl(
+        Arc::new(
+            TestSource::sample()
+                .with_component_version("hello", "3.0", &[("hello.c", b"v3\n")])
+                .with_component_version("new", "0.1", &[("new.c", b"new\n")])
+                .with_release("macos", "12.0", &[("hello", "3.0"), ("new", "0.1")]),
+        ),
+        false,
+    )
+    .await?;
+
+    let report = WhatsNew::between(&previous, &current, "previous.json");
+    assert_eq!(report.components, vec!["new".to_string()]);
+    assert_eq!(
+        report
+            .versions
+            .iter()
+            .map(|v| (v.component.as_str(), v.version.as_str()))
+            .collect::<Vec<_>>(),
+        vec![("hello", "3.0"), ("new", "0.1")]
+    );
+    assert_eq!(
+        report
+            .releases
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["12.0"]
+    );
+
+    let text = report.to_text();
+    assert!(text.starts_with("New since previous.json:\n"));
+    assert!(text.contains("  hello 3.0\n"));
+
+    let markdown = report.to_markdown();
+    assert!(markdown.starts_with("# What's New Since previous.json\n"));
+    assert!(markdown.contains(
+        "| hello | [3.0](https://opensource.apple.com/tarballs/hello/hello-3.0.tar.gz) |  |"
+    ));
+
+    assert!(WhatsNew::between(&current, &current, "current.json").is_empty());
+    assert_eq!(
+        WhatsNew::between(&current, &current, "current.json").to_text(),
+        "Nothing new since current.json.\n"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests of the chronological ordering of software releases.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        chronology::{
+            chronological_releases, interleave_releases, order_platform_releases, order_releases,
+            releases_since, verify_order, OrderVerification, OrderingIssueKind,
+        },
+        download::ReleaseRecord,
+    },
+    chrono::NaiveDate,
+};
+
+fn release(entity: &str, version: &str, date: Option<&str>) -> ReleaseRecord {
+    ReleaseRecord {
+        entity: entity.to_string(),
+        version: version.to_string(),
+        url: format!(
+            "https://opensource.apple.com/release/{}-{}.html",
+            entity, version
+        ),
+        date: date.map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap()),
+        original_entity: None,
+    }
+}
+
+fn versions(releases: &[ReleaseRecord]) -> Vec<&str> {
+    releases.iter().map(|r| r.version.as_str()).collect()
+}
+
+#[test]
+fn undated_releases_ordered_by_version() {
+    let releases = order_releases(vec![
+        release("macos", "10.13", Some("2017-09-25")),
+        release("mac-os-x", "10.4.11", None),
+        release("macos", "10.12.6", None),
+        release("macos", "10.12", Some("2016-09-20")),
+        release("macos", "10.14", None),
+        release("mac-os-x", "10.5", None),
+    ]);
+
+    assert_eq!(
+        versions(&releases),
+        vec!["10.4.11", "10.5", "10.12", "10.12.6", "10.13", "10.14"]
+    );
+    assert!(verify_order(&releases).is_empty());
+}
+
+fn mixed_releases() -> Vec<ReleaseRecord> {
+    vec![
+        release("macos", "12.0.1", Some("2021-10-25")),
+        release("macos", "12.6", None),
+        release("ios", "15.0", Some("2021-09-20")),
+        release("macos", "11.7", None),
+        release("macos", "13.0", Some("2022-10-24")),
+        release("macos", "11.0.1", Some("2020-11-12")),
+        release("macos", "13.1", None),
+    ]
+}
+
+#[test]
+fn mixed_dated_and_undated_releases_sort_by_version() {
+    let mut releases = mixed_releases();
+    releases.sort();
+
+    assert_eq!(
+        versions(&releases),
+        vec!["15.0", "11.0.1", "11.7", "12.0.1", "12.6", "13.0", "13.1"]
+    );
+}
+
+#[test]
+fn undated_releases_since() {
+    let date = |d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+    let macos = || mixed_releases().into_iter().filter(|r| r.entity == "macos");
+
+    // Undated releases are kept unless the next dated release is older.
+    assert_eq!(
+        versions(&releases_since(macos(), date("2022-01-01"))),
+        vec!["12.6", "13.0", "13.1"]
+    );
+    assert_eq!(
+        versions(&releases_since(macos(), date("2021-10-25"))),
+        vec!["11.7", "12.0.1", "12.6", "13.0", "13.1"]
+    );
+    assert_eq!(
+        versions(&releases_since(macos(), date("2023-01-01"))),
+        vec!["13.1"]
+    );
+
+    assert_eq!(
+        versions(&order_platform_releases(mixed_releases(), None)),
+        vec!["15.0", "11.0.1", "11.7", "12.0.1", "12.6", "13.0", "13.1"]
+    );
+    assert_eq!(
+        versions(&order_platform_releases(
+            mixed_releases(),
+            Some(date("2021-10-01"))
+        )),
+        vec!["11.7", "12.0.1", "12.6", "13.0", "13.1"]
+    );
+}
+
+#[test]
+fn ambiguous_order() -> Result<()> {
+    let releases = vec![
+        release("macos", "11.0.1", Some("2020-11-12")),
+        // A security update of the previous major version published later.
+        release("macos", "10.15.7", Some("2020-11-15")),
+        release("macos", "11.1", Some("2020-12-14")),
+        release("os-x", "11.1", Some("2020-12-14")),
+    ];
+
+    let ordered = order_releases(releases.clone());
+    assert_eq!(
+        versions(&ordered),
+        vec!["11.0.1", "10.15.7", "11.1", "11.1"]
+    );
+
+    let issues = verify_order(&ordered);
+    assert_eq!(
+        issues.iter().map(|i| i.kind).collect::<Vec<_>>(),
+        vec![
+            OrderingIssueKind::VersionRegression,
+            OrderingIssueKind::DuplicateVersion
+        ]
+    );
+    assert_eq!(issues[0].release.version, "10.15.7");
+    assert_eq!(
+        issues[0].to_string(),
+        "macos 10.15.7 (2020-11-15) follows newer version macos 11.0.1 (2020-11-12)"
+    );
+
+    assert_eq!(
+        chronological_releases(releases.clone(), OrderVerification::Warn)?,
+        ordered
+    );
+    assert!(chronological_releases(releases, OrderVerification::Abort).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn histories_are_interleaved() {
+    let releases = interleave_releases(vec![
+        vec![
+            release("macos", "10.15", Some("2019-10-07")),
+            release("macos", "11.0", Some("2020-11-12")),
+        ],
+        vec![
+            release("ios", "12.0", None),
+            release("ios", "13.0", Some("2019-09-19")),
+            release("ios", "14.0", Some("2020-09-16")),
+        ],
+    ]);
+
+    assert_eq!(
+        releases
+            .iter()
+            .map(|(index, r)| (*index, r.version.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, "12.0"),
+            (1, "13.0"),
+            (0, "10.15"),
+            (1, "14.0"),
+            (0, "11.0")
+        ]
+    );
+}
@@ -0,0 +1,168 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests of the command line interface.
+//!
+//! Commands run offline against a catalog of [TestSource::sample] and a cache
+//! holding its artifacts.
+
+use {
+    anyhow::{anyhow, Result},
+    apple_opensource_downloader::{
+        catalog::Catalog, download::Downloader, middleware::CacheLayer, testing::TestSource,
+    },
+    git2::Repository,
+    std::{
+        ffi::OsString,
+        path::{Path, PathBuf},
+        process::Command,
+        sync::Arc,
+    },
+};
+
+/// Obtain an empty directory unique to a test.
+fn test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("aosd-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    path
+}
+
+/// Write a catalog and fill a cache, returning arguments using them.
+async fn offline_args(dir: &Path) -> Result<Vec<OsString>> {
+    let catalog_path = dir.join("catalog.json");
+    let cache_dir = dir.join("cache");
+    std::fs::create_dir_all(dir)?;
+
+    let source = Arc::new(TestSource::sample());
+    let catalog = Catalog::crawl(source.clone(), false).await?;
+    catalog.write(&catalog_path)?;
+
+    let downloader = Downloader::builder()?
+        .layer(CacheLayer::new(&cache_dir))
+        .source(source)
+        .build();
+    for component in catalog.components.values() {
+        for version in &component.versions {
+            downloader.get_artifact(&version.url).await?;
+        }
+    }
+
+    Ok(vec![
+        "--catalog".into(),
+        catalog_path.into(),
+        "--cache-dir".into(),
+        cache_dir.into(),
+    ])
+}
+
+/// Run the executable, failing if it does.
+fn run(args: &[OsString]) -> Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_apple-opensource-downloader"))
+        .args(args)
+        .output()?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} failed: {}{}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+#[tokio::test]
+async fn bare_flag_after_command_wins() -> Result<()> {
+    let dir = test_dir("bare_flag_after_command_wins");
+    let offline = offline_args(&dir).await?;
+
+    for (before, after, bare) in [
+        (&["--no-bare"][..], &["--bare"][..], true),
+        (&["--bare"], &["--no-bare"], false),
+        (&["--no-bare"], &["--no-bare"], false),
+        (&["--bare"], &["--bare"], true),
+        (&["--no-bare"], &[], false),
+        (&[], &["--no-bare", "--bare"], true),
+        (&["--bare", "--no-bare"], &[], false),
+        (&[], &[], true),
+    ] {
+        let dest = dir.join(format!("hello{}{}", before.join(""), after.join("")));
+
+        let mut args = offline.clone();
+        args.extend(before.iter().map(OsString::from));
+        args.push("component-to-git".into());
+        args.extend(after.iter().map(OsString::from));
+        args.extend(["hello".into(), dest.clone().into()]);
+        run(&args)?;
+
+        assert_eq!(
+            Repository::open(&dest)?.is_bare(),
+            bare,
+            "{:?} {:?}",
+            before,
+            after
+        );
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn dest_root_resolves_repositories() -> Result<()> {
+    let dir = test_dir("dest_root_resolves_repositories");
+    let offline = offline_args(&dir).await?;
+
+    let command = |args: &[&str]| {
+        let mut all = offline.clone();
+        all.extend(["--dest-root".into(), dir.clone().into()]);
+        all.extend(args.iter().map(OsString::from));
+        run(&all)
+    };
+
+    command(&["component-to-git", "hello", "a.git"])?;
+    command(&["component-to-git", "hello", "b.git"])?;
+    assert!(dir.join("a.git").is_dir());
+
+    let tag = Repository::open(dir.join("a.git"))?
+        .tag_names(None)?
+        .iter()
+        .flatten()
+        .next()
+        .map(|tag| tag.to_string())
+        .ok_or_else(|| anyhow!("no tags"))?;
+
+    command(&["export-tree", "a.git", &tag, "export"])?;
+    assert!(dir.join("export").join("hello.c").is_file());
+
+    command(&["compare-repos", "a.git", "b.git"])?;
+    command(&["reimport", "a.git"])?;
+    command(&["mirror-config", "."])?;
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_rejects_zero_interval() -> Result<()> {
+    let dir = test_dir("watch_rejects_zero_interval");
+    let mut args = offline_args(&dir).await?;
+    args.extend(["watch".into(), "--interval".into(), "0s".into()]);
+
+    let err = run(&args).expect_err("zero interval is rejected");
+    assert!(
+        err.to_string().contains("--interval must be positive"),
+        "{}",
+        err
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
@@ -0,0 +1,552 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for parsing of index pages.
+
+use apple_opensource_downloader::{
+    crawler::{
+        source_tree::{self, SourceListing},
+        ComponentManifest, ComponentsPage, ReleasePage, ReleasesPage, SiteLayout, VersionParser,
+        VersionsPage, WaybackSource,
+    },
+    download::{artifact_filenames, ArtifactKind, ReleaseComponentRecord, ReleaseRecord},
+};
+
+const RELEASES_HTML: &str = r#"
+<a href="/release/macos-1015.html">10.15</a>
+<a href="/release/macos-1101.html">11.0.1</a>
+<a href="/release/mac-os-x-1068.html">10.6.8</a>
+<a href="/release/developer-tools-91.html">9.1</a>
+"#;
+
+const RELEASE_HTML: &str = r#"
+<a href="/tarballs/xnu/xnu-7195.141.2.tar.gz">xnu</a>
+<a href="/tarballs/hfs/hfs-556.60.1.tar.gz">hfs</a>
+<a href="/source/xnu/xnu-7195.141.2/">source</a>
+<a href="files/Installer.pkg">installer</a>
+"#;
+
+const COMPONENTS_HTML: &str = r#"
+<tr><td valign="top"><a href="xnu/"><img src="/static/images/icons/folder.png" alt="[DIR]"></a></td></tr>
+<tr><td valign="top"><a href="hfs/"><img src="/static/images/icons/folder.png" alt="[DIR]"></a></td></tr>
+"#;
+
+const VERSIONS_HTML: &str = r#"
+<tr><td valign="top"><a href="xnu-7195.141.2.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+<tr><td valign="top"><a href="xnu-517.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+<tr><td valign="top"><a href="xnu-1228.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+"#;
+
+#[test]
+fn releases_page() {
+    let page = ReleasesPage::parse_html(RELEASES_HTML).unwrap();
+
+    assert_eq!(
+        page.releases
+            .iter()
+            .map(|r| (r.entity.as_str(), r.version.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("developer-tools", "9.1"),
+            ("mac-os-x", "10.6.8"),
+            ("macos", "10.15"),
+            ("macos", "11.0.1"),
+        ]
+    );
+    assert_eq!(
+        page.releases[0].url,
+        "https://opensource.apple.com/release/developer-tools-91.html"
+    );
+}
+
+#[test]
+fn releases_page_dates() {
+    let page = ReleasesPage::parse_html(
+        r#"
+<div><a href="/release/macos-1151.html">11.5.1</a><span>2021-07-26</span></div>
+<div><a href="/release/macos-1150.html">11.5</a><span>July 21, 2021</span></div>
+<div><a href="/release/macos-1140.html">11.4</a></div>
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        page.releases
+            .iter()
+            .map(|r| (r.version.as_str(), r.date.map(|d| d.to_string())))
+            .collect::<Vec<_>>(),
+        vec![
+            ("11.4", None),
+            ("11.5", Some("2021-07-21".to_string())),
+            ("11.5.1", Some("2021-07-26".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn release_page() {
+    let record = ReleaseRecord {
+        entity: "macos".to_string(),
+        version: "11.5".to_string(),
+        url: "https://opensource.apple.com/release/macos-115.html".to_string(),
+        date: None,
+        original_entity: None,
+    };
+
+    let page = ReleasePage::parse_html(&record, RELEASE_HTML).unwrap();
+
+    assert_eq!(
+        page.components
+            .iter()
+            .map(|r| (r.component.as_str(), r.url.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "xnu",
+                "https://opensource.apple.com/tarballs/xnu/xnu-7195.141.2.tar.gz"
+            ),
+            (
+                "hfs",
+                "https://opensource.apple.com/tarballs/hfs/hfs-556.60.1.tar.gz"
+            ),
+        ]
+    );
+    assert_eq!(page.other_artifacts.len(), 1);
+    assert_eq!(page.other_artifacts[0].kind, ArtifactKind::Package);
+    assert_eq!(page.other_artifacts[0].component, "Installer");
+    assert_eq!(
+        page.other_artifacts[0].url,
+        "https://opensource.apple.com/release/files/Installer.pkg"
+    );
+}
+
+#[test]
+fn release_component_versions() {
+    for (component, filename, version) in [
+        ("xnu", "xnu-7195.141.2.tar.gz", Some("7195.141.2")),
+        ("libdispatch-foo", "libdispatch-foo-1.2.tar.gz", Some("1.2")),
+        ("Libc", "Libc-1439.141.1.tar.gz", Some("1439.141.1")),
+        ("xnu", "hfs-556.60.1.tar.gz", None),
+        ("xnu", "xnu-7195.141.2.zip", None),
+    ] {
+        let record = ReleaseComponentRecord {
+            entity: "macos".to_string(),
+            component: component.to_string(),
+            url: format!(
+                "https://opensource.apple.com/tarballs/{}/{}",
+                component, filename
+            ),
+            kind: ArtifactKind::Tarball,
+        };
+
+        assert_eq!(record.version(), version, "{}", filename);
+    }
+}
+
+#[test]
+fn artifact_filenames_are_distinct() {
+    let records = [
+        "https://opensource.apple.com/tarballs/xnu/xnu-7195.141.2.tar.gz",
+        "https://opensource.apple.com/release/xnu-7195.141.2.tar.gz",
+        "https://opensource.apple.com/tarballs/xnu/xnu-7195.141.2.tar.gz",
+        "https://opensource.apple.com/other/XNU-7195.141.2.tar.gz",
+        "https://opensource.apple.com/packages/Installer%20Tools.pkg",
+        "https://opensource.apple.com/packages/..",
+    ]
+    .into_iter()
+    .map(|url| ReleaseComponentRecord {
+        entity: "macos".to_string(),
+        component: "xnu".to_string(),
+        url: url.to_string(),
+        kind: ArtifactKind::Tarball,
+    })
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+        artifact_filenames(&records),
+        vec![
+            "xnu-7195.141.2.tar.gz",
+            "xnu-7195.141.2-2.tar.gz",
+            "xnu-7195.141.2.tar.gz",
+            "XNU-7195.141.2-3.tar.gz",
+            "Installer_20Tools.pkg",
+            "artifact",
+        ]
+    );
+}
+
+#[test]
+fn components_page() {
+    let page = ComponentsPage::parse_html(COMPONENTS_HTML).unwrap();
+
+    assert_eq!(
+        page.components.iter().collect::<Vec<_>>(),
+        vec!["hfs", "xnu"]
+    );
+}
+
+#[test]
+fn versions_page() {
+    let page = VersionsPage::parse_html("xnu", VERSIONS_HTML).unwrap();
+
+    assert_eq!(
+        page.versions
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["517", "1228", "7195.141.2"]
+    );
+    assert_eq!(
+        page.versions[0].url,
+        "https://opensource.apple.com/tarballs/xnu/xnu-517.tar.gz"
+    );
+}
+
+#[test]
+fn versions_page_row_metadata() {
+    let html = r#"
+<tr><td valign="top"><a href="xnu-7195.141.2.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td><td><a href="xnu-7195.141.2.tar.gz">xnu-7195.141.2.tar.gz</a></td><td align="right">2021-08-04 19:42  </td><td align="right"> 42M</td></tr>
+<tr><td valign="top"><a href="xnu-517.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td><td align="right">2003-10-24 10:00  </td><td align="right">  - </td></tr>
+<tr><td valign="top"><a href="xnu-1228.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+"#;
+    let page = VersionsPage::parse_html("xnu", html).unwrap();
+
+    assert_eq!(
+        page.versions
+            .iter()
+            .map(|r| (
+                r.version.as_str(),
+                r.size,
+                r.last_modified.map(|t| t.to_string())
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("517", None, Some("2003-10-24 10:00:00".to_string())),
+            ("1228", None, None),
+            (
+                "7195.141.2",
+                Some(42 * 1024 * 1024),
+                Some("2021-08-04 19:42:00".to_string())
+            ),
+        ]
+    );
+
+    let html = r#"
+<tr><td><img src="/icons/compressed.gif" alt="[   ]"></td><td><a href="xnu-1504.15.3.tar.gz">xnu-1504.15.3.tar.gz</a></td><td align="right">04-Aug-2011 19:42  </td><td align="right">1.5K</td></tr>
+"#;
+    let page = SiteLayout::Legacy
+        .parse_versions("xnu", html, &VersionParser::default())
+        .unwrap();
+    assert_eq!(page.versions[0].size, Some(1536));
+    assert_eq!(
+        page.versions[0].last_modified.map(|t| t.to_string()),
+        Some("2011-08-04 19:42:00".to_string())
+    );
+}
+
+#[test]
+fn source_tree_listings() {
+    let listing = SourceListing::parse_html(
+        r#"
+<a href="?C=N;O=D">Name</a>
+<a href="/source/xnu/">Parent Directory</a>
+<a href="../">Parent Directory</a>
+<a href="bsd/">bsd/</a>
+<a href="Makefile">Makefile</a>
+<a href=".gitignore">.gitignore</a>
+<a href="https://www.apple.com/">Apple</a>
+"#,
+    );
+    assert_eq!(listing.directories, vec!["bsd/"]);
+    assert_eq!(listing.files, vec!["Makefile", ".gitignore"]);
+
+    let page = source_tree::parse_versions(
+        "xnu",
+        r#"<a href="xnu-1228/">xnu-1228/</a> <a href="xnu-517/">xnu-517/</a> <a href="other/">other/</a>"#,
+    );
+    assert_eq!(
+        page.versions
+            .iter()
+            .map(|r| (r.version.as_str(), r.url.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("517", "https://opensource.apple.com/source/xnu/xnu-517/"),
+            ("1228", "https://opensource.apple.com/source/xnu/xnu-1228/"),
+        ]
+    );
+}
+
+#[test]
+fn versions_page_filename_override() {
+    let html = r#"
+<tr><td valign="top"><a href="Libc_Darwin-1439.40.11.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+<tr><td valign="top"><a href="Libc.plist"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+"#;
+
+    // The default parser splits on the first hyphen.
+    let page = VersionsPage::parse_html("Libc", html).unwrap();
+    assert_eq!(page.versions[0].version, "1439.40.11");
+
+    let parser = VersionParser::default()
+        .with_override("Libc", r"^Libc_(?P<version>[^-]+)-.*\.tar\.gz$")
+        .unwrap();
+    let page = VersionsPage::parse_html_with("Libc", html, &parser).unwrap();
+
+    assert_eq!(
+        page.versions
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Darwin"]
+    );
+
+    assert!(VersionParser::default()
+        .with_override("Libc", r"^Libc-.*$")
+        .is_err());
+}
+
+#[test]
+fn versions_page_manifests() {
+    let html = r#"
+<tr><td valign="top"><a href="dyld-852.tar.gz"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+<tr><td valign="top"><a href="dyld-852.plist"><img src="/static/images/icons/gz.png" alt="[   ]"></a></td></tr>
+"#;
+
+    let page = VersionsPage::parse_html("dyld", html).unwrap();
+    assert_eq!(
+        page.manifests,
+        vec!["https://opensource.apple.com/tarballs/dyld/dyld-852.plist"]
+    );
+}
+
+#[test]
+fn component_manifest() {
+    let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>OpenSourceProject</key>
+    <string>dyld</string>
+    <key>OriginalProjectName</key>
+    <string>dyld</string>
+    <key>ProjectVersion</key>
+    <string>852</string>
+    <key>Files</key>
+    <array><string>dyld.c</string></array>
+</dict>
+</plist>
+"#;
+
+    let manifest = ComponentManifest::parse("dyld-852.plist", xml).unwrap();
+    assert_eq!(manifest.project_name.as_deref(), Some("dyld"));
+    assert_eq!(manifest.version.as_deref(), Some("852"));
+    assert!(!manifest.properties.contains_key("Files"));
+    assert_eq!(
+        manifest.provenance_notes(),
+        "Project: dyld\nProject version: 852\nManifest: dyld-852.plist\n"
+    );
+
+    assert!(ComponentManifest::parse("bad.plist", b"not a plist").is_err());
+}
+
+#[test]
+fn release_page_notes() {
+    let record = ReleaseRecord {
+        entity: "macos".to_string(),
+        version: "11.0.1".to_string(),
+        url: "https://opensource.apple.com/release/macos-1101.html".to_string(),
+        date: None,
+        original_entity: None,
+    };
+
+    let html = r#"<html><head><title>macOS 11.0.1</title><style>p { }</style></head>
+<body>
+<h2>macOS 11.0.1 Source</h2>
+<p>See the <a href="/license/apsl/">APSL &amp; other licenses</a>.</p>
+<table><tr><td><a href="/tarballs/xnu/xnu-7195.50.7.tar.gz">xnu-7195.50.7</a></td></tr></table>
+</body></html>"#;
+
+    let page = ReleasePage::parse_html(&record, html).unwrap();
+
+    assert_eq!(
+        page.notes(&record).unwrap().unwrap(),
+        "# macos 11.0.1\n\n\
+         Captured from <https://opensource.apple.com/release/macos-1101.html>.\n\n\
+         macOS 11.0.1 Source\n\n\
+         See the [APSL & other licenses](https://opensource.apple.com/license/apsl/).\n\n\
+         [xnu-7195.50.7](https://opensource.apple.com/tarballs/xnu/xnu-7195.50.7.tar.gz)\n"
+    );
+
+    assert!(ReleasePage::default().notes(&record).unwrap().is_none());
+}
+
+#[test]
+fn release_page_updates() {
+    let record = ReleaseRecord {
+        entity: "macos".to_string(),
+        version: "12.6".to_string(),
+        url: "https://opensource.apple.com/release/macos-126.html".to_string(),
+        date: None,
+        original_entity: None,
+    };
+
+    let html = r#"
+<h3>Updates</h3>
+<a href="/release/macos-126.html">12.6</a>
+<a href="/release/macos-1261.html">12.6.1</a> October 24, 2022
+<a href="https://opensource.apple.com/release/security-update-2022-007-catalina.html">Security Update 2022-007 Catalina</a>
+<a href="/release/macos-1261.html">12.6.1</a>
+"#;
+
+    let page = ReleasePage::parse_html(&record, html).unwrap();
+
+    assert_eq!(
+        page.updates
+            .iter()
+            .map(|r| (r.entity.as_str(), r.version.as_str(), r.url.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            // Releases sort by version, whether dated or not.
+            (
+                "macos",
+                "12.6.1",
+                "https://opensource.apple.com/release/macos-1261.html"
+            ),
+            (
+                "macos",
+                "security-update-2022-007-catalina",
+                "https://opensource.apple.com/release/security-update-2022-007-catalina.html"
+            ),
+        ]
+    );
+    assert_eq!(
+        page.updates[0].date.map(|d| d.to_string()),
+        Some("2022-10-24".to_string())
+    );
+}
+
+const LEGACY_RELEASES_HTML: &str = r#"
+<li><a href="/release/mac-os-x-1068/">10.6.8</a> June 23, 2011</li>
+<li><a href="/release/developer-tools-41/">4.1</a></li>
+"#;
+
+const LEGACY_VERSIONS_HTML: &str = r#"
+<tr><td><img src="/icons/back.gif" alt="[DIR]"></td><td><a href="/tarballs/">Parent Directory</a></td></tr>
+<tr><td><a href="?C=N;O=D">Name</a></td></tr>
+<tr><td><img src="/icons/compressed.gif" alt="[   ]"></td><td><a href="xnu-1504.15.3.tar.gz">xnu-1504.15.3.tar.gz</a></td></tr>
+<tr><td><img src="/icons/compressed.gif" alt="[   ]"></td><td><a href="xnu-1228.tar.gz">xnu-1228.tar.gz</a></td></tr>
+"#;
+
+const CLASSIC_RELEASES_HTML: &str = r#"
+<a href="http://opensource.apple.com/release/os-x-1012.html">10.12</a>
+<a href="https://opensource.apple.com/release/developer-tools-83.html">8.3</a>
+"#;
+
+#[test]
+fn site_layout_detection() {
+    assert_eq!(SiteLayout::detect(LEGACY_RELEASES_HTML), SiteLayout::Legacy);
+    assert_eq!(SiteLayout::detect(LEGACY_VERSIONS_HTML), SiteLayout::Legacy);
+    assert_eq!(
+        SiteLayout::detect(CLASSIC_RELEASES_HTML),
+        SiteLayout::Classic
+    );
+    assert_eq!(SiteLayout::detect(RELEASES_HTML), SiteLayout::Current);
+    assert_eq!(SiteLayout::detect(VERSIONS_HTML), SiteLayout::Current);
+}
+
+#[test]
+fn legacy_layout() {
+    let page = SiteLayout::Legacy
+        .parse_releases(LEGACY_RELEASES_HTML)
+        .unwrap();
+    assert_eq!(
+        page.releases
+            .iter()
+            .map(|r| (r.entity.as_str(), r.version.as_str(), r.url.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "developer-tools",
+                "4.1",
+                "https://opensource.apple.com/release/developer-tools-41/"
+            ),
+            (
+                "mac-os-x",
+                "10.6.8",
+                "https://opensource.apple.com/release/mac-os-x-1068/"
+            ),
+        ]
+    );
+    assert_eq!(
+        page.releases[1].date.map(|d| d.to_string()).as_deref(),
+        Some("2011-06-23")
+    );
+
+    let page = SiteLayout::Legacy
+        .parse_components(
+            r#"
+<tr><td><a href="/">Parent Directory</a></td></tr>
+<tr><td><img src="/icons/folder.gif" alt="[DIR]"></td><td><a href="xnu/">xnu/</a></td></tr>
+<tr><td><img src="/icons/folder.gif" alt="[DIR]"></td><td><a href="hfs/">hfs/</a></td></tr>
+"#,
+        )
+        .unwrap();
+    assert_eq!(
+        page.components.iter().collect::<Vec<_>>(),
+        vec!["hfs", "xnu"]
+    );
+
+    let page = SiteLayout::Legacy
+        .parse_versions("xnu", LEGACY_VERSIONS_HTML, &VersionParser::default())
+        .unwrap();
+    assert_eq!(
+        page.versions
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["1228", "1504.15.3"]
+    );
+    assert_eq!(
+        page.versions[0].url,
+        "https://opensource.apple.com/tarballs/xnu/xnu-1228.tar.gz"
+    );
+}
+
+#[test]
+fn classic_layout() {
+    let page = SiteLayout::Classic
+        .parse_releases(CLASSIC_RELEASES_HTML)
+        .unwrap();
+    assert_eq!(
+        page.releases
+            .iter()
+            .map(|r| r.url.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "https://opensource.apple.com/release/developer-tools-83.html",
+            "https://opensource.apple.com/release/os-x-1012.html",
+        ]
+    );
+
+    let record = page.releases[1].clone();
+    let html = r#"<a href="http://opensource.apple.com/tarballs/xnu/xnu-3789.1.32.tar.gz">xnu</a>"#;
+    let page = SiteLayout::Classic.parse_release(&record, html).unwrap();
+    assert_eq!(
+        page.components[0].url,
+        "https://opensource.apple.com/tarballs/xnu/xnu-3789.1.32.tar.gz"
+    );
+    assert_eq!(page.html.as_deref(), Some(html));
+}
+
+#[test]
+fn wayback_urls() {
+    let source = WaybackSource::new(reqwest::Client::new(), "2011").unwrap();
+    assert_eq!(
+        source.archive_url("https://opensource.apple.com/tarballs/xnu/"),
+        "https://web.archive.org/web/2011id_/https://opensource.apple.com/tarballs/xnu/"
+    );
+
+    assert!(WaybackSource::new(reqwest::Client::new(), "2011-06").is_err());
+    assert!(WaybackSource::new(reqwest::Client::new(), "").is_err());
+}
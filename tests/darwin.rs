@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for the macOS, Darwin, and xnu version table.
+
+use apple_opensource_downloader::darwin::{find_darwin, find_macos, DARWIN_RELEASES};
+
+#[test]
+fn macos_versions_map_to_darwin() {
+    for (macos, darwin, xnu) in [
+        ("10.13", "17.0.0", "4570.1.46"),
+        ("10.13.6", "17.7.0", "4570.71.2"),
+        ("10.14.1", "18.2.0", "4903.221.2"),
+        ("10.14.3", "18.2.0", "4903.241.1"),
+        ("10.14.4", "18.5.0", "4903.251.3"),
+        ("10.15.1", "19.0.0", "6153.41.3"),
+        ("11.0.1", "20.1.0", "7195.50.7"),
+        ("12.3", "21.4.0", "8020.101.4"),
+        ("13.0", "22.1.0", "8792.41.9"),
+    ] {
+        let release = find_macos(macos).unwrap_or_else(|| panic!("{} should be known", macos));
+        assert_eq!((release.darwin, release.xnu), (darwin, xnu), "{}", macos);
+    }
+
+    assert_eq!(find_macos("9.0"), None);
+}
+
+#[test]
+fn darwin_versions_map_to_macos() {
+    for (darwin, macos) in [
+        ("17.0.0", vec!["10.13"]),
+        ("18.2.0", vec!["10.14.1", "10.14.3"]),
+        ("18.5.0", vec!["10.14.4"]),
+        ("19.0.0", vec!["10.15", "10.15.1"]),
+        ("20.1.0", vec!["11.0.1"]),
+        ("22.6.0", vec!["13.5"]),
+        ("1.0.0", vec![]),
+    ] {
+        assert_eq!(
+            find_darwin(darwin)
+                .iter()
+                .map(|release| release.macos)
+                .collect::<Vec<_>>(),
+            macos,
+            "{}",
+            darwin
+        );
+    }
+}
+
+#[test]
+fn releases_are_ordered() {
+    let key = |version: &str| {
+        version
+            .split('.')
+            .map(|part| part.parse::<u32>().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    for pair in DARWIN_RELEASES.windows(2) {
+        assert!(key(pair[0].macos) < key(pair[1].macos), "{:?}", pair);
+        assert!(key(pair[0].darwin) <= key(pair[1].darwin), "{:?}", pair);
+    }
+}
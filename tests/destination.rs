@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for templates of component repository paths.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        destination::DestinationTemplate,
+        download::Downloader,
+        git::{create_components_repositories, tag_commits, ImportOptions},
+        testing::TestSource,
+    },
+    std::{path::Path, str::FromStr, sync::Arc},
+};
+
+#[test]
+fn templates_resolve() -> Result<()> {
+    let dest = Path::new("/srv/git");
+
+    assert_eq!(
+        DestinationTemplate::default().resolve(dest, "xnu"),
+        Path::new("/srv/git/xnu")
+    );
+    assert_eq!(
+        DestinationTemplate::from_str("{dest}/{component[0]}/{component}.git")?
+            .resolve(dest, "xnu"),
+        Path::new("/srv/git/x/xnu.git")
+    );
+    assert_eq!(
+        DestinationTemplate::from_str("{dest}/{component[0]}{component[1]}/{component}")?
+            .resolve(dest, "z"),
+        Path::new("/srv/git/z_/z")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn invalid_templates() {
+    for template in [
+        "{dest}/{component",
+        "{dest}/{name}",
+        "{dest}/{component[x]}",
+        "{dest}/static",
+    ] {
+        assert!(
+            DestinationTemplate::from_str(template).is_err(),
+            "{}",
+            template
+        );
+    }
+}
+
+#[tokio::test]
+async fn components_are_sharded() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-sharded-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let downloader = Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build();
+    let options = ImportOptions {
+        destination: DestinationTemplate::from_str("{dest}/{component[0]}/{component}.git")?,
+        ..ImportOptions::default()
+    };
+
+    create_components_repositories(&downloader, &dir, &options).await?;
+
+    assert_eq!(tag_commits(&dir.join("h").join("hello.git"))?.len(), 2);
+    assert_eq!(tag_commits(&dir.join("w").join("world.git"))?.len(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
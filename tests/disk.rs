@@ -0,0 +1,172 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for disk space accounting.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::disk::{
+        available_space, directory_size, expansion_ratio, preflight, DiskUsage, DiskUsageLog,
+        ImportSize, SpaceReservations,
+    },
+    git2::{Oid, Repository, Signature},
+    std::path::PathBuf,
+};
+
+/// Obtain an empty directory unique to a test.
+fn test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("aosd-disk-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    path
+}
+
+fn import_size(archive_bytes: u64, disk_bytes: u64) -> ImportSize {
+    ImportSize {
+        repository: PathBuf::from("repo"),
+        archive_bytes,
+        disk_bytes,
+    }
+}
+
+#[test]
+fn expansion_ratio_from_logged_imports() -> Result<()> {
+    let dir = test_dir("expansion_ratio_from_logged_imports");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("usage.jsonl");
+
+    let log = DiskUsageLog::open(&path)?;
+    assert_eq!(expansion_ratio(&DiskUsageLog::read(&path)?), None);
+
+    log.record(&import_size(100, 150))?;
+    log.record(&import_size(300, 450))?;
+    log.record(&import_size(0, 10))?;
+    assert_eq!(expansion_ratio(&DiskUsageLog::read(&path)?), Some(1.525));
+    assert_eq!(expansion_ratio(&[import_size(0, 10)]), None);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_imports_share_free_space() -> Result<()> {
+    let dir = test_dir("concurrent_imports_share_free_space");
+    std::fs::create_dir_all(&dir)?;
+
+    let reservations = SpaceReservations::default();
+    let archive_bytes = available_space(&dir)? / 10 * 6;
+
+    let first = preflight(&dir, archive_bytes, 1.0, None, &reservations)?;
+    assert_eq!(first.archive_bytes(), archive_bytes);
+    assert_eq!(reservations.reserved(), archive_bytes);
+
+    let err = preflight(&dir, archive_bytes, 1.0, None, &reservations).unwrap_err();
+    assert!(err.to_string().contains("reserved by concurrent imports"));
+
+    // Independent reservations don't see each other.
+    preflight(
+        &dir,
+        archive_bytes,
+        1.0,
+        None,
+        &SpaceReservations::default(),
+    )?;
+
+    drop(first);
+    assert_eq!(reservations.reserved(), 0);
+    preflight(&dir, archive_bytes, 1.0, None, &reservations)?;
+
+    let err = preflight(&dir, 100, 3.0, Some(200), &reservations).unwrap_err();
+    assert!(err.to_string().contains("exceeding the disk usage limit"));
+
+    // Data already in the repository counts towards the limit.
+    preflight(&dir, 100, 1.0, Some(200), &reservations)?;
+    std::fs::write(dir.join("existing"), [0; 150])?;
+    let err = preflight(&dir, 100, 1.0, Some(200), &reservations).unwrap_err();
+    assert!(err.to_string().contains("exceeding the disk usage limit"));
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+/// Commit a tree of `files` with directories `a` and `b`, on top of `parent`.
+fn commit(repo: &Repository, files: &[(&str, &[u8])], parent: Option<Oid>) -> Result<Oid> {
+    let mut root = repo.treebuilder(None)?;
+
+    for dir in ["a", "b"] {
+        let mut builder = repo.treebuilder(None)?;
+        for (name, data) in files {
+            if let Some(name) = name.strip_prefix(&format!("{}/", dir)) {
+                builder.insert(name, repo.blob(data)?, 0o100644)?;
+            }
+        }
+        root.insert(dir, builder.write()?, 0o040000)?;
+    }
+
+    let tree = repo.find_tree(root.write()?)?;
+    let signature = Signature::now("test", "test@example.com")?;
+    let parents = parent
+        .map(|oid| repo.find_commit(oid))
+        .transpose()?
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    Ok(repo.commit(
+        None,
+        &signature,
+        &signature,
+        "commit",
+        &tree,
+        &parents.iter().collect::<Vec<_>>(),
+    )?)
+}
+
+fn loose_size(repo: &Repository, oid: Oid) -> Result<u64> {
+    let hex = oid.to_string();
+    let path = repo.path().join("objects").join(&hex[0..2]).join(&hex[2..]);
+
+    Ok(std::fs::metadata(path)?.len())
+}
+
+#[test]
+fn disk_usage_counts_new_objects() -> Result<()> {
+    let dir = test_dir("disk_usage_counts_new_objects");
+    let repo = Repository::init_bare(&dir)?;
+
+    let mut usage = DiskUsage::new(&repo, &dir)?;
+    assert_eq!(usage.used(), directory_size(&dir)?);
+    assert_eq!(usage.used(), usage.initial());
+
+    let first = commit(&repo, &[("a/x", b"x"), ("b/y", b"y")], None)?;
+    usage.add_commit(&repo, &repo.find_commit(first)?)?;
+    assert_eq!(usage.used(), directory_size(&dir)?);
+
+    // Only the changed blob, the trees holding it, and the commit are new.
+    let second = commit(&repo, &[("a/x", b"x"), ("b/y", b"z")], Some(first))?;
+    let before = usage.used();
+    usage.add_commit(&repo, &repo.find_commit(second)?)?;
+    let tree = repo.find_commit(second)?.tree()?;
+    let b = tree.get_name("b").unwrap().id();
+    assert_eq!(
+        usage.used() - before,
+        loose_size(&repo, second)?
+            + loose_size(&repo, tree.id())?
+            + loose_size(&repo, b)?
+            + loose_size(&repo, repo.blob(b"z")?)?
+    );
+    assert_eq!(usage.used(), directory_size(&dir)?);
+
+    // Objects are only counted once.
+    assert!(!usage.add_object(second));
+
+    usage.enforce(usage.used())?;
+    let err = usage.enforce(usage.used() - 1).unwrap_err();
+    assert!(err.to_string().contains("exceeding the disk usage limit"));
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
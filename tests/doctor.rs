@@ -0,0 +1,73 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for diagnosis of the environment.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::doctor::{
+        check_disk_space, check_libgit2, check_writable_dir, CheckStatus, Diagnosis,
+    },
+};
+
+fn test_dir(name: &str) -> Result<std::path::PathBuf> {
+    let dir = std::env::temp_dir()
+        .join("apple-opensource-downloader-doctor")
+        .join(name);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+#[test]
+fn local_checks() -> Result<()> {
+    let dir = test_dir("local_checks")?;
+
+    let writable = check_writable_dir("cache directory", &dir.join("cache"));
+    assert_eq!(writable.status, CheckStatus::Ok);
+    assert!(dir.join("cache").is_dir());
+    assert_eq!(std::fs::read_dir(dir.join("cache"))?.count(), 0);
+
+    std::fs::write(dir.join("file"), b"")?;
+    let unwritable = check_writable_dir("cache directory", &dir.join("file"));
+    assert_eq!(unwritable.status, CheckStatus::Error);
+    assert!(unwritable.advice.is_some());
+
+    assert_eq!(
+        check_disk_space("disk space", &dir, 0).status,
+        CheckStatus::Ok
+    );
+    let low = check_disk_space("disk space", &dir.join("missing"), u64::MAX);
+    assert_eq!(low.status, CheckStatus::Warning);
+
+    assert_eq!(check_libgit2().status, CheckStatus::Ok);
+
+    Ok(())
+}
+
+#[test]
+fn diagnosis_reports_worst_status() -> Result<()> {
+    let dir = test_dir("diagnosis_reports_worst_status")?;
+    std::fs::write(dir.join("file"), b"")?;
+
+    let mut diagnosis = Diagnosis {
+        checks: vec![check_disk_space("disk space", &dir, 0)],
+    };
+    assert_eq!(diagnosis.status(), CheckStatus::Ok);
+
+    diagnosis
+        .checks
+        .push(check_writable_dir("cache directory", &dir.join("file")));
+    assert_eq!(diagnosis.status(), CheckStatus::Error);
+
+    let text = diagnosis.to_text();
+    assert!(text.starts_with("ok       disk space: "));
+    assert!(text.contains("\nerror    cache directory: "));
+    assert!(diagnosis.to_json()?.contains("\"status\": \"error\""));
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for exporting listings.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::export::{ExportFormat, Table},
+    parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    },
+};
+
+fn table() -> Table {
+    let mut table = Table::new(&["entity", "version", "date"]);
+    table.push(vec![
+        Some("macos".to_string()),
+        Some("11.0".to_string()),
+        Some("2020-11-12".to_string()),
+    ]);
+    table.push(vec![
+        Some("developer-tools, \"beta\"".to_string()),
+        Some("9.1".to_string()),
+        None,
+    ]);
+
+    table
+}
+
+fn render(format: ExportFormat) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    table().write(format, &mut data)?;
+
+    Ok(data)
+}
+
+#[test]
+fn text() -> Result<()> {
+    assert_eq!(
+        String::from_utf8(render(ExportFormat::Text)?)?,
+        "macos\t11.0\t2020-11-12\ndeveloper-tools, \"beta\"\t9.1\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn json() -> Result<()> {
+    let value: serde_json::Value = serde_json::from_slice(&render(ExportFormat::Json)?)?;
+
+    assert_eq!(
+        value,
+        serde_json::json!([
+            {"entity": "macos", "version": "11.0", "date": "2020-11-12"},
+            {"entity": "developer-tools, \"beta\"", "version": "9.1", "date": null},
+        ])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn csv() -> Result<()> {
+    assert_eq!(
+        String::from_utf8(render(ExportFormat::Csv)?)?,
+        "entity,version,date\r\n\
+         macos,11.0,2020-11-12\r\n\
+         \"developer-tools, \"\"beta\"\"\",9.1,\r\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parquet() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("aosd-export-{}.parquet", std::process::id()));
+    std::fs::write(&path, render(ExportFormat::Parquet)?)?;
+
+    let reader = SerializedFileReader::new(std::fs::File::open(&path)?)?;
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+
+    let rows = reader
+        .get_row_iter(None)?
+        .map(|row| {
+            Ok(row?
+                .get_column_iter()
+                .map(|(name, field)| {
+                    let value = match field {
+                        Field::Str(s) => Some(s.clone()),
+                        _ => None,
+                    };
+
+                    (name.clone(), value)
+                })
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    assert_eq!(
+        rows[1],
+        vec![
+            (
+                "entity".to_string(),
+                Some("developer-tools, \"beta\"".to_string())
+            ),
+            ("version".to_string(), Some("9.1".to_string())),
+            ("date".to_string(), None),
+        ]
+    );
+
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
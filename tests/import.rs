@@ -0,0 +1,1746 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests importing content from a canned source into Git repositories.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        compare::{compare_repositories, TagStatus},
+        config::Config,
+        crawler::URL_TARBALLS,
+        denylist::{AuditLog, AuditRecord, Denylist},
+        download::{parse_versions_file, Downloader, VersionEntry},
+        error::{exit_code, InterruptedError, NoVersionsError, EXIT_INTERRUPTED},
+        git::{
+            append_component_version, create_component_repository, create_components_repositories,
+            create_multi_release_repository, create_release_repository, find_tag_commit,
+            reimport_component_repository, stored_archive, tag_commits, IdenticalVersionPolicy,
+            ImportOptions, MissingComponentPolicy, ReimportOutcome, ReleaseManifest,
+            ARCHIVE_REFS_PREFIX, COMPONENT_REFS_PREFIX, MISSING_FILENAME, RELEASE_HTML_FILENAME,
+            RELEASE_MANIFEST_FILENAME, RELEASE_NOTES_FILENAME, RELEASE_REFS_PREFIX,
+        },
+        hooks::{CommandHook, Hooks, ImportEvent, ImportHook},
+        identity::{CommitIdentity, DEFAULT_TIMESTAMP},
+        inventory::{FileKind, InventoryLog, ReportedFile, SizeBucket, LARGE_FILE_THRESHOLD},
+        lock::{lock_path, ImportLock},
+        memory::MemoryBudget,
+        mirror::{Provenance, RepositoryKind, RepositoryMetadata},
+        split::{create_split_component_repositories, ROOT_REPOSITORY},
+        status::{repo_status, RepoStatus},
+        tasks::CancelHandle,
+        testing::{tarball, TestSource},
+        tree::{TreeConverter, TreeOptions},
+    },
+    async_trait::async_trait,
+    chrono::NaiveDate,
+    git2::{Oid, Repository},
+    std::{cmp::Ordering, path::PathBuf, sync::Arc, time::Duration},
+};
+
+fn downloader() -> Result<Downloader> {
+    Ok(Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build())
+}
+
+/// Obtain an empty directory unique to a test.
+fn test_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("aosd-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    path
+}
+
+/// Obtain the paths of files in a tagged tree.
+fn tag_files(repo: &Repository, tag: &str) -> Result<Vec<String>> {
+    let tree = find_tag_commit(repo, tag)?
+        .expect("tag should exist")
+        .tree()?;
+
+    let mut paths = vec![];
+    tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(git2::ObjectType::Blob) {
+            paths.push(format!("{}{}", root, entry.name().unwrap()));
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+
+    Ok(paths)
+}
+
+#[tokio::test]
+async fn component_versions_are_tagged() -> Result<()> {
+    let path = test_dir("component");
+
+    create_component_repository(&downloader()?, &path, "hello", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(tag_files(&repo, "1.0")?, vec!["hello.c"]);
+    assert_eq!(tag_files(&repo, "2.0")?, vec!["README", "hello.c"]);
+
+    let commits = tag_commits(&path)?;
+    assert_eq!(commits.keys().collect::<Vec<_>>(), vec!["1.0", "2.0"]);
+    assert_eq!(
+        commits["2.0"],
+        find_tag_commit(&repo, "2.0")?.unwrap().id().to_string()
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn disk_usage_limit_aborts_imports() -> Result<()> {
+    let path = test_dir("disk-usage-limit");
+
+    let options = ImportOptions {
+        max_disk_usage: Some(u64::MAX),
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+    std::fs::remove_dir_all(&path)?;
+
+    let options = ImportOptions {
+        max_disk_usage: Some(1),
+        ..ImportOptions::default()
+    };
+    let err = create_component_repository(&downloader()?, &path, "hello", &options)
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("exceeding the disk usage limit"));
+
+    let repo = Repository::open(&path)?;
+    assert!(find_tag_commit(&repo, "1.0")?.is_some());
+    assert!(find_tag_commit(&repo, "2.0")?.is_none());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn prefetched_versions_are_committed_in_order() -> Result<()> {
+    let path = test_dir("prefetch");
+
+    let versions = ["1", "2", "3", "10", "11", "20"];
+    let source = versions.iter().fold(TestSource::new(), |source, version| {
+        source.with_component_version("many", version, &[("version", version.as_bytes())])
+    });
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        prefetch: Some(3),
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader, &path, "many", &options).await?;
+
+    let repo = Repository::open(&path)?;
+
+    for pair in versions.windows(2) {
+        let commit = find_tag_commit(&repo, pair[1])?.unwrap();
+        let parent = find_tag_commit(&repo, pair[0])?.unwrap();
+
+        assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![parent.id()]);
+    }
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn single_versions_are_appended() -> Result<()> {
+    let path = test_dir("append");
+    let repo = Repository::init(&path)?;
+    let downloader = downloader()?;
+    let options = ImportOptions::default();
+
+    let first =
+        append_component_version(&downloader, &path, "hello", "1.0", "main", &options).await?;
+    let second =
+        append_component_version(&downloader, &path, "hello", "2.0", "main", &options).await?;
+
+    let commit = repo.find_commit(second)?;
+    assert_eq!(commit.parent_ids().collect::<Vec<_>>(), vec![first]);
+    assert_eq!(find_tag_commit(&repo, "2.0")?.unwrap().id(), second);
+    assert_eq!(
+        repo.find_branch("main", git2::BranchType::Local)?
+            .get()
+            .target(),
+        Some(second)
+    );
+
+    assert!(
+        append_component_version(&downloader, &path, "hello", "2.0", "main", &options)
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn non_empty_destinations_are_refused() -> Result<()> {
+    let path = test_dir("non-empty");
+    let downloader = downloader()?;
+
+    std::fs::create_dir_all(&path)?;
+    std::fs::write(path.join("unrelated"), b"data")?;
+
+    let options = ImportOptions::default();
+    assert!(
+        create_component_repository(&downloader, &path, "hello", &options)
+            .await
+            .is_err()
+    );
+
+    let options = ImportOptions {
+        force: true,
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader, &path, "hello", &options).await?;
+
+    // Repositories created by an import can be imported into again.
+    create_component_repository(&downloader, &path, "hello", &ImportOptions::default()).await?;
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unrelated_repositories_are_refused() -> Result<()> {
+    let path = test_dir("unrelated");
+    let repo = Repository::init(&path)?;
+
+    let signature = git2::Signature::now("Someone", "someone@example.com")?;
+    let tree = repo.find_tree(repo.treebuilder(None)?.write()?)?;
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "unrelated",
+        &tree,
+        &[],
+    )?;
+
+    assert!(
+        create_component_repository(&downloader()?, &path, "hello", &ImportOptions::default())
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+/// Import a component whose versions 1.0 and 1.1 are identical.
+async fn import_identical(name: &str, policy: IdenticalVersionPolicy) -> Result<Repository> {
+    let path = test_dir(name);
+
+    let source = TestSource::new()
+        .with_component_version("same", "1.0", &[("file", b"content")])
+        .with_component_version("same", "1.1", &[("file", b"content")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        identical_versions: policy,
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader, &path, "same", &options).await?;
+
+    Ok(Repository::open(&path)?)
+}
+
+#[tokio::test]
+async fn identical_versions_commit() -> Result<()> {
+    let repo = import_identical("identical-commit", IdenticalVersionPolicy::Commit).await?;
+
+    let old = find_tag_commit(&repo, "1.0")?.unwrap();
+    let new = find_tag_commit(&repo, "1.1")?.unwrap();
+    assert_eq!(new.parent_ids().collect::<Vec<_>>(), vec![old.id()]);
+    assert_eq!(new.tree_id(), old.tree_id());
+    assert!(!new.message().unwrap().contains("identical"));
+
+    std::fs::remove_dir_all(repo.workdir().unwrap())?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn identical_versions_tag() -> Result<()> {
+    let repo = import_identical("identical-tag", IdenticalVersionPolicy::Tag).await?;
+
+    let old = find_tag_commit(&repo, "1.0")?.unwrap();
+    let new = find_tag_commit(&repo, "1.1")?.unwrap();
+    assert_eq!(new.id(), old.id());
+
+    std::fs::remove_dir_all(repo.workdir().unwrap())?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn identical_versions_annotate() -> Result<()> {
+    let repo = import_identical("identical-annotate", IdenticalVersionPolicy::Annotate).await?;
+
+    let old = find_tag_commit(&repo, "1.0")?.unwrap();
+    let new = find_tag_commit(&repo, "1.1")?.unwrap();
+    assert_eq!(new.parent_ids().collect::<Vec<_>>(), vec![old.id()]);
+    assert!(new
+        .message()
+        .unwrap()
+        .contains("Content is identical to version 1.0."));
+    assert!(!old.message().unwrap().contains("identical"));
+
+    std::fs::remove_dir_all(repo.workdir().unwrap())?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_components_are_imported() -> Result<()> {
+    let path = test_dir("release");
+
+    create_release_repository(&downloader()?, &path, "macos", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        tag_files(&repo, "11.0")?,
+        vec![
+            RELEASE_MANIFEST_FILENAME,
+            "hello/README",
+            "hello/hello.c",
+            "world/world.c"
+        ]
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_manifests_describe_commits() -> Result<()> {
+    let path = test_dir("release-manifest");
+    let world_url = "https://opensource.apple.com/tarballs/world/world-1.0.tar.gz";
+
+    let source = TestSource::sample().without_content(world_url);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    // The failed download is reported once the release is committed.
+    assert!(
+        create_release_repository(&downloader, &path, "macos", &ImportOptions::default())
+            .await
+            .is_err()
+    );
+
+    let repo = Repository::open(&path)?;
+    let commit = find_tag_commit(&repo, "11.0")?.unwrap();
+    let manifest = ReleaseManifest::from_commit(&repo, &commit)?.unwrap();
+    assert_eq!(
+        (manifest.entity.as_str(), manifest.version.as_str()),
+        ("macos", "11.0")
+    );
+    assert_eq!(manifest.components.len(), 1);
+    assert_eq!(manifest.components[0].component, "hello");
+    assert_eq!(manifest.components[0].version.as_deref(), Some("2.0"));
+    let sha256 = manifest.components[0].sha256.clone().unwrap();
+    assert_eq!(sha256.len(), 64);
+    assert_eq!(manifest.failed.len(), 1);
+    assert_eq!(manifest.failed[0].url, world_url);
+    assert!(manifest.failed[0].error.contains("404"));
+
+    // Checksums of reused archives are carried forward.
+    let options = ImportOptions {
+        resume: true,
+        ..ImportOptions::default()
+    };
+    create_release_repository(&self::downloader()?, &path, "macos", &options).await?;
+
+    let commit = find_tag_commit(&repo, "11.0")?.unwrap();
+    let manifest = ReleaseManifest::from_commit(&repo, &commit)?.unwrap();
+    assert_eq!(manifest.components.len(), 2);
+    assert_eq!(manifest.components[0].sha256, Some(sha256));
+    assert!(manifest.components[1].sha256.is_some());
+    assert!(manifest.failed.is_empty());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_release_components() -> Result<()> {
+    let path = test_dir("release-missing");
+    let world_url = "https://opensource.apple.com/tarballs/world/world-1.0.tar.gz";
+
+    let source = TestSource::sample().without_content(world_url);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    assert!(
+        create_release_repository(&downloader, &path, "macos", &ImportOptions::default())
+            .await
+            .is_err()
+    );
+
+    let repo = Repository::open(&path)?;
+    let commit = find_tag_commit(&repo, "11.0")?.unwrap();
+    assert_eq!(
+        commit.message(),
+        Some(
+            format!(
+                "macos 11.0\n\nSkipped components that failed to download:\n\n  world {}\n",
+                world_url
+            )
+            .as_str()
+        )
+    );
+    std::fs::remove_dir_all(&path)?;
+
+    // Strict imports fail before committing an incomplete release.
+    let options = ImportOptions {
+        missing_components: MissingComponentPolicy::Fail,
+        ..ImportOptions::default()
+    };
+    let err = create_release_repository(&downloader, &path, "macos", &options)
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains(world_url));
+
+    let repo = Repository::open(&path)?;
+    assert!(find_tag_commit(&repo, "11.0")?.is_none());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_release_component_placeholders() -> Result<()> {
+    let path = test_dir("release-placeholder");
+    let world_url = "https://opensource.apple.com/tarballs/world/world-1.0.tar.gz";
+
+    let source = TestSource::sample().without_content(world_url);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        missing_components: MissingComponentPolicy::Placeholder,
+        resume: true,
+        ..ImportOptions::default()
+    };
+    assert!(
+        create_release_repository(&downloader, &path, "macos", &options)
+            .await
+            .is_err()
+    );
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        tag_files(&repo, "11.0")?,
+        vec![
+            RELEASE_MANIFEST_FILENAME,
+            "hello/README",
+            "hello/hello.c",
+            "world/MISSING.txt"
+        ]
+    );
+    let tree = find_tag_commit(&repo, "11.0")?.unwrap().tree()?;
+    let entry = tree.get_path(&PathBuf::from("world").join(MISSING_FILENAME))?;
+    let text = String::from_utf8(repo.find_blob(entry.id())?.content().to_vec())?;
+    assert!(text.contains(world_url));
+
+    // Placeholders are replaced once the component can be downloaded.
+    create_release_repository(&self::downloader()?, &path, "macos", &options).await?;
+    assert_eq!(
+        tag_files(&repo, "11.0")?,
+        vec![
+            RELEASE_MANIFEST_FILENAME,
+            "hello/README",
+            "hello/hello.c",
+            "world/world.c"
+        ]
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_aliases_are_stitched() -> Result<()> {
+    let path = test_dir("release-aliases");
+
+    let source = TestSource::sample()
+        .with_release("mac-os-x", "10.6", &[("hello", "1.0")])
+        .with_release("os-x", "10.10", &[("hello", "1.0")])
+        // Releases published under the canonical name are preferred.
+        .with_release("macos", "10.10", &[("hello", "1.0"), ("world", "1.0")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    create_release_repository(&downloader, &path, "mac-os-x", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    let messages = walk
+        .map(|oid| Ok(repo.find_commit(oid?)?.summary().unwrap().to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(messages, vec!["macos 11.0", "macos 10.10", "macos 10.6"]);
+    assert_eq!(
+        tag_files(&repo, "10.10")?,
+        vec![RELEASE_MANIFEST_FILENAME, "hello/hello.c", "world/world.c"]
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn commit_dates() -> Result<()> {
+    let path = test_dir("commit-dates");
+
+    create_component_repository(&downloader()?, &path, "hello", &ImportOptions::default()).await?;
+    let repo = Repository::open(&path)?;
+    let commit = find_tag_commit(&repo, "1.0")?.unwrap();
+    assert_eq!(commit.author().when().seconds(), DEFAULT_TIMESTAMP);
+    assert_eq!(commit.committer().name(), Some("Apple Open Source"));
+    std::fs::remove_dir_all(&path)?;
+
+    // An override dates every commit.
+    let date = NaiveDate::from_ymd_opt(2022, 10, 24).unwrap();
+    let options = ImportOptions {
+        identity: CommitIdentity::with_date(date),
+        ..ImportOptions::default()
+    };
+    create_release_repository(&downloader()?, &path, "macos", &options).await?;
+    let repo = Repository::open(&path)?;
+    let commit = find_tag_commit(&repo, "11.0")?.unwrap();
+    assert_eq!(commit.author().when().seconds(), 1666569600);
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn source_trees_are_imported() -> Result<()> {
+    let path = test_dir("source-trees");
+
+    let source = TestSource::sample().with_source_tree(
+        "hello",
+        "3.0",
+        &[("hello.c", b"v3\n"), ("include/sys/hello.h", b"header\n")],
+    );
+    let downloader = Downloader::builder()?
+        .source(Arc::new(source))
+        .source_trees(true)
+        .build();
+
+    let versions = downloader.get_component_versions("hello").await?;
+    assert_eq!(
+        versions
+            .iter()
+            .map(|r| (r.version.as_str(), r.is_source_tree()))
+            .collect::<Vec<_>>(),
+        vec![("1.0", false), ("2.0", false), ("3.0", true)]
+    );
+    // Components without source trees are unaffected.
+    assert_eq!(downloader.get_component_versions("world").await?.len(), 1);
+
+    create_component_repository(&downloader, &path, "hello", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        tag_files(&repo, "3.0")?,
+        vec!["hello.c", "include/sys/hello.h"]
+    );
+    assert!(find_tag_commit(&repo, "3.0")?
+        .unwrap()
+        .message()
+        .unwrap()
+        .contains("https://opensource.apple.com/source/hello/hello-3.0/"));
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_pages_are_committed() -> Result<()> {
+    let path = test_dir("release-notes");
+
+    let source =
+        TestSource::sample().with_release_html("macos", "11.0", "<p>Read <b>this</b>.</p>");
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    create_release_repository(&downloader, &path, "macos", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        tag_files(&repo, "11.0")?,
+        vec![
+            RELEASE_MANIFEST_FILENAME,
+            RELEASE_HTML_FILENAME,
+            RELEASE_NOTES_FILENAME,
+            "hello/README",
+            "hello/hello.c",
+            "world/world.c"
+        ]
+    );
+
+    let tree = find_tag_commit(&repo, "11.0")?.unwrap().tree()?;
+    let notes = tree
+        .get_name(RELEASE_NOTES_FILENAME)
+        .unwrap()
+        .to_object(&repo)?;
+    assert!(std::str::from_utf8(notes.as_blob().unwrap().content())?.ends_with("\nRead this.\n"));
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_updates_are_imported() -> Result<()> {
+    let path = test_dir("release-updates");
+
+    let source =
+        TestSource::sample().with_release_update("macos", "11.0", "11.0.1", &[("hello", "2.0")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    assert_eq!(downloader.get_releases().await?.len(), 1);
+    assert_eq!(
+        downloader
+            .get_releases_with_updates()
+            .await?
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect::<Vec<_>>(),
+        vec!["11.0", "11.0.1"]
+    );
+
+    let options = ImportOptions {
+        include_updates: true,
+        ..ImportOptions::default()
+    };
+    create_release_repository(&downloader, &path, "macos", &options).await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        tag_files(&repo, "11.0.1")?,
+        vec![RELEASE_MANIFEST_FILENAME, "hello/README", "hello/hello.c"]
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_content_is_not_found() -> Result<()> {
+    let downloader = downloader()?;
+
+    assert!(downloader
+        .get_artifact("https://opensource.apple.com/tarballs/missing/missing-1.0.tar.gz")
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn locked_destinations_are_refused() -> Result<()> {
+    let path = test_dir("locked");
+
+    let lock = ImportLock::acquire(&path)?;
+
+    assert!(
+        create_component_repository(&downloader()?, &path, "hello", &ImportOptions::default())
+            .await
+            .is_err()
+    );
+
+    drop(lock);
+    assert!(!lock_path(&path).exists());
+
+    create_component_repository(&downloader()?, &path, "hello", &ImportOptions::default()).await?;
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[test]
+fn stale_locks_are_ignored() -> Result<()> {
+    let path = test_dir("stale-lock");
+
+    // A lock file from a process that no longer holds the lock.
+    std::fs::write(lock_path(&path), "999999\n")?;
+
+    let lock = ImportLock::acquire(&path)?;
+    assert_eq!(
+        std::fs::read_to_string(lock_path(&path))?.trim(),
+        std::process::id().to_string()
+    );
+    drop(lock);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn components_without_versions_are_reported() -> Result<()> {
+    let path = test_dir("no-versions");
+    let source = TestSource::sample().with_component("empty");
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let versions = downloader.get_components_versions().await?;
+    assert_eq!(
+        versions.keys().collect::<Vec<_>>(),
+        vec!["empty", "hello", "world"]
+    );
+    assert!(versions["empty"].is_empty());
+
+    let err = create_component_repository(&downloader, &path, "empty", &ImportOptions::default())
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<NoVersionsError>().is_some());
+    assert!(!path.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn repositories_are_described() -> Result<()> {
+    let path = test_dir("described");
+    let options = ImportOptions {
+        bare: true,
+        clone_url: Some("https://git.example.com/{name}.git".to_string()),
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+
+    let metadata = RepositoryMetadata::read(&path)?;
+    assert_eq!(metadata.kind, RepositoryKind::Component);
+    assert_eq!(metadata.name, "hello");
+    assert_eq!(metadata.versions, vec!["1.0", "2.0"]);
+    assert_eq!(
+        std::fs::read_to_string(path.join("description"))?,
+        "Apple open source component hello\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(path.join("cloneurl"))?,
+        "https://git.example.com/hello.git\n"
+    );
+
+    // Descriptions edited by administrators are kept.
+    std::fs::write(path.join("description"), "Hello, world\n")?;
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+    assert_eq!(
+        std::fs::read_to_string(path.join("description"))?,
+        "Hello, world\n"
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn provenance_is_configured() -> Result<()> {
+    let path = test_dir("provenance");
+
+    create_component_repository(&downloader()?, &path, "hello", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        Provenance::read(&repo)?,
+        Some(Provenance {
+            source: "test".to_string(),
+            kind: RepositoryKind::Component,
+            name: "hello".to_string(),
+            last_version: Some("2.0".to_string()),
+            importer_version: env!("CARGO_PKG_VERSION").to_string(),
+        })
+    );
+    assert_eq!(repo.config()?.get_string("apple-oss.lastVersion")?, "2.0");
+
+    let release_path = test_dir("provenance-release");
+    create_release_repository(
+        &downloader()?,
+        &release_path,
+        "macos",
+        &ImportOptions::default(),
+    )
+    .await?;
+
+    let provenance =
+        Provenance::read(&Repository::open(&release_path)?)?.expect("provenance recorded");
+    assert_eq!(provenance.kind, RepositoryKind::Release);
+    assert_eq!(provenance.name, "macos");
+    assert_eq!(provenance.last_version.as_deref(), Some("11.0"));
+
+    std::fs::remove_dir_all(&path)?;
+    std::fs::remove_dir_all(&release_path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn importer_trailers() -> Result<()> {
+    let path = test_dir("importer-trailers");
+    let options = ImportOptions {
+        importer_trailers: true,
+        ..ImportOptions::default()
+    };
+    let trailers = options.trailers().expect("trailers enabled");
+    assert!(trailers.starts_with(&format!(
+        "Importer-Version: {}\nImporter-Config: ",
+        env!("CARGO_PKG_VERSION")
+    )));
+
+    // The hash changes with options affecting content.
+    let dated = ImportOptions {
+        identity: CommitIdentity::with_date(NaiveDate::from_ymd_opt(2022, 10, 24).unwrap()),
+        ..options.clone()
+    };
+    assert_ne!(dated.trailers(), Some(trailers.clone()));
+    assert_eq!(ImportOptions::default().trailers(), None);
+
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+    let repo = Repository::open(&path)?;
+    let commit = find_tag_commit(&repo, "1.0")?.unwrap();
+    assert!(commit
+        .message()
+        .unwrap()
+        .ends_with(&format!("\n\n{}", trailers)));
+    std::fs::remove_dir_all(&path)?;
+
+    create_release_repository(&downloader()?, &path, "macos", &options).await?;
+    let repo = Repository::open(&path)?;
+    let commit = find_tag_commit(&repo, "11.0")?.unwrap();
+    assert_eq!(
+        commit.message().unwrap(),
+        format!("macos 11.0\n\n{}", trailers)
+    );
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+fn main_head(repo: &Repository) -> Result<git2::Oid> {
+    Ok(repo
+        .find_branch("main", git2::BranchType::Local)?
+        .get()
+        .peel_to_commit()?
+        .id())
+}
+
+#[tokio::test]
+async fn reimports_compare_histories() -> Result<()> {
+    let path = test_dir("reimport");
+    let options = ImportOptions::default();
+
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+    let repo = Repository::open(&path)?;
+    let head = main_head(&repo)?;
+
+    assert_eq!(
+        reimport_component_repository(&downloader()?, &path, true, &options).await?,
+        ReimportOutcome::Identical
+    );
+
+    // New versions are fast-forwarded to.
+    let source =
+        TestSource::sample().with_component_version("hello", "3.0", &[("hello.c", b"v3\n")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+    assert_eq!(
+        reimport_component_repository(&downloader, &path, false, &options).await?,
+        ReimportOutcome::FastForward {
+            added: vec!["3.0".to_string()]
+        }
+    );
+    assert_eq!(main_head(&repo)?, head);
+    assert!(find_tag_commit(&repo, "3.0")?.is_none());
+
+    reimport_component_repository(&downloader, &path, true, &options).await?;
+    let commit = find_tag_commit(&repo, "3.0")?.unwrap();
+    assert_eq!(main_head(&repo)?, commit.id());
+    assert_eq!(commit.parent_id(0)?, head);
+    let head = commit.id();
+
+    // Differently committed versions are preserved on a new branch.
+    let dated = ImportOptions {
+        identity: CommitIdentity::with_date(NaiveDate::from_ymd_opt(2022, 10, 24).unwrap()),
+        ..ImportOptions::default()
+    };
+    assert_eq!(
+        reimport_component_repository(&downloader, &path, true, &dated).await?,
+        ReimportOutcome::Diverged {
+            changed: vec!["1.0".to_string(), "2.0".to_string(), "3.0".to_string()],
+            branch: Some("main-v2".to_string()),
+        }
+    );
+    assert_eq!(main_head(&repo)?, head);
+    assert_eq!(
+        find_tag_commit(&repo, "main-v2/3.0")?.unwrap().id(),
+        repo.find_branch("main-v2", git2::BranchType::Local)?
+            .get()
+            .peel_to_commit()?
+            .id()
+    );
+    assert!(repo
+        .references_glob("refs/apple-oss/reimport/*")?
+        .next()
+        .is_none());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn archives_are_stored_in_refs() -> Result<()> {
+    let downloader = downloader()?;
+    let path = test_dir("archive-refs");
+    let options = ImportOptions {
+        archive_refs: true,
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader, &path, "hello", &options).await?;
+
+    let repo = Repository::open(&path)?;
+    let mut names = repo
+        .references_glob(&format!("{}*", ARCHIVE_REFS_PREFIX))?
+        .map(|reference| Ok(reference?.name().unwrap().to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            format!("{}1.0", ARCHIVE_REFS_PREFIX),
+            format!("{}2.0", ARCHIVE_REFS_PREFIX)
+        ]
+    );
+
+    for record in downloader.get_component_versions("hello").await? {
+        assert_eq!(
+            stored_archive(&path, &record.version)?,
+            Some(downloader.get_component_record(&record).await?),
+            "{}",
+            record.version
+        );
+    }
+    assert_eq!(stored_archive(&path, "3.0")?, None);
+
+    std::fs::remove_dir_all(&path)?;
+
+    // Archives aren't stored by default.
+    create_component_repository(&downloader, &path, "hello", &ImportOptions::default()).await?;
+    assert_eq!(stored_archive(&path, "1.0")?, None);
+    assert!(Repository::open(&path)?
+        .references_glob(&format!("{}*", ARCHIVE_REFS_PREFIX))?
+        .next()
+        .is_none());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reimports_use_stored_archives() -> Result<()> {
+    let path = test_dir("reimport-archives");
+    let options = ImportOptions {
+        archive_refs: true,
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+
+    // The published archive of a version changed since it was imported.
+    let source = TestSource::new()
+        .with_component_version("hello", "1.0", &[("hello.c", b"changed\n")])
+        .with_component_version(
+            "hello",
+            "2.0",
+            &[("hello.c", b"v2\n"), ("README", b"hello\n")],
+        );
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    assert_eq!(
+        reimport_component_repository(&downloader, &path, false, &options).await?,
+        ReimportOutcome::Identical
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn repositories_are_compared() -> Result<()> {
+    let a = test_dir("compare-a");
+    let b = test_dir("compare-b");
+
+    create_component_repository(&downloader()?, &a, "hello", &ImportOptions::default()).await?;
+
+    // Commit metadata doesn't matter, only content.
+    let source =
+        TestSource::sample().with_component_version("hello", "3.0", &[("hello.c", b"v3\n")]);
+    let options = ImportOptions {
+        identity: CommitIdentity::with_date(NaiveDate::from_ymd_opt(2022, 10, 24).unwrap()),
+        ..ImportOptions::default()
+    };
+    create_component_repository(
+        &Downloader::builder()?.source(Arc::new(source)).build(),
+        &b,
+        "hello",
+        &options,
+    )
+    .await?;
+
+    let statuses = |a, b| -> Result<Vec<(String, TagStatus)>> {
+        Ok(compare_repositories(a, b)?
+            .into_iter()
+            .map(|c| (c.tag.clone(), c.status()))
+            .collect())
+    };
+
+    assert_eq!(
+        statuses(&a, &b)?,
+        vec![
+            ("1.0".to_string(), TagStatus::Match),
+            ("2.0".to_string(), TagStatus::Match),
+            ("3.0".to_string(), TagStatus::OnlyB),
+        ]
+    );
+
+    std::fs::remove_dir_all(&b)?;
+    let options = ImportOptions {
+        converter: TreeConverter::default().with_options(TreeOptions {
+            strip_components: 0,
+            ..TreeOptions::default()
+        }),
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader()?, &b, "hello", &options).await?;
+    assert_eq!(
+        statuses(&b, &a)?,
+        vec![
+            ("1.0".to_string(), TagStatus::Differ),
+            ("2.0".to_string(), TagStatus::Differ),
+        ]
+    );
+
+    std::fs::remove_dir_all(&a)?;
+    std::fs::remove_dir_all(&b)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn repositories_are_optimized() -> Result<()> {
+    let path = test_dir("optimized");
+    let options = ImportOptions {
+        bare: true,
+        optimize: true,
+        partial_clone: true,
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+
+    assert!(path.join("objects/info/commit-graph").exists());
+    assert!(path.join("objects/pack/multi-pack-index").exists());
+
+    let repo = Repository::open(&path)?;
+    let config = repo.config()?.snapshot()?;
+    assert!(config.get_bool("uploadpack.allowFilter")?);
+    assert!(config.get_bool("uploadpack.allowAnySHA1InWant")?);
+
+    // Packed history is still intact.
+    assert_eq!(tag_files(&repo, "2.0")?, vec!["README", "hello.c"]);
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn components_are_split_by_top_level_directory() -> Result<()> {
+    let dest = test_dir("split");
+
+    let source = TestSource::new()
+        .with_component_version(
+            "big",
+            "1.0",
+            &[
+                ("README", b"v1\n"),
+                ("lib/a.c", b"a\n"),
+                ("tools/t.c", b"t\n"),
+            ],
+        )
+        .with_component_version(
+            "big",
+            "2.0",
+            &[
+                ("README", b"v2\n"),
+                ("lib/a.c", b"a\n"),
+                ("docs/index.md", b"docs\n"),
+            ],
+        );
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let paths =
+        create_split_component_repositories(&downloader, &dest, "big", &ImportOptions::default())
+            .await?;
+    let names = paths
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![ROOT_REPOSITORY, "docs", "lib", "tools"]);
+
+    // Every repository has every version.
+    for path in &paths {
+        let tags = tag_commits(path)?;
+        assert_eq!(tags.keys().collect::<Vec<_>>(), vec!["1.0", "2.0"]);
+    }
+
+    let repo = Repository::open(dest.join("lib"))?;
+    assert_eq!(tag_files(&repo, "2.0")?, vec!["a.c"]);
+    let repo = Repository::open(dest.join("tools"))?;
+    assert_eq!(tag_files(&repo, "1.0")?, vec!["t.c"]);
+    assert!(tag_files(&repo, "2.0")?.is_empty());
+    let repo = Repository::open(dest.join(ROOT_REPOSITORY))?;
+    assert_eq!(tag_files(&repo, "1.0")?, vec!["README"]);
+    let commit = find_tag_commit(&repo, "2.0")?.unwrap();
+    assert_eq!(commit.author().when().seconds(), DEFAULT_TIMESTAMP);
+    assert!(commit.message().unwrap().starts_with("big 2.0\n"));
+
+    assert!(!dest.join(".apple-oss-full").exists());
+
+    std::fs::remove_dir_all(&dest)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn content_reports() -> Result<()> {
+    let dest = test_dir("content-report");
+    std::fs::create_dir_all(&dest)?;
+    let report = dest.join("report.ndjson");
+    let repo_path = dest.join("mixed");
+
+    let large = vec![b'x'; LARGE_FILE_THRESHOLD as usize];
+    let source = TestSource::new()
+        .with_component_version(
+            "mixed",
+            "1.0",
+            &[("README", b"text\n"), ("icon.png", b"\x89PNG\0\0")],
+        )
+        .with_component_version(
+            "mixed",
+            "2.0",
+            &[("README", b"text\n"), ("data/large.txt", &large)],
+        );
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        content_report: Some(Arc::new(InventoryLog::open(&report)?)),
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader, &repo_path, "mixed", &options).await?;
+
+    let records = InventoryLog::read(&report)?;
+    assert_eq!(records.len(), 2);
+
+    assert_eq!(records[0].version.as_deref(), Some("1.0"));
+    assert_eq!((records[0].text_files, records[0].binary_files), (1, 1));
+    assert_eq!(records[0].binary_bytes, 6);
+    assert_eq!(
+        records[0].files,
+        vec![ReportedFile {
+            path: "icon.png".to_string(),
+            size: 6,
+            kind: FileKind::Binary,
+        }]
+    );
+
+    assert_eq!(records[1].version.as_deref(), Some("2.0"));
+    assert_eq!((records[1].text_files, records[1].binary_files), (2, 0));
+    assert_eq!(records[1].sizes[&SizeBucket::Small], 1);
+    assert_eq!(records[1].sizes[&SizeBucket::Large], 1);
+    assert_eq!(records[1].files[0].path, "data/large.txt");
+
+    std::fs::remove_dir_all(&dest)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn denylists_exclude_content() -> Result<()> {
+    let dest = test_dir("denylist");
+    std::fs::create_dir_all(&dest)?;
+    let audit = dest.join("audit.ndjson");
+    let release_path = dest.join("release");
+
+    let denylist = Denylist::from_json(
+        br#"{"rules": [
+            {"component": "world", "reason": "export control"},
+            {"component": "hel*", "path": "README", "reason": "license"}
+        ]}"#,
+    )?;
+    let options = ImportOptions {
+        denylist: Some(Arc::new(denylist)),
+        deny_audit: Some(Arc::new(AuditLog::open(&audit)?)),
+        ..ImportOptions::default()
+    };
+
+    create_release_repository(&downloader()?, &release_path, "macos", &options).await?;
+    let repo = Repository::open(&release_path)?;
+    assert_eq!(
+        tag_files(&repo, "11.0")?,
+        vec![RELEASE_MANIFEST_FILENAME, "hello/hello.c"]
+    );
+    // Excluded content is never written.
+    let readme = git2::Oid::hash_object(git2::ObjectType::Blob, b"hello\n")?;
+    assert!(repo.find_blob(readme).is_err());
+
+    let err = create_component_repository(&downloader()?, &dest.join("world"), "world", &options)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("excluded by the denylist"));
+
+    assert_eq!(
+        AuditLog::read(&audit)?,
+        vec![
+            AuditRecord {
+                component: "world".to_string(),
+                version: Some("1.0".to_string()),
+                path: None,
+                reason: "export control".to_string(),
+            },
+            AuditRecord {
+                component: "hello".to_string(),
+                version: Some("2.0".to_string()),
+                path: Some("README".to_string()),
+                reason: "license".to_string(),
+            },
+            AuditRecord {
+                component: "world".to_string(),
+                version: None,
+                path: None,
+                reason: "export control".to_string(),
+            },
+        ]
+    );
+
+    assert!(Denylist::from_json(br#"{"rules": [{"reason": "none"}]}"#).is_err());
+
+    std::fs::remove_dir_all(&dest)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn entities_share_release_repositories() -> Result<()> {
+    let path = test_dir("multi-release");
+
+    let source = TestSource::sample()
+        .with_release("macos", "12.0", &[("hello", "2.0")])
+        .with_release("ios", "11.0", &[("hello", "1.0"), ("world", "1.0")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    create_multi_release_repository(
+        &downloader,
+        &path,
+        &["macos".to_string(), "ios".to_string()],
+        &ImportOptions::default(),
+    )
+    .await?;
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(
+        tag_commits(&path)?.keys().collect::<Vec<_>>(),
+        vec!["ios/11.0", "macos/11.0", "macos/12.0"]
+    );
+    assert_eq!(
+        tag_files(&repo, "ios/11.0")?,
+        vec![RELEASE_MANIFEST_FILENAME, "hello/hello.c", "world/world.c"]
+    );
+
+    // Each entity has its own branch and the first is checked out.
+    let ios = repo
+        .find_branch("ios", git2::BranchType::Local)?
+        .get()
+        .peel_to_commit()?;
+    assert_eq!(ios.message().unwrap(), "ios 11.0");
+    assert_eq!(ios.parent_count(), 0);
+    assert_eq!(repo.head()?.shorthand(), Some("macos"));
+    let macos = repo.head()?.peel_to_commit()?;
+    assert_eq!(macos.parent(0)?.message().unwrap(), "macos 11.0");
+
+    // Both histories share the tree of world 1.0.
+    let world = |tag: &str| -> Result<git2::Oid> {
+        Ok(find_tag_commit(&repo, tag)?
+            .unwrap()
+            .tree()?
+            .get_name("world")
+            .unwrap()
+            .id())
+    };
+    assert_eq!(world("ios/11.0")?, world("macos/11.0")?);
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[test]
+fn versions_files_are_parsed() -> Result<()> {
+    assert_eq!(
+        parse_versions_file("# ordered\n2.0\n\n1.0  https://example.com/hello-1.0.tar.gz\n")?,
+        vec![
+            VersionEntry {
+                version: "2.0".to_string(),
+                url: None,
+            },
+            VersionEntry {
+                version: "1.0".to_string(),
+                url: Some("https://example.com/hello-1.0.tar.gz".to_string()),
+            },
+        ]
+    );
+    assert!(parse_versions_file("1.0\n1.0\n").is_err());
+    assert!(parse_versions_file("1.0 a b\n").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn versions_files_control_history() -> Result<()> {
+    let path = test_dir("versions-file");
+    let url = "https://example.com/hello-0.9.tar.gz";
+
+    let source =
+        TestSource::sample().with_content(url, tarball("hello-0.9", &[("hello.c", b"v0\n")]));
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        versions: Some(parse_versions_file(&format!("0.9 {}\n2.0\n1.0\n", url))?),
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader, &path, "hello", &options).await?;
+
+    let repo = Repository::open(&path)?;
+    let mut walk = repo.revwalk()?;
+    walk.push(main_head(&repo)?)?;
+    let messages = walk
+        .map(|oid| Ok(repo.find_commit(oid?)?.summary().unwrap().to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(messages, vec!["hello 1.0", "hello 2.0", "hello 0.9"]);
+    assert_eq!(tag_files(&repo, "0.9")?, vec!["hello.c"]);
+
+    let unknown = ImportOptions {
+        versions: Some(parse_versions_file("3.0\n")?),
+        force: true,
+        ..ImportOptions::default()
+    };
+    assert!(create_component_repository(
+        &downloader,
+        &test_dir("versions-file-unknown"),
+        "hello",
+        &unknown
+    )
+    .await
+    .is_err());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn version_orders_control_history() -> Result<()> {
+    let path = test_dir("version-orders");
+
+    let config = serde_json::from_str::<Config>(
+        r#"{"version_orders": {"hello": ["2.0", "1.0"], "world": {"schemes": ["^[0-9]{4}", "^[0-9]"]}}}"#,
+    )?;
+    let orders = config.version_orders()?;
+    assert_eq!(orders.compare("world", "2021.1", "3.0"), Ordering::Less);
+    assert_eq!(orders.compare("world", "3.0", "10.0"), Ordering::Less);
+    assert_eq!(orders.compare("world", "3.0", "beta"), Ordering::Less);
+    assert_eq!(orders.compare("other", "10.0", "3.0"), Ordering::Greater);
+
+    let source =
+        TestSource::sample().with_component_version("hello", "10.0", &[("hello.c", b"v10\n")]);
+    let downloader = Downloader::builder()?
+        .version_orders(orders)
+        .source(Arc::new(source))
+        .build();
+
+    create_component_repository(&downloader, &path, "hello", &ImportOptions::default()).await?;
+
+    let repo = Repository::open(&path)?;
+    let mut walk = repo.revwalk()?;
+    walk.push(main_head(&repo)?)?;
+    let messages = walk
+        .map(|oid| Ok(repo.find_commit(oid?)?.summary().unwrap().to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(messages, vec!["hello 10.0", "hello 1.0", "hello 2.0"]);
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn repo_status_reports_missing_versions() -> Result<()> {
+    let downloader = downloader()?;
+    let path = test_dir("repo-status");
+
+    let status = repo_status(&downloader, &path, "hello").await?;
+    assert_eq!(status.missing_versions, vec!["1.0", "2.0"]);
+    assert_eq!(status.behind_by, 2);
+
+    let import = |versions: &str| -> Result<ImportOptions> {
+        Ok(ImportOptions {
+            versions: Some(parse_versions_file(versions)?),
+            force: true,
+            ..ImportOptions::default()
+        })
+    };
+
+    create_component_repository(&downloader, &path, "hello", &import("1.0\n")?).await?;
+    let status = repo_status(&downloader, &path, "hello").await?;
+    assert_eq!(
+        status,
+        RepoStatus {
+            component: "hello".to_string(),
+            imported_versions: vec!["1.0".to_string()],
+            missing_versions: vec!["2.0".to_string()],
+            behind_by: 1,
+        }
+    );
+    assert!(!status.is_up_to_date());
+
+    // Versions older than the newest imported version are gaps.
+    std::fs::remove_dir_all(&path)?;
+    create_component_repository(&downloader, &path, "hello", &import("2.0\n")?).await?;
+    let status = repo_status(&downloader, &path, "hello").await?;
+    assert_eq!(status.missing_versions, vec!["1.0"]);
+    assert_eq!(status.behind_by, 0);
+
+    create_component_repository(&downloader, &path, "hello", &ImportOptions::default()).await?;
+    assert!(repo_status(&downloader, &path, "hello")
+        .await?
+        .is_up_to_date());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn memory_budget_limits_buffered_archives() -> Result<()> {
+    let budget = MemoryBudget::new(1024 * 1024);
+
+    let reservation = budget.reserve(Some(600 * 1024)).await;
+    assert_eq!(budget.available(), 424 * 1024);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), budget.reserve(Some(600 * 1024)))
+            .await
+            .is_err()
+    );
+    assert!(budget.try_reserve(Some(600 * 1024)).is_none());
+    assert!(budget.try_reserve(Some(400 * 1024)).is_some());
+    drop(reservation);
+
+    // Archives larger than the budget are processed alone.
+    let large = budget.reserve(Some(1 << 40)).await;
+    assert_eq!(budget.available(), 0);
+    drop(large);
+    assert_eq!(budget.available(), budget.bytes());
+
+    // Imports complete when archives don't fit the budget together.
+    let downloader = downloader()?;
+    let options = ImportOptions {
+        memory_budget: Some(MemoryBudget::new(1)),
+        prefetch: Some(4),
+        ..ImportOptions::default()
+    };
+
+    let path = test_dir("memory-budget-components");
+    create_components_repositories(&downloader, &path, &options).await?;
+    assert_eq!(tag_commits(&path.join("hello"))?.len(), 2);
+    std::fs::remove_dir_all(&path)?;
+
+    let path = test_dir("memory-budget-release");
+    create_release_repository(&downloader, &path, "macos", &options).await?;
+    assert_eq!(tag_commits(&path)?.len(), 1);
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn command_hooks_receive_events() -> Result<()> {
+    let path = test_dir("command-hooks");
+    let events_path = test_dir("command-hooks-events");
+
+    let mut hooks = Hooks::default();
+    hooks.push(CommandHook::new(format!(
+        "cat >> {} && echo >> {}",
+        events_path.display(),
+        events_path.display()
+    )));
+    hooks.push(CommandHook::new("exit 1"));
+    let options = ImportOptions {
+        hooks,
+        ..ImportOptions::default()
+    };
+
+    create_component_repository(&downloader()?, &path, "hello", &options).await?;
+
+    let events = std::fs::read_to_string(&events_path)?
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["commit", "tag", "commit", "tag", "repository-complete"]
+    );
+    assert_eq!(events[0]["version"], "1.0");
+
+    std::fs::remove_dir_all(&path)?;
+    std::fs::remove_file(&events_path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn command_hooks_exiting_early_are_reported() -> Result<()> {
+    // The event is larger than a pipe buffer, so writing it fails once the
+    // hook closes stdin.
+    let event = ImportEvent::RepositoryComplete {
+        repository: PathBuf::from("x".repeat(1024 * 1024)),
+        branch: "main".to_string(),
+        head: None,
+    };
+
+    let err = CommandHook::new("exec 0<&-; exit 3")
+        .on_event(&event)
+        .await
+        .unwrap_err();
+    let message = format!("{:#}", err);
+    assert!(message.contains("writing event to hook"), "{}", message);
+    assert!(
+        message.contains("exited with exit status: 3"),
+        "{}",
+        message
+    );
+
+    Ok(())
+}
+
+/// Cancels a handle once a commit is created.
+struct CancelOnCommit(CancelHandle);
+
+#[async_trait]
+impl ImportHook for CancelOnCommit {
+    async fn on_event(&self, event: &ImportEvent) -> Result<()> {
+        if matches!(event, ImportEvent::Commit { .. }) {
+            self.0.cancel();
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn shutdown_keeps_committed_versions() -> Result<()> {
+    let downloader = downloader()?;
+    let path = test_dir("shutdown");
+
+    let shutdown = CancelHandle::default();
+    let mut hooks = Hooks::default();
+    hooks.push(CancelOnCommit(shutdown.clone()));
+    let options = ImportOptions {
+        hooks,
+        shutdown,
+        ..ImportOptions::default()
+    };
+
+    let err = create_component_repository(&downloader, &path, "hello", &options)
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<InterruptedError>().is_some());
+    assert_eq!(exit_code(&Err(err)), EXIT_INTERRUPTED);
+
+    let repo = Repository::open(&path)?;
+    assert_eq!(tag_commits(&path)?.keys().collect::<Vec<_>>(), vec!["1.0"]);
+    assert_eq!(
+        Some(main_head(&repo)?),
+        find_tag_commit(&repo, "1.0")?.map(|c| c.id())
+    );
+
+    // Importing again resumes.
+    let options = ImportOptions {
+        resume: true,
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader, &path, "hello", &options).await?;
+    assert_eq!(
+        tag_commits(&path)?.keys().collect::<Vec<_>>(),
+        vec!["1.0", "2.0"]
+    );
+
+    // Nothing is started once shut down.
+    let shutdown = CancelHandle::default();
+    shutdown.cancel();
+    let options = ImportOptions {
+        shutdown,
+        ..ImportOptions::default()
+    };
+    let err = create_components_repositories(&downloader, &test_dir("shutdown-all"), &options)
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<InterruptedError>().is_some());
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_refs_point_at_shipped_versions() -> Result<()> {
+    let path = test_dir("release-refs");
+
+    let source =
+        TestSource::sample().with_release("macos", "12.0", &[("hello", "1.0"), ("world", "1.0")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        release_refs: true,
+        ..ImportOptions::default()
+    };
+    create_component_repository(&downloader, &path, "hello", &options).await?;
+
+    let repo = Repository::open(&path)?;
+    let release_commit = |release: &str| -> Result<Oid> {
+        Ok(repo
+            .find_reference(&format!("{}{}", RELEASE_REFS_PREFIX, release))?
+            .peel_to_commit()?
+            .id())
+    };
+
+    assert_eq!(
+        release_commit("macos-11.0")?,
+        find_tag_commit(&repo, "2.0")?.unwrap().id()
+    );
+    assert_eq!(
+        release_commit("macos-12.0")?,
+        find_tag_commit(&repo, "1.0")?.unwrap().id()
+    );
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn release_reports_summarize_changes() -> Result<()> {
+    let path = test_dir("release-reports");
+    let reports = test_dir("release-reports-md");
+
+    let source = TestSource::sample()
+        .with_component_version("new", "0.1", &[("new.c", b"new\n")])
+        .with_release("macos", "12.0", &[("hello", "1.0"), ("new", "0.1")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        release_reports: Some(reports.clone()),
+        ..ImportOptions::default()
+    };
+    create_release_repository(&downloader, &path, "macos", &options).await?;
+
+    let first = std::fs::read_to_string(reports.join("11.0.md"))?;
+    assert!(first.starts_with("# macos 11.0\n"));
+    assert!(first.contains("2 components: 2 added, 0 updated, 0 unchanged, 0 removed."));
+
+    let second = std::fs::read_to_string(reports.join("12.0.md"))?;
+    assert!(second.contains("2 components: 1 added, 1 updated, 0 unchanged, 1 removed."));
+    assert!(second.contains("- Updated `hello` 2.0 → 1.0\n"));
+    assert!(second.contains("- Added `new` 0.1\n"));
+    assert!(second.contains("- Removed `world` 1.0\n"));
+    assert!(second.contains(&format!(
+        "| hello | [1.0]({}/hello/hello-1.0.tar.gz) | 3 B | updated from 2.0 |",
+        URL_TARBALLS
+    )));
+
+    std::fs::remove_dir_all(&path)?;
+    std::fs::remove_dir_all(&reports)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn component_refs_track_release_components() -> Result<()> {
+    let path = test_dir("component-refs");
+
+    let source =
+        TestSource::sample().with_release("macos", "12.0", &[("hello", "1.0"), ("world", "1.0")]);
+    let downloader = Downloader::builder()?.source(Arc::new(source)).build();
+
+    let options = ImportOptions {
+        component_refs: true,
+        ..ImportOptions::default()
+    };
+    create_release_repository(&downloader, &path, "macos", &options).await?;
+
+    let repo = Repository::open(&path)?;
+    let history = |component: &str| -> Result<Vec<String>> {
+        let mut walk = repo.revwalk()?;
+        walk.push_ref(&format!("{}{}", COMPONENT_REFS_PREFIX, component))?;
+        walk.map(|oid| {
+            let commit = repo.find_commit(oid?)?;
+            let files = commit
+                .tree()?
+                .iter()
+                .map(|e| e.name().unwrap().to_string())
+                .collect::<Vec<_>>();
+            Ok(format!(
+                "{}: {}",
+                commit.message().unwrap(),
+                files.join(",")
+            ))
+        })
+        .collect()
+    };
+
+    assert_eq!(
+        history("hello")?,
+        vec!["macos 12.0: hello.c", "macos 11.0: README,hello.c"]
+    );
+    // Unchanged components aren't committed again.
+    assert_eq!(history("world")?, vec!["macos 11.0: world.c"]);
+
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
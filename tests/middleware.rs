@@ -0,0 +1,741 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for download middleware.
+
+use {
+    anyhow::{anyhow, Result},
+    apple_opensource_downloader::{
+        crawler::{HtmlSource, PageCache, PageChange, UrlSchemes},
+        crypto::{is_encrypted, sha256_hex, EncryptionKey},
+        download::{
+            parse_duration, parse_rate, parse_resolve, parse_size, parse_socks5, ArtifactMetadata,
+            ComponentRecord, Downloader, IpFamily,
+        },
+        error::{DigestMismatchError, HttpStatusError},
+        fetch::{parse_sha256, verify_sha256},
+        middleware::{
+            CacheLayer, CoalesceLayer, Layer, Metrics, MetricsLayer, Request, RetryLayer,
+            SchemeFallbackLayer, Service, DIGEST_INDEX_DIRNAME,
+        },
+        testing::TestSource,
+    },
+    async_trait::async_trait,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    },
+};
+
+/// A service failing a number of times before returning the URL as content.
+struct Flaky {
+    failures: usize,
+    calls: AtomicUsize,
+    status: Option<u16>,
+}
+
+impl Flaky {
+    fn new(failures: usize, status: Option<u16>) -> Arc<Self> {
+        Arc::new(Self {
+            failures,
+            calls: AtomicUsize::new(0),
+            status,
+        })
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Count a request, failing while failures remain.
+    fn attempt(&self, url: &str) -> Result<()> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+            Err(match self.status {
+                Some(status) => HttpStatusError {
+                    status,
+                    url: url.to_string(),
+                }
+                .into(),
+                None => anyhow!("connection reset"),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl Service for Flaky {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        self.attempt(request.url)?;
+
+        Ok(request.url.as_bytes().to_vec())
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.attempt(request.url)?;
+
+        Ok(ArtifactMetadata {
+            size: Some(request.url.len() as u64),
+            last_modified: None,
+        })
+    }
+}
+
+async fn call(service: &Arc<dyn Service>, url: &str) -> Result<Vec<u8>> {
+    let source = HtmlSource::new(reqwest::Client::new());
+
+    service.call(Request::new(&source, url)).await
+}
+
+async fn head(service: &Arc<dyn Service>, url: &str) -> Result<ArtifactMetadata> {
+    let source = HtmlSource::new(reqwest::Client::new());
+
+    service.head(Request::new(&source, url)).await
+}
+
+#[tokio::test]
+async fn retry_recovers() -> Result<()> {
+    let inner = Flaky::new(2, None);
+    let service = RetryLayer::new(3, Duration::ZERO).layer(inner.clone());
+
+    assert_eq!(call(&service, "a").await?, b"a");
+    assert_eq!(inner.calls(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metadata_requests_pass_through_layers() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-cache-head-{}", std::process::id()));
+    let metrics = Arc::new(Metrics::default());
+
+    let inner = Flaky::new(2, None);
+    let service = [
+        Box::new(RetryLayer::new(3, Duration::ZERO)) as Box<dyn Layer>,
+        Box::new(SchemeFallbackLayer::new(UrlSchemes::default())),
+        Box::new(CacheLayer::new(&dir)),
+        Box::new(CoalesceLayer),
+        Box::new(MetricsLayer::new(metrics.clone())),
+    ]
+    .iter()
+    .fold(inner.clone() as Arc<dyn Service>, |service, layer| {
+        layer.layer(service)
+    });
+
+    assert_eq!(head(&service, "abc").await?.size, Some(3));
+    assert_eq!(inner.calls(), 3);
+    assert_eq!(metrics.requests(), 0);
+    assert!(!dir.exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn artifact_metadata_is_requested_from_source() -> Result<()> {
+    let downloader = Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build();
+
+    let record = &downloader.get_component_versions("hello").await?[0];
+    let metadata = downloader.get_artifact_metadata(&record.url).await?;
+    assert_eq!(
+        metadata.size,
+        Some(downloader.get_artifact(&record.url).await?.len() as u64)
+    );
+
+    let err = downloader
+        .get_artifact_metadata("https://opensource.apple.com/tarballs/missing.tar.gz")
+        .await
+        .unwrap_err();
+    assert_eq!(err.downcast_ref::<HttpStatusError>().unwrap().status, 404);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn retry_gives_up() {
+    let inner = Flaky::new(5, None);
+    let service = RetryLayer::new(2, Duration::ZERO).layer(inner.clone());
+
+    assert!(call(&service, "a").await.is_err());
+    assert_eq!(inner.calls(), 3);
+}
+
+#[tokio::test]
+async fn retry_skips_client_errors() {
+    let inner = Flaky::new(1, Some(404));
+    let service = RetryLayer::new(3, Duration::ZERO).layer(inner.clone());
+
+    assert!(call(&service, "a").await.is_err());
+    assert_eq!(inner.calls(), 1);
+}
+
+#[tokio::test]
+async fn metrics_count_requests() -> Result<()> {
+    let metrics = Arc::new(Metrics::default());
+    let inner = Flaky::new(1, None);
+    let service = MetricsLayer::new(metrics.clone()).layer(inner);
+
+    assert!(call(&service, "abc").await.is_err());
+    call(&service, "abc").await?;
+
+    assert_eq!(metrics.requests(), 2);
+    assert_eq!(metrics.failures(), 1);
+    assert_eq!(metrics.bytes(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn metrics_record_download_time() -> Result<()> {
+    let metrics = Arc::new(Metrics::default());
+    let service = MetricsLayer::new(metrics.clone()).layer(Arc::new(Slow::default()));
+
+    futures::future::try_join(call(&service, "a"), call(&service, "b")).await?;
+
+    assert!(metrics.download_time() >= Duration::from_millis(100));
+
+    Ok(())
+}
+
+/// A service taking a while to return the URL as content.
+#[derive(Default)]
+struct Slow {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl Service for Slow {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        Ok(request.url.as_bytes().to_vec())
+    }
+}
+
+#[tokio::test]
+async fn concurrent_requests_are_coalesced() -> Result<()> {
+    let inner = Arc::new(Slow::default());
+    let service = CoalesceLayer.layer(inner.clone());
+
+    let (a, b, c) = tokio::join!(
+        call(&service, "a"),
+        call(&service, "a"),
+        call(&service, "b")
+    );
+    assert_eq!((a?, b?, c?), (b"a".to_vec(), b"a".to_vec(), b"b".to_vec()));
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+
+    // Completed downloads aren't remembered.
+    call(&service, "a").await?;
+    assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+
+    // Waiters download URLs themselves when the shared download fails.
+    let inner = Flaky::new(1, None);
+    let service = CoalesceLayer.layer(inner.clone());
+    let (a, b) = tokio::join!(call(&service, "a"), call(&service, "a"));
+    assert!(a.is_err());
+    assert_eq!(b?, b"a");
+    assert_eq!(inner.calls(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_serves_repeat_requests() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-cache-{}", std::process::id()));
+    let inner = Flaky::new(0, None);
+    let service = CacheLayer::new(&dir).layer(inner.clone());
+
+    assert_eq!(
+        call(&service, "https://example.com/a.tar.gz").await?,
+        b"https://example.com/a.tar.gz"
+    );
+    assert_eq!(
+        call(&service, "https://example.com/a.tar.gz").await?,
+        b"https://example.com/a.tar.gz"
+    );
+    assert_eq!(inner.calls(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_writes_urls_differing_in_extension_concurrently() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-cache-extensions-{}", std::process::id()));
+    let urls = (0..16)
+        .flat_map(|i| {
+            [
+                format!("https://example.com/{}.tar.gz", i),
+                format!("https://example.com/{}.tar.bz2", i),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let service = CacheLayer::new(&dir).layer(Flaky::new(0, None));
+    futures::future::try_join_all(urls.iter().map(|url| call(&service, url))).await?;
+
+    // Every cached entry holds its own content.
+    let service = CacheLayer::new(&dir).layer(Flaky::new(usize::MAX, None));
+    for url in &urls {
+        assert_eq!(call(&service, url).await?, url.as_bytes());
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_encrypts_content() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-cache-encrypted-{}", std::process::id()));
+    let key = EncryptionKey::new(&[7; 32]);
+    let url = "https://example.com/b.tar.gz";
+
+    let inner = Flaky::new(0, None);
+    let service = CacheLayer::new(&dir)
+        .with_encryption(key.clone())
+        .layer(inner.clone());
+
+    assert_eq!(call(&service, url).await?, url.as_bytes());
+    assert_eq!(call(&service, url).await?, url.as_bytes());
+    assert_eq!(inner.calls(), 1);
+
+    let entry = std::fs::read_dir(&dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .find(|path| path.is_file())
+        .unwrap();
+    let stored = std::fs::read(&entry)?;
+    assert!(is_encrypted(&stored));
+    assert_eq!(key.decrypt(&stored)?, url.as_bytes());
+
+    // Encrypted entries can't be read without the key or with another key.
+    let service = CacheLayer::new(&dir).layer(inner.clone());
+    assert!(call(&service, url).await.is_err());
+
+    let service = CacheLayer::new(&dir)
+        .with_encryption(EncryptionKey::new(&[8; 32]))
+        .layer(inner.clone());
+    assert!(call(&service, url).await.is_err());
+    assert_eq!(inner.calls(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cache_indexes_digests() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-cache-digests-{}", std::process::id()));
+    let url = "https://example.com/c.tar.gz";
+    let digest = sha256_hex(url.as_bytes());
+
+    let cache = CacheLayer::new(&dir);
+    assert_eq!(cache.url_for_digest(&digest)?, None);
+    assert_eq!(cache.scan_for_digest(&digest)?, None);
+
+    let service = cache.clone().layer(Flaky::new(0, None));
+    call(&service, url).await?;
+
+    assert_eq!(cache.url_for_digest(&digest)?, Some(url.to_string()));
+    assert_eq!(
+        cache.scan_for_digest(&digest)?,
+        Some(url.as_bytes().to_vec())
+    );
+
+    // Content cached before it was indexed is found by scanning.
+    std::fs::remove_dir_all(dir.join(DIGEST_INDEX_DIRNAME))?;
+    assert_eq!(cache.url_for_digest(&digest)?, None);
+    assert_eq!(
+        cache.scan_for_digest(&digest)?,
+        Some(url.as_bytes().to_vec())
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn digests_are_verified() -> Result<()> {
+    let digest = sha256_hex(b"content");
+
+    assert_eq!(parse_sha256(&digest.to_uppercase())?, digest);
+    assert!(parse_sha256("abc").is_err());
+    assert!(parse_sha256(&"g".repeat(64)).is_err());
+
+    verify_sha256("https://example.com/d", b"content", &digest)?;
+
+    let err = verify_sha256("https://example.com/d", b"other", &digest).unwrap_err();
+    let mismatch = err.downcast_ref::<DigestMismatchError>().unwrap();
+    assert_eq!(mismatch.expected, digest);
+    assert_eq!(mismatch.actual, sha256_hex(b"other"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn index_pages_expire() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-index-cache-{}", std::process::id()));
+    let url = "https://opensource.apple.com/tarballs";
+
+    let cache = PageCache::new(&dir, Duration::from_secs(3600));
+    assert_eq!(
+        cache
+            .get_or_fetch(url, async { Ok("a".to_string()) })
+            .await?,
+        "a"
+    );
+    assert_eq!(
+        cache
+            .get_or_fetch(url, async { Err(anyhow!("not fetched")) })
+            .await?,
+        "a"
+    );
+
+    let cache = PageCache::new(&dir, Duration::ZERO);
+    assert_eq!(cache.get(url).await, None);
+    assert_eq!(
+        cache
+            .get_or_fetch(url, async { Ok("b".to_string()) })
+            .await?,
+        "b"
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn index_pages_differing_in_extension_are_stored_concurrently() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-index-extensions-{}", std::process::id()));
+    let urls = (0..16)
+        .flat_map(|i| {
+            [
+                format!("https://opensource.apple.com/{}.html", i),
+                format!("https://opensource.apple.com/{}.htm", i),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let cache = PageCache::new(&dir, Duration::from_secs(3600));
+    futures::future::try_join_all(urls.iter().map(|url| cache.store(url, url, None))).await?;
+
+    for url in &urls {
+        assert_eq!(cache.get(url).await.as_deref(), Some(url.as_str()));
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn index_page_changes_are_tracked() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-index-changes-{}", std::process::id()));
+    let a = "https://opensource.apple.com/tarballs/a/";
+    let b = "https://opensource.apple.com/tarballs/b/";
+
+    let cache = PageCache::new(&dir, Duration::ZERO);
+    cache.get_or_fetch(a, async { Ok("a".to_string()) }).await?;
+    assert_eq!(cache.store(b, "b", Some("\"1\"")).await?, PageChange::New);
+
+    let cache = PageCache::new(&dir, Duration::ZERO);
+    cache.get_or_fetch(a, async { Ok("a".to_string()) }).await?;
+    cache
+        .get_or_fetch(b, async { Ok("b2".to_string()) })
+        .await?;
+    assert_eq!(
+        cache.changes().into_iter().collect::<Vec<_>>(),
+        vec![
+            (a.to_string(), PageChange::Unchanged),
+            (b.to_string(), PageChange::Changed)
+        ]
+    );
+
+    let (text, validators) = cache.get_stale(b).await.unwrap();
+    assert_eq!(text, "b2");
+    assert_eq!(validators.etag, None);
+    assert_eq!(validators.sha256, Some(sha256_hex(b"b2")));
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn artifact_metadata_is_cached() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-metadata-cache-{}", std::process::id()));
+    // Nothing listens here, so metadata can only come from the cache.
+    let cached_url = "http://127.0.0.1:9/xnu-517.tar.gz";
+    let missing_url = "http://127.0.0.1:9/xnu-1228.tar.gz";
+
+    let cache = PageCache::new(&dir, Duration::from_secs(3600));
+    cache
+        .put(
+            &format!("HEAD {}", cached_url),
+            r#"{"size": 42, "last_modified": "2003-10-24 10:00:00"}"#,
+        )
+        .await?;
+
+    let downloader = Downloader::builder()?.page_cache(Some(cache)).build();
+    let record = |url: &str| ComponentRecord {
+        component: "xnu".to_string(),
+        filename: url.rsplit('/').next().unwrap().to_string(),
+        url: url.to_string(),
+        version: "517".to_string(),
+        size: None,
+        last_modified: None,
+    };
+    let mut records = vec![record(cached_url), record(missing_url)];
+
+    downloader.enrich_component_records(&mut records).await;
+    assert_eq!(records[0].size, Some(42));
+    assert_eq!(
+        records[0].last_modified.map(|t| t.to_string()),
+        Some("2003-10-24 10:00:00".to_string())
+    );
+    // Failed requests leave records alone.
+    assert_eq!(records[1], record(missing_url));
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn durations() -> Result<()> {
+    assert_eq!(parse_duration("90")?, Duration::from_secs(90));
+    assert_eq!(parse_duration("30m")?, Duration::from_secs(1800));
+    assert_eq!(parse_duration("12h")?, Duration::from_secs(43200));
+    assert_eq!(parse_duration("7d")?, Duration::from_secs(604800));
+    assert!(parse_duration("soon").is_err());
+    assert_eq!(parse_duration("0")?, Duration::ZERO);
+    assert_eq!(parse_duration("0d")?, Duration::ZERO);
+    assert!(parse_duration("99999999999999999d").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn sizes_and_rates() -> Result<()> {
+    assert_eq!(parse_size("10GB")?, 10_000_000_000);
+    assert_eq!(parse_size("10GiB")?, 10 * 1024 * 1024 * 1024);
+    assert_eq!(parse_size("10G")?, 10 * 1024 * 1024 * 1024);
+    assert_eq!(parse_size("2kb")?, 2000);
+    assert_eq!(parse_size("1.5k")?, 1536);
+    assert_eq!(parse_size("1.5Ki")?, 1536);
+    assert_eq!(parse_size("3")?, 3);
+    assert_eq!(parse_size("3B")?, 3);
+    assert_eq!(parse_rate("5MB/s")?, 5_000_000);
+    assert_eq!(parse_rate("5MiB/s")?, 5 * 1024 * 1024);
+    assert_eq!(parse_rate("0.5k/s")?, 512);
+
+    for invalid in ["0", "-1", "0.5", "0.0001k", "NaN", "inf", "1e400", "lots"] {
+        assert!(parse_size(invalid).is_err(), "{}", invalid);
+        assert!(parse_rate(invalid).is_err(), "{}", invalid);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn encryption_keys() -> Result<()> {
+    let key = EncryptionKey::from_hex(&"ab".repeat(32))?;
+    assert_eq!(key.decrypt(&key.encrypt(b"data")?)?, b"data");
+
+    let mut tampered = key.encrypt(b"data")?;
+    *tampered.last_mut().unwrap() ^= 1;
+    assert!(key.decrypt(&tampered).is_err());
+
+    assert!(EncryptionKey::from_hex("abcd").is_err());
+    assert!(EncryptionKey::from_hex(&"zz".repeat(32)).is_err());
+
+    Ok(())
+}
+
+/// A service only having content under a URL prefix, recording requests.
+struct Moved {
+    prefix: &'static str,
+    requests: Mutex<Vec<String>>,
+}
+
+impl Moved {
+    /// Record a request, failing unless the URL has the prefix.
+    fn attempt(&self, url: &str) -> Result<()> {
+        self.requests.lock().unwrap().push(url.to_string());
+
+        if url.starts_with(self.prefix) {
+            Ok(())
+        } else {
+            Err(HttpStatusError {
+                status: 404,
+                url: url.to_string(),
+            }
+            .into())
+        }
+    }
+}
+
+#[async_trait]
+impl Service for Moved {
+    async fn call(&self, request: Request<'_>) -> Result<Vec<u8>> {
+        self.attempt(request.url)?;
+
+        Ok(request.url.as_bytes().to_vec())
+    }
+
+    async fn head(&self, request: Request<'_>) -> Result<ArtifactMetadata> {
+        self.attempt(request.url)?;
+
+        Ok(ArtifactMetadata {
+            size: Some(request.url.len() as u64),
+            last_modified: None,
+        })
+    }
+}
+
+#[test]
+fn alternate_artifact_urls() -> Result<()> {
+    let schemes = UrlSchemes::default();
+
+    assert_eq!(
+        schemes.alternate_urls("https://opensource.apple.com/tarballs/xnu/xnu-7195.81.3.tar.gz"),
+        vec![
+            "https://opensource.apple.com/release/tarballs/xnu/xnu-7195.81.3.tar.gz",
+            "https://github.com/apple-oss-distributions/xnu/archive/refs/tags/xnu-7195.81.3.tar.gz",
+        ]
+    );
+    assert_eq!(
+        schemes.alternate_urls(
+            "https://github.com/apple-oss-distributions/dyld/archive/refs/tags/dyld-852.tar.gz"
+        )[0],
+        "https://opensource.apple.com/tarballs/dyld/dyld-852.tar.gz"
+    );
+    assert!(schemes
+        .alternate_urls("https://opensource.apple.com/source/xnu/")
+        .is_empty());
+
+    assert!(UrlSchemes::new(["https://example.com/{filename}"]).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn moved_artifacts_are_found() -> Result<()> {
+    let inner = Arc::new(Moved {
+        prefix: "https://github.com/",
+        requests: Mutex::new(vec![]),
+    });
+    let service = SchemeFallbackLayer::new(UrlSchemes::default()).layer(inner.clone());
+
+    let url = "https://opensource.apple.com/tarballs/xnu/xnu-7195.81.3.tar.gz";
+    assert_eq!(
+        call(&service, url).await?,
+        b"https://github.com/apple-oss-distributions/xnu/archive/refs/tags/xnu-7195.81.3.tar.gz"
+    );
+    assert_eq!(inner.requests.lock().unwrap().len(), 3);
+
+    // The scheme that worked is tried first from now on.
+    inner.requests.lock().unwrap().clear();
+    call(
+        &service,
+        "https://opensource.apple.com/tarballs/dyld/dyld-852.tar.gz",
+    )
+    .await?;
+    assert_eq!(inner.requests.lock().unwrap().len(), 2);
+
+    // Metadata is requested under the same schemes.
+    inner.requests.lock().unwrap().clear();
+    assert_eq!(
+        head(&service, url).await?.size,
+        Some(
+            "https://github.com/apple-oss-distributions/xnu/archive/refs/tags/xnu-7195.81.3.tar.gz"
+                .len() as u64
+        )
+    );
+    assert_eq!(inner.requests.lock().unwrap().len(), 2);
+
+    // Artifacts missing under every scheme fail with the original error.
+    let inner = Arc::new(Moved {
+        prefix: "https://nowhere/",
+        requests: Mutex::new(vec![]),
+    });
+    let service = SchemeFallbackLayer::new(UrlSchemes::default()).layer(inner);
+    let err = call(&service, url).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<HttpStatusError>().unwrap().url, url);
+
+    Ok(())
+}
+
+#[test]
+fn socks5_proxies() -> Result<()> {
+    parse_socks5("127.0.0.1:9050")?;
+    parse_socks5("localhost:1080")?;
+    assert!(parse_socks5("127.0.0.1").is_err());
+    assert!(parse_socks5(":9050").is_err());
+    assert!(parse_socks5("127.0.0.1:tor").is_err());
+
+    Downloader::builder()?
+        .proxy(Some(parse_socks5("127.0.0.1:9050")?))?
+        .build();
+
+    Ok(())
+}
+
+#[test]
+fn dns_overrides() -> Result<()> {
+    let o = parse_resolve("opensource.apple.com:17.253.1.1")?;
+    assert_eq!(o.host, "opensource.apple.com");
+    assert_eq!(o.addr.to_string(), "17.253.1.1");
+    assert_eq!(parse_resolve("mirror:[::1]")?.addr.to_string(), "::1");
+    assert!(parse_resolve("mirror").is_err());
+    assert!(parse_resolve("mirror:example.com").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resolved_hosts_are_connected_to() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await;
+        }
+    });
+
+    let url = format!("http://mirror.invalid:{}/", port);
+    let resolve = vec![parse_resolve("mirror.invalid:127.0.0.1")?];
+
+    let downloader = Downloader::builder()?.resolve(resolve.clone())?.build();
+    let res = downloader.client().get(&url).send().await?;
+    assert_eq!(res.text().await?, "ok");
+
+    // The override is an IPv4 address.
+    let downloader = Downloader::builder()?
+        .resolve(resolve)?
+        .ip_family(IpFamily::V6)?
+        .build();
+    assert!(downloader.client().get(&url).send().await.is_err());
+
+    Ok(())
+}
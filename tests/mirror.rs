@@ -0,0 +1,72 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for configuration of mirrored repositories.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        destination::DestinationTemplate,
+        download::Downloader,
+        git::{create_components_repositories, create_release_repository, ImportOptions},
+        mirror::{cgitrc, find_repositories, gitolite_conf},
+        testing::TestSource,
+    },
+    std::{str::FromStr, sync::Arc},
+};
+
+#[tokio::test]
+async fn mirror_configuration() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-mirror-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let downloader = Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build();
+    let options = ImportOptions {
+        bare: true,
+        destination: DestinationTemplate::from_str("{dest}/{component[0]}/{component}.git")?,
+        clone_url: Some("https://git.example.com/{name}.git".to_string()),
+        ..ImportOptions::default()
+    };
+
+    create_components_repositories(&downloader, &dir.join("components"), &options).await?;
+    create_release_repository(
+        &downloader,
+        &dir.join("releases").join("macos"),
+        "macos",
+        &ImportOptions::default(),
+    )
+    .await?;
+
+    let repos = find_repositories(&dir)?;
+    assert_eq!(
+        repos.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+        vec!["components/h/hello", "components/w/world", "releases/macos"]
+    );
+
+    let cgit = cgitrc(&repos);
+    assert!(cgit.starts_with("section=Components\n\nrepo.url=components/h/hello\n"));
+    assert!(cgit.contains("repo.clone-url=https://git.example.com/hello.git\n"));
+    assert!(cgit.contains("section=macOS\n\nrepo.url=releases/macos\n"));
+    assert!(cgit.contains(&format!(
+        "repo.path={}\n",
+        dir.join("releases").join("macos").join(".git").display()
+    )));
+
+    let gitolite = gitolite_conf(&repos);
+    assert!(gitolite.starts_with(
+        "@apple-components = components/h/hello components/w/world\n\
+         @apple-macos = releases/macos\n\
+         \n\
+         repo @apple-components @apple-macos\n    R = @all\n"
+    ));
+    assert!(gitolite.contains(
+        "repo components/w/world\n    config gitweb.description = \"Apple open source component world\"\n"
+    ));
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for logs of newline-delimited JSON records.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::ndjson::NdjsonLog,
+    serde::{Deserialize, Serialize},
+};
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Record {
+    name: String,
+    count: u32,
+}
+
+fn record(name: &str, count: u32) -> Record {
+    Record {
+        name: name.to_string(),
+        count,
+    }
+}
+
+#[test]
+fn records_are_appended_and_read() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-ndjson-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("log.jsonl");
+
+    NdjsonLog::open(&path)?.record(&record("a", 1))?;
+    NdjsonLog::open(&path)?.record(&record("b", 2))?;
+    assert_eq!(
+        NdjsonLog::<Record>::read(&path)?,
+        vec![record("a", 1), record("b", 2)]
+    );
+
+    std::fs::write(&path, "{\"name\":\"a\",\"count\":1}\n\n{\"name\":\"b\"}\n")?;
+    let err = NdjsonLog::<Record>::read(&path).unwrap_err();
+    assert!(format!("{:#}", err).contains("line 3"));
+
+    assert!(NdjsonLog::<Record>::read(dir.join("missing")).is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for repository paths.
+
+use {apple_opensource_downloader::path::RepoPath, proptest::prelude::*};
+
+#[test]
+fn paths_are_normalized() {
+    assert_eq!(RepoPath::new(b"a/b/c").as_bytes(), b"a/b/c");
+    assert_eq!(RepoPath::new(b"/a//b/c/").as_bytes(), b"a/b/c");
+    assert_eq!(RepoPath::new(b"./a/./b/.").as_bytes(), b"a/b");
+    assert_eq!(RepoPath::new(b"a\xffb/c").as_bytes(), b"a\xffb/c");
+    assert!(RepoPath::new(b"").is_root());
+    assert!(RepoPath::new(b"//").is_root());
+    assert!(RepoPath::new(b"./").is_root());
+}
+
+#[test]
+fn paths_are_split() {
+    let path = RepoPath::from("a/b/c");
+
+    assert_eq!(
+        path.components().collect::<Vec<_>>(),
+        vec![&b"a"[..], b"b", b"c"]
+    );
+    assert_eq!(path.split(), Some((RepoPath::from("a/b"), &b"c"[..])));
+    assert_eq!(path.parent(), Some(RepoPath::from("a/b")));
+    assert_eq!(path.file_name(), Some(&b"c"[..]));
+    assert_eq!(
+        path.ancestors().collect::<Vec<_>>(),
+        vec![RepoPath::from("a/b"), RepoPath::from("a"), RepoPath::root()]
+    );
+
+    assert_eq!(
+        RepoPath::from("a").split(),
+        Some((RepoPath::root(), &b"a"[..]))
+    );
+    assert_eq!(RepoPath::root().split(), None);
+    assert_eq!(RepoPath::root().file_name(), None);
+    assert_eq!(RepoPath::root().ancestors().count(), 0);
+}
+
+#[test]
+fn paths_are_joined() {
+    assert_eq!(RepoPath::from("a").join(b"b/c"), RepoPath::from("a/b/c"));
+    assert_eq!(RepoPath::root().join(b"b"), RepoPath::from("b"));
+    assert_eq!(RepoPath::from("a").join(b"/b/"), RepoPath::from("a/b"));
+}
+
+#[test]
+fn components_are_stripped() {
+    let path = RepoPath::from("top/dir/file");
+
+    assert_eq!(path.strip_components(0), path);
+    assert_eq!(path.strip_components(1), RepoPath::from("dir/file"));
+    assert_eq!(path.strip_components(2), RepoPath::from("file"));
+    assert_eq!(path.strip_components(5), RepoPath::from("file"));
+}
+
+#[test]
+fn invalid_paths_are_rejected() {
+    assert!(RepoPath::from("a/b").validate().is_ok());
+    assert!(RepoPath::from(".gitignore").validate().is_ok());
+    assert!(RepoPath::from("a/../b").validate().is_err());
+    assert!(RepoPath::from("./a").validate().is_ok());
+    assert!(RepoPath::from("a/.git/config").validate().is_err());
+    assert!(RepoPath::from(".GIT").validate().is_err());
+    assert!(RepoPath::new(b"a\0b").validate().is_err());
+}
+
+proptest! {
+    #[test]
+    fn split_and_join_round_trip(path in "[a-z/]{0,16}") {
+        let path = RepoPath::from(path.as_str());
+
+        prop_assert_eq!(RepoPath::new(path.as_bytes()), path.clone());
+
+        if let Some((parent, name)) = path.split() {
+            prop_assert_eq!(parent.join(name), path.clone());
+            prop_assert_eq!(path.components().count(), parent.components().count() + 1);
+        } else {
+            prop_assert!(path.is_root());
+        }
+    }
+}
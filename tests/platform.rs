@@ -0,0 +1,205 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for canonicalization of release entities.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        chronology::{order_releases, verify_order},
+        download::{Downloader, ReleaseRecord},
+        platform::{stitch_aliases, EntityAliases, Platform},
+        testing::TestSource,
+    },
+    std::sync::Arc,
+};
+
+fn record(entity: &str, version: &str, url: &str) -> ReleaseRecord {
+    ReleaseRecord {
+        entity: entity.to_string(),
+        version: version.to_string(),
+        url: url.to_string(),
+        date: None,
+        original_entity: None,
+    }
+}
+
+#[test]
+fn aliases_are_canonicalized() {
+    let records = EntityAliases::default().normalize(vec![
+        record("mac-os-x", "10.6.8", "a"),
+        record("os-x", "10.10", "b"),
+        record("macos", "11.0", "c"),
+        record("something", "1.0", "d"),
+    ]);
+
+    assert_eq!(
+        records
+            .iter()
+            .map(|r| (r.entity.as_str(), r.published_entity()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("macos", "mac-os-x"),
+            ("macos", "os-x"),
+            ("macos", "macos"),
+            ("something", "something"),
+        ]
+    );
+    assert_eq!(records[2].original_entity, None);
+}
+
+#[test]
+fn duplicate_pages_are_listed_once() {
+    let mut dated = record("mac-os-x", "10.6.8", "a");
+    dated.date = chrono::NaiveDate::from_ymd_opt(2011, 6, 23);
+
+    let records = EntityAliases::default().normalize(vec![
+        record("mac-os-x", "10.6.8", "a"),
+        dated,
+        record("mac-os-x", "10.6.8", "b"),
+    ]);
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(
+        records.iter().find(|r| r.url == "a").unwrap().date,
+        chrono::NaiveDate::from_ymd_opt(2011, 6, 23)
+    );
+}
+
+#[test]
+fn aliases_are_configurable() {
+    let aliases = EntityAliases::default().with_alias("os-x", "osx");
+    assert_eq!(aliases.canonical("OS-X"), "osx");
+    assert_eq!(aliases.canonical("mac-os-x"), "macos");
+
+    assert_eq!(EntityAliases::disabled().canonical("mac-os-x"), "mac-os-x");
+}
+
+#[tokio::test]
+async fn downloader_canonicalizes_releases() -> Result<()> {
+    let source = Arc::new(TestSource::sample().with_release("mac-os-x", "10.6", &[]));
+
+    let downloader = Downloader::builder()?.source(source.clone()).build();
+    let release = downloader.find_release("mac-os-x", "10.6").await?;
+    assert_eq!(release.entity, "macos");
+    assert_eq!(release.original_entity.as_deref(), Some("mac-os-x"));
+
+    let downloader = Downloader::builder()?
+        .source(source)
+        .entity_aliases(EntityAliases::disabled())
+        .build();
+    assert_eq!(
+        downloader.find_release("macos", "10.6").await?.entity,
+        "mac-os-x"
+    );
+
+    Ok(())
+}
+
+/// macOS releases as published, in the order they are listed.
+///
+/// Major releases are dated. Point releases are undated.
+const MACOS_HISTORY: &[(&str, &str, Option<&str>)] = &[
+    ("macos", "13.0", Some("2022-10-24")),
+    ("macos", "12.6", None),
+    ("macos", "12.0.1", Some("2021-10-25")),
+    ("macos", "11.7", None),
+    ("macos", "11.0.1", Some("2020-11-12")),
+    ("macos", "10.15.6", None),
+    ("macos", "10.15", Some("2019-10-07")),
+    ("macos", "10.14.6", None),
+    ("macos", "10.14", Some("2018-09-24")),
+    ("macos", "10.13.6", None),
+    ("macos", "10.13", Some("2017-09-25")),
+    ("macos", "10.12.6", None),
+    ("macos", "10.12", Some("2016-09-20")),
+    ("os-x", "10.11.6", None),
+    ("os-x", "10.11", Some("2015-09-30")),
+    ("os-x", "10.10.5", None),
+    ("os-x", "10.10", Some("2014-10-16")),
+    ("os-x", "10.9.5", None),
+    ("os-x", "10.9", Some("2013-10-22")),
+    ("os-x", "10.8.5", None),
+    ("os-x", "10.8", Some("2012-07-25")),
+    // Listed under both names during the rename.
+    ("os-x", "10.7.5", None),
+    ("mac-os-x", "10.7.5", None),
+    ("mac-os-x", "10.7", Some("2011-07-20")),
+    ("mac-os-x", "10.6.8", None),
+    ("mac-os-x", "10.6", Some("2009-08-28")),
+    ("mac-os-x", "10.5.8", None),
+    ("mac-os-x", "10.5", Some("2007-10-26")),
+    ("mac-os-x", "10.4.11", None),
+    ("mac-os-x", "10.4", Some("2005-04-29")),
+    ("mac-os-x", "10.3.9", None),
+    ("mac-os-x", "10.3", Some("2003-10-24")),
+    ("mac-os-x", "10.2.8", None),
+    ("mac-os-x", "10.2", Some("2002-08-24")),
+    ("mac-os-x", "10.1.5", None),
+    ("mac-os-x", "10.1", Some("2001-09-25")),
+    ("mac-os-x", "10.0.4", None),
+    ("mac-os-x", "10.0", Some("2001-03-24")),
+    ("ios", "16.0", Some("2022-09-12")),
+    ("mac-os-x-server", "10.6", Some("2009-08-28")),
+];
+
+fn macos_history() -> Vec<ReleaseRecord> {
+    MACOS_HISTORY
+        .iter()
+        .map(|(entity, version, date)| {
+            let mut record = record(
+                entity,
+                version,
+                &format!(
+                    "https://opensource.apple.com/release/{}-{}.html",
+                    entity, version
+                ),
+            );
+            record.date = date.map(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap());
+            record
+        })
+        .collect()
+}
+
+#[test]
+fn entity_names_are_normalized() {
+    assert_eq!(Platform::from_entity("Mac OS X"), Platform::MacOs);
+    assert_eq!(Platform::from_entity("OS X Server"), Platform::MacOsServer);
+    assert_eq!(
+        Platform::from_entity("Something Else"),
+        Platform::Other("something else".to_string())
+    );
+}
+
+#[test]
+fn macos_aliases_form_one_history() {
+    for aliases in [EntityAliases::default(), EntityAliases::disabled()] {
+        for entity in ["macos", "os-x", "mac-os-x", "Mac OS X"] {
+            let releases =
+                order_releases(stitch_aliases(entity, aliases.normalize(macos_history())));
+
+            assert!(verify_order(&releases).is_empty());
+            assert_eq!(releases.len(), 37);
+            assert_eq!(releases[0].version, "10.0");
+            assert_eq!(releases[36].version, "13.0");
+            assert!(releases.iter().all(|r| r.platform() == Platform::MacOs));
+
+            assert_eq!(releases.iter().filter(|r| r.version == "10.7.5").count(), 1);
+
+            // Each era keeps the entity it was published under.
+            assert_eq!(
+                releases.iter().map(|r| r.published_entity()).fold(
+                    Vec::new(),
+                    |mut eras, entity| {
+                        if eras.last() != Some(&entity) {
+                            eras.push(entity);
+                        }
+                        eras
+                    }
+                ),
+                vec!["mac-os-x", "os-x", "macos"]
+            );
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for service definitions.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::service::{ServiceDefinition, ServiceManager, LAUNCHD_LABEL},
+    std::path::PathBuf,
+};
+
+fn definition(manager: ServiceManager) -> ServiceDefinition {
+    ServiceDefinition {
+        manager,
+        program: PathBuf::from("/usr/local/bin/apple-opensource-downloader"),
+        args: vec![
+            "--config".to_string(),
+            "/srv/mirror config/100%.json".to_string(),
+            "watch".to_string(),
+        ],
+    }
+}
+
+#[test]
+fn systemd_units_quote_arguments() -> Result<()> {
+    let unit = definition(ServiceManager::Systemd).render()?;
+
+    assert!(unit.contains(
+        "\nExecStart=/usr/local/bin/apple-opensource-downloader --config \"/srv/mirror config/100%%.json\" watch\n"
+    ));
+    assert!(unit.contains("\nSuccessExitStatus=4\n"));
+
+    Ok(())
+}
+
+#[test]
+fn launchd_plists_list_arguments() -> Result<()> {
+    let text = definition(ServiceManager::Launchd).render()?;
+    let value = plist::Value::from_reader_xml(text.as_bytes())?;
+    let dict = value.as_dictionary().unwrap();
+
+    assert_eq!(
+        dict.get("Label").and_then(|v| v.as_string()),
+        Some(LAUNCHD_LABEL)
+    );
+    assert_eq!(
+        dict.get("ProgramArguments")
+            .and_then(|v| v.as_array())
+            .unwrap()
+            .iter()
+            .map(|v| v.as_string().unwrap())
+            .collect::<Vec<_>>(),
+        vec![
+            "/usr/local/bin/apple-opensource-downloader",
+            "--config",
+            "/srv/mirror config/100%.json",
+            "watch"
+        ]
+    );
+
+    Ok(())
+}
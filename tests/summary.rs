@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests of run summaries for automation.
+
+use {
+    anyhow::{anyhow, Context, Result},
+    apple_opensource_downloader::{error::PartialFailure, summary::RunSummary},
+    std::time::Duration,
+};
+
+#[test]
+fn run_outcomes() {
+    let success = RunSummary::new("sync", &Ok(()), Duration::from_secs(42));
+    assert_eq!((success.status, success.exit_code), ("success", 0));
+    assert_eq!(
+        success.to_json(),
+        r#"{"command":"sync","status":"success","exit_code":0,"duration_secs":42,"error":null}"#
+    );
+
+    let partial = RunSummary::new(
+        "components-to-gits",
+        &PartialFailure::check("components", 1, 3),
+        Duration::ZERO,
+    );
+    assert_eq!((partial.status, partial.exit_code), ("partial-failure", 2));
+    assert_eq!(partial.error.as_deref(), Some("1 of 3 components failed"));
+
+    let failure: Result<()> = Err(anyhow!("not found")).context("fetching components");
+    let failure = RunSummary::new("components", &failure, Duration::ZERO);
+    assert_eq!((failure.status, failure.exit_code), ("failure", 1));
+    assert_eq!(
+        failure.error.as_deref(),
+        Some("fetching components: not found")
+    );
+}
+
+#[test]
+fn github_outputs() {
+    let mut summary = RunSummary::new("sync", &Ok(()), Duration::from_secs(1));
+    assert_eq!(
+        summary.github_output(),
+        format!(
+            "command=sync\nstatus=success\nexit-code=0\nduration-secs=1\nsummary={}\n",
+            summary.to_json()
+        )
+    );
+
+    summary.error = Some("first\nsecond".to_string());
+    assert!(summary
+        .github_output()
+        .ends_with("error<<EOF_error\nfirst\nsecond\nEOF_error\n"));
+}
@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for updating mirrors from configuration.
+
+use {
+    anyhow::Result,
+    apple_opensource_downloader::{
+        download::Downloader,
+        error::InterruptedError,
+        git::{tag_commits, ImportOptions},
+        hooks::{Hooks, ImportEvent, ImportHook},
+        sync::{sync, watch, SyncConfig},
+        tasks::CancelHandle,
+        testing::TestSource,
+    },
+    async_trait::async_trait,
+    git2::Repository,
+    std::{sync::Arc, time::Duration},
+};
+
+#[tokio::test]
+async fn sync_updates_and_publishes() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-sync-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let published = dir.join("published");
+    for name in ["hello", "macos"] {
+        Repository::init_bare(published.join(format!("{}.git", name)))?;
+    }
+
+    let config = SyncConfig {
+        components: vec!["hel*".to_string()],
+        components_dest: Some(dir.join("components")),
+        releases: vec!["macos".to_string()],
+        releases_dest: Some(dir.join("releases")),
+        publish: vec![format!("{}/{{name}}.git", published.display())],
+        ..SyncConfig::default()
+    };
+
+    let downloader = Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build();
+
+    // Running again only brings repositories up to date.
+    for _ in 0..2 {
+        sync(&downloader, &config, &ImportOptions::default()).await?;
+    }
+
+    assert!(!dir.join("components").join("world").exists());
+    assert_eq!(
+        tag_commits(&dir.join("components").join("hello"))?,
+        tag_commits(&published.join("hello.git"))?
+    );
+    assert_eq!(
+        tag_commits(&published.join("macos.git"))?
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["11.0"]
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sync_requires_configuration() -> Result<()> {
+    let downloader = Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build();
+
+    assert!(sync(
+        &downloader,
+        &SyncConfig::default(),
+        &ImportOptions::default()
+    )
+    .await
+    .is_err());
+
+    Ok(())
+}
+
+/// Cancels a handle once a repository is complete.
+struct CancelOnComplete(CancelHandle);
+
+#[async_trait]
+impl ImportHook for CancelOnComplete {
+    async fn on_event(&self, event: &ImportEvent) -> Result<()> {
+        if matches!(event, ImportEvent::RepositoryComplete { .. }) {
+            self.0.cancel();
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn watch_syncs_until_shutdown() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("aosd-watch-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let config = SyncConfig {
+        components: vec!["hello".to_string()],
+        components_dest: Some(dir.clone()),
+        ..SyncConfig::default()
+    };
+
+    let downloader = Downloader::builder()?
+        .source(Arc::new(TestSource::sample()))
+        .build();
+
+    let shutdown = CancelHandle::default();
+    let mut hooks = Hooks::default();
+    hooks.push(CancelOnComplete(shutdown.clone()));
+    let options = ImportOptions {
+        hooks,
+        shutdown,
+        ..ImportOptions::default()
+    };
+
+    let err = watch(&downloader, &config, &options, Duration::from_secs(3600))
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<InterruptedError>().is_some());
+    assert_eq!(tag_commits(&dir.join("hello"))?.len(), 2);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
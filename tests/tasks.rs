@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for supervised task execution.
+
+use {
+    anyhow::{anyhow, Result},
+    apple_opensource_downloader::{
+        error::{TaskCancelledError, TaskPanicError},
+        tasks::{LocalTaskSet, TaskSet},
+    },
+    std::{
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+};
+
+#[tokio::test]
+async fn outcomes_are_in_spawn_order() {
+    let mut tasks = TaskSet::new();
+
+    for i in 0..5u64 {
+        tasks.spawn(format!("task {}", i), async move {
+            // Later tasks finish first.
+            tokio::time::sleep(Duration::from_millis(50 - i * 10)).await;
+            Ok(i)
+        });
+    }
+
+    let outcomes = tasks.join().await;
+    assert_eq!(
+        outcomes
+            .iter()
+            .map(|o| (o.label.as_str(), *o.result.as_ref().unwrap()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("task 0", 0),
+            ("task 1", 1),
+            ("task 2", 2),
+            ("task 3", 3),
+            ("task 4", 4)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn concurrency_is_limited() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+
+    let mut tasks = TaskSet::with_limit(2);
+
+    for i in 0..6 {
+        let running = running.clone();
+        let peak = peak.clone();
+
+        tasks.spawn(i.to_string(), async move {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(())
+        });
+    }
+
+    assert!(tasks.join().await.iter().all(|o| o.result.is_ok()));
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn panics_are_errors() {
+    let mut tasks = TaskSet::new();
+    tasks.spawn("ok", async { Ok(1) });
+    tasks.spawn("panics", async {
+        if true {
+            panic!("oh no");
+        }
+        Ok(2)
+    });
+
+    let outcomes = tasks.join().await;
+    assert_eq!(*outcomes[0].result.as_ref().unwrap(), 1);
+
+    let err = outcomes[1].result.as_ref().unwrap_err();
+    let panic = err.downcast_ref::<TaskPanicError>().unwrap();
+    assert_eq!(panic.task, "panics");
+    assert_eq!(panic.message, "oh no");
+}
+
+#[tokio::test]
+async fn failures_cancel_remaining_tasks() {
+    let mut tasks = TaskSet::new();
+    tasks.spawn("fails", async { Err::<(), _>(anyhow!("failed")) });
+    tasks.spawn("slow", async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(())
+    });
+
+    let err = tokio::time::timeout(Duration::from_secs(5), tasks.try_join())
+        .await
+        .expect("remaining tasks should be cancelled")
+        .unwrap_err();
+    assert_eq!(err.root_cause().to_string(), "failed");
+}
+
+#[tokio::test]
+async fn cancelled_tasks_are_reported() {
+    let mut tasks = TaskSet::new();
+    tasks.spawn("slow", async {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(())
+    });
+
+    let handle = tasks.cancel_handle();
+    handle.cancel();
+
+    let outcomes = tasks.join().await;
+    assert!(outcomes[0]
+        .result
+        .as_ref()
+        .unwrap_err()
+        .downcast_ref::<TaskCancelledError>()
+        .is_some());
+}
+
+#[tokio::test]
+async fn local_tasks_need_not_be_send() -> Result<()> {
+    let shared = Rc::new(AtomicUsize::new(0));
+    let mut tasks = LocalTaskSet::with_limit(1);
+
+    for _ in 0..3 {
+        let shared = shared.clone();
+
+        tasks.spawn("local", async move {
+            tokio::task::yield_now().await;
+            Ok(shared.fetch_add(1, Ordering::SeqCst))
+        });
+    }
+
+    assert_eq!(tasks.try_join().await?, vec![0, 1, 2]);
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for the telemetry log and its history.
+
+use {
+    anyhow::{anyhow, Result},
+    apple_opensource_downloader::{
+        middleware::Metrics,
+        summary::RunSummary,
+        telemetry::{History, RunRecord, TelemetryLog},
+    },
+    std::time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+fn test_dir(name: &str) -> Result<std::path::PathBuf> {
+    let dir = std::env::temp_dir()
+        .join("apple-opensource-downloader-telemetry")
+        .join(name);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+fn run(started: u64, command: &str, requests: u64, failures: u64, download_secs: f64) -> RunRecord {
+    RunRecord {
+        started,
+        command: command.to_string(),
+        status: "success".to_string(),
+        exit_code: 0,
+        duration_secs: 60,
+        requests,
+        failures,
+        bytes: 2_500_000,
+        download_secs,
+    }
+}
+
+#[test]
+fn records_round_trip() -> Result<()> {
+    let dir = test_dir("records_round_trip")?;
+    let path = dir.join("telemetry.jsonl");
+
+    let started = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let summary = RunSummary::new("sync", &Err(anyhow!("failed")), Duration::from_secs(90));
+    let record = RunRecord::new(&summary, &Metrics::default(), started);
+    assert_eq!(record.started, 1_700_000_000);
+    assert_eq!(record.status, "failure");
+    assert_eq!(record.duration_secs, 90);
+    assert_eq!(record.mean_request_secs(), None);
+
+    TelemetryLog::open(&path)?.record(&record)?;
+    let log = TelemetryLog::open(&path)?;
+    log.record(&run(1_700_100_000, "sync", 10, 1, 5.0))?;
+
+    let records = TelemetryLog::read(&path)?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0], record);
+    assert_eq!(records[1].failure_rate(), 0.1);
+    assert_eq!(records[1].mean_request_secs(), Some(0.5));
+
+    assert!(RunRecord::new(&summary, &Metrics::default(), SystemTime::now()).started > 0);
+
+    Ok(())
+}
+
+#[test]
+fn history_is_filtered_and_charted() -> Result<()> {
+    let runs = vec![
+        run(1_700_200_000, "sync", 10, 0, 10.0),
+        run(1_700_000_000, "sync", 10, 1, 5.0),
+        run(1_700_100_000, "doctor", 0, 0, 0.0),
+        run(1_700_300_000, "sync", 0, 0, 0.0),
+    ];
+
+    let history = History::new(runs.clone(), Some("sync"), Some(2));
+    assert_eq!(
+        history
+            .runs
+            .iter()
+            .map(|run| run.started)
+            .collect::<Vec<_>>(),
+        vec![1_700_200_000, 1_700_300_000]
+    );
+
+    let history = History::new(runs, None, None);
+    assert_eq!(history.runs.len(), 4);
+
+    let text = history.to_text();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("started "));
+    assert!(lines[1].starts_with("2023-11-14 22:13  sync "));
+    assert!(lines[1].contains("  10.0%  "));
+    assert!(lines[1].ends_with(&format!(" 0.50  {}", "#".repeat(10))));
+    assert!(lines[2].contains(" doctor "));
+    assert!(lines[2].ends_with(" -"));
+    assert!(lines[3].ends_with(&format!(" 1.00  {}", "#".repeat(20))));
+
+    assert!(history.to_json()?.contains("\"command\": \"doctor\""));
+    assert_eq!(History::default().to_text(), "no runs recorded\n");
+
+    Ok(())
+}
@@ -0,0 +1,925 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests for conversion of tar archives to Git trees.
+
+use {
+    apple_opensource_downloader::{
+        git::diff_tar_data,
+        tree::{
+            compressed_tar_stream_to_tree, tar_data_to_tree, tar_reader_to_tree,
+            tar_stream_to_tree, AppleMetadataPolicy, Compression, DuplicatePolicy, ModeOverride,
+            SymlinkPolicy, TreeOptions,
+        },
+    },
+    async_compression::tokio::bufread::{BzEncoder, XzEncoder},
+    git2::{ObjectType, Odb, Oid, Repository},
+    proptest::prelude::*,
+    std::{collections::BTreeMap, io::Read},
+    tokio::io::AsyncReadExt,
+};
+
+/// A member of a synthetic tar archive.
+enum Member<'a> {
+    File(&'a str, &'a [u8], u32),
+    Dir(&'a str),
+    Symlink(&'a str, &'a str),
+    HardLink(&'a str, &'a str),
+    /// A file whose path is written verbatim, without normalization.
+    RawFile(&'a [u8], &'a [u8]),
+}
+
+fn build_archive(members: &[Member]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        vec![],
+        flate2::Compression::fast(),
+    ));
+
+    for member in members {
+        let mut header = tar::Header::new_gnu();
+
+        match member {
+            Member::File(path, data, mode) => {
+                header.set_size(data.len() as u64);
+                header.set_mode(*mode);
+                header.set_cksum();
+                builder.append_data(&mut header, path, *data).unwrap();
+            }
+            Member::Dir(path) => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                header.set_mode(0o755);
+                header.set_cksum();
+                builder.append_data(&mut header, path, &[][..]).unwrap();
+            }
+            Member::Symlink(path, target) => {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                header.set_mode(0o777);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            Member::HardLink(path, target) => {
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_size(0);
+                header.set_mode(0o644);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            Member::RawFile(path, data) => {
+                header.as_old_mut().name[..path.len()].copy_from_slice(path);
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append(&header, *data).unwrap();
+            }
+        }
+    }
+
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+fn memory_repo() -> Repository {
+    let odb = Odb::new().unwrap();
+    odb.add_new_mempack_backend(1).unwrap();
+    Repository::from_odb(odb).unwrap()
+}
+
+/// Resolve all blobs in a tree as a map of path to (mode, content).
+fn tree_files(repo: &Repository, tree_oid: Oid) -> BTreeMap<String, (i32, Vec<u8>)> {
+    let tree = repo.find_tree(tree_oid).unwrap();
+    let mut files = BTreeMap::new();
+
+    tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            let blob = repo.find_blob(entry.id()).unwrap();
+            files.insert(
+                format!("{}{}", root, entry.name().unwrap()),
+                (entry.filemode(), blob.content().to_vec()),
+            );
+        }
+        git2::TreeWalkResult::Ok
+    })
+    .unwrap();
+
+    files
+}
+
+fn convert(members: &[Member]) -> BTreeMap<String, (i32, Vec<u8>)> {
+    convert_with_options(members, &TreeOptions::default())
+}
+
+fn convert_with_options(
+    members: &[Member],
+    options: &TreeOptions,
+) -> BTreeMap<String, (i32, Vec<u8>)> {
+    let repo = memory_repo();
+    let tree_oid = tar_data_to_tree(&build_archive(members), &repo, options).unwrap();
+
+    tree_files(&repo, tree_oid)
+}
+
+/// Construct AppleDouble data holding Finder info and an extended attribute.
+fn apple_double(xattr_name: &str, xattr_value: &[u8]) -> Vec<u8> {
+    let mut data = vec![];
+    data.extend_from_slice(&0x0005_1607u32.to_be_bytes());
+    data.extend_from_slice(&0x0002_0000u32.to_be_bytes());
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&1u16.to_be_bytes());
+
+    // Finder info entry, following the single entry header.
+    let finder_info_offset = 26 + 12;
+    let attr_offset = finder_info_offset + 32 + 2;
+    let name = format!("{}\0", xattr_name);
+    let entry_length = 4 + 4 + 2 + 1 + name.len();
+    let value_offset = (attr_offset + 36 + entry_length + 3) & !3;
+    let total = value_offset + xattr_value.len();
+
+    data.extend_from_slice(&9u32.to_be_bytes());
+    data.extend_from_slice(&(finder_info_offset as u32).to_be_bytes());
+    data.extend_from_slice(&((total - finder_info_offset) as u32).to_be_bytes());
+
+    let mut finder_info = [0u8; 32];
+    finder_info[..8].copy_from_slice(b"TEXTR*ch");
+    data.extend_from_slice(&finder_info);
+    data.extend_from_slice(&[0; 2]);
+
+    data.extend_from_slice(b"ATTR");
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&(total as u32).to_be_bytes());
+    data.extend_from_slice(&(value_offset as u32).to_be_bytes());
+    data.extend_from_slice(&(xattr_value.len() as u32).to_be_bytes());
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(&0u16.to_be_bytes());
+    data.extend_from_slice(&1u16.to_be_bytes());
+
+    data.extend_from_slice(&(value_offset as u32).to_be_bytes());
+    data.extend_from_slice(&(xattr_value.len() as u32).to_be_bytes());
+    data.extend_from_slice(&0u16.to_be_bytes());
+    data.push(name.len() as u8);
+    data.extend_from_slice(name.as_bytes());
+    data.resize(value_offset, 0);
+    data.extend_from_slice(xattr_value);
+
+    data
+}
+
+#[test]
+fn strips_top_level_directory() {
+    let files = convert(&[
+        Member::Dir("project-1.0/"),
+        Member::File("project-1.0/README", b"readme", 0o644),
+    ]);
+
+    assert_eq!(files.keys().collect::<Vec<_>>(), vec!["README"]);
+}
+
+#[test]
+fn deeply_nested_directories() {
+    let files = convert(&[Member::File("top/a/b/c/d/e/f/g/file", b"deep", 0o644)]);
+
+    assert_eq!(
+        files.get("a/b/c/d/e/f/g/file"),
+        Some(&(0o100644, b"deep".to_vec()))
+    );
+
+    // Intermediate trees must exist even without directory entries.
+    let repo = memory_repo();
+    let tree_oid = tar_data_to_tree(
+        &build_archive(&[Member::File("top/a/b/c/file", b"x", 0o644)]),
+        &repo,
+        &TreeOptions::default(),
+    )
+    .unwrap();
+    let tree = repo.find_tree(tree_oid).unwrap();
+    assert!(tree.get_path(std::path::Path::new("a/b")).is_ok());
+}
+
+#[test]
+fn irregular_separators_imply_directories() {
+    let files = convert(&[
+        Member::RawFile(b"top//a///b/c/", b"trailing"),
+        Member::RawFile(b"top/a/b//d/file", b"repeated"),
+        Member::RawFile(b"top/./a/./e/file", b"current"),
+        Member::RawFile(b"/top/f", b"leading"),
+    ]);
+
+    assert_eq!(
+        files
+            .into_iter()
+            .map(|(path, (_, data))| (path, data))
+            .collect::<Vec<_>>(),
+        vec![
+            ("a/b/c".to_string(), b"trailing".to_vec()),
+            ("a/b/d/file".to_string(), b"repeated".to_vec()),
+            ("a/e/file".to_string(), b"current".to_vec()),
+            ("f".to_string(), b"leading".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn leading_current_directory_is_not_stripped() {
+    let files = convert(&[
+        Member::Dir("./"),
+        Member::RawFile(b"./top/a/b/c/d/file", b"deep"),
+    ]);
+
+    assert_eq!(files.keys().collect::<Vec<_>>(), vec!["a/b/c/d/file"]);
+}
+
+#[test]
+fn intermediate_directories_are_independent_of_member_order() {
+    let members = [
+        Member::File("top/a/b/c/d/1", b"1", 0o644),
+        Member::File("top/a/2", b"2", 0o644),
+        Member::File("top/a/b/3", b"3", 0o644),
+        Member::File("top/e/f/4", b"4", 0o644),
+    ];
+
+    let repo = memory_repo();
+    let forward =
+        tar_data_to_tree(&build_archive(&members), &repo, &TreeOptions::default()).unwrap();
+    let reversed = members.iter().rev().map(|m| match m {
+        Member::File(path, data, mode) => Member::File(path, data, *mode),
+        _ => unreachable!(),
+    });
+    let backward = tar_data_to_tree(
+        &build_archive(&reversed.collect::<Vec<_>>()),
+        &repo,
+        &TreeOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(forward, backward);
+}
+
+#[test]
+fn sibling_directories_share_parents() {
+    let files = convert(&[
+        Member::File("top/a/x/1", b"1", 0o644),
+        Member::File("top/a/y/2", b"2", 0o644),
+        Member::File("top/a/3", b"3", 0o644),
+        Member::File("top/4", b"4", 0o644),
+    ]);
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["4", "a/3", "a/x/1", "a/y/2"]
+    );
+}
+
+#[test]
+fn keep_root() {
+    let files = convert_with_options(
+        &[
+            Member::File("LICENSE", b"license", 0o644),
+            Member::File("top/dir/file", b"file", 0o644),
+        ],
+        &TreeOptions {
+            strip_components: 0,
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["LICENSE", "top/dir/file"]
+    );
+}
+
+#[test]
+fn strip_multiple_components() {
+    let files = convert_with_options(
+        &[
+            Member::File("top/dir/file", b"file", 0o644),
+            Member::File("top/dir/nested/file", b"nested", 0o644),
+            Member::File("top/shallow", b"shallow", 0o644),
+        ],
+        &TreeOptions {
+            strip_components: 2,
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["file", "nested/file", "shallow"]
+    );
+}
+
+#[test]
+fn excluded_paths() {
+    let files = convert_with_options(
+        &[
+            Member::File("top/README", b"readme", 0o644),
+            Member::File("top/Tests/data/huge", b"huge", 0o644),
+            Member::File("top/src/Tests/unit", b"unit", 0o644),
+            Member::File("top/src/lib.a", b"lib", 0o644),
+            Member::File("top/lib.a", b"lib", 0o644),
+        ],
+        &TreeOptions {
+            exclude: vec![
+                glob::Pattern::new("Tests/**").unwrap(),
+                glob::Pattern::new("**/*.a").unwrap(),
+            ],
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["README", "src/Tests/unit"]
+    );
+}
+
+#[test]
+fn file_modes() {
+    let files = convert(&[
+        Member::File("top/exec", b"#!/bin/sh", 0o755),
+        Member::File("top/plain", b"data", 0o644),
+        Member::File("top/zero", b"data", 0),
+        Member::Symlink("top/link", "plain"),
+    ]);
+
+    assert_eq!(files["exec"].0, 0o100755);
+    assert_eq!(files["plain"].0, 0o100644);
+    assert_eq!(files["zero"].0, 0o100644);
+    assert_eq!(files["link"], (0o120000, b"plain".to_vec()));
+}
+
+#[test]
+fn mode_overrides() {
+    let files = convert_with_options(
+        &[
+            Member::File("top/include/header.h", b"header", 0o755),
+            Member::File("top/scripts/build.sh", b"#!/bin/sh", 0o644),
+            Member::File("top/scripts/unreadable.sh", b"#!/bin/sh", 0o200),
+            Member::File("top/src/header.h", b"header", 0o744),
+            Member::Symlink("top/link.h", "include/header.h"),
+        ],
+        &TreeOptions {
+            mode_overrides: vec![
+                ModeOverride::new("scripts/*", "100755").unwrap(),
+                ModeOverride::new("**/*.h", "644").unwrap(),
+            ],
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(files["include/header.h"].0, 0o100644);
+    assert_eq!(files["scripts/build.sh"].0, 0o100755);
+    assert_eq!(files["scripts/unreadable.sh"].0, 0o100755);
+    assert_eq!(files["src/header.h"].0, 0o100644);
+    assert_eq!(files["link.h"].0, 0o120000);
+
+    assert!(ModeOverride::new("*.h", "100600").is_err());
+    assert!(ModeOverride::new("[", "100644").is_err());
+}
+
+fn symlink_members<'a>() -> Vec<Member<'a>> {
+    vec![
+        Member::File("top/bin/tool", b"tool", 0o755),
+        Member::File("top/lib/data/file", b"data", 0o644),
+        Member::Symlink("top/lib/data/tool", "../../bin/tool"),
+        Member::Symlink("top/chain", "lib/data/tool"),
+        Member::Symlink("top/share", "lib/data"),
+        Member::Symlink("top/lib/data/loop", ".."),
+        Member::Symlink("top/absolute", "/etc/passwd"),
+        Member::Symlink("top/outside", "../other/file"),
+        Member::Symlink("top/dangling", "missing"),
+    ]
+}
+
+#[test]
+fn symlinks_dereferenced() {
+    let files = convert_with_options(
+        &symlink_members(),
+        &TreeOptions {
+            symlinks: SymlinkPolicy::Dereference,
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(
+        files
+            .into_iter()
+            .map(|(path, (mode, data))| (path, mode, data))
+            .collect::<Vec<_>>(),
+        vec![
+            ("bin/tool".to_string(), 0o100755, b"tool".to_vec()),
+            ("chain".to_string(), 0o100755, b"tool".to_vec()),
+            ("lib/data/file".to_string(), 0o100644, b"data".to_vec()),
+            ("lib/data/tool".to_string(), 0o100755, b"tool".to_vec()),
+            ("share/file".to_string(), 0o100644, b"data".to_vec()),
+            ("share/tool".to_string(), 0o100755, b"tool".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn symlinks_skipped() {
+    let files = convert_with_options(
+        &symlink_members(),
+        &TreeOptions {
+            symlinks: SymlinkPolicy::Skip,
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["bin/tool", "lib/data/file"]
+    );
+}
+
+#[test]
+fn symlinks_kept() {
+    let files = convert(&symlink_members());
+
+    assert_eq!(files["share"], (0o120000, b"lib/data".to_vec()));
+    assert_eq!(files["lib/data/loop"], (0o120000, b"..".to_vec()));
+    assert_eq!(files.len(), 9);
+}
+
+#[test]
+fn hard_links_are_copies_of_their_target() {
+    let members = [
+        Member::File("top/bin/tool", b"tool", 0o755),
+        Member::Symlink("top/bin/alias", "tool"),
+        Member::HardLink("top/lib/tool", "top/bin/tool"),
+        Member::HardLink("top/lib/again", "./top/lib/tool"),
+        Member::HardLink("top/lib/symlink", "top/bin/alias"),
+        Member::HardLink("top/lib/dangling", "top/missing"),
+    ];
+
+    for symlinks in [
+        SymlinkPolicy::Keep,
+        SymlinkPolicy::Dereference,
+        SymlinkPolicy::Skip,
+    ] {
+        let files = convert_with_options(
+            &members,
+            &TreeOptions {
+                symlinks,
+                ..TreeOptions::default()
+            },
+        );
+
+        assert_eq!(
+            files["lib/tool"],
+            (0o100755, b"tool".to_vec()),
+            "{:?}",
+            symlinks
+        );
+        assert_eq!(
+            files["lib/again"],
+            (0o100755, b"tool".to_vec()),
+            "{:?}",
+            symlinks
+        );
+        assert!(!files.contains_key("lib/symlink"), "{:?}", symlinks);
+        assert!(!files.contains_key("lib/dangling"), "{:?}", symlinks);
+        assert!(
+            files.values().filter(|(mode, _)| *mode == 0o120000).count()
+                == usize::from(symlinks == SymlinkPolicy::Keep),
+            "{:?}",
+            symlinks
+        );
+    }
+}
+
+#[test]
+fn root_level_files_are_imported() {
+    let files = convert(&[
+        Member::File("LICENSE", b"license", 0o644),
+        Member::File("top/file", b"file", 0o644),
+    ]);
+
+    assert_eq!(files.keys().collect::<Vec<_>>(), vec!["LICENSE", "file"]);
+}
+
+#[test]
+fn trailing_slash_directory_entries() {
+    let files = convert(&[
+        Member::Dir("top/"),
+        Member::Dir("top/dir/"),
+        Member::Dir("top/dir/nested/"),
+        Member::File("top/dir/nested/file", b"file", 0o644),
+    ]);
+
+    assert_eq!(files.keys().collect::<Vec<_>>(), vec!["dir/nested/file"]);
+}
+
+#[test]
+fn duplicate_entries_last_wins() {
+    let files = convert(&[
+        Member::File("top/file", b"first", 0o644),
+        Member::File("top/file", b"second", 0o644),
+    ]);
+
+    assert_eq!(files["file"].1, b"second");
+}
+
+#[test]
+fn duplicate_entries_strict() {
+    let repo = memory_repo();
+    let options = TreeOptions {
+        duplicates: DuplicatePolicy::Error,
+        ..TreeOptions::default()
+    };
+
+    let res = tar_data_to_tree(
+        &build_archive(&[
+            Member::File("top/dir/file", b"first", 0o644),
+            Member::File("top/dir/file", b"second", 0o644),
+        ]),
+        &repo,
+        &options,
+    );
+
+    assert_eq!(
+        res.unwrap_err().to_string(),
+        "duplicate tar member dir/file"
+    );
+}
+
+#[test]
+fn file_replacing_directory_last_wins() {
+    let files = convert(&[
+        Member::File("top/dir/file", b"file", 0o644),
+        Member::File("top/dir", b"dir", 0o644),
+        Member::File("top/other", b"other", 0o644),
+        Member::File("top/other/file", b"nested", 0o644),
+    ]);
+
+    assert_eq!(
+        files
+            .into_iter()
+            .map(|(path, (_, data))| (path, data))
+            .collect::<Vec<_>>(),
+        vec![
+            ("dir".to_string(), b"dir".to_vec()),
+            ("other/file".to_string(), b"nested".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn file_replacing_directory_strict() {
+    let repo = memory_repo();
+    let options = TreeOptions {
+        duplicates: DuplicatePolicy::Error,
+        ..TreeOptions::default()
+    };
+
+    let res = tar_data_to_tree(
+        &build_archive(&[
+            Member::File("top/dir", b"dir", 0o644),
+            Member::File("top/dir/file", b"file", 0o644),
+        ]),
+        &repo,
+        &options,
+    );
+
+    assert_eq!(
+        res.unwrap_err().to_string(),
+        "duplicate tar member dir/file"
+    );
+}
+
+#[test]
+fn apple_metadata_keep() {
+    let double = apple_double("com.apple.test", b"value");
+    let files = convert(&[
+        Member::File("top/file", b"file", 0o644),
+        Member::File("top/._file", &double, 0o644),
+        Member::File("top/PaxHeader/file", b"17 path=top/file\n", 0o644),
+    ]);
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec!["._file", "PaxHeader/file", "file"]
+    );
+}
+
+#[test]
+fn apple_metadata_strip() {
+    let double = apple_double("com.apple.test", b"value");
+    let files = convert_with_options(
+        &[
+            Member::File("top/file", b"file", 0o644),
+            Member::File("top/._file", &double, 0o644),
+            Member::File("top/PaxHeader/file", b"17 path=top/file\n", 0o644),
+        ],
+        &TreeOptions {
+            apple_metadata: AppleMetadataPolicy::Strip,
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(files.keys().collect::<Vec<_>>(), vec!["file"]);
+}
+
+#[test]
+fn apple_metadata_sidecar() {
+    let double = apple_double("com.apple.test", b"value");
+    let files = convert_with_options(
+        &[
+            Member::File("top/dir/file", b"file", 0o644),
+            Member::File("top/dir/._file", &double, 0o644),
+        ],
+        &TreeOptions {
+            apple_metadata: AppleMetadataPolicy::Sidecar,
+            ..TreeOptions::default()
+        },
+    );
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        vec![".apple-metadata.json", "dir/file"]
+    );
+
+    let sidecar: serde_json::Value =
+        serde_json::from_slice(&files[".apple-metadata.json"].1).unwrap();
+    assert_eq!(
+        sidecar,
+        serde_json::json!({
+            "dir/file": {
+                "xattrs": {"com.apple.test": "76616c7565"},
+                "finder_info": format!("{}{}", "54455854522a6368", "0".repeat(48)),
+            }
+        })
+    );
+}
+
+#[test]
+fn apple_metadata_sidecar_pax_xattrs() {
+    let mut builder = tar::Builder::new(vec![]);
+    builder
+        .append_pax_extensions([("SCHILY.xattr.user.test", &b"value"[..])])
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "top/file", &b"file"[..])
+        .unwrap();
+    let data = builder.into_inner().unwrap();
+
+    let repo = memory_repo();
+    let tree_oid = tar_data_to_tree(
+        &data,
+        &repo,
+        &TreeOptions {
+            apple_metadata: AppleMetadataPolicy::Sidecar,
+            ..TreeOptions::default()
+        },
+    )
+    .unwrap();
+    let files = tree_files(&repo, tree_oid);
+
+    let sidecar: serde_json::Value =
+        serde_json::from_slice(&files[".apple-metadata.json"].1).unwrap();
+    assert_eq!(
+        sidecar,
+        serde_json::json!({"file": {"xattrs": {"user.test": "76616c7565"}}})
+    );
+}
+
+#[test]
+fn empty_archive() {
+    let repo = memory_repo();
+    let tree_oid = tar_data_to_tree(&build_archive(&[]), &repo, &TreeOptions::default()).unwrap();
+
+    assert_eq!(repo.find_tree(tree_oid).unwrap().len(), 0);
+}
+
+#[test]
+fn deterministic() {
+    let members = [
+        Member::File("top/b/file", b"b", 0o644),
+        Member::File("top/a/file", b"a", 0o644),
+    ];
+
+    let repo = memory_repo();
+    let a = tar_data_to_tree(&build_archive(&members), &repo, &TreeOptions::default()).unwrap();
+    let b = tar_data_to_tree(&build_archive(&members), &repo, &TreeOptions::default()).unwrap();
+
+    assert_eq!(a, b);
+}
+
+#[tokio::test]
+async fn streams_convert_like_buffered_archives() {
+    let double = apple_double("com.apple.test", b"value");
+    let members = [
+        Member::Dir("top/"),
+        Member::File("top/dir/file", b"file", 0o644),
+        Member::File("top/dir/._file", &double, 0o644),
+        Member::File("top/run", b"#!/bin/sh\n", 0o755),
+        Member::Symlink("top/link", "dir/file"),
+        Member::HardLink("top/copy", "top/run"),
+    ];
+    let options = TreeOptions {
+        apple_metadata: AppleMetadataPolicy::Sidecar,
+        ..TreeOptions::default()
+    };
+
+    let gzip = build_archive(&members);
+    let mut plain = vec![];
+    flate2::read::GzDecoder::new(&gzip[..])
+        .read_to_end(&mut plain)
+        .unwrap();
+    let mut bzip2 = vec![];
+    BzEncoder::new(&plain[..])
+        .read_to_end(&mut bzip2)
+        .await
+        .unwrap();
+    let mut xz = vec![];
+    XzEncoder::new(&plain[..])
+        .read_to_end(&mut xz)
+        .await
+        .unwrap();
+
+    let repo = memory_repo();
+    let expected = tar_data_to_tree(&gzip, &repo, &options).unwrap();
+    assert_eq!(
+        tree_files(&repo, expected).keys().collect::<Vec<_>>(),
+        vec![".apple-metadata.json", "copy", "dir/file", "link", "run"]
+    );
+
+    for (compression, data) in [
+        (Compression::None, &plain),
+        (Compression::Gzip, &gzip),
+        (Compression::Bzip2, &bzip2),
+        (Compression::Xz, &xz),
+    ] {
+        assert_eq!(Compression::sniff(data), compression);
+        assert_eq!(
+            tar_data_to_tree(data, &repo, &options).unwrap(),
+            expected,
+            "{:?}",
+            compression
+        );
+        assert_eq!(
+            compressed_tar_stream_to_tree(&data[..], &repo, &options)
+                .await
+                .unwrap(),
+            expected,
+            "{:?}",
+            compression
+        );
+    }
+
+    assert_eq!(
+        tar_stream_to_tree(&plain[..], &repo, &options)
+            .await
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        tar_reader_to_tree(&plain[..], &repo, &options).unwrap(),
+        expected
+    );
+}
+
+#[tokio::test]
+async fn large_files_are_streamed_to_blobs() {
+    let dir = std::env::temp_dir().join(format!("aosd-tree-large-files-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    // Mempack repositories don't support streaming, so a filesystem
+    // repository is needed to write blobs in chunks.
+    let repo = Repository::init_bare(&dir).unwrap();
+
+    // Spans several 8 MiB chunks, the last one partial.
+    let large = (0..2 * 8 * 1024 * 1024 + 12345u32)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let members = [
+        Member::File("top/large", &large, 0o644),
+        Member::File("top/small", b"small", 0o644),
+    ];
+    let options = TreeOptions::default();
+
+    let gzip = build_archive(&members);
+    let mut plain = vec![];
+    flate2::read::GzDecoder::new(&gzip[..])
+        .read_to_end(&mut plain)
+        .unwrap();
+
+    let expected = tar_data_to_tree(&gzip, &repo, &options).unwrap();
+    let files = tree_files(&repo, expected);
+    assert_eq!(files["large"].1, large);
+    assert_eq!(files["small"].1, b"small");
+
+    assert_eq!(
+        tar_reader_to_tree(&plain[..], &repo, &options).unwrap(),
+        expected
+    );
+    assert_eq!(
+        tar_stream_to_tree(&plain[..], &repo, &options)
+            .await
+            .unwrap(),
+        expected
+    );
+
+    // Buffered blobs are identical.
+    let memory = memory_repo();
+    assert_eq!(
+        tar_stream_to_tree(&plain[..], &memory, &options)
+            .await
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        tar_data_to_tree(&gzip, &memory, &options).unwrap(),
+        expected
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn archives_in_any_format_are_diffed() {
+    let old = build_archive(&[
+        Member::File("top/same", b"same", 0o644),
+        Member::File("top/changed", b"old", 0o644),
+        Member::File("top/removed", b"removed", 0o644),
+    ]);
+    let new = build_archive(&[
+        Member::File("top/same", b"same", 0o644),
+        Member::File("top/changed", b"new", 0o644),
+        Member::File("top/added", b"added", 0o644),
+    ]);
+
+    let mut plain = vec![];
+    flate2::read::GzDecoder::new(&new[..])
+        .read_to_end(&mut plain)
+        .unwrap();
+    let mut xz = vec![];
+    XzEncoder::new(&plain[..])
+        .read_to_end(&mut xz)
+        .await
+        .unwrap();
+
+    let mut changes = diff_tar_data(old, xz)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(status, path)| (format!("{:?}", status), path))
+        .collect::<Vec<_>>();
+    changes.sort();
+    assert_eq!(
+        changes,
+        vec![
+            ("Added".to_string(), "added".to_string()),
+            ("Deleted".to_string(), "removed".to_string()),
+            ("Modified".to_string(), "changed".to_string()),
+        ]
+    );
+}
+
+/// Generate relative file paths where no path is a directory of another.
+fn path_layouts() -> impl Strategy<Value = BTreeMap<String, Vec<u8>>> {
+    prop::collection::btree_map(
+        prop::collection::vec("[a-z0-9_.-]{1,8}", 1..6).prop_map(|parts| parts.join("/")),
+        prop::collection::vec(any::<u8>(), 0..64),
+        1..24,
+    )
+    .prop_filter("paths must not conflict with directories", |files| {
+        !files.keys().any(|a| {
+            files
+                .keys()
+                .any(|b| a != b && b.starts_with(&format!("{}/", a)))
+        })
+    })
+    .prop_filter("paths must not contain special components", |files| {
+        files
+            .keys()
+            .all(|p| p.split('/').all(|part| part != "." && part != ".."))
+    })
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_path_layouts(files in path_layouts()) {
+        let paths = files
+            .iter()
+            .map(|(path, data)| (format!("top/{}", path), data))
+            .collect::<Vec<_>>();
+        let members = paths
+            .iter()
+            .map(|(path, data)| Member::File(path, data, 0o644))
+            .collect::<Vec<_>>();
+
+        let converted = convert(&members);
+
+        prop_assert_eq!(
+            converted
+                .into_iter()
+                .map(|(path, (_, data))| (path, data))
+                .collect::<BTreeMap<_, _>>(),
+            files
+        );
+    }
+}